@@ -2,7 +2,7 @@
 
 use anyhow::Result;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Emitter, State};
 
 #[derive(Default)]
@@ -22,7 +22,15 @@ struct TaskResult {
 }
 
 #[tauri::command]
-fn encode_path(app: AppHandle, busy: State<'_, Busy>, input: String, out_dir: String) -> Result<()> {
+fn encode_path(
+    app: AppHandle,
+    busy: State<'_, Busy>,
+    input: String,
+    out_dir: String,
+    passphrase: Option<String>,
+    video: Option<String>,
+    video_export: Option<sllv_core::VideoExportParams>,
+) -> Result<()> {
     {
         let mut b = busy.0.lock().unwrap();
         if *b {
@@ -47,8 +55,26 @@ fn encode_path(app: AppHandle, busy: State<'_, Busy>, input: String, out_dir: St
                 },
             )?;
 
-            let p = sllv_core::RasterParams::default();
-            let _m = sllv_core::encode_bytes_to_frames_dir(&tar_bytes, &format!("{}.tar", name), &out_dir, &p)?;
+            let mut p = sllv_core::RasterParams::default();
+            p.encryption = passphrase.filter(|s| !s.is_empty()).map(sllv_core::EncryptionKey::Passphrase);
+            let video_export = video_export.unwrap_or_default();
+            if video.is_some() {
+                video_export.validate(&p)?;
+            }
+            let m = sllv_core::encode_bytes_to_frames_dir(&tar_bytes, &format!("{}.tar", name), &out_dir, &p)?;
+
+            if let Some(video) = video.map(PathBuf::from) {
+                app.emit(
+                    "progress",
+                    Progress {
+                        stage: "Writing video".into(),
+                        done: 0,
+                        total: 1,
+                    },
+                )?;
+                sllv_core::frames_to_video(&out_dir, &video, &p, &video_export, None)?;
+                std::fs::write(sllv_core::video_manifest_path(&video), serde_json::to_vec_pretty(&m)?)?;
+            }
 
             app.emit(
                 "progress",
@@ -77,7 +103,18 @@ fn encode_path(app: AppHandle, busy: State<'_, Busy>, input: String, out_dir: St
 }
 
 #[tauri::command]
-fn decode_frames(app: AppHandle, busy: State<'_, Busy>, in_dir: String, output: String) -> Result<()> {
+fn decode_frames(
+    app: AppHandle,
+    busy: State<'_, Busy>,
+    in_dir: String,
+    output: Option<String>,
+    out_dir: Option<String>,
+    on_conflict: Option<sllv_core::CollisionPolicy>,
+    passphrase: Option<String>,
+) -> Result<()> {
+    if output.is_none() && out_dir.is_none() {
+        anyhow::bail!("choose an output .tar or an output folder");
+    }
     {
         let mut b = busy.0.lock().unwrap();
         if *b {
@@ -87,25 +124,40 @@ fn decode_frames(app: AppHandle, busy: State<'_, Busy>, in_dir: String, output:
     }
 
     std::thread::spawn(move || {
-        let res: anyhow::Result<()> = (|| {
+        let res: anyhow::Result<String> = (|| {
             app.emit("progress", Progress { stage: "Decoding frames".into(), done: 0, total: 1 })?;
             let in_dir = PathBuf::from(in_dir);
-            let output = PathBuf::from(output);
-            let bytes = sllv_core::decode_frames_dir_to_bytes(&in_dir)?;
-            app.emit(
-                "progress",
-                Progress { stage: "Writing output".into(), done: 0, total: bytes.len() as u64 },
-            )?;
-            std::fs::write(output, bytes)?;
+            let mut p = sllv_core::RasterParams::default();
+            p.decryption = passphrase.filter(|s| !s.is_empty()).map(sllv_core::DecryptionKey::Passphrase);
+
+            let summary = if let Some(out_dir) = out_dir {
+                // Verify the whole archive before anything is extracted.
+                let manifest = sllv_core::raster::read_manifest(&in_dir)?;
+                let mut scratch = sllv_core::PartialFile::scratch_in(Path::new(&out_dir))?;
+                sllv_core::decode_frames_dir_to_writer(&in_dir, &p, &mut scratch)?;
+                let opts = sllv_core::ExtractOptions {
+                    on_collision: on_conflict.unwrap_or_default(),
+                };
+                let report = sllv_core::extract_payload_to_dir(scratch.reopen()?, &manifest, Path::new(&out_dir), &opts)?;
+                let mut lines: Vec<String> = report.entries.iter().map(|e| e.to_string()).collect();
+                lines.push(format!("Extracted {} files to {}", report.files_written(), out_dir));
+                lines.join("\n")
+            } else {
+                let output = PathBuf::from(output.unwrap_or_default());
+                let mut out = sllv_core::PartialFile::create(&output)?;
+                sllv_core::decode_frames_dir_to_writer(&in_dir, &p, &mut out)?;
+                out.commit()?;
+                "Decode complete".to_string()
+            };
             app.emit(
                 "progress",
                 Progress { stage: "Done".into(), done: 1, total: 1 },
             )?;
-            Ok(())
+            Ok(summary)
         })();
 
         let msg = match res {
-            Ok(_) => TaskResult { ok: true, message: "Decode complete".into() },
+            Ok(summary) => TaskResult { ok: true, message: summary },
             Err(e) => TaskResult { ok: false, message: format!("Decode failed: {e}") },
         };
         let _ = app.emit("task_result", msg);
@@ -32,6 +32,27 @@ el.innerHTML = `
           <div id="enc_out" style="margin-top:6px; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 12px; word-break: break-all;"></div>
         </div>
 
+        <div style="height:10px"></div>
+        <label>Passphrase (optional) <input id="enc_pass" type="password" autocomplete="new-password" /></label>
+
+        <div style="height:10px"></div>
+
+        <div>
+          <button id="pick_video">Also save a video (needs ffmpeg)</button>
+          <button id="clear_video">No video</button>
+          <div id="enc_video" style="margin-top:6px; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 12px; word-break: break-all;"></div>
+          <div style="margin-top:6px">
+            <select id="enc_codec">
+              <option value="ffv1">FFV1</option>
+              <option value="x264rgb">H.264 lossless RGB</option>
+              <option value="vp9-lossless">VP9 lossless</option>
+            </select>
+            <label>FPS <input id="enc_fps" type="number" min="1" max="240" value="24" style="width:4em" /></label>
+            <label>Lead-in (s) <input id="enc_lead_in" type="number" min="0" step="0.5" value="0" style="width:4em" /></label>
+            <label>Lead-out (s) <input id="enc_lead_out" type="number" min="0" step="0.5" value="0" style="width:4em" /></label>
+          </div>
+        </div>
+
         <div style="height:10px"></div>
         <button id="enc_btn">Encode</button>
       </div>
@@ -51,6 +72,21 @@ el.innerHTML = `
           <div id="dec_out" style="margin-top:6px; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 12px; word-break: break-all;"></div>
         </div>
 
+        <div style="height:10px"></div>
+
+        <div>
+          <button id="pick_dir">Or pick a folder to extract into</button>
+          <select id="dec_conflict">
+            <option value="skip">If a file exists: skip</option>
+            <option value="overwrite">If a file exists: overwrite</option>
+            <option value="rename">If a file exists: rename</option>
+          </select>
+          <div id="dec_dir" style="margin-top:6px; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 12px; word-break: break-all;"></div>
+        </div>
+
+        <div style="height:10px"></div>
+        <label>Passphrase (if encrypted) <input id="dec_pass" type="password" autocomplete="off" /></label>
+
         <div style="height:10px"></div>
         <button id="dec_btn">Decode</button>
       </div>
@@ -72,8 +108,10 @@ el.innerHTML = `
 
 let encodeInput = '';
 let encodeOut = '';
+let encodeVideo = '';
 let decodeIn = '';
 let decodeOut = '';
+let decodeDir = '';
 
 const log = (s) => {
   const pre = document.getElementById('log');
@@ -124,6 +162,22 @@ document.getElementById('pick_out').onclick = async () => {
   }
 };
 
+document.getElementById('pick_video').onclick = async () => {
+  const v = await save({
+    defaultPath: 'out.mkv',
+    filters: [{ name: 'Video', extensions: ['mkv', 'mp4', 'webm', 'avi'] }]
+  });
+  if (v) {
+    encodeVideo = v;
+    document.getElementById('enc_video').textContent = v;
+  }
+};
+
+document.getElementById('clear_video').onclick = () => {
+  encodeVideo = '';
+  document.getElementById('enc_video').textContent = '';
+};
+
 document.getElementById('pick_frames').onclick = async () => {
   const v = await open({ multiple: false, directory: true });
   if (v) {
@@ -143,6 +197,14 @@ document.getElementById('pick_tar').onclick = async () => {
   }
 };
 
+document.getElementById('pick_dir').onclick = async () => {
+  const v = await open({ multiple: false, directory: true });
+  if (v) {
+    decodeDir = v;
+    document.getElementById('dec_dir').textContent = v;
+  }
+};
+
 // Actions
 
 document.getElementById('enc_btn').onclick = async () => {
@@ -152,7 +214,22 @@ document.getElementById('enc_btn').onclick = async () => {
   }
   log(`[encode] input=${encodeInput}`);
   try {
-    await invoke('encode_path', { input: encodeInput, outDir: encodeOut });
+    const passphrase = document.getElementById('enc_pass').value || null;
+    const ext = encodeVideo.split('.').pop().toLowerCase();
+    const videoExport = encodeVideo ? {
+      codec: document.getElementById('enc_codec').value,
+      container: ['mkv', 'mp4', 'webm', 'avi'].includes(ext) ? ext : 'mkv',
+      fps: Number(document.getElementById('enc_fps').value) || 24,
+      lead_in_secs: Number(document.getElementById('enc_lead_in').value) || 0,
+      lead_out_secs: Number(document.getElementById('enc_lead_out').value) || 0
+    } : null;
+    await invoke('encode_path', {
+      input: encodeInput,
+      outDir: encodeOut,
+      passphrase,
+      video: encodeVideo || null,
+      videoExport
+    });
     log('[encode] started');
   } catch (e) {
     log('[encode] error: ' + e);
@@ -160,13 +237,21 @@ document.getElementById('enc_btn').onclick = async () => {
 };
 
 document.getElementById('dec_btn').onclick = async () => {
-  if (!decodeIn || !decodeOut) {
-    log('[decode] pick frames folder + output path first');
+  if (!decodeIn || (!decodeOut && !decodeDir)) {
+    log('[decode] pick frames folder + output path (or folder) first');
     return;
   }
   log(`[decode] in_dir=${decodeIn}`);
   try {
-    await invoke('decode_frames', { inDir: decodeIn, output: decodeOut });
+    const passphrase = document.getElementById('dec_pass').value || null;
+    const onConflict = document.getElementById('dec_conflict').value;
+    await invoke('decode_frames', {
+      inDir: decodeIn,
+      output: decodeOut || null,
+      outDir: decodeDir || null,
+      onConflict,
+      passphrase
+    });
     log('[decode] started');
   } catch (e) {
     log('[decode] error: ' + e);
@@ -35,13 +35,65 @@ fn prompt_yes_no(label: &str, default_no: bool) -> anyhow::Result<bool> {
 }
 
 fn prompt_profile() -> anyhow::Result<sllv_core::Profile> {
-    let s = prompt_line("Profile (archive/scan) [archive]: ")?.to_lowercase();
+    let s = prompt_line("Profile (archive/scan/video/print) [archive]: ")?.to_lowercase();
     Ok(match s.as_str() {
         "scan" => sllv_core::Profile::Scan,
+        "video" => sllv_core::Profile::Video,
+        "print" => sllv_core::Profile::Print,
         _ => sllv_core::Profile::Archive,
     })
 }
 
+fn prompt_compression() -> anyhow::Result<sllv_core::Compression> {
+    let s = prompt_line("Compression (none/zstd/xz) [none]: ")?.to_lowercase();
+    Ok(match s.as_str() {
+        "zstd" => sllv_core::Compression::Zstd {
+            level: sllv_core::Compression::ZSTD_DEFAULT_LEVEL,
+        },
+        "xz" => sllv_core::Compression::Xz {
+            preset: sllv_core::Compression::XZ_DEFAULT_PRESET,
+        },
+        _ => sllv_core::Compression::None,
+    })
+}
+
+fn prompt_collision() -> anyhow::Result<sllv_core::CollisionPolicy> {
+    let s = prompt_line("If a file already exists (skip/overwrite/rename) [skip]: ")?.to_lowercase();
+    Ok(match s.as_str() {
+        "overwrite" => sllv_core::CollisionPolicy::Overwrite,
+        "rename" => sllv_core::CollisionPolicy::Rename,
+        _ => sllv_core::CollisionPolicy::Skip,
+    })
+}
+
+fn prompt_passphrase(label: &str) -> anyhow::Result<Option<String>> {
+    let s = prompt_line(label)?;
+    Ok(if s.is_empty() { None } else { Some(s) })
+}
+
+fn prompt_filter() -> anyhow::Result<sllv_core::PackFilter> {
+    let exclude = prompt_line("Exclude globs, comma-separated (blank = none): ")?;
+    Ok(sllv_core::PackFilter {
+        exclude: exclude.split(',').map(|g| g.trim().to_string()).filter(|g| !g.is_empty()).collect(),
+        ignore_files: prompt_yes_no("Honor .gitignore/.sllvignore and skip .git/?", true)?,
+        ..Default::default()
+    })
+}
+
+/// Pack `input` with `filter`, list what was skipped and record the filter in `rp`.
+fn pack_input(input: &Path, filter: sllv_core::PackFilter, rp: &mut sllv_core::RasterParams) -> anyhow::Result<(Vec<u8>, String)> {
+    let opts = sllv_core::PackOptions {
+        filter,
+        ..Default::default()
+    };
+    let packed = sllv_core::pack::pack_path_to_tar_bytes_with_options(input, &opts).context("pack input")?;
+    for s in &packed.skipped {
+        println!("{s}");
+    }
+    rp.filter = packed.filter_info(&opts.filter);
+    Ok((packed.tar, packed.name))
+}
+
 fn pause_exit() {
     let _ = prompt_line("\nPress Enter to exit...");
 }
@@ -53,7 +105,7 @@ pub fn run() -> anyhow::Result<()> {
         println!("Choose an action:");
         println!("  1) Encode -> frames");
         println!("  2) Encode -> frames + mkv (ffmpeg)");
-        println!("  3) Decode frames -> recovered.tar");
+        println!("  3) Decode frames -> recovered.tar or folder");
         println!("  4) Decode mkv -> recovered.tar (ffmpeg)");
         println!("  5) Doctor");
         println!("  0) Exit");
@@ -65,9 +117,12 @@ pub fn run() -> anyhow::Result<()> {
                 let profile = prompt_profile()?;
                 let input = prompt_path("Input file/folder path: ")?;
                 let out_frames = prompt_path("Output frames directory: ")?;
-                let rp = profile.defaults();
+                let mut rp = profile.defaults();
+                rp.compression = prompt_compression()?;
+                rp.encryption =
+                    prompt_passphrase("Passphrase (blank = no encryption): ")?.map(sllv_core::EncryptionKey::Passphrase);
 
-                let (tar, name) = sllv_core::pack::pack_path_to_tar_bytes(&input).context("pack input")?;
+                let (tar, name) = pack_input(&input, prompt_filter()?, &mut rp)?;
                 let manifest =
                     sllv_core::raster::encode_bytes_to_frames_dir(&tar, &name, &out_frames, &rp).context("encode")?;
 
@@ -84,13 +139,20 @@ pub fn run() -> anyhow::Result<()> {
 
                 let ffmpeg_path = prompt_optional_path("Optional ffmpeg path (blank = PATH): ")?;
 
-                let rp = profile.defaults();
-                let (tar, name) = sllv_core::pack::pack_path_to_tar_bytes(&input).context("pack input")?;
+                let mut rp = profile.defaults();
+                rp.compression = prompt_compression()?;
+                rp.encryption =
+                    prompt_passphrase("Passphrase (blank = no encryption): ")?.map(sllv_core::EncryptionKey::Passphrase);
+                let (tar, name) = pack_input(&input, prompt_filter()?, &mut rp)?;
                 let manifest =
                     sllv_core::raster::encode_bytes_to_frames_dir(&tar, &name, &out_frames, &rp).context("encode")?;
 
-                crate::ffmpeg::frames_to_ffv1_mkv(&out_frames, &out_mkv, fps, ffmpeg_path.as_deref())
-                    .context("ffmpeg frames->mkv")?;
+                let video = sllv_core::VideoExportParams {
+                    container: sllv_core::VideoContainer::from_path(&out_mkv).unwrap_or_default(),
+                    ..sllv_core::VideoExportParams::ffv1(fps)
+                };
+                sllv_core::frames_to_video(&out_frames, &out_mkv, &rp, &video, ffmpeg_path.as_deref())
+                    .context("ffmpeg frames->video")?;
 
                 println!(
                     "\nOK: Wrote {} frames to {} and mkv to {}",
@@ -102,15 +164,39 @@ pub fn run() -> anyhow::Result<()> {
             "3" => {
                 let profile = prompt_profile()?;
                 let input_frames = prompt_path("Input frames directory: ")?;
-                let out_tar = prompt_path("Output tar file path (e.g. recovered.tar): ")?;
+                let to_folder = prompt_yes_no("Extract to a folder instead of writing a .tar?", true)?;
 
-                let rp = profile.defaults();
-                let bytes = sllv_core::raster::decode_frames_dir_to_bytes_with_params(&input_frames, &rp)
-                    .context("decode frames")?;
-                std::fs::write(&out_tar, bytes).context("write tar")?;
+                let mut rp = profile.defaults();
+                if to_folder {
+                    let out_dir = prompt_path("Output folder: ")?;
+                    let on_collision = prompt_collision()?;
+                    rp.decryption = prompt_passphrase("Passphrase (blank = not encrypted): ")?
+                        .map(sllv_core::DecryptionKey::Passphrase);
 
-                println!("\nOK: Wrote recovered tar to {}", out_tar.display());
-                println!("Tip: extract with: tar -xf \"{}\" -C out_dir", out_tar.display());
+                    let manifest = sllv_core::raster::read_manifest(&input_frames).context("read manifest")?;
+                    let mut scratch = sllv_core::PartialFile::scratch_in(&out_dir).context("create scratch file")?;
+                    sllv_core::raster::decode_frames_dir_to_writer(&input_frames, &rp, &mut scratch)
+                        .context("decode frames")?;
+                    let opts = sllv_core::ExtractOptions { on_collision };
+                    let payload = scratch.reopen().context("read scratch file")?;
+                    let report = sllv_core::extract_payload_to_dir(payload, &manifest, &out_dir, &opts)
+                        .context("extract archive")?;
+                    for entry in &report.entries {
+                        println!("  {entry}");
+                    }
+                    println!("\nOK: Extracted {} files to {}", report.files_written(), out_dir.display());
+                } else {
+                    let out_tar = prompt_path("Output tar file path (e.g. recovered.tar): ")?;
+                    rp.decryption = prompt_passphrase("Passphrase (blank = not encrypted): ")?
+                        .map(sllv_core::DecryptionKey::Passphrase);
+                    let mut out = sllv_core::PartialFile::create(&out_tar).context("create tar")?;
+                    sllv_core::raster::decode_frames_dir_to_writer(&input_frames, &rp, &mut out)
+                        .context("decode frames")?;
+                    out.commit().context("write tar")?;
+
+                    println!("\nOK: Wrote recovered tar to {}", out_tar.display());
+                    println!("Tip: extract with: tar -xf \"{}\" -C out_dir", out_tar.display());
+                }
             }
             "4" => {
                 let profile = prompt_profile()?;
@@ -118,17 +204,21 @@ pub fn run() -> anyhow::Result<()> {
                 let out_tar = prompt_path("Output tar file path (e.g. recovered.tar): ")?;
                 let ffmpeg_path = prompt_optional_path("Optional ffmpeg path (blank = PATH): ")?;
 
-                let tmp = out_tar
-                    .parent()
-                    .unwrap_or(Path::new("."))
-                    .join("_sllv_tmp_frames");
-
-                crate::ffmpeg::mkv_to_frames(&input_mkv, &tmp, ffmpeg_path.as_deref()).context("ffmpeg mkv->frames")?;
+                let manifest = sllv_core::read_video_manifest(&input_mkv)?;
 
-                let rp = profile.defaults();
-                let bytes = sllv_core::raster::decode_frames_dir_to_bytes_with_params(&tmp, &rp)
-                    .context("decode frames")?;
-                std::fs::write(&out_tar, bytes).context("write tar")?;
+                let mut rp = profile.defaults();
+                rp.decryption =
+                    prompt_passphrase("Passphrase (blank = not encrypted): ")?.map(sllv_core::DecryptionKey::Passphrase);
+                let probe = sllv_core::probe_video(&input_mkv, ffmpeg_path.as_deref()).context("probe video")?;
+                println!("Video: {}", probe.summary());
+                for w in probe.warnings(&manifest, &rp) {
+                    println!("Warning: {w}");
+                }
+                let out = sllv_core::PartialFile::create(&out_tar).context("create tar")?;
+                sllv_core::decode_video_to_writer(&input_mkv, &manifest, &rp, out, ffmpeg_path.as_deref(), None)
+                    .context("decode video")?
+                    .commit()
+                    .context("write tar")?;
 
                 println!("\nOK: Wrote recovered tar to {}", out_tar.display());
             }
@@ -149,16 +239,11 @@ pub fn run() -> anyhow::Result<()> {
                         .map(|x| x.display().to_string())
                         .unwrap_or_else(|| "(PATH)".to_string());
 
-                    match crate::ffmpeg::mkv_to_frames(Path::new("__nonexistent__.mkv"), Path::new("."), ffmpeg_path.as_deref()) {
+                    match sllv_core::resolve_ffmpeg(ffmpeg_path.as_deref()) {
                         Ok(_) => println!("- FFmpeg: ok ({p})"),
                         Err(e) => {
-                            let msg = format!("{e:#}").to_lowercase();
-                            if msg.contains("ffmpeg not found") || msg.contains("not found") {
-                                println!("- FFmpeg: missing ({p})");
-                                println!("  Install ffmpeg or provide a full path.");
-                            } else {
-                                println!("- FFmpeg: ok ({p})");
-                            }
+                            println!("- FFmpeg: missing ({p}): {e:#}");
+                            println!("  Install ffmpeg or provide a full path.");
                         }
                     }
                 }
@@ -1,16 +1,16 @@
-mod ffmpeg;
 mod interactive;
 
 use anyhow::Context;
 use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use std::path::{Path, PathBuf};
+use std::io::{Read, Write};
 
 #[derive(Parser)]
 #[command(
     name = "sllv",
     version,
     about = "SLLV turns files/folders into TV-static frames and can recover them later.",
-    after_help = "Examples:\n  sllv encode -i <path> -o <frames_dir>\n  sllv encode -i <path> -o <frames_dir> --out-mkv out.mkv\n  sllv decode -i <frames_dir> -o recovered.tar\n  sllv decode -m input.mkv -o recovered.tar\n  sllv doctor --check-ffmpeg\n\nNotes:\n  - Decode always outputs a .tar file; extract it with: tar -xf recovered.tar -C out_dir\n  - Encode/decode must use the same --profile (archive vs scan).\n\nTip:\n  - If you double-click sllv.exe on Windows, it opens an interactive menu."
+    after_help = "Examples:\n  sllv encode -i <path> -o <frames_dir>\n  sllv encode -i <path> -o <frames_dir> --out-mkv out.mkv\n  sllv encode -i <path> -o <frames_dir> --compress zstd\n  sllv encode -i <path> -o <frames_dir> --passphrase <secret>\n  sllv keygen -o mykey && sllv encode -i <path> -o <frames_dir> --recipient mykey.pub\n  sllv keygen --signing -o signer && sllv encode -i <path> -o <frames_dir> --sign-key signer\n  sllv verify -i <frames_dir> --pubkey signer.pub\n  sllv decode -i <frames_dir> -o recovered.tar\n  sllv decode -m input.mkv -o recovered.tar\n  sllv decode -i <frames_dir> --out-dir restored --on-conflict rename\n  sllv doctor --check-ffmpeg\n\nNotes:\n  - Decode outputs a .tar file (extract it with: tar -xf recovered.tar -C out_dir), or unpacks directly with --out-dir.\n  - Encode/decode must use the same --profile (archive vs scan).\n\nTip:\n  - If you double-click sllv.exe on Windows, it opens an interactive menu."
 )]
 struct Cli {
     #[command(subcommand)]
@@ -23,6 +23,10 @@ enum ProfileArg {
     Archive,
     /// Robust path intended for camera/screen pipelines (deskew + FEC).
     Scan,
+    /// 1080p frames that survive lossy H.264/H.265/AV1 re-encoding with 4:2:0 chroma (video sites).
+    Video,
+    /// Paper backups: pages for --out-print, decoded from flatbed scans with --scans.
+    Print,
 }
 
 impl ProfileArg {
@@ -30,27 +34,258 @@ impl ProfileArg {
         match self {
             ProfileArg::Archive => sllv_core::Profile::Archive,
             ProfileArg::Scan => sllv_core::Profile::Scan,
+            ProfileArg::Video => sllv_core::Profile::Video,
+            ProfileArg::Print => sllv_core::Profile::Print,
+        }
+    }
+
+    /// Profile defaults; for `print`, laid out for the given page settings.
+    fn params(&self, print: &sllv_core::PrintParams) -> anyhow::Result<sllv_core::RasterParams> {
+        match self {
+            ProfileArg::Print => {
+                print.validate()?;
+                Ok(print.raster_params())
+            }
+            _ => Ok(self.to_profile().defaults()),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum PaperArg {
+    /// 210 x 297 mm.
+    A4,
+    /// 8.5 x 11 in.
+    Letter,
+}
+
+impl PaperArg {
+    fn to_print_params(&self, dpi: u32, cell_mm: f64, margin_mm: f64) -> sllv_core::PrintParams {
+        sllv_core::PrintParams {
+            paper: match self {
+                PaperArg::A4 => sllv_core::PaperSize::A4,
+                PaperArg::Letter => sllv_core::PaperSize::Letter,
+            },
+            dpi,
+            cell_mm,
+            margin_mm,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum VideoCodecArg {
+    /// FFV1, lossless.
+    Ffv1,
+    /// H.264 lossless RGB (libx264rgb).
+    X264rgb,
+    /// H.264 4:2:0, lossy (libx264).
+    Libx264,
+    /// H.265/HEVC 4:2:0, lossy (libx265).
+    Libx265,
+    /// AV1 4:2:0, lossy (libaom-av1).
+    Av1,
+    /// VP9 lossless (libvpx-vp9).
+    Vp9Lossless,
+}
+
+impl VideoCodecArg {
+    fn to_codec(&self) -> sllv_core::VideoCodec {
+        match self {
+            VideoCodecArg::Ffv1 => sllv_core::VideoCodec::Ffv1,
+            VideoCodecArg::X264rgb => sllv_core::VideoCodec::X264rgb,
+            VideoCodecArg::Libx264 => sllv_core::VideoCodec::Libx264,
+            VideoCodecArg::Libx265 => sllv_core::VideoCodec::Libx265,
+            VideoCodecArg::Av1 => sllv_core::VideoCodec::Av1,
+            VideoCodecArg::Vp9Lossless => sllv_core::VideoCodec::Vp9Lossless,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ContainerArg {
+    Mkv,
+    Mp4,
+    Webm,
+    Avi,
+}
+
+impl ContainerArg {
+    fn to_container(&self) -> sllv_core::VideoContainer {
+        match self {
+            ContainerArg::Mkv => sllv_core::VideoContainer::Mkv,
+            ContainerArg::Mp4 => sllv_core::VideoContainer::Mp4,
+            ContainerArg::Webm => sllv_core::VideoContainer::Webm,
+            ContainerArg::Avi => sllv_core::VideoContainer::Avi,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum AnimFormatArg {
+    /// Animated PNG.
+    Apng,
+    /// Animated WebP, lossless (VP8L).
+    Webp,
+    /// GIF; every frame must fit in 256 colours.
+    Gif,
+}
+
+impl AnimFormatArg {
+    fn to_format(&self) -> sllv_core::AnimFormat {
+        match self {
+            AnimFormatArg::Apng => sllv_core::AnimFormat::Apng,
+            AnimFormatArg::Webp => sllv_core::AnimFormat::Webp,
+            AnimFormatArg::Gif => sllv_core::AnimFormat::Gif,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FrameFormatArg {
+    /// 24-bit RGB PNG.
+    Png,
+    /// Palette PNG at 1-4 bits per pixel; smaller and faster to write than RGB PNG.
+    PngIndexed,
+    /// QOI; faster to write and read than PNG.
+    Qoi,
+    /// Lossless WebP; the smallest files, the slowest to write.
+    Webp,
+    /// Uncompressed binary PPM.
+    Ppm,
+}
+
+impl FrameFormatArg {
+    fn to_format(&self) -> sllv_core::FrameFormat {
+        match self {
+            FrameFormatArg::Png => sllv_core::FrameFormat::Png,
+            FrameFormatArg::PngIndexed => sllv_core::FrameFormat::PngIndexed,
+            FrameFormatArg::Qoi => sllv_core::FrameFormat::Qoi,
+            FrameFormatArg::Webp => sllv_core::FrameFormat::Webp,
+            FrameFormatArg::Ppm => sllv_core::FrameFormat::Ppm,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum CompressArg {
+    /// Store the packed input as-is.
+    None,
+    /// zstd (fast decode, good ratio).
+    Zstd,
+    /// xz/LZMA2 (slower, usually the smallest output).
+    Xz,
+}
+
+impl CompressArg {
+    fn to_compression(&self, level: Option<i32>) -> sllv_core::Compression {
+        match self {
+            CompressArg::None => sllv_core::Compression::None,
+            CompressArg::Zstd => sllv_core::Compression::Zstd {
+                level: level.unwrap_or(sllv_core::Compression::ZSTD_DEFAULT_LEVEL),
+            },
+            CompressArg::Xz => sllv_core::Compression::Xz {
+                preset: level
+                    .map(|l| l.max(0) as u32)
+                    .unwrap_or(sllv_core::Compression::XZ_DEFAULT_PRESET),
+            },
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ConflictArg {
+    /// Keep the existing file.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Write the new file as "name (1).ext".
+    Rename,
+}
+
+impl ConflictArg {
+    fn to_policy(&self) -> sllv_core::CollisionPolicy {
+        match self {
+            ConflictArg::Skip => sllv_core::CollisionPolicy::Skip,
+            ConflictArg::Overwrite => sllv_core::CollisionPolicy::Overwrite,
+            ConflictArg::Rename => sllv_core::CollisionPolicy::Rename,
         }
     }
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Command {
-    /// Encode a file or folder into a directory of PNG frames (and optionally an MKV via ffmpeg).
+    /// Encode a file or folder into a directory of PNG frames (and optionally an MKV via ffmpeg),
+    /// or into a single Y4M or animated image.
     Encode {
-        /// File/folder to encode (it will be packed into a tar internally).
-        #[arg(long, short = 'i', value_name = "PATH")]
-        input: PathBuf,
+        /// Files/folders to encode, each stored as a top-level tar entry. `-` reads stdin.
+        #[arg(long, short = 'i', value_name = "PATH", required = true, num_args = 1..)]
+        input: Vec<PathBuf>,
+
+        /// Entry name for data read from stdin (`-i -`).
+        #[arg(long, value_name = "NAME", default_value = "stdin")]
+        stdin_name: String,
 
         /// Output directory that will receive frame_000000.png, manifest.json, etc.
-        #[arg(long, short = 'o', value_name = "DIR")]
-        out_frames: PathBuf,
+        #[arg(long, short = 'o', value_name = "DIR", required_unless_present_any = ["out_mkv", "out_y4m", "out_anim", "out_print"])]
+        out_frames: Option<PathBuf>,
 
-        /// Optional output MKV path (FFV1 in Matroska). Requires ffmpeg.
-        #[arg(long, value_name = "FILE")]
+        /// Image format of the frame files written to -o (recorded in the manifest; decode detects it).
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = FrameFormatArg::Png, requires = "out_frames")]
+        frame_format: FrameFormatArg,
+
+        /// Output video path (FFV1 in Matroska unless --codec/--container say otherwise). Requires
+        /// ffmpeg. Without -o, frames are piped to ffmpeg with no PNGs written, and the manifest goes
+        /// next to it as <name>.manifest.json.
+        #[arg(long, visible_alias = "out-video", value_name = "FILE")]
         out_mkv: Option<PathBuf>,
 
-        /// FPS to use when writing an MKV (ignored unless --out-mkv is set).
+        /// Output a single YUV4MPEG2 (4:4:4) video with the manifest in its header. Needs no ffmpeg;
+        /// decode it with -m.
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["out_frames", "out_mkv"])]
+        out_y4m: Option<PathBuf>,
+
+        /// Output a single animated image (APNG, lossless WebP or GIF) with the manifest embedded.
+        /// Needs no ffmpeg; decode it with -m.
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["out_frames", "out_mkv", "out_y4m"])]
+        out_anim: Option<PathBuf>,
+
+        /// Format for --out-anim (default: from its extension, else apng).
+        #[arg(long, value_enum, value_name = "FORMAT", requires = "out_anim")]
+        anim_format: Option<AnimFormatArg>,
+
+        /// How long each frame of --out-anim is shown, in milliseconds.
+        #[arg(long, value_name = "MS", default_value_t = 200, requires = "out_anim")]
+        frame_delay: u32,
+
+        /// Output printable pages, one frame each after a manifest page: a multi-page PDF if FILE
+        /// ends in .pdf, else a directory of page PNGs. Needs --profile print.
+        #[arg(long, value_name = "FILE.pdf|DIR", conflicts_with_all = ["out_frames", "out_mkv", "out_y4m", "out_anim"])]
+        out_print: Option<PathBuf>,
+
+        /// Paper size for --profile print.
+        #[arg(long, value_enum, default_value_t = PaperArg::A4)]
+        paper: PaperArg,
+
+        /// Print resolution for --profile print.
+        #[arg(long, value_name = "DPI", default_value_t = 300)]
+        dpi: u32,
+
+        /// Cell size in millimetres for --profile print (rounded to whole pixels at --dpi).
+        #[arg(long, value_name = "MM", default_value_t = 0.6)]
+        cell_mm: f64,
+
+        /// Page margin in millimetres for --profile print.
+        #[arg(long, value_name = "MM", default_value_t = 10.0)]
+        margin_mm: f64,
+
+        /// Lay out COLSxROWS complete code tiles per frame image (e.g. 2x2), each with its own
+        /// fiducials and FEC shard, for large displays. Needs a profile with FEC (scan, video).
+        #[arg(long, value_name = "COLSxROWS", value_parser = parse_tiles, conflicts_with = "out_print")]
+        tiles: Option<[u32; 2]>,
+
+        /// FPS to use when writing a video or Y4M (ignored otherwise).
         #[arg(long, default_value_t = 24)]
         fps: u32,
 
@@ -58,34 +293,358 @@ enum Command {
         #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
         profile: ProfileArg,
 
+        /// Compress the packed input before encoding (decode detects this from the manifest).
+        #[arg(long, value_enum, default_value_t = CompressArg::None)]
+        compress: CompressArg,
+
+        /// Compression level: zstd 1-22 (default 3), xz 0-9 (default 6).
+        #[arg(long, value_name = "N")]
+        compress_level: Option<i32>,
+
+        /// Store repeated content once: split the packed input into content-defined chunks
+        /// (FastCDC) before compression and keep each distinct chunk a single time.
+        #[arg(long)]
+        dedup: bool,
+
+        /// Average dedup chunk size (rounded down to a power of two; implies --dedup). Default 16K.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        dedup_chunk: Option<u64>,
+
+        /// Encrypt the payload with a key derived from this passphrase (Argon2id).
+        #[arg(long, value_name = "PASS", conflicts_with = "recipient")]
+        passphrase: Option<String>,
+
+        /// Encrypt the payload to an X25519 public key (64 hex chars, or a file from `sllv keygen`).
+        #[arg(long, value_name = "KEY")]
+        recipient: Option<String>,
+
+        /// Store the files symlinks point to instead of the symlinks themselves.
+        #[arg(long)]
+        follow_symlinks: bool,
+
+        /// Record extended attributes (Unix).
+        #[arg(long)]
+        xattrs: bool,
+
+        /// Sorted entries, fixed owner and mtime: identical input gives identical frames.
+        #[arg(long)]
+        reproducible: bool,
+
+        /// Only pack files matching this glob (repeatable), e.g. "src/**" or "*.rs".
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Leave out files and folders matching this glob (repeatable), e.g. "target" or "*.log".
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Honor .gitignore and .sllvignore files inside the input, and skip .git/.
+        #[arg(long)]
+        gitignore: bool,
+
+        /// Skip files larger than this (bytes, or with a K/M/G suffix).
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_file_size: Option<u64>,
+
+        /// Don't write table-of-contents frames (`sllv list` then needs a full decode).
+        #[arg(long)]
+        no_toc: bool,
+
+        /// Encode only what changed since this archive: its frames directory, or its table of contents from `sllv list --json`.
+        #[arg(long, value_name = "DIR|FILE")]
+        base: Option<PathBuf>,
+
+        /// Passphrase for an encrypted --base (defaults to --passphrase).
+        #[arg(long, value_name = "PASS", conflicts_with = "base_identity")]
+        base_passphrase: Option<String>,
+
+        /// X25519 secret key for a --base encrypted with --recipient.
+        #[arg(long, value_name = "KEY")]
+        base_identity: Option<String>,
+
+        /// Split into volumes of at most N frames each, written to vol_000/, vol_001/, ... under -o.
+        #[arg(long, value_name = "N")]
+        volume_frames: Option<u32>,
+
+        /// Split into volumes carrying at most this many payload bytes each (K/M/G suffix).
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        volume_size: Option<u64>,
+
+        /// Add N Reed-Solomon parity volumes; any N volumes of the set can then be lost.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        parity_volumes: u32,
+
+        /// Sign the manifest (and so the payload hash) with an Ed25519 secret key from `sllv keygen --signing`.
+        #[arg(long, value_name = "KEY")]
+        sign_key: Option<String>,
+
+        /// Continue an interrupted encode: keep frames in -o that already match what would be written.
+        #[arg(long, requires = "out_frames")]
+        resume: bool,
+
+        /// Video codec for --out-mkv. The lossy ones (libx264, libx265, av1) need --profile video.
+        #[arg(long, value_enum, value_name = "CODEC", default_value_t = VideoCodecArg::Ffv1, requires = "out_mkv")]
+        codec: VideoCodecArg,
+
+        /// Container for --out-mkv (default: from its extension, else mkv).
+        #[arg(long, value_enum, value_name = "FORMAT", requires = "out_mkv")]
+        container: Option<ContainerArg>,
+
+        /// ffmpeg pixel format for --out-mkv (default: rgb24, gbrp for vp9-lossless, yuv420p for lossy codecs).
+        #[arg(long, value_name = "FMT", requires = "out_mkv")]
+        pix_fmt: Option<String>,
+
+        /// Constant rate factor for a lossy --codec (default: 18 for libx264, 20 for libx265, 24 for av1).
+        #[arg(long, value_name = "N", requires = "out_mkv")]
+        crf: Option<u8>,
+
+        /// Show each frame N times in a row, so a camera filming playback catches all of them (FEC profiles only).
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "out_mkv")]
+        repeat: u32,
+
+        /// Seconds of solid sync colour before the first frame, for a camera to settle.
+        #[arg(long, value_name = "SECS", default_value_t = 0.0, requires = "out_mkv")]
+        lead_in: f32,
+
+        /// Seconds of solid sync colour after the last frame.
+        #[arg(long, value_name = "SECS", default_value_t = 0.0, requires = "out_mkv")]
+        lead_out: f32,
+
         /// Optional path to an ffmpeg executable (avoids needing it on PATH).
         #[arg(long, value_name = "PATH")]
         ffmpeg_path: Option<PathBuf>,
     },
 
-    /// Decode a frames directory (or an MKV) back into a .tar archive.
+    /// Decode a frames directory (or a video, animated image or scanned pages) back into a .tar archive, or straight into a folder.
     #[command(
         group = ArgGroup::new("source")
             .required(true)
-            .args(["input_frames", "input_mkv"])
+            .multiple(true)
+            .args(["input_frames", "input_mkv", "volume", "scans"]),
+        group = ArgGroup::new("media").args(["input_mkv", "scans"]),
+        group = ArgGroup::new("output")
+            .required(true)
+            .args(["out_tar", "out_dir"])
     )]
     Decode {
-        /// Directory containing frames + manifest.json.
-        #[arg(long, short = 'i', alias = "input", value_name = "DIR")]
+        /// Directory containing frames + manifest.json, or a volume set directory.
+        #[arg(long, short = 'i', alias = "input", value_name = "DIR", conflicts_with = "input_mkv")]
         input_frames: Option<PathBuf>,
 
-        /// Input MKV path; frames will be extracted to a temp dir first.
-        #[arg(long, short = 'm', value_name = "FILE")]
+        /// Input video path; ffmpeg decodes it and frames are read from its output (no temp files).
+        /// A .y4m or an animated PNG, WebP or GIF is read directly, without ffmpeg.
+        #[arg(long, short = 'm', value_name = "FILE", conflicts_with = "volume")]
         input_mkv: Option<PathBuf>,
 
-        /// Output tar file path (the recovered data is written here).
+        /// Manifest for -m, if it isn't next to the video as <name>.manifest.json (or embedded in a .y4m or animated image),
+        /// or for --scans if the manifest page is missing.
+        #[arg(long, value_name = "FILE", requires = "media")]
+        manifest: Option<PathBuf>,
+
+        /// Scanned pages of an --out-print encode: image files or directories of them (PNG, JPEG, TIFF),
+        /// in any order and orientation. Needs --profile print and the page settings used for encode.
+        #[arg(long, value_name = "PATH", num_args = 1.., conflicts_with_all = ["input_frames", "input_mkv", "volume"])]
+        scans: Vec<PathBuf>,
+
+        /// Paper size for --profile print.
+        #[arg(long, value_enum, default_value_t = PaperArg::A4)]
+        paper: PaperArg,
+
+        /// Print resolution for --profile print.
+        #[arg(long, value_name = "DPI", default_value_t = 300)]
+        dpi: u32,
+
+        /// Cell size in millimetres for --profile print.
+        #[arg(long, value_name = "MM", default_value_t = 0.6)]
+        cell_mm: f64,
+
+        /// Page margin in millimetres for --profile print.
+        #[arg(long, value_name = "MM", default_value_t = 10.0)]
+        margin_mm: f64,
+
+        /// A volume of a split encode (repeatable); missing volumes are rebuilt from parity if possible.
+        #[arg(long, value_name = "DIR")]
+        volume: Vec<PathBuf>,
+
+        /// Output file for the recovered payload (a .tar, or the raw bytes for `encode -i -`); `-` writes to stdout.
         #[arg(long, short = 'o', value_name = "FILE")]
-        out_tar: PathBuf,
+        out_tar: Option<PathBuf>,
+
+        /// Extract the recovered archive into this folder instead of writing a .tar.
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+
+        /// With --out-dir: what to do when a file already exists.
+        #[arg(long, value_enum, default_value_t = ConflictArg::Skip)]
+        on_conflict: ConflictArg,
 
         /// Preset controlling decoding parameters; must match what was used for encode.
         #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
         profile: ProfileArg,
 
+        /// Passphrase for an archive encrypted with --passphrase.
+        #[arg(long, value_name = "PASS", conflicts_with = "identity")]
+        passphrase: Option<String>,
+
+        /// X25519 secret key for an archive encrypted with --recipient (hex, or the file from `sllv keygen`).
+        #[arg(long, value_name = "KEY")]
+        identity: Option<String>,
+
+        /// Trusted Ed25519 public key(s); the signature status is reported after decoding.
+        #[arg(long, value_name = "KEY")]
+        pubkey: Vec<String>,
+
+        /// Cache what each frame decoded to in this file, so a re-run only decodes new or changed frames.
+        #[arg(long, value_name = "FILE")]
+        journal: Option<PathBuf>,
+
+        /// Optional path to an ffmpeg executable.
+        #[arg(long, value_name = "PATH")]
+        ffmpeg_path: Option<PathBuf>,
+    },
+
+    /// List the archive contents from its table-of-contents frames, without decoding the payload.
+    List {
+        /// Directory containing frames + manifest.json, or a volume set directory.
+        #[arg(long, short = 'i', value_name = "DIR")]
+        input_frames: PathBuf,
+
+        /// Preset controlling decoding parameters; must match what was used for encode.
+        #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
+        profile: ProfileArg,
+
+        /// Passphrase for an archive encrypted with --passphrase.
+        #[arg(long, value_name = "PASS", conflicts_with = "identity")]
+        passphrase: Option<String>,
+
+        /// X25519 secret key for an archive encrypted with --recipient.
+        #[arg(long, value_name = "KEY")]
+        identity: Option<String>,
+
+        /// Also show mode, mtime and SHA-256.
+        #[arg(long, short = 'l')]
+        long: bool,
+
+        /// Print the table of contents as JSON (usable as `encode --base`).
+        #[arg(long, conflicts_with = "long")]
+        json: bool,
+    },
+
+    /// Rebuild the latest state from a full archive and the delta archives made after it.
+    Restore {
+        /// Frames directories: the full archive first, then each delta in the order they were made.
+        #[arg(long, short = 'i', value_name = "DIR", required = true, num_args = 1..)]
+        input_frames: Vec<PathBuf>,
+
+        /// Folder to restore into.
+        #[arg(long, value_name = "DIR")]
+        out_dir: PathBuf,
+
+        /// What to do when a file already exists.
+        #[arg(long, value_enum, default_value_t = ConflictArg::Skip)]
+        on_conflict: ConflictArg,
+
+        /// Preset controlling decoding parameters; must match what was used for encode.
+        #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
+        profile: ProfileArg,
+
+        /// Passphrase shared by the encrypted archives.
+        #[arg(long, value_name = "PASS", conflicts_with = "identity")]
+        passphrase: Option<String>,
+
+        /// X25519 secret key shared by the archives encrypted with --recipient.
+        #[arg(long, value_name = "KEY")]
+        identity: Option<String>,
+    },
+
+    /// Recover individual files, decoding only the frames that hold them.
+    Extract {
+        /// Directory containing frames + manifest.json.
+        #[arg(long, short = 'i', value_name = "DIR")]
+        input_frames: PathBuf,
+
+        /// File (or folder) inside the archive, as shown by `sllv list` (repeatable).
+        #[arg(long, value_name = "PATH", required = true)]
+        path: Vec<String>,
+
+        /// Write the files under this folder, keeping their archive paths.
+        #[arg(long, value_name = "DIR", default_value = ".", conflicts_with = "out")]
+        out_dir: PathBuf,
+
+        /// Write a single selected file here instead; `-` writes to stdout.
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+
+        /// What to do when a file already exists in --out-dir.
+        #[arg(long, value_enum, default_value_t = ConflictArg::Skip)]
+        on_conflict: ConflictArg,
+
+        /// Preset controlling decoding parameters; must match what was used for encode.
+        #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
+        profile: ProfileArg,
+
+        /// Passphrase for an archive encrypted with --passphrase.
+        #[arg(long, value_name = "PASS", conflicts_with = "identity")]
+        passphrase: Option<String>,
+
+        /// X25519 secret key for an archive encrypted with --recipient.
+        #[arg(long, value_name = "KEY")]
+        identity: Option<String>,
+
+        /// Trusted Ed25519 public key(s); the signature status is reported.
+        #[arg(long, value_name = "KEY")]
+        pubkey: Vec<String>,
+    },
+
+    /// Check that a frames directory is intact and signed by a trusted key.
+    Verify {
+        /// Directory containing frames + manifest.json.
+        #[arg(long, short = 'i', value_name = "DIR")]
+        input_frames: PathBuf,
+
+        /// Trusted Ed25519 public key(s) (hex, or a .pub file from `sllv keygen --signing`).
+        #[arg(long, value_name = "KEY", required = true)]
+        pubkey: Vec<String>,
+
+        /// Preset controlling decoding parameters; must match what was used for encode.
+        #[arg(long, value_enum, default_value_t = ProfileArg::Archive)]
+        profile: ProfileArg,
+    },
+
+    /// Generate an X25519 keypair for --recipient / --identity (or Ed25519 with --signing).
+    Keygen {
+        /// Secret key file to create; the public key is written next to it as <FILE>.pub.
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: PathBuf,
+
+        /// Generate an Ed25519 signing key for --sign-key / --pubkey instead.
+        #[arg(long)]
+        signing: bool,
+    },
+
+    /// Check that the video profile survives a lossy codec: encode random data, optionally
+    /// re-encode it like a video platform, then compare every cell and decode it. Needs ffmpeg.
+    VideoSelftest {
+        /// Codec for the uploaded video.
+        #[arg(long, value_enum, value_name = "CODEC", default_value_t = VideoCodecArg::Libx264)]
+        codec: VideoCodecArg,
+
+        /// Constant rate factor for the uploaded video (default depends on the codec).
+        #[arg(long, value_name = "N")]
+        crf: Option<u8>,
+
+        /// Re-encode the upload with H.264 4:2:0 at this CRF, as a platform would (e.g. 28).
+        #[arg(long, value_name = "N")]
+        transcode_crf: Option<u8>,
+
+        /// Profile to test.
+        #[arg(long, value_enum, default_value_t = ProfileArg::Video)]
+        profile: ProfileArg,
+
+        /// Amount of random test data.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "1M")]
+        size: u64,
+
         /// Optional path to an ffmpeg executable.
         #[arg(long, value_name = "PATH")]
         ffmpeg_path: Option<PathBuf>,
@@ -115,49 +674,688 @@ fn main() -> anyhow::Result<()> {
     match cli.cmd {
         Command::Encode {
             input,
+            stdin_name,
             out_frames,
+            frame_format,
             out_mkv,
+            out_y4m,
+            out_anim,
+            anim_format,
+            frame_delay,
+            out_print,
+            paper,
+            dpi,
+            cell_mm,
+            margin_mm,
+            tiles,
             fps,
             profile,
+            compress,
+            compress_level,
+            dedup,
+            dedup_chunk,
+            passphrase,
+            recipient,
+            sign_key,
+            follow_symlinks,
+            xattrs,
+            reproducible,
+            include,
+            exclude,
+            gitignore,
+            max_file_size,
+            no_toc,
+            base,
+            base_passphrase,
+            base_identity,
+            volume_frames,
+            volume_size,
+            parity_volumes,
+            resume,
+            codec,
+            container,
+            pix_fmt,
+            crf,
+            repeat,
+            lead_in,
+            lead_out,
             ffmpeg_path,
         } => {
-            let (tar, name) = sllv_core::pack::pack_path_to_tar_bytes(&input).context("pack input")?;
-            let rp = profile.to_profile().defaults();
+            let pack_opts = sllv_core::PackOptions {
+                follow_symlinks,
+                xattrs,
+                reproducible,
+                filter: sllv_core::PackFilter {
+                    include,
+                    exclude,
+                    ignore_files: gitignore,
+                    max_file_size,
+                },
+            };
+            let is_stdin = |p: &PathBuf| p.as_os_str() == "-";
+            if input.iter().filter(|p| is_stdin(p)).count() > 1 {
+                anyhow::bail!("`-i -` can only be given once");
+            }
+            let read_stdin = || -> anyhow::Result<Vec<u8>> {
+                let mut data = Vec::new();
+                std::io::stdin().read_to_end(&mut data).context("read stdin")?;
+                Ok(data)
+            };
+            let print = paper.to_print_params(dpi, cell_mm, margin_mm);
+            let mut rp = profile.params(&print)?;
+            rp.toc = !no_toc;
+            rp.frame_format = frame_format.to_format();
+            if let Some(tiles) = tiles {
+                if rp.fec.is_none() {
+                    anyhow::bail!("--tiles needs a profile with FEC (scan or video), not {}", profile.to_profile().name());
+                }
+                rp.tiles = tiles;
+                rp.border_fiducials = true;
+            }
+            let packed = if input.len() == 1 && is_stdin(&input[0]) {
+                // A lone stdin stream is stored as-is rather than wrapped in a tar.
+                rp.payload = sllv_core::PayloadKind::Raw;
+                sllv_core::PackedInput {
+                    tar: read_stdin()?,
+                    name: stdin_name,
+                    skipped: Vec::new(),
+                }
+            } else {
+                let mut inputs = Vec::with_capacity(input.len());
+                for path in input {
+                    inputs.push(if is_stdin(&path) {
+                        sllv_core::PackInput::Bytes {
+                            name: stdin_name.clone(),
+                            data: read_stdin()?,
+                        }
+                    } else {
+                        sllv_core::PackInput::Path(path)
+                    });
+                }
+                let packed = sllv_core::pack::pack_inputs_to_tar_bytes(&inputs, &pack_opts).context("pack input")?;
+                for s in &packed.skipped {
+                    println!("{s}");
+                }
+                rp.filter = packed.filter_info(&pack_opts.filter);
+                packed
+            };
+            let mut packed = packed;
+            if let Some(base) = base {
+                if !rp.payload.is_tar() || !rp.toc {
+                    anyhow::bail!("--base needs a tar payload with a table of contents (not a lone `-i -` or --no-toc)");
+                }
+                let key = match (base_passphrase.or_else(|| passphrase.clone()), base_identity) {
+                    (Some(pass), _) => Some(sllv_core::DecryptionKey::Passphrase(pass)),
+                    (None, Some(key)) => Some(sllv_core::DecryptionKey::Identity(
+                        read_key(&key).context("read --base-identity key")?,
+                    )),
+                    (None, None) => None,
+                };
+                let base_toc = read_base_toc(&base, profile.to_profile(), key)?;
+                let (tar, plan) = sllv_core::plan_delta(&packed.tar, &base_toc).context("compare with --base")?;
+                let d = &plan.info;
+                println!(
+                    "Delta: {} added, {} changed, {} deleted, {} unchanged ({} bytes not re-encoded)",
+                    d.added, d.changed, d.deleted, d.unchanged, d.unchanged_bytes
+                );
+                packed.tar = tar;
+                rp.delta = Some(plan);
+            }
+            if dedup || dedup_chunk.is_some() {
+                rp.dedup = Some(match dedup_chunk {
+                    Some(avg) => sllv_core::DedupParams::with_average(avg.min(u32::MAX as u64) as u32),
+                    None => sllv_core::DedupParams::default(),
+                });
+            }
+            rp.compression = compress.to_compression(compress_level);
+            rp.encryption = match (passphrase, recipient) {
+                (Some(pass), _) => Some(sllv_core::EncryptionKey::Passphrase(pass)),
+                (None, Some(key)) => Some(sllv_core::EncryptionKey::Recipient(
+                    read_key(&key).context("read --recipient key")?,
+                )),
+                (None, None) => None,
+            };
+            if let Some(key) = sign_key {
+                rp.signing = Some(sllv_core::ManifestSigner(read_key(&key).context("read --sign-key")?));
+            }
+            rp.resume = resume;
+            // Only `--resume` reports anything (frames kept from the last run); collect its lines on
+            // a thread so per-frame progress doesn't pile up in the channel.
+            let (tx, info) = if resume {
+                let (tx, rx) = std::sync::mpsc::channel();
+                (Some(tx), Some(std::thread::spawn(move || info_lines(rx))))
+            } else {
+                (None, None)
+            };
+            let video = sllv_core::VideoExportParams {
+                codec: codec.to_codec(),
+                container: match (&container, &out_mkv) {
+                    (Some(c), _) => c.to_container(),
+                    (None, Some(out)) => sllv_core::VideoContainer::from_path(out).unwrap_or_default(),
+                    (None, None) => sllv_core::VideoContainer::Mkv,
+                },
+                pix_fmt,
+                crf,
+                fps,
+                repeat,
+                lead_in_secs: lead_in,
+                lead_out_secs: lead_out,
+            };
+            if out_mkv.is_some() {
+                video.validate(&rp)?;
+                if !video.codec.is_lossless() && !matches!(profile, ProfileArg::Video) {
+                    eprintln!("Note: only --profile video is laid out to survive lossy codecs");
+                }
+            }
 
-            let manifest = sllv_core::raster::encode_bytes_to_frames_dir(&tar, &name, &out_frames, &rp)
+            let vp = sllv_core::VolumeParams {
+                max_frames: volume_frames,
+                max_bytes: volume_size,
+                parity_volumes,
+            };
+            let manifest = if vp.is_split() {
+                let Some(out_frames) = out_frames else {
+                    anyhow::bail!("splitting into volumes needs -o <DIR>");
+                };
+                let set =
+                    sllv_core::encode_bytes_to_volumes(&packed.tar, &packed.name, &out_frames, &rp, &vp, tx)
+                        .context("encode bytes->volumes")?;
+                for v in &set.volumes {
+                    let dir = out_frames.join(&v.dir);
+                    if let Some(out) = &out_mkv {
+                        let out = volume_file_name(out, &v.dir);
+                        sllv_core::frames_to_video(&dir, &out, &rp, &video, ffmpeg_path.as_deref())
+                            .context("ffmpeg frames->video")?;
+                        println!("  {} {:>8} frames  {}", v.dir, v.frames, out.display());
+                    } else {
+                        println!("  {} {:>8} frames  {}", v.dir, v.frames, dir.display());
+                    }
+                }
+                println!(
+                    "Volumes: {} data + {} parity, {} bytes each",
+                    set.data_volumes, set.parity_volumes, set.stripe_bytes
+                );
+                set.manifest
+            } else if let Some(out_frames) = out_frames {
+                let manifest = sllv_core::raster::encode_bytes_to_frames_dir_with_progress(
+                    &packed.tar,
+                    &packed.name,
+                    &out_frames,
+                    &rp,
+                    tx,
+                )
                 .context("encode bytes->frames")?;
+                if let Some(out) = out_mkv {
+                    sllv_core::frames_to_video(&out_frames, &out, &rp, &video, ffmpeg_path.as_deref())
+                        .context("ffmpeg frames->video")?;
+                    let sidecar = sllv_core::video_manifest_path(&out);
+                    std::fs::write(&sidecar, serde_json::to_vec_pretty(&manifest)?).context("write video manifest")?;
+                }
+                manifest
+            } else if let Some(out) = out_mkv {
+                let manifest = sllv_core::encode_bytes_to_video(
+                    &packed.tar,
+                    &packed.name,
+                    &out,
+                    &rp,
+                    &video,
+                    ffmpeg_path.as_deref(),
+                    tx,
+                )
+                .context("encode bytes->video")?;
+                println!("Wrote {} and {}", out.display(), sllv_core::video_manifest_path(&out).display());
+                manifest
+            } else if let Some(out) = out_y4m {
+                let manifest = sllv_core::encode_bytes_to_y4m(&packed.tar, &packed.name, &out, fps, &rp, tx)
+                    .context("encode bytes->y4m")?;
+                println!("Wrote {}", out.display());
+                manifest
+            } else if let Some(out) = out_anim {
+                let format = anim_format
+                    .map(|f| f.to_format())
+                    .or_else(|| sllv_core::AnimFormat::from_path(&out))
+                    .unwrap_or_default();
+                let manifest = sllv_core::encode_bytes_to_anim(
+                    &packed.tar,
+                    &packed.name,
+                    &out,
+                    format,
+                    frame_delay,
+                    &rp,
+                    tx,
+                )
+                .context("encode bytes->animation")?;
+                println!("Wrote {} ({})", out.display(), format.name());
+                manifest
+            } else if let Some(out) = out_print {
+                if !matches!(profile, ProfileArg::Print) {
+                    anyhow::bail!("--out-print needs --profile print");
+                }
+                let manifest =
+                    sllv_core::encode_bytes_to_print(&packed.tar, &packed.name, &out, &print, &rp, tx)
+                        .context("encode bytes->pages")?;
+                println!("Wrote {} ({} pages)", out.display(), manifest.frames + 1);
+                manifest
+            } else {
+                anyhow::bail!("must provide -o <DIR>, --out-mkv <FILE>, --out-y4m <FILE>, --out-anim <FILE> or --out-print <FILE>");
+            };
 
-            if let Some(out) = out_mkv {
-                ffmpeg::frames_to_ffv1_mkv(&out_frames, &out, fps, ffmpeg_path.as_deref())
-                    .context("ffmpeg frames->mkv")?;
+            if let Some(info) = info {
+                for line in info.join().unwrap_or_default() {
+                    println!("{line}");
+                }
+            }
+            if let Some(d) = &manifest.dedup {
+                println!(
+                    "Deduplicated: {} chunks, {} unique, {} -> {} bytes (ratio {:.2}x)",
+                    d.chunks,
+                    d.unique_chunks,
+                    d.logical_bytes,
+                    d.stored_bytes,
+                    d.ratio()
+                );
+            }
+            if let Some(c) = &manifest.compression {
+                println!(
+                    "Compressed ({}): {} -> {} bytes",
+                    c.method.name(),
+                    c.uncompressed_bytes,
+                    manifest.total_bytes
+                );
+            }
+            if manifest.encryption.is_some() {
+                println!("Encrypted: yes");
+            }
+            if let Some(sig) = &manifest.signature {
+                println!("Signed: key {}", sig.key_id);
+            }
+            if let Some(f) = &manifest.filter {
+                println!("Filtered: {} entries ({} bytes) skipped", f.skipped_entries, f.skipped_bytes);
             }
-
             println!("Frames: {}", manifest.frames);
         }
         Command::Decode {
             input_frames,
             input_mkv,
+            manifest,
+            scans,
+            paper,
+            dpi,
+            cell_mm,
+            margin_mm,
+            mut volume,
             out_tar,
+            out_dir,
+            on_conflict,
             profile,
+            passphrase,
+            identity,
+            pubkey,
+            journal,
             ffmpeg_path,
         } => {
-            let frames_dir = if let Some(frames) = input_frames {
-                frames
+            if let Some(dir) = input_frames.as_ref().filter(|d| sllv_core::volume::is_volume_path(d)) {
+                volume.insert(0, dir.clone());
+            }
+            let print = paper.to_print_params(dpi, cell_mm, margin_mm);
+            let mut rp = profile.params(&print)?;
+            let source = if !volume.is_empty() {
+                let scan = sllv_core::scan_volumes(&volume).context("find volumes")?;
+                let missing = scan.missing();
+                println!("Volumes: {} of {} found", scan.found.len(), scan.set.count());
+                if !missing.is_empty() {
+                    println!("Missing volumes: {}", join_indices(&missing));
+                }
+                Source::Volumes(Box::new(scan))
+            } else if let Some(frames) = input_frames {
+                Source::Frames(frames)
             } else if let Some(mkv) = input_mkv {
-                let tmp = out_tar
-                    .parent()
-                    .unwrap_or(Path::new("."))
-                    .join("_sllv_tmp_frames");
-                ffmpeg::mkv_to_frames(&mkv, &tmp, ffmpeg_path.as_deref()).context("ffmpeg mkv->frames")?;
-                tmp
+                let manifest = match manifest {
+                    Some(path) => serde_json::from_slice(&std::fs::read(&path).context("read --manifest")?)
+                        .context("parse --manifest")?,
+                    None => sllv_core::read_video_manifest(&mkv)?,
+                };
+                Source::Video {
+                    path: mkv,
+                    manifest: Box::new(manifest),
+                    ffmpeg_path: ffmpeg_path.clone(),
+                }
+            } else if !scans.is_empty() {
+                if !matches!(profile, ProfileArg::Print) {
+                    anyhow::bail!("--scans needs --profile print");
+                }
+                let files = sllv_core::scan_files(&scans).context("find scans")?;
+                let manifest = match manifest {
+                    Some(path) => serde_json::from_slice(&std::fs::read(&path).context("read --manifest")?)
+                        .context("parse --manifest")?,
+                    None => sllv_core::read_scans_manifest(&files, &rp).context("read manifest page")?,
+                };
+                Source::Scans {
+                    files,
+                    manifest: Box::new(manifest),
+                }
+            } else {
+                anyhow::bail!("must provide --input-frames, --input-mkv, --volume or --scans");
+            };
+
+            rp.decryption = match (passphrase, identity) {
+                (Some(pass), _) => Some(sllv_core::DecryptionKey::Passphrase(pass)),
+                (None, Some(key)) => Some(sllv_core::DecryptionKey::Identity(
+                    read_key(&key).context("read --identity key")?,
+                )),
+                (None, None) => None,
+            };
+            rp.journal = journal;
+            let trusted = read_keys(&pubkey)?;
+            let manifest = source.manifest()?;
+            if let Source::Video { path, ffmpeg_path, .. } = &source {
+                let probe = sllv_core::probe_video(path, ffmpeg_path.as_deref()).context("probe video")?;
+                eprintln!("Video: {}", probe.summary());
+                for w in probe.warnings(&manifest, &rp) {
+                    eprintln!("Warning: {w}");
+                }
+            }
+            let status = check_signature(&manifest, &trusted)?;
+            let to_stdout = out_tar.as_deref() == Some(Path::new("-"));
+
+            let volumes = if let Some(out_dir) = out_dir {
+                // Decode into a scratch file and verify the whole archive first so nothing unverified
+                // lands on disk.
+                let mut scratch = sllv_core::PartialFile::scratch_in(&out_dir).context("create scratch file")?;
+                let (volumes, info) = source.decode(&rp, &mut scratch)?;
+                let opts = sllv_core::ExtractOptions {
+                    on_collision: on_conflict.to_policy(),
+                };
+                let payload = scratch.reopen().context("read scratch file")?;
+                let report = sllv_core::extract_payload_to_dir(payload, &manifest, &out_dir, &opts)
+                    .context("extract archive")?;
+                for entry in &report.entries {
+                    println!("  {entry}");
+                }
+                println!(
+                    "Extracted {} files ({} bytes) to {}",
+                    report.files_written(),
+                    report.bytes_written(),
+                    out_dir.display()
+                );
+                (volumes, info)
+            } else if to_stdout {
+                let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+                let decoded = source.decode(&rp, &mut out)?;
+                out.flush().context("write stdout")?;
+                decoded
+            } else if let Some(out_tar) = out_tar {
+                let mut out = sllv_core::PartialFile::create(&out_tar).context("create recovered tar")?;
+                let decoded = source.decode(&rp, &mut out)?;
+                out.commit().context("write recovered tar")?;
+                decoded
+            } else {
+                (None, Vec::new())
+            };
+            let (volumes, mut notes) = volumes;
+            // Keep stdout clean when the payload itself goes there.
+            if let Some(report) = volumes {
+                for (index, reason) in &report.failed {
+                    notes.push(format!("Volume {index} unreadable: {reason}"));
+                }
+                if !report.rebuilt.is_empty() {
+                    notes.push(format!("Rebuilt from parity: volume {}", join_indices(&report.rebuilt)));
+                }
+            }
+            notes.push(format!("Signature: {status}"));
+            for note in notes {
+                if to_stdout {
+                    eprintln!("{note}");
+                } else {
+                    println!("{note}");
+                }
+            }
+        }
+        Command::List {
+            input_frames,
+            profile,
+            passphrase,
+            identity,
+            long,
+            json,
+        } => {
+            let mut rp = profile.to_profile().defaults();
+            rp.decryption = match (passphrase, identity) {
+                (Some(pass), _) => Some(sllv_core::DecryptionKey::Passphrase(pass)),
+                (None, Some(key)) => Some(sllv_core::DecryptionKey::Identity(
+                    read_key(&key).context("read --identity key")?,
+                )),
+                (None, None) => None,
+            };
+            // Every volume of a split encode carries the table of contents; any one will do.
+            let input_frames = if sllv_core::volume::is_volume_path(&input_frames) {
+                let scan = sllv_core::scan_volumes(std::slice::from_ref(&input_frames)).context("find volumes")?;
+                scan.found.into_values().next().context("no volumes found")?
             } else {
-                anyhow::bail!("must provide --input-frames or --input-mkv");
+                input_frames
+            };
+            let toc = sllv_core::read_toc(&input_frames, &rp).context("read table of contents")?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&toc)?);
+                return Ok(());
+            }
+            for e in &toc.entries {
+                let path = match &e.link_target {
+                    Some(target) => format!("{} -> {target}", e.path),
+                    None => e.path.clone(),
+                };
+                if long {
+                    println!(
+                        "{:<8} {:>12} {:04o} {:>10} {:<64} {path}",
+                        e.kind.name(),
+                        e.size,
+                        e.mode & 0o7777,
+                        e.mtime,
+                        e.sha256_hex
+                    );
+                } else {
+                    println!("{:<8} {:>12}  {path}", e.kind.name(), e.size);
+                }
+            }
+            for path in &toc.deleted {
+                println!("{:<8} {:>12}  {path}", "deleted", "-");
+            }
+            println!("{} entries, {} bytes in files", toc.entries.len(), toc.file_bytes());
+            if !toc.unchanged.is_empty() || !toc.deleted.is_empty() {
+                println!(
+                    "Delta archive: {} entries unchanged from the base, {} deleted",
+                    toc.unchanged.len(),
+                    toc.deleted.len()
+                );
+            }
+        }
+        Command::Restore {
+            input_frames,
+            out_dir,
+            on_conflict,
+            profile,
+            passphrase,
+            identity,
+        } => {
+            let mut rp = profile.to_profile().defaults();
+            rp.decryption = match (passphrase, identity) {
+                (Some(pass), _) => Some(sllv_core::DecryptionKey::Passphrase(pass)),
+                (None, Some(key)) => Some(sllv_core::DecryptionKey::Identity(
+                    read_key(&key).context("read --identity key")?,
+                )),
+                (None, None) => None,
+            };
+            let opts = sllv_core::ExtractOptions {
+                on_collision: on_conflict.to_policy(),
             };
+            let report = sllv_core::restore_to_dir(&input_frames, &rp, &out_dir, &opts).context("restore")?;
+            for entry in &report.entries {
+                println!("  {entry}");
+            }
+            println!(
+                "Restored {} files ({} bytes) from {} archives to {}",
+                report.files_written(),
+                report.bytes_written(),
+                input_frames.len(),
+                out_dir.display()
+            );
+        }
+        Command::Extract {
+            input_frames,
+            path,
+            out_dir,
+            out,
+            on_conflict,
+            profile,
+            passphrase,
+            identity,
+            pubkey,
+        } => {
+            let mut rp = profile.to_profile().defaults();
+            rp.decryption = match (passphrase, identity) {
+                (Some(pass), _) => Some(sllv_core::DecryptionKey::Passphrase(pass)),
+                (None, Some(key)) => Some(sllv_core::DecryptionKey::Identity(
+                    read_key(&key).context("read --identity key")?,
+                )),
+                (None, None) => None,
+            };
+            let status = signature_status(&input_frames, &read_keys(&pubkey)?)?;
+            let manifest = sllv_core::raster::read_manifest(&input_frames).context("read manifest")?;
+            if let Some(v) = &manifest.volume {
+                anyhow::bail!(
+                    "{} is volume {} of a {}-volume set; recover files with `sllv decode` on the set",
+                    input_frames.display(),
+                    v.index,
+                    v.count()
+                );
+            }
+            let toc = sllv_core::toc::read_toc_with_manifest(&input_frames, &manifest, &rp)
+                .context("read table of contents")?;
 
+            let mut entries = Vec::new();
+            for p in &path {
+                let found = toc.select(p);
+                if found.is_empty() {
+                    return Err(sllv_core::TocError::NotFound(p.clone())).context("select files");
+                }
+                for e in found {
+                    if !entries.iter().any(|x: &&sllv_core::TocEntry| x.path == e.path) {
+                        entries.push(e);
+                    }
+                }
+            }
+            if out.is_some() && entries.len() != 1 {
+                anyhow::bail!("-o needs exactly one file, but {} were selected", entries.len());
+            }
+
+            let contents =
+                sllv_core::read_entries(&input_frames, &manifest, &rp, &entries).context("decode selected files")?;
+            let note = match contents.frames_decoded {
+                Some(n) => format!(
+                    "decoded {n} of {} data frames",
+                    manifest.data_end().saturating_sub(rp.sync_frames + rp.calibration_frames)
+                ),
+                None => "deduplicated, compressed or encrypted archive: decoded every frame".to_string(),
+            };
+
+            match out {
+                Some(out) if out.as_os_str() == "-" => {
+                    std::io::stdout().write_all(&contents.data[0]).context("write stdout")?;
+                    eprintln!("{} ({note})", entries[0].path);
+                    eprintln!("Signature: {status}");
+                    return Ok(());
+                }
+                Some(out) => {
+                    std::fs::write(&out, &contents.data[0]).context("write output")?;
+                    println!("  wrote       {} -> {} ({} bytes)", entries[0].path, out.display(), entries[0].size);
+                }
+                None => {
+                    let opts = sllv_core::ExtractOptions {
+                        on_collision: on_conflict.to_policy(),
+                    };
+                    for (e, bytes) in entries.iter().zip(&contents.data) {
+                        let report = sllv_core::extract_raw_bytes_to_dir(bytes, &e.path, &out_dir, &opts)
+                            .context("write file")?;
+                        for r in &report.entries {
+                            println!("  {r}");
+                        }
+                    }
+                }
+            }
+            println!("Extracted {} files, sha256 verified ({note})", entries.len());
+            println!("Signature: {status}");
+        }
+        Command::Verify {
+            input_frames,
+            pubkey,
+            profile,
+        } => {
+            let trusted = read_keys(&pubkey)?;
+            let status = signature_status(&input_frames, &trusted)?;
+            let rp = profile.to_profile().defaults();
+            let bytes = sllv_core::raster::verify_frames_dir_payload(&input_frames, &rp).context("verify payload")?;
+            println!("Payload: {bytes} bytes, sha256 matches manifest");
+            println!("Signature: {status}");
+            if !status.is_trusted() {
+                anyhow::bail!("not signed by a trusted key");
+            }
+        }
+        Command::Keygen { out, signing } => {
+            let (secret, public) = if signing {
+                sllv_core::sign::generate_ed25519_keypair().context("generate key")?
+            } else {
+                sllv_core::crypto::generate_x25519_keypair().context("generate key")?
+            };
+            let mut pub_path = out.clone().into_os_string();
+            pub_path.push(".pub");
+            std::fs::write(&out, format!("{}\n", hex::encode(secret))).context("write secret key")?;
+            std::fs::write(&pub_path, format!("{}\n", hex::encode(public))).context("write public key")?;
+            let use_for = if signing { "sign" } else { "decode" };
+            println!("Secret key: {} (keep it private; needed to {use_for})", out.display());
+            println!("Public key: {}", Path::new(&pub_path).display());
+            println!("{}", hex::encode(public));
+            if signing {
+                println!("Key id: {}", sllv_core::sign::key_id(&public));
+            }
+        }
+        Command::VideoSelftest {
+            codec,
+            crf,
+            transcode_crf,
+            profile,
+            size,
+            ffmpeg_path,
+        } => {
             let rp = profile.to_profile().defaults();
-            let bytes = sllv_core::raster::decode_frames_dir_to_bytes_with_params(&frames_dir, &rp)
-                .context("decode frames")?;
-            std::fs::write(&out_tar, bytes).context("write recovered tar")?;
+            let v = sllv_core::VideoExportParams {
+                codec: codec.to_codec(),
+                crf,
+                ..Default::default()
+            };
+            let r = sllv_core::lossy_video_selftest(&rp, &v, transcode_crf, size as usize, ffmpeg_path.as_deref())
+                .context("video self-test")?;
+            println!(
+                "Upload: {}{}{}",
+                v.codec.name(),
+                match crf.or(v.codec.default_crf()) {
+                    Some(c) => format!(" crf {c}"),
+                    None => String::new(),
+                },
+                match transcode_crf {
+                    Some(c) => format!(", re-encoded with h264 crf {c}"),
+                    None => String::new(),
+                }
+            );
+            println!("Video: {} frames, {} bytes", r.frames, r.video_bytes);
+            println!(
+                "Cells: {} compared, {} wrong, {} of {} frames damaged",
+                r.cells, r.symbol_errors, r.damaged_frames, r.frames
+            );
+            println!("Margin: worst {:.2}, mean {:.2} (1.00 = exact colour, 0 = decision boundary)", r.min_margin, r.mean_margin);
+            match r.decode_error {
+                None => println!("Decode: OK, {size} bytes recovered and verified"),
+                Some(e) => anyhow::bail!("decode failed: {e}"),
+            }
         }
         Command::Doctor {
             check_ffmpeg,
@@ -170,6 +1368,176 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// A byte count for `--max-file-size`: `1234`, `64K`, `10M`, `2G` (binary units).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (num, mult) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let mult = match c.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                _ => return Err(format!("unknown size suffix {c:?} (use K, M or G)")),
+            };
+            (&s[..i], mult)
+        }
+        _ => (s, 1),
+    };
+    let n: u64 = num.trim().parse().map_err(|_| format!("invalid size {s:?}"))?;
+    n.checked_mul(mult).ok_or_else(|| format!("size {s:?} is too large"))
+}
+
+/// `2x2`, `3x1`: tile columns by rows.
+fn parse_tiles(s: &str) -> Result<[u32; 2], String> {
+    let max = sllv_core::raster::MAX_TILES;
+    let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| (1..=max).contains(&n));
+    match s.split_once(['x', 'X']).map(|(c, r)| (parse(c), parse(r))) {
+        Some((Some(cols), Some(rows))) => Ok([cols, rows]),
+        _ => Err(format!("invalid tiles {s:?} (use COLSxROWS, each 1-{max}, e.g. 2x2)")),
+    }
+}
+
+/// Accept a 32-byte key either inline as hex or as a path to a file containing the hex.
+fn read_key(arg: &str) -> anyhow::Result<[u8; 32]> {
+    let path = Path::new(arg);
+    let text = if path.is_file() {
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?
+    } else {
+        arg.to_string()
+    };
+    Ok(sllv_core::crypto::parse_key_hex(&text)?)
+}
+
+fn read_keys(args: &[String]) -> anyhow::Result<Vec<[u8; 32]>> {
+    args.iter()
+        .map(|k| read_key(k).with_context(|| format!("read --pubkey {k}")))
+        .collect()
+}
+
+/// Check the manifest signature up front; a signature that doesn't verify stops the decode.
+fn signature_status(frames_dir: &Path, trusted: &[[u8; 32]]) -> anyhow::Result<sllv_core::SignatureStatus> {
+    let manifest = sllv_core::raster::read_manifest(frames_dir).context("read manifest")?;
+    check_signature(&manifest, trusted)
+}
+
+fn check_signature(
+    manifest: &sllv_core::EncodeManifest,
+    trusted: &[[u8; 32]],
+) -> anyhow::Result<sllv_core::SignatureStatus> {
+    let status = sllv_core::sign::verify_manifest(manifest, trusted).context("check signature")?;
+    if matches!(status, sllv_core::SignatureStatus::Invalid { .. }) {
+        anyhow::bail!("signature {status}");
+    }
+    Ok(status)
+}
+
+/// Table of contents of an `encode --base`: a TOC JSON file from `sllv list --json`, or read from
+/// the frames of an archive (any volume, for a volume set).
+fn read_base_toc(
+    base: &Path,
+    profile: sllv_core::Profile,
+    key: Option<sllv_core::DecryptionKey>,
+) -> anyhow::Result<sllv_core::Toc> {
+    if base.is_file() {
+        let text = std::fs::read(base).with_context(|| format!("read {}", base.display()))?;
+        return serde_json::from_slice(&text).context("parse --base table of contents");
+    }
+    let dir = if sllv_core::volume::is_volume_path(base) {
+        let scan = sllv_core::scan_volumes(&[base.to_path_buf()]).context("find --base volumes")?;
+        scan.found.into_values().next().context("no --base volumes found")?
+    } else {
+        base.to_path_buf()
+    };
+    let mut rp = profile.defaults();
+    rp.decryption = key;
+    sllv_core::read_toc(&dir, &rp).context("read --base table of contents")
+}
+
+/// Where `decode` reads from: one frames directory, the volumes of a split encode, a video, or
+/// scanned pages.
+enum Source {
+    Frames(PathBuf),
+    Volumes(Box<sllv_core::VolumeScan>),
+    Video {
+        path: PathBuf,
+        manifest: Box<sllv_core::EncodeManifest>,
+        ffmpeg_path: Option<PathBuf>,
+    },
+    Scans {
+        files: Vec<PathBuf>,
+        manifest: Box<sllv_core::EncodeManifest>,
+    },
+}
+
+impl Source {
+    fn manifest(&self) -> anyhow::Result<sllv_core::EncodeManifest> {
+        match self {
+            Source::Frames(dir) => sllv_core::raster::read_manifest(dir).context("read manifest"),
+            Source::Volumes(scan) => Ok(scan.set.manifest.clone()),
+            Source::Video { manifest, .. } | Source::Scans { manifest, .. } => Ok((**manifest).clone()),
+        }
+    }
+
+    /// Decode into `out`, returning the volume report (for a set) and informational lines.
+    fn decode<W: Write>(
+        &self,
+        rp: &sllv_core::RasterParams,
+        out: &mut W,
+    ) -> anyhow::Result<(Option<sllv_core::VolumeDecodeReport>, Vec<String>)> {
+        match self {
+            Source::Frames(dir) => {
+                let (tx, rx) = std::sync::mpsc::channel();
+                sllv_core::raster::decode_frames_dir_to_writer_with_progress(dir, rp, out, Some(tx))
+                    .context("decode frames")?;
+                Ok((None, info_lines(rx.try_iter())))
+            }
+            Source::Volumes(scan) => {
+                let report = sllv_core::decode_volumes_to_writer(scan, rp, out, None).context("decode volumes")?;
+                Ok((Some(report), Vec::new()))
+            }
+            Source::Video {
+                path,
+                manifest,
+                ffmpeg_path,
+            } => {
+                sllv_core::decode_video_to_writer(path, manifest, rp, out, ffmpeg_path.as_deref(), None)
+                    .context("decode video")?;
+                Ok((None, Vec::new()))
+            }
+            Source::Scans { files, manifest } => {
+                let (tx, rx) = std::sync::mpsc::channel();
+                sllv_core::decode_scans_to_writer(files, manifest, rp, out, Some(tx)).context("decode scans")?;
+                Ok((None, info_lines(rx.try_iter())))
+            }
+        }
+    }
+}
+
+/// The [`ProgressMsg::Info`](sllv_core::raster::ProgressMsg::Info) lines among `msgs`.
+fn info_lines(msgs: impl IntoIterator<Item = sllv_core::raster::ProgressMsg>) -> Vec<String> {
+    msgs.into_iter()
+        .filter_map(|msg| match msg {
+            sllv_core::raster::ProgressMsg::Info(line) => Some(line),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `out.mkv` -> `out.vol_000.mkv`.
+fn volume_file_name(path: &Path, dir: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{dir}"));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+fn join_indices(indices: &[u32]) -> String {
+    indices.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
 fn run_doctor(check_ffmpeg: bool, ffmpeg_path: Option<&Path>) -> anyhow::Result<()> {
     println!("SLLV doctor");
 
@@ -185,20 +1553,73 @@ fn run_doctor(check_ffmpeg: bool, ffmpeg_path: Option<&Path>) -> anyhow::Result<
             .map(|x| x.display().to_string())
             .unwrap_or_else(|| "(PATH)".to_string());
 
-        // This call will succeed only if ffmpeg is runnable; it may still error due to missing input.
-        match ffmpeg::mkv_to_frames(Path::new("__nonexistent__.mkv"), Path::new("."), ffmpeg_path) {
+        match sllv_core::resolve_ffmpeg(ffmpeg_path) {
             Ok(_) => println!("- FFmpeg: ok ({p})"),
             Err(e) => {
-                let msg = format!("{e:#}").to_lowercase();
-                if msg.contains("ffmpeg not found") || msg.contains("not found") {
-                    println!("- FFmpeg: missing ({p})");
-                    println!("  Install ffmpeg or pass --ffmpeg-path.");
-                } else {
-                    println!("- FFmpeg: ok ({p})");
-                }
+                println!("- FFmpeg: missing ({p}): {e:#}");
+                println!("  Install ffmpeg or pass --ffmpeg-path.");
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        assert_eq!(parse_size("1234"), Ok(1234));
+        assert_eq!(parse_size(" 64k "), Ok(64 << 10));
+        assert_eq!(parse_size("10M"), Ok(10 << 20));
+        assert_eq!(parse_size("2 G"), Ok(2 << 30));
+        for bad in ["", "M", "1.5M", "-1", "10T", "99999999999G"] {
+            assert!(parse_size(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn keys_are_read_inline_or_from_a_file() {
+        let hex = "ab".repeat(32);
+        assert_eq!(read_key(&hex).unwrap(), [0xab; 32]);
+        let path = std::env::temp_dir().join(format!("sllv_cli_key_{}.pub", std::process::id()));
+        std::fs::write(&path, format!("{}\n", "01".repeat(32))).unwrap();
+        assert_eq!(read_key(path.to_str().unwrap()).unwrap(), [1; 32]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_key("abcd").is_err());
+        assert!(read_key(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn encode_takes_several_inputs_and_stdin() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["sllv", "encode", "-i", "a.txt", "docs", "-", "-o", "frames"]).unwrap();
+        let Command::Encode { input, stdin_name, .. } = cli.cmd else {
+            panic!("not an encode");
+        };
+        assert_eq!(input, [PathBuf::from("a.txt"), "docs".into(), "-".into()]);
+        assert_eq!(stdin_name, "stdin");
+        assert!(Cli::try_parse_from(["sllv", "encode", "-o", "frames"]).is_err());
+    }
+
+    #[test]
+    fn volume_outputs_are_named_after_the_volume() {
+        assert_eq!(volume_file_name(Path::new("out/a.mkv"), "vol_001"), Path::new("out/a.vol_001.mkv"));
+        assert_eq!(volume_file_name(Path::new("video"), "vol_000"), Path::new("video.vol_000"));
+        assert_eq!(join_indices(&[0, 3, 12]), "0, 3, 12");
+    }
+
+    #[test]
+    fn tiles_are_columns_by_rows() {
+        assert_eq!(parse_tiles("2x2"), Ok([2, 2]));
+        assert_eq!(parse_tiles("3X1"), Ok([3, 1]));
+        let max = sllv_core::raster::MAX_TILES;
+        assert_eq!(parse_tiles(&format!("{max}x1")), Ok([max, 1]));
+        for bad in ["2", "0x2", "2x", "x2", "2x2x2", &format!("1x{}", max + 1)] {
+            assert!(parse_tiles(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,618 @@
+//! Animated images as a single-file frame source: APNG, lossless WebP and GIF.
+//!
+//! Each format embeds the manifest, so one file is enough to share and decode:
+//!
+//! | format | frames | manifest |
+//! |--------|--------|----------|
+//! | APNG   | RGB8, deflate | `iTXt` chunk `sllv-manifest` |
+//! | WebP   | VP8L lossless (see [`crate::webp`]) | `SLLV` chunk |
+//! | GIF    | LZW, one colour table per frame (see [`crate::gif`]) | comment extension |
+//!
+//! All three are lossless. GIF needs every frame to fit in 256 colours, which the built-in
+//! palettes always do. The reader also takes plain (single-image) PNGs and animations written by
+//! other tools, as long as nothing has resampled the frames.
+
+use crate::frames::{DecodeStatus, FrameDecoder, FrameEncoder};
+use crate::gif::{GifError, GifReader, GifWriter};
+use crate::manifest::EncodeManifest;
+use crate::raster::{ProgressMsg, RasterError, RasterParams};
+use crate::webp::{WebpError, WebpReader, WebpWriter};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use thiserror::Error;
+
+/// Keyword of the APNG text chunk holding the manifest.
+const APNG_MANIFEST_KEY: &str = "sllv-manifest";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Error)]
+pub enum AnimError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("png: {0}")]
+    PngEncode(#[from] png::EncodingError),
+    #[error("png: {0}")]
+    PngDecode(#[from] png::DecodingError),
+    #[error("gif: {0}")]
+    Gif(#[from] GifError),
+    #[error("webp: {0}")]
+    Webp(#[from] WebpError),
+    #[error("not an APNG, GIF or WebP file")]
+    UnknownFormat,
+    #[error("embedded manifest: {0}")]
+    Manifest(String),
+    #[error("frame {index}: {msg}")]
+    Frame { index: u64, msg: String },
+}
+
+/// Animated image container.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimFormat {
+    #[default]
+    Apng,
+    Webp,
+    Gif,
+}
+
+impl AnimFormat {
+    pub const ALL: [AnimFormat; 3] = [AnimFormat::Apng, AnimFormat::Webp, AnimFormat::Gif];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnimFormat::Apng => "apng",
+            AnimFormat::Webp => "webp",
+            AnimFormat::Gif => "gif",
+        }
+    }
+
+    /// File extension without the dot. APNG uses `.png` so it opens anywhere.
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimFormat::Apng => "png",
+            AnimFormat::Webp => "webp",
+            AnimFormat::Gif => "gif",
+        }
+    }
+
+    /// Format implied by a file name's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" | "apng" => Some(AnimFormat::Apng),
+            "webp" => Some(AnimFormat::Webp),
+            "gif" => Some(AnimFormat::Gif),
+            _ => None,
+        }
+    }
+
+    /// Format of an existing file, from its signature; `None` if it is none of the three.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut head = [0u8; 12];
+        let mut f = File::open(path).ok()?;
+        f.read_exact(&mut head).ok()?;
+        if head.starts_with(PNG_SIGNATURE) {
+            Some(AnimFormat::Apng)
+        } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+            Some(AnimFormat::Gif)
+        } else if &head[..4] == b"RIFF" && &head[8..] == b"WEBP" {
+            Some(AnimFormat::Webp)
+        } else {
+            None
+        }
+    }
+}
+
+enum Sink<W: Write + Seek> {
+    Apng(png::Writer<W>),
+    Webp(WebpWriter<W>),
+    Gif(GifWriter<W>),
+}
+
+/// Writes RGB frames as an animated image with the manifest embedded.
+pub struct AnimWriter<W: Write + Seek> {
+    sink: Sink<W>,
+    dims: (u32, u32),
+    frames: u64,
+}
+
+impl<W: Write + Seek> AnimWriter<W> {
+    /// Start an animation of `frames` frames (APNG declares the count up front), each shown for
+    /// `delay_ms`.
+    pub fn new(
+        out: W,
+        format: AnimFormat,
+        width: u32,
+        height: u32,
+        frames: u32,
+        delay_ms: u32,
+        manifest: Option<&EncodeManifest>,
+    ) -> Result<Self, AnimError> {
+        let json = manifest
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| AnimError::Manifest(e.to_string()))?;
+        let sink = match format {
+            AnimFormat::Apng => {
+                let mut enc = png::Encoder::new(out, width, height);
+                enc.set_color(png::ColorType::Rgb);
+                enc.set_depth(png::BitDepth::Eight);
+                enc.set_animated(frames.max(1), 0)?;
+                enc.set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+                if let Some(json) = json {
+                    enc.add_itxt_chunk(APNG_MANIFEST_KEY.into(), json)?;
+                }
+                Sink::Apng(enc.write_header()?)
+            }
+            AnimFormat::Webp => {
+                Sink::Webp(WebpWriter::new(out, width, height, delay_ms, json.as_deref().map(str::as_bytes))?)
+            }
+            AnimFormat::Gif => {
+                Sink::Gif(GifWriter::new(out, width, height, delay_ms, json.as_deref().map(str::as_bytes))?)
+            }
+        };
+        Ok(Self { sink, dims: (width, height), frames: 0 })
+    }
+
+    pub fn write_frame(&mut self, img: &RgbImage) -> Result<(), AnimError> {
+        if img.dimensions() != self.dims {
+            return Err(AnimError::Frame {
+                index: self.frames,
+                msg: format!("{}x{}, expected {}x{}", img.width(), img.height(), self.dims.0, self.dims.1),
+            });
+        }
+        match &mut self.sink {
+            Sink::Apng(w) => w.write_image_data(img.as_raw())?,
+            Sink::Webp(w) => w.write_frame(img)?,
+            Sink::Gif(w) => w.write_frame(img)?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Write the trailer. APNG fails here if fewer frames were written than declared.
+    pub fn finish(self) -> Result<(), AnimError> {
+        match self.sink {
+            Sink::Apng(w) => w.finish()?,
+            Sink::Webp(w) => {
+                w.finish()?.flush()?;
+            }
+            Sink::Gif(w) => {
+                w.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// APNG decoding state: the canvas plus what the previous frame asked to be disposed.
+struct ApngSource<R: Read> {
+    reader: png::Reader<R>,
+    buf: Vec<u8>,
+    /// RGBA canvas; APNG frames may be partial and translucent.
+    canvas: Vec<u8>,
+    width: u32,
+    saved: Option<Vec<u8>>,
+    dispose: Option<(png::DisposeOp, (u32, u32, u32, u32))>,
+    remaining: u32,
+    skip_default: bool,
+    delay_ms: u32,
+}
+
+impl<R: Read> ApngSource<R> {
+    fn new(input: R) -> Result<(Self, Option<String>), AnimError> {
+        let mut decoder = png::Decoder::new(input);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let reader = decoder.read_info()?;
+        let info = reader.info();
+        let (width, height) = info.size();
+        let mut manifest = None;
+        for chunk in &info.utf8_text {
+            if chunk.keyword == APNG_MANIFEST_KEY {
+                manifest = Some(chunk.get_text()?);
+            }
+        }
+        for chunk in &info.uncompressed_latin1_text {
+            if manifest.is_none() && chunk.keyword == APNG_MANIFEST_KEY {
+                manifest = Some(chunk.text.clone());
+            }
+        }
+        // Without an acTL this is a plain PNG: one frame. A default image without an fcTL is
+        // not part of the animation.
+        let (remaining, skip_default) = match info.animation_control() {
+            Some(ac) => (ac.num_frames, info.frame_control().is_none()),
+            None => (1, false),
+        };
+        let delay_ms = info
+            .frame_control()
+            .map(|fc| {
+                let den = if fc.delay_den == 0 { 100 } else { fc.delay_den as u32 };
+                fc.delay_num as u32 * 1000 / den
+            })
+            .unwrap_or_default();
+        let buf = vec![0; reader.output_buffer_size()];
+        let source = Self {
+            reader,
+            buf,
+            canvas: vec![0; width as usize * height as usize * 4],
+            width,
+            saved: None,
+            dispose: None,
+            remaining,
+            skip_default,
+            delay_ms,
+        };
+        Ok((source, manifest))
+    }
+
+    fn read_frame(&mut self, index: u64) -> Result<Option<RgbImage>, AnimError> {
+        if self.skip_default {
+            self.skip_default = false;
+            self.reader.next_frame(&mut self.buf)?;
+        }
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        // Apply the previous frame's disposal before drawing this one.
+        match self.dispose.take() {
+            Some((png::DisposeOp::Background, rect)) => self.fill(rect, [0; 4]),
+            Some((png::DisposeOp::Previous, _)) => {
+                if let Some(saved) = self.saved.take() {
+                    self.canvas = saved;
+                }
+            }
+            _ => {}
+        }
+
+        let out = self.reader.next_frame(&mut self.buf)?;
+        let (fw, fh) = (out.width, out.height);
+        let fc = self.reader.info().frame_control().copied();
+        let (x, y, blend, dispose) = match fc {
+            Some(fc) => (fc.x_offset, fc.y_offset, fc.blend_op, fc.dispose_op),
+            None => (0, 0, png::BlendOp::Source, png::DisposeOp::None),
+        };
+        let height = (self.canvas.len() / 4) as u32 / self.width;
+        if x as u64 + fw as u64 > self.width as u64 || y as u64 + fh as u64 > height as u64 {
+            return Err(AnimError::Frame {
+                index,
+                msg: format!("{fw}x{fh} at ({x},{y}) outside the {}x{height} canvas", self.width),
+            });
+        }
+        if dispose == png::DisposeOp::Previous {
+            self.saved = Some(self.canvas.clone());
+        }
+        let samples = out.color_type.samples();
+        for row in 0..fh {
+            let line = &self.buf[row as usize * out.line_size..][..fw as usize * samples];
+            for col in 0..fw {
+                let s = &line[col as usize * samples..][..samples];
+                let src = match samples {
+                    1 => [s[0], s[0], s[0], 255],
+                    2 => [s[0], s[0], s[0], s[1]],
+                    3 => [s[0], s[1], s[2], 255],
+                    _ => [s[0], s[1], s[2], s[3]],
+                };
+                let i = ((y + row) as usize * self.width as usize + (x + col) as usize) * 4;
+                let dst = &mut self.canvas[i..i + 4];
+                if blend == png::BlendOp::Over && src[3] < 255 {
+                    dst.copy_from_slice(&blend_over(src, [dst[0], dst[1], dst[2], dst[3]]));
+                } else {
+                    dst.copy_from_slice(&src);
+                }
+            }
+        }
+        self.dispose = Some((dispose, (x, y, fw, fh)));
+
+        let rgb: Vec<u8> = self.canvas.chunks_exact(4).flat_map(|px| [px[0], px[1], px[2]]).collect();
+        Ok(RgbImage::from_raw(self.width, height, rgb))
+    }
+
+    fn fill(&mut self, (x, y, w, h): (u32, u32, u32, u32), px: [u8; 4]) {
+        for row in y..y + h {
+            for col in x..x + w {
+                let i = (row as usize * self.width as usize + col as usize) * 4;
+                self.canvas[i..i + 4].copy_from_slice(&px);
+            }
+        }
+    }
+}
+
+/// Porter-Duff "over" for straight (non-premultiplied) RGBA.
+fn blend_over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    let sa = src[3] as u32;
+    let da = dst[3] as u32 * (255 - sa) / 255;
+    let a = sa + da;
+    if a == 0 {
+        return [0; 4];
+    }
+    let mix = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da) / a) as u8;
+    [mix(src[0], dst[0]), mix(src[1], dst[1]), mix(src[2], dst[2]), a as u8]
+}
+
+enum Source<R: Read> {
+    Apng(Box<ApngSource<R>>),
+    Webp(WebpReader<R>),
+    Gif(GifReader<R>),
+}
+
+/// Reads an APNG, GIF or WebP as a sequence of composited RGB frames.
+pub struct AnimReader<R: Read> {
+    source: Source<R>,
+    format: AnimFormat,
+    manifest: Option<EncodeManifest>,
+    read: u64,
+}
+
+impl AnimReader<BufReader<File>> {
+    /// Open `path`, detecting the format from its signature.
+    pub fn open(path: &Path) -> Result<Self, AnimError> {
+        let format = AnimFormat::detect(path).ok_or(AnimError::UnknownFormat)?;
+        Self::new(BufReader::new(File::open(path)?), format)
+    }
+}
+
+impl<R: Read> AnimReader<R> {
+    /// Parse the header of a `format` stream and its embedded manifest, if any.
+    pub fn new(input: R, format: AnimFormat) -> Result<Self, AnimError> {
+        let (source, json) = match format {
+            AnimFormat::Apng => {
+                let (source, json) = ApngSource::new(input)?;
+                (Source::Apng(Box::new(source)), json.map(String::into_bytes))
+            }
+            AnimFormat::Webp => {
+                let reader = WebpReader::new(input)?;
+                let json = reader.manifest().map(<[u8]>::to_vec);
+                (Source::Webp(reader), json)
+            }
+            AnimFormat::Gif => {
+                let reader = GifReader::new(input)?;
+                // Other tools add their own comments; only a parseable manifest counts.
+                let json = reader
+                    .comments()
+                    .iter()
+                    .find(|c| serde_json::from_slice::<EncodeManifest>(c).is_ok())
+                    .cloned();
+                (Source::Gif(reader), json)
+            }
+        };
+        let manifest = json
+            .map(|json| serde_json::from_slice::<EncodeManifest>(&json))
+            .transpose()
+            .map_err(|e| AnimError::Manifest(e.to_string()))?;
+        if let Some(m) = &manifest {
+            if !m.is_supported() {
+                return Err(AnimError::Manifest(format!("unsupported manifest version {}", m.version)));
+            }
+        }
+        Ok(Self { source, format, manifest, read: 0 })
+    }
+
+    pub fn format(&self) -> AnimFormat {
+        self.format
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match &self.source {
+            Source::Apng(s) => s.reader.info().size(),
+            Source::Webp(r) => r.dimensions(),
+            Source::Gif(r) => r.dimensions(),
+        }
+    }
+
+    /// Delay of the first frame in milliseconds (0 if unknown).
+    pub fn delay_ms(&self) -> u32 {
+        match &self.source {
+            Source::Apng(s) => s.delay_ms,
+            Source::Webp(r) => r.delay_ms(),
+            Source::Gif(r) => r.delay_ms(),
+        }
+    }
+
+    /// The manifest embedded by [`AnimWriter`], if present.
+    pub fn manifest(&self) -> Option<&EncodeManifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Frames returned so far.
+    pub fn frames_read(&self) -> u64 {
+        self.read
+    }
+
+    /// The next frame, or `None` after the last.
+    pub fn read_frame(&mut self) -> Result<Option<RgbImage>, AnimError> {
+        let frame = match &mut self.source {
+            Source::Apng(s) => s.read_frame(self.read)?,
+            Source::Webp(r) => r.read_frame()?,
+            Source::Gif(r) => r.read_frame()?,
+        };
+        if frame.is_some() {
+            self.read += 1;
+        }
+        Ok(frame)
+    }
+}
+
+impl<R: Read> Iterator for AnimReader<R> {
+    type Item = Result<RgbImage, AnimError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Encode `input_bytes` straight into one animated image: no ffmpeg, no frames directory.
+///
+/// The manifest is embedded, so the file decodes on its own. GIF fails with
+/// [`GifError::TooManyColors`] if a frame has more than 256 colours, which only a custom palette
+/// with added colours could cause.
+pub fn encode_bytes_to_anim(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_path: &Path,
+    format: AnimFormat,
+    delay_ms: u32,
+    p: &RasterParams,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<EncodeManifest, RasterError> {
+    let encoder = FrameEncoder::new(input_bytes, file_name, p)?;
+    let (w, h) = encoder.frame(0)?.dimensions();
+    let total = encoder.total_frames() as u64;
+    let out = BufWriter::new(File::create(out_path)?);
+    let mut writer = AnimWriter::new(out, format, w, h, total as u32, delay_ms, Some(encoder.manifest()))?;
+    encoder.render_in_order(|i, img| -> Result<(), RasterError> {
+        writer.write_frame(&img)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "encode".into(),
+                done: i as u64 + 1,
+                total,
+            });
+        }
+        Ok(())
+    })?;
+    writer.finish()?;
+    Ok(encoder.manifest().clone())
+}
+
+/// Decode an animated image into `out`.
+///
+/// `m` overrides the embedded manifest; with neither, only FEC archives decode.
+pub fn decode_anim_to_writer<W: Write>(
+    path: &Path,
+    m: Option<&EncodeManifest>,
+    p: &RasterParams,
+    out: W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<W, RasterError> {
+    let mut reader = AnimReader::open(path)?;
+    let manifest = m.or(reader.manifest()).cloned();
+    let total = manifest.as_ref().map(|m| m.frames as u64).unwrap_or_default();
+    let mut decoder = FrameDecoder::with_writer(p, out);
+    if let Some(m) = manifest {
+        decoder = decoder.with_manifest(m);
+    }
+    while let Some(frame) = reader.read_frame()? {
+        let status = decoder.push_frame(&frame)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "decode".into(),
+                done: reader.frames_read(),
+                total,
+            });
+        }
+        if status == DecodeStatus::Complete {
+            break;
+        }
+    }
+    decoder.finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::PayloadKind;
+    use crate::profile::Profile;
+    use std::io::Cursor;
+
+    fn small_params() -> RasterParams {
+        RasterParams {
+            grid_w: 48,
+            grid_h: 40,
+            sync_frames: 2,
+            payload: PayloadKind::Raw,
+            ..Profile::Archive.defaults()
+        }
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_anim_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writer_reader_round_trip() {
+        let p = small_params();
+        let encoder = FrameEncoder::new(&[7u8; 2000], "data.bin", &p).unwrap();
+        let frames: Vec<RgbImage> = (0..encoder.total_frames()).map(|i| encoder.frame(i).unwrap()).collect();
+        let (w, h) = frames[0].dimensions();
+        for format in AnimFormat::ALL {
+            let mut out = Cursor::new(Vec::new());
+            let mut writer = AnimWriter::new(&mut out, format, w, h, frames.len() as u32, 100, Some(encoder.manifest())).unwrap();
+            for f in &frames {
+                writer.write_frame(f).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut reader = AnimReader::new(&out.get_ref()[..], format).unwrap();
+            assert_eq!(reader.dimensions(), (w, h), "{format:?}");
+            assert_eq!(reader.manifest().unwrap().sha256_hex, encoder.manifest().sha256_hex, "{format:?}");
+            let read: Vec<RgbImage> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, frames, "{format:?}");
+            assert_eq!(reader.delay_ms(), 100, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let dir = scratch_dir("roundtrip");
+        let p = small_params();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for format in AnimFormat::ALL {
+            let path = dir.join(format!("out.{}", format.extension()));
+            encode_bytes_to_anim(&data, "data.bin", &path, format, 50, &p, None).unwrap();
+            assert_eq!(AnimFormat::detect(&path), Some(format));
+            let out = decode_anim_to_writer(&path, None, &p, Vec::new(), None).unwrap();
+            assert_eq!(out, data, "{format:?}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        let dir = scratch_dir("malformed");
+        let path = dir.join("x.gif");
+        std::fs::write(&path, b"not an animation").unwrap();
+        assert!(matches!(AnimReader::open(&path), Err(AnimError::UnknownFormat)));
+
+        let p = small_params();
+        let encoder = FrameEncoder::new(&[1u8; 300], "data.bin", &p).unwrap();
+        let frame = encoder.frame(0).unwrap();
+        let (w, h) = frame.dimensions();
+        for format in AnimFormat::ALL {
+            let mut out = Cursor::new(Vec::new());
+            let mut writer = AnimWriter::new(&mut out, format, w, h, 1, 0, None).unwrap();
+            assert!(matches!(writer.write_frame(&RgbImage::new(w + 1, h)), Err(AnimError::Frame { .. })));
+            writer.write_frame(&frame).unwrap();
+            writer.finish().unwrap();
+            let bytes = out.into_inner();
+            for len in (0..bytes.len()).step_by(7) {
+                let _ = AnimReader::new(&bytes[..len], format).and_then(|r| r.collect::<Result<Vec<_>, _>>());
+            }
+        }
+        let bad_manifest = {
+            let mut out = Cursor::new(Vec::new());
+            let mut m = encoder.manifest().clone();
+            m.version = u16::MAX;
+            let mut writer = AnimWriter::new(&mut out, AnimFormat::Apng, w, h, 1, 0, Some(&m)).unwrap();
+            writer.write_frame(&frame).unwrap();
+            writer.finish().unwrap();
+            out.into_inner()
+        };
+        assert!(matches!(AnimReader::new(&bad_manifest[..], AnimFormat::Apng), Err(AnimError::Manifest(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
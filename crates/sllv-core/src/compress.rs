@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use thiserror::Error;
+
+/// Optional compression applied to the packed payload before FEC/encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd, level 1..=22.
+    Zstd { level: i32 },
+    /// xz/LZMA2, preset 0..=9.
+    Xz { preset: u32 },
+}
+
+impl Compression {
+    /// zstd's own default: most of the ratio of the high levels at a fraction of the encode time.
+    /// Decode speed barely depends on the level; pass a higher one for archives written once.
+    pub const ZSTD_DEFAULT_LEVEL: i32 = 3;
+    pub const XZ_DEFAULT_PRESET: u32 = 6;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd { .. } => "zstd",
+            Compression::Xz { .. } => "xz",
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, Compression::None)
+    }
+}
+
+/// Recorded in the manifest when the payload was compressed.
+///
+/// The manifest's own `total_bytes`/`sha256_hex` describe the compressed bytes carried by the
+/// frames; these fields describe the original packed bytes recovered after decompression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionInfo {
+    #[serde(flatten)]
+    pub method: Compression,
+    pub uncompressed_bytes: u64,
+    /// Empty when the payload is also encrypted.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub uncompressed_sha256_hex: String,
+}
+
+#[derive(Debug, Error)]
+pub enum CompressError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid compression level {level} for {algorithm}")]
+    InvalidLevel { algorithm: &'static str, level: i64 },
+}
+
+pub fn compress_bytes(input: &[u8], c: Compression) -> Result<Vec<u8>, CompressError> {
+    match c {
+        Compression::None => Ok(input.to_vec()),
+        Compression::Zstd { level } => {
+            if !(1..=22).contains(&level) {
+                return Err(CompressError::InvalidLevel {
+                    algorithm: "zstd",
+                    level: level as i64,
+                });
+            }
+            Ok(zstd::stream::encode_all(input, level)?)
+        }
+        Compression::Xz { preset } => {
+            if preset > 9 {
+                return Err(CompressError::InvalidLevel {
+                    algorithm: "xz",
+                    level: preset as i64,
+                });
+            }
+            let mut enc = xz2::write::XzEncoder::new(Vec::new(), preset);
+            enc.write_all(input)?;
+            Ok(enc.finish()?)
+        }
+    }
+}
+
+/// Hash and count everything written through to `inner`.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+    limit: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+            limit: u64::MAX,
+        }
+    }
+
+    /// Fail writes that would take the total past `limit` bytes.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the inner writer, the byte count and the hex SHA-256 of everything written.
+    pub fn finish(self) -> (W, u64, String) {
+        (self.inner, self.bytes, hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bytes.saturating_add(buf.len() as u64) > self.limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("output exceeds the expected {} bytes", self.limit),
+            ));
+        }
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Streaming decompressor used on the decode path. Output is hashed so the uncompressed SHA-256
+/// can be checked without buffering, and capped so a corrupt or hostile stream cannot expand
+/// past the size the manifest records.
+pub enum Decompressor<W: Write> {
+    None(HashingWriter<W>),
+    Zstd(zstd::stream::write::Decoder<'static, HashingWriter<W>>),
+    Xz(xz2::write::XzDecoder<HashingWriter<W>>),
+}
+
+impl<W: Write> Decompressor<W> {
+    pub fn new(c: Compression, limit: u64, out: W) -> io::Result<Self> {
+        let out = HashingWriter::new(out).with_limit(limit);
+        Ok(match c {
+            Compression::None => Decompressor::None(out),
+            Compression::Zstd { .. } => Decompressor::Zstd(zstd::stream::write::Decoder::new(out)?),
+            Compression::Xz { .. } => Decompressor::Xz(xz2::write::XzDecoder::new(out)),
+        })
+    }
+
+    /// Bytes of decompressed output produced so far.
+    pub fn bytes_out(&self) -> u64 {
+        match self {
+            Decompressor::None(w) => w.bytes_written(),
+            Decompressor::Zstd(d) => d.get_ref().bytes_written(),
+            Decompressor::Xz(d) => d.get_ref().bytes_written(),
+        }
+    }
+
+    /// Flush the decompressor and return the inner writer with the output's byte count and SHA-256.
+    pub fn finish(self) -> io::Result<(W, u64, String)> {
+        let mut out = match self {
+            Decompressor::None(w) => w,
+            Decompressor::Zstd(mut d) => {
+                d.flush()?;
+                d.into_inner()
+            }
+            Decompressor::Xz(mut d) => d.finish()?,
+        };
+        out.flush()?;
+        Ok(out.finish())
+    }
+}
+
+impl<W: Write> Write for Decompressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Decompressor::None(w) => w.write(buf),
+            Decompressor::Zstd(d) => d.write(buf),
+            Decompressor::Xz(d) => d.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Decompressor::None(w) => w.flush(),
+            Decompressor::Zstd(d) => d.flush(),
+            Decompressor::Xz(d) => d.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(c: Compression, limit: u64, data: &[u8]) -> io::Result<(Vec<u8>, u64, String)> {
+        let mut d = Decompressor::new(c, limit, Vec::new())?;
+        d.write_all(data)?;
+        d.finish()
+    }
+
+    #[test]
+    fn round_trip() {
+        let input: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        for c in [
+            Compression::None,
+            Compression::Zstd { level: Compression::ZSTD_DEFAULT_LEVEL },
+            Compression::Xz { preset: Compression::XZ_DEFAULT_PRESET },
+        ] {
+            let packed = compress_bytes(&input, c).unwrap();
+            let (out, n, sha) = decompress(c, input.len() as u64, &packed).unwrap();
+            assert_eq!(out, input, "{}", c.name());
+            assert_eq!(n, input.len() as u64);
+            assert_eq!(sha, hex::encode(Sha256::digest(&input)));
+        }
+    }
+
+    #[test]
+    fn output_past_the_limit_fails() {
+        let input = vec![0u8; 1 << 20];
+        for c in [Compression::Zstd { level: 3 }, Compression::Xz { preset: 0 }] {
+            let packed = compress_bytes(&input, c).unwrap();
+            let err = decompress(c, 1000, &packed).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", c.name());
+        }
+    }
+
+    #[test]
+    fn invalid_level_is_rejected() {
+        assert!(compress_bytes(b"x", Compression::Zstd { level: 23 }).is_err());
+        assert!(compress_bytes(b"x", Compression::Xz { preset: 10 }).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{generic_array::GenericArray, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::{self, Write};
+use thiserror::Error;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+/// Plaintext bytes per STREAM segment; each segment gains a 16-byte Poly1305 tag.
+const SEGMENT_BYTES: usize = 64 * 1024;
+const TAG_BYTES: usize = 16;
+/// ChaCha20Poly1305 nonce (12) minus the STREAM-BE32 counter and last-block flag (5).
+const NONCE_PREFIX_BYTES: usize = 7;
+const HKDF_INFO: &[u8] = b"sllv x25519 chacha20poly1305 v1";
+
+/// Who can decrypt an encrypted archive. Used on the encode side.
+#[derive(Clone)]
+pub enum EncryptionKey {
+    /// Key derived from a passphrase with Argon2id.
+    Passphrase(String),
+    /// X25519 public key of the recipient.
+    Recipient([u8; 32]),
+}
+
+/// Secret used on the decode side.
+#[derive(Clone)]
+pub enum DecryptionKey {
+    Passphrase(String),
+    /// X25519 secret key matching the recipient public key used at encode time.
+    Identity([u8; 32]),
+}
+
+// Never print key material (RasterParams is Debug and ends up in logs).
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptionKey::Passphrase(_) => f.write_str("Passphrase(<redacted>)"),
+            EncryptionKey::Recipient(pk) => write!(f, "Recipient({})", hex::encode(pk)),
+        }
+    }
+}
+
+impl std::fmt::Debug for DecryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecryptionKey::Passphrase(_) => f.write_str("Passphrase(<redacted>)"),
+            DecryptionKey::Identity(_) => f.write_str("Identity(<redacted>)"),
+        }
+    }
+}
+
+/// How the content key was derived. Never contains the key itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kdf", rename_all = "lowercase")]
+pub enum KeyDerivation {
+    Argon2id {
+        salt_hex: String,
+        m_cost_kib: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    X25519 {
+        ephemeral_public_hex: String,
+        recipient_public_hex: String,
+    },
+}
+
+/// Recorded in the manifest when the payload is encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionInfo {
+    pub cipher: String,
+    #[serde(flatten)]
+    pub kdf: KeyDerivation,
+    /// STREAM nonce prefix; per-segment nonces append a counter and last-segment flag.
+    pub nonce_hex: String,
+    pub segment_bytes: u32,
+}
+
+impl EncryptionInfo {
+    pub const CIPHER: &'static str = "chacha20poly1305-stream-be32";
+}
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("archive is encrypted; a passphrase or identity key is required")]
+    KeyRequired,
+    #[error("wrong key type: archive was encrypted with {0}")]
+    WrongKeyType(&'static str),
+    #[error("decryption failed: wrong passphrase/key or the data was tampered with")]
+    DecryptFailed,
+    #[error("unsupported cipher {0}")]
+    UnsupportedCipher(String),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("kdf: {0}")]
+    Kdf(String),
+    #[error("random: {0}")]
+    Random(String),
+    #[error("encryption failed")]
+    EncryptFailed,
+}
+
+/// Argon2id cost used for new archives (64 MiB, 3 passes).
+const ARGON2_M_COST_KIB: u32 = 64 * 1024;
+const ARGON2_T_COST: u32 = 3;
+const ARGON2_P_COST: u32 = 1;
+
+/// Largest Argon2id costs accepted from a manifest (1 GiB, 10 passes, 16 lanes), so a crafted
+/// archive cannot make decode allocate or grind without limit before the passphrase is checked.
+const ARGON2_MAX_M_COST_KIB: u32 = 1024 * 1024;
+const ARGON2_MAX_T_COST: u32 = 10;
+const ARGON2_MAX_P_COST: u32 = 16;
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], CryptoError> {
+    let mut b = [0u8; N];
+    getrandom::getrandom(&mut b).map_err(|e| CryptoError::Random(e.to_string()))?;
+    Ok(b)
+}
+
+fn argon2_key(passphrase: &str, salt: &[u8], m: u32, t: u32, p: u32) -> Result<Zeroizing<[u8; 32]>, CryptoError> {
+    let params = argon2::Params::new(m, t, p, Some(32)).map_err(|e| CryptoError::Kdf(e.to_string()))?;
+    let argon = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
+fn x25519_key(shared: &[u8; 32], ephemeral_pub: &[u8; 32], recipient_pub: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral_pub);
+    salt[32..].copy_from_slice(recipient_pub);
+    let hk = hkdf::Hkdf::<Sha256>::new(Some(&salt), shared);
+    let mut key = Zeroizing::new([0u8; 32]);
+    // 32 bytes is always a valid HKDF-SHA256 output length.
+    hk.expand(HKDF_INFO, key.as_mut()).expect("hkdf output length");
+    key
+}
+
+/// Public key for an X25519 secret (what recipients hand out).
+pub fn x25519_public_from_secret(secret: &[u8; 32]) -> [u8; 32] {
+    PublicKey::from(&StaticSecret::from(*secret)).to_bytes()
+}
+
+/// Fresh X25519 keypair as `(secret, public)`.
+pub fn generate_x25519_keypair() -> Result<([u8; 32], [u8; 32]), CryptoError> {
+    let secret = random_bytes::<32>()?;
+    let public = x25519_public_from_secret(&secret);
+    Ok((secret, public))
+}
+
+/// Parse a 32-byte key given as 64 hex characters (surrounding whitespace ignored).
+pub fn parse_key_hex(s: &str) -> Result<[u8; 32], CryptoError> {
+    let bytes = hex::decode(s.trim()).map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey("expected 32 bytes (64 hex characters)".into()))
+}
+
+/// Encrypt `plaintext` with ChaCha20-Poly1305 in STREAM mode.
+pub fn encrypt_bytes(plaintext: &[u8], key: &EncryptionKey) -> Result<(Vec<u8>, EncryptionInfo), CryptoError> {
+    let (content_key, kdf) = match key {
+        EncryptionKey::Passphrase(pass) => {
+            let salt = random_bytes::<16>()?;
+            let k = argon2_key(pass, &salt, ARGON2_M_COST_KIB, ARGON2_T_COST, ARGON2_P_COST)?;
+            let kdf = KeyDerivation::Argon2id {
+                salt_hex: hex::encode(salt),
+                m_cost_kib: ARGON2_M_COST_KIB,
+                t_cost: ARGON2_T_COST,
+                p_cost: ARGON2_P_COST,
+            };
+            (k, kdf)
+        }
+        EncryptionKey::Recipient(recipient) => {
+            let eph_secret = StaticSecret::from(random_bytes::<32>()?);
+            let eph_public = PublicKey::from(&eph_secret).to_bytes();
+            let shared = Zeroizing::new(eph_secret.diffie_hellman(&PublicKey::from(*recipient)).to_bytes());
+            let k = x25519_key(&shared, &eph_public, recipient);
+            let kdf = KeyDerivation::X25519 {
+                ephemeral_public_hex: hex::encode(eph_public),
+                recipient_public_hex: hex::encode(recipient),
+            };
+            (k, kdf)
+        }
+    };
+
+    let nonce = random_bytes::<NONCE_PREFIX_BYTES>()?;
+    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(content_key.as_ref()));
+    let mut enc = EncryptorBE32::from_aead(aead, GenericArray::from_slice(&nonce));
+
+    let segments = plaintext.len().div_ceil(SEGMENT_BYTES).max(1);
+    let mut out = Vec::with_capacity(plaintext.len() + segments * TAG_BYTES);
+    let mut chunks = plaintext.chunks(SEGMENT_BYTES).peekable();
+    loop {
+        let chunk = chunks.next().unwrap_or(&[]);
+        if chunks.peek().is_none() {
+            out.extend(enc.encrypt_last(chunk).map_err(|_| CryptoError::EncryptFailed)?);
+            break;
+        }
+        out.extend(enc.encrypt_next(chunk).map_err(|_| CryptoError::EncryptFailed)?);
+    }
+
+    let info = EncryptionInfo {
+        cipher: EncryptionInfo::CIPHER.to_string(),
+        kdf,
+        nonce_hex: hex::encode(nonce),
+        segment_bytes: SEGMENT_BYTES as u32,
+    };
+    Ok((out, info))
+}
+
+fn content_key_for(info: &EncryptionInfo, key: &DecryptionKey) -> Result<Zeroizing<[u8; 32]>, CryptoError> {
+    if info.cipher != EncryptionInfo::CIPHER {
+        return Err(CryptoError::UnsupportedCipher(info.cipher.clone()));
+    }
+    match (&info.kdf, key) {
+        (
+            KeyDerivation::Argon2id {
+                salt_hex,
+                m_cost_kib,
+                t_cost,
+                p_cost,
+            },
+            DecryptionKey::Passphrase(pass),
+        ) => {
+            if *m_cost_kib > ARGON2_MAX_M_COST_KIB || *t_cost > ARGON2_MAX_T_COST || *p_cost > ARGON2_MAX_P_COST {
+                return Err(CryptoError::Kdf(format!(
+                    "argon2id cost m={m_cost_kib} KiB, t={t_cost}, p={p_cost} exceeds the limit \
+                     (m={ARGON2_MAX_M_COST_KIB} KiB, t={ARGON2_MAX_T_COST}, p={ARGON2_MAX_P_COST})"
+                )));
+            }
+            let salt = hex::decode(salt_hex).map_err(|e| CryptoError::Kdf(e.to_string()))?;
+            argon2_key(pass, &salt, *m_cost_kib, *t_cost, *p_cost)
+        }
+        (
+            KeyDerivation::X25519 {
+                ephemeral_public_hex,
+                recipient_public_hex,
+            },
+            DecryptionKey::Identity(secret),
+        ) => {
+            let eph_public = parse_key_hex(ephemeral_public_hex)?;
+            let recipient = parse_key_hex(recipient_public_hex)?;
+            if x25519_public_from_secret(secret) != recipient {
+                return Err(CryptoError::DecryptFailed);
+            }
+            let shared = Zeroizing::new(StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(eph_public)).to_bytes());
+            Ok(x25519_key(&shared, &eph_public, &recipient))
+        }
+        (KeyDerivation::Argon2id { .. }, _) => Err(CryptoError::WrongKeyType("a passphrase")),
+        (KeyDerivation::X25519 { .. }, _) => Err(CryptoError::WrongKeyType("an X25519 recipient key")),
+    }
+}
+
+/// Streaming decrypt stage on the decode path. `Plain` passes bytes through untouched.
+pub enum Decryptor<W: Write> {
+    Plain(W),
+    Stream {
+        dec: DecryptorBE32<ChaCha20Poly1305>,
+        buf: Vec<u8>,
+        segment_ct: usize,
+        out: W,
+    },
+}
+
+impl<W: Write> Decryptor<W> {
+    pub fn new(info: Option<&EncryptionInfo>, key: Option<&DecryptionKey>, out: W) -> Result<Self, CryptoError> {
+        let Some(info) = info else {
+            return Ok(Decryptor::Plain(out));
+        };
+        let key = key.ok_or(CryptoError::KeyRequired)?;
+        let content_key = content_key_for(info, key)?;
+        let nonce = hex::decode(&info.nonce_hex).map_err(|e| CryptoError::Kdf(e.to_string()))?;
+        if nonce.len() != NONCE_PREFIX_BYTES {
+            return Err(CryptoError::Kdf("bad nonce length".into()));
+        }
+        let aead = ChaCha20Poly1305::new(GenericArray::from_slice(content_key.as_ref()));
+        Ok(Decryptor::Stream {
+            dec: DecryptorBE32::from_aead(aead, GenericArray::from_slice(&nonce)),
+            buf: Vec::new(),
+            segment_ct: info.segment_bytes as usize + TAG_BYTES,
+            out,
+        })
+    }
+
+    /// Authenticate and flush the final segment, returning the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Decryptor::Plain(out) => Ok(out),
+            Decryptor::Stream { dec, buf, mut out, .. } => {
+                let pt = dec.decrypt_last(buf.as_slice()).map_err(|_| decrypt_failed())?;
+                out.write_all(&pt)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+fn decrypt_failed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, CryptoError::DecryptFailed)
+}
+
+impl<W: Write> Write for Decryptor<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Decryptor::Plain(out) => out.write(data),
+            Decryptor::Stream {
+                dec,
+                buf,
+                segment_ct,
+                out,
+            } => {
+                buf.extend_from_slice(data);
+                // Keep at least one full segment buffered: only `finish` knows which one is last.
+                while buf.len() > *segment_ct {
+                    let pt = dec.decrypt_next(&buf[..*segment_ct]).map_err(|_| decrypt_failed())?;
+                    out.write_all(&pt)?;
+                    buf.drain(..*segment_ct);
+                }
+                Ok(data.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Decryptor::Plain(out) => out.flush(),
+            Decryptor::Stream { out, .. } => out.flush(),
+        }
+    }
+}
+
+impl CryptoError {
+    /// Recover a [`CryptoError`] that travelled through the `io::Write` pipeline.
+    pub fn from_io(e: io::Error) -> Result<CryptoError, io::Error> {
+        if !e.get_ref().is_some_and(|inner| inner.is::<CryptoError>()) {
+            return Err(e);
+        }
+        match e.into_inner().map(|inner| inner.downcast::<CryptoError>()) {
+            Some(Ok(c)) => Ok(*c),
+            _ => Ok(CryptoError::DecryptFailed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decrypt(ct: &[u8], info: &EncryptionInfo, key: &DecryptionKey) -> Result<Vec<u8>, CryptoError> {
+        let mut d = Decryptor::new(Some(info), Some(key), Vec::new())?;
+        let written = d.write_all(ct).and_then(|()| d.finish());
+        written.map_err(|e| CryptoError::from_io(e).unwrap_or(CryptoError::DecryptFailed))
+    }
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 13 + i / 251) as u8).collect()
+    }
+
+    /// Two full segments and a short last one.
+    fn sealed_for_identity() -> (Vec<u8>, EncryptionInfo, DecryptionKey) {
+        let (secret, public) = generate_x25519_keypair().unwrap();
+        let (ct, info) = encrypt_bytes(&plaintext(2 * SEGMENT_BYTES + 1000), &EncryptionKey::Recipient(public)).unwrap();
+        (ct, info, DecryptionKey::Identity(secret))
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let data = plaintext(SEGMENT_BYTES + 5);
+        let (ct, info) = encrypt_bytes(&data, &EncryptionKey::Passphrase("correct horse".into())).unwrap();
+        assert_eq!(ct.len(), data.len() + 2 * TAG_BYTES);
+        assert_eq!(decrypt(&ct, &info, &DecryptionKey::Passphrase("correct horse".into())).unwrap(), data);
+        assert!(matches!(
+            decrypt(&ct, &info, &DecryptionKey::Passphrase("battery staple".into())),
+            Err(CryptoError::DecryptFailed)
+        ));
+        assert!(matches!(
+            decrypt(&ct, &info, &DecryptionKey::Identity([7; 32])),
+            Err(CryptoError::WrongKeyType(_))
+        ));
+    }
+
+    #[test]
+    fn x25519_round_trip() {
+        let (secret, public) = generate_x25519_keypair().unwrap();
+        assert_eq!(x25519_public_from_secret(&secret), public);
+        assert_eq!(parse_key_hex(&hex::encode(public)).unwrap(), public);
+        for len in [0, 1, SEGMENT_BYTES, 3 * SEGMENT_BYTES + 17] {
+            let data = plaintext(len);
+            let (ct, info) = encrypt_bytes(&data, &EncryptionKey::Recipient(public)).unwrap();
+            assert_eq!(decrypt(&ct, &info, &DecryptionKey::Identity(secret)).unwrap(), data);
+
+            // Same result when the ciphertext arrives in small, uneven writes.
+            let mut d = Decryptor::new(Some(&info), Some(&DecryptionKey::Identity(secret)), Vec::new()).unwrap();
+            for piece in ct.chunks(1000) {
+                d.write_all(piece).unwrap();
+            }
+            assert_eq!(d.finish().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn wrong_or_missing_identity_fails() {
+        let (ct, info, _) = sealed_for_identity();
+        let (other, _) = generate_x25519_keypair().unwrap();
+        assert!(matches!(decrypt(&ct, &info, &DecryptionKey::Identity(other)), Err(CryptoError::DecryptFailed)));
+        assert!(matches!(
+            decrypt(&ct, &info, &DecryptionKey::Passphrase("p".into())),
+            Err(CryptoError::WrongKeyType(_))
+        ));
+        assert!(matches!(Decryptor::new(Some(&info), None, Vec::new()), Err(CryptoError::KeyRequired)));
+        assert!(parse_key_hex("abcd").is_err());
+    }
+
+    #[test]
+    fn truncated_or_reordered_segments_are_rejected() {
+        let (ct, info, key) = sealed_for_identity();
+        let seg = SEGMENT_BYTES + TAG_BYTES;
+        assert_eq!(ct.len(), 2 * seg + 1000 + TAG_BYTES);
+
+        // Cut at the segment boundaries (a non-last segment can't pass as the last one) and
+        // anywhere else.
+        for len in [0, 1, seg, 2 * seg, 2 * seg + 1, ct.len() - 1] {
+            assert!(matches!(decrypt(&ct[..len], &info, &key), Err(CryptoError::DecryptFailed)), "len {len}");
+        }
+
+        let mut swapped = ct[seg..2 * seg].to_vec();
+        swapped.extend_from_slice(&ct[..seg]);
+        swapped.extend_from_slice(&ct[2 * seg..]);
+        assert!(matches!(decrypt(&swapped, &info, &key), Err(CryptoError::DecryptFailed)));
+
+        let mut repeated = ct[..seg].to_vec();
+        repeated.extend_from_slice(&ct);
+        assert!(matches!(decrypt(&repeated, &info, &key), Err(CryptoError::DecryptFailed)));
+
+        let mut dropped = ct[..seg].to_vec();
+        dropped.extend_from_slice(&ct[2 * seg..]);
+        assert!(matches!(decrypt(&dropped, &info, &key), Err(CryptoError::DecryptFailed)));
+    }
+
+    #[test]
+    fn tampered_ciphertext_or_header_is_rejected() {
+        let (ct, info, key) = sealed_for_identity();
+        for pos in [0, SEGMENT_BYTES, SEGMENT_BYTES + TAG_BYTES + 3, ct.len() - 1] {
+            let mut bad = ct.clone();
+            bad[pos] ^= 0x20;
+            assert!(matches!(decrypt(&bad, &info, &key), Err(CryptoError::DecryptFailed)), "pos {pos}");
+        }
+
+        let mut nonce = info.clone();
+        nonce.nonce_hex = hex::encode([0u8; NONCE_PREFIX_BYTES]);
+        assert!(matches!(decrypt(&ct, &nonce, &key), Err(CryptoError::DecryptFailed)));
+        nonce.nonce_hex = "00".into();
+        assert!(matches!(decrypt(&ct, &nonce, &key), Err(CryptoError::Kdf(_))));
+
+        let mut ephemeral = info.clone();
+        if let KeyDerivation::X25519 { ephemeral_public_hex, .. } = &mut ephemeral.kdf {
+            *ephemeral_public_hex = hex::encode(generate_x25519_keypair().unwrap().1);
+        }
+        assert!(matches!(decrypt(&ct, &ephemeral, &key), Err(CryptoError::DecryptFailed)));
+
+        let mut segment = info.clone();
+        segment.segment_bytes /= 2;
+        assert!(matches!(decrypt(&ct, &segment, &key), Err(CryptoError::DecryptFailed)));
+
+        let mut cipher = info;
+        cipher.cipher = "aes-ctr".into();
+        assert!(matches!(decrypt(&ct, &cipher, &key), Err(CryptoError::UnsupportedCipher(_))));
+    }
+
+    #[test]
+    fn excessive_argon2_cost_is_rejected() {
+        let (ct, mut info) = encrypt_bytes(b"data", &EncryptionKey::Passphrase("p".into())).unwrap();
+        if let KeyDerivation::Argon2id { m_cost_kib, .. } = &mut info.kdf {
+            *m_cost_kib = 4 * 1024 * 1024;
+        }
+        assert!(matches!(
+            decrypt(&ct, &info, &DecryptionKey::Passphrase("p".into())),
+            Err(CryptoError::Kdf(_))
+        ));
+    }
+}
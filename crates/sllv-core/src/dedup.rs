@@ -0,0 +1,435 @@
+//! Optional deduplication before compression.
+//!
+//! The packed payload is split with content-defined chunking (FastCDC: a gear rolling hash with
+//! normalized chunk sizes), so identical content produces identical chunks wherever it sits in
+//! the stream. Each distinct chunk is stored once. The result is a container:
+//!
+//! ```text
+//! "SLDD" | chunk refs: u64 | unique chunks: u64 | unique lengths: u32 each | refs: u32 each | unique chunk bytes
+//! ```
+//!
+//! Unique chunks are stored in order of first use, so [`Rededup`] can rebuild the stream while
+//! decoding, keeping in memory only chunks that are referenced again later.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"SLDD";
+const HEADER_BYTES: usize = 4 + 8 + 8;
+
+/// Chunk size targets for content-defined chunking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DedupParams {
+    pub min_chunk: u32,
+    /// Average chunk size; rounded down to a power of two.
+    pub avg_chunk: u32,
+    pub max_chunk: u32,
+}
+
+impl Default for DedupParams {
+    fn default() -> Self {
+        Self::with_average(16 * 1024)
+    }
+}
+
+impl DedupParams {
+    /// `avg / 4` minimum and `avg * 4` maximum, as FastCDC suggests.
+    pub fn with_average(avg: u32) -> Self {
+        let avg = avg.clamp(256, 1 << 26);
+        let avg = if avg.is_power_of_two() { avg } else { avg.next_power_of_two() >> 1 };
+        Self {
+            min_chunk: avg / 4,
+            avg_chunk: avg,
+            max_chunk: avg * 4,
+        }
+    }
+}
+
+/// Recorded in the manifest when the payload was deduplicated.
+///
+/// `stored_bytes` is the container handed to compression; `logical_bytes` and
+/// `logical_sha256_hex` describe the packed payload rebuilt from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DedupInfo {
+    pub chunker: String,
+    #[serde(flatten)]
+    pub params: DedupParams,
+    pub chunks: u64,
+    pub unique_chunks: u64,
+    pub logical_bytes: u64,
+    pub stored_bytes: u64,
+    /// Empty when the payload is also encrypted.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub logical_sha256_hex: String,
+}
+
+impl DedupInfo {
+    pub const CHUNKER: &'static str = "fastcdc";
+
+    /// Logical bytes per stored byte (1.0 = nothing saved).
+    pub fn ratio(&self) -> f64 {
+        self.logical_bytes as f64 / self.stored_bytes.max(1) as f64
+    }
+}
+
+/// Random but fixed gear values; changing them changes every chunk boundary.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x534c_4c56_4443_4443u64;
+    let mut i = 0;
+    while i < 256 {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Mask of the top `bits` bits: those depend on the last 64 bytes hashed.
+fn top_mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits.clamp(1, 63))
+}
+
+/// Length of the next chunk of `data`.
+fn cut_point(data: &[u8], p: &DedupParams) -> usize {
+    let (min, avg, max) = (p.min_chunk as usize, p.avg_chunk as usize, p.max_chunk as usize);
+    if data.len() <= min {
+        return data.len();
+    }
+    let max = max.min(data.len());
+    let normal = avg.min(max);
+    let bits = avg.max(2).ilog2();
+    // Normalized chunking: harder to cut before the average size, easier after it.
+    let (mask_small, mask_large) = (top_mask(bits + 2), top_mask(bits - 2));
+    let mut h = 0u64;
+    for (i, &b) in data.iter().enumerate().take(max).skip(min) {
+        h = (h << 1).wrapping_add(GEAR[b as usize]);
+        let mask = if i < normal { mask_small } else { mask_large };
+        if h & mask == 0 {
+            return i + 1;
+        }
+    }
+    max
+}
+
+/// Deduplicate `input`, returning the container and what to record in the manifest.
+///
+/// `keep_hash: false` leaves out the payload hash (for encrypted archives).
+pub fn dedup_bytes(input: &[u8], p: &DedupParams, keep_hash: bool) -> (Vec<u8>, DedupInfo) {
+    let mut seen: HashMap<[u8; 32], u32> = HashMap::new();
+    let mut unique: Vec<&[u8]> = Vec::new();
+    let mut refs: Vec<u32> = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let len = cut_point(&input[pos..], p);
+        let chunk = &input[pos..pos + len];
+        let id = *seen.entry(Sha256::digest(chunk).into()).or_insert_with(|| {
+            unique.push(chunk);
+            unique.len() as u32 - 1
+        });
+        refs.push(id);
+        pos += len;
+    }
+
+    let data_bytes: usize = unique.iter().map(|c| c.len()).sum();
+    let mut out = Vec::with_capacity(HEADER_BYTES + 4 * (unique.len() + refs.len()) + data_bytes);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(refs.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(unique.len() as u64).to_le_bytes());
+    for c in &unique {
+        out.extend_from_slice(&(c.len() as u32).to_le_bytes());
+    }
+    for r in &refs {
+        out.extend_from_slice(&r.to_le_bytes());
+    }
+    for c in &unique {
+        out.extend_from_slice(c);
+    }
+
+    let info = DedupInfo {
+        chunker: DedupInfo::CHUNKER.to_string(),
+        params: *p,
+        chunks: refs.len() as u64,
+        unique_chunks: unique.len() as u64,
+        logical_bytes: input.len() as u64,
+        stored_bytes: out.len() as u64,
+        logical_sha256_hex: if keep_hash {
+            hex::encode(Sha256::digest(input))
+        } else {
+            String::new()
+        },
+    };
+    (out, info)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("dedup container: {msg}"))
+}
+
+/// Last stage of the decode path: rebuilds a deduplicated payload, or passes bytes through.
+pub enum Rededup<W: Write> {
+    None(W),
+    Active(Box<Rebuilder<W>>),
+}
+
+/// State of an active [`Rededup`].
+pub struct Rebuilder<W: Write> {
+    out: W,
+    info: DedupInfo,
+    /// Input not yet consumed.
+    pending: Vec<u8>,
+    /// Whether the header and index have been read.
+    indexed: bool,
+    /// Lengths of the unique chunks and the ref list.
+    lens: Vec<u32>,
+    refs: Vec<u32>,
+    /// Position in `refs` of each unique chunk's last use.
+    last_use: Vec<usize>,
+    next_ref: usize,
+    next_unique: u32,
+    kept: HashMap<u32, Vec<u8>>,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> Rededup<W> {
+    pub fn new(info: Option<&DedupInfo>, out: W) -> Self {
+        match info {
+            None => Rededup::None(out),
+            Some(info) => Rededup::Active(Box::new(Rebuilder {
+                out,
+                info: info.clone(),
+                pending: Vec::new(),
+                indexed: false,
+                lens: Vec::new(),
+                refs: Vec::new(),
+                last_use: Vec::new(),
+                next_ref: 0,
+                next_unique: 0,
+                kept: HashMap::new(),
+                hasher: Sha256::new(),
+                written: 0,
+            })),
+        }
+    }
+
+    /// Flush and return the writer, plus for an active rebuild the bytes written and whether the
+    /// rebuilt payload matches the manifest (complete, same size and SHA-256).
+    pub fn finish(self) -> io::Result<(W, Option<(u64, bool)>)> {
+        match self {
+            Rededup::None(mut out) => {
+                out.flush()?;
+                Ok((out, None))
+            }
+            Rededup::Active(mut r) => {
+                r.out.flush()?;
+                let done = r.indexed && r.next_ref == r.refs.len() && r.pending.is_empty();
+                let sha_ok = r.info.logical_sha256_hex.is_empty()
+                    || hex::encode(r.hasher.clone().finalize()) == r.info.logical_sha256_hex;
+                let ok = done && sha_ok && r.written == r.info.logical_bytes;
+                Ok((r.out, Some((r.written, ok))))
+            }
+        }
+    }
+}
+
+impl<W: Write> Rebuilder<W> {
+    fn emit(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.written + bytes.len() as u64 > self.info.logical_bytes {
+            return Err(invalid("more bytes than the manifest records"));
+        }
+        self.out.write_all(bytes)?;
+        self.hasher.update(bytes);
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Consume as much of `pending` as possible.
+    fn advance(&mut self) -> io::Result<()> {
+        let mut used = 0;
+        if !self.indexed {
+            // The manifest's counts are untrusted: the index must fit in the stored container.
+            let index_bytes = self
+                .info
+                .chunks
+                .checked_add(self.info.unique_chunks)
+                .and_then(|n| n.checked_mul(4))
+                .and_then(|n| n.checked_add(HEADER_BYTES as u64))
+                .filter(|&n| n <= self.info.stored_bytes)
+                .and_then(|n| usize::try_from(n).ok())
+                .ok_or_else(|| invalid("index larger than the container"))?;
+            let (n_refs, n_unique) = (self.info.chunks as usize, self.info.unique_chunks as usize);
+            if self.pending.len() < index_bytes {
+                return Ok(());
+            }
+            let le64 = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap_or_default());
+            if &self.pending[..4] != MAGIC
+                || le64(&self.pending[4..12]) != n_refs as u64
+                || le64(&self.pending[12..20]) != n_unique as u64
+            {
+                return Err(invalid("header does not match the manifest"));
+            }
+            let words: Vec<u32> = self.pending[HEADER_BYTES..index_bytes]
+                .chunks_exact(4)
+                .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+                .collect();
+            let (lens, refs) = words.split_at(n_unique);
+            if refs.iter().any(|&r| r as usize >= n_unique) {
+                return Err(invalid("chunk ref out of range"));
+            }
+            if lens.iter().map(|&l| u64::from(l)).sum::<u64>() != self.info.stored_bytes - index_bytes as u64 {
+                return Err(invalid("chunk lengths do not add up to the container"));
+            }
+            let mut last_use = vec![0; n_unique];
+            for (i, &r) in refs.iter().enumerate() {
+                last_use[r as usize] = i;
+            }
+            self.lens = lens.to_vec();
+            self.refs = refs.to_vec();
+            self.last_use = last_use;
+            self.indexed = true;
+            used = index_bytes;
+        }
+
+        while self.next_ref < self.refs.len() {
+            let (i, id) = (self.next_ref, self.refs[self.next_ref]);
+            if id < self.next_unique {
+                let chunk = self.kept.get(&id).cloned().ok_or_else(|| invalid("chunk used after its last use"))?;
+                self.emit(&chunk)?;
+                if self.last_use[id as usize] == i {
+                    self.kept.remove(&id);
+                }
+            } else if id == self.next_unique {
+                let len = self.lens[id as usize] as usize;
+                if self.pending.len() - used < len {
+                    break;
+                }
+                let chunk = self.pending[used..used + len].to_vec();
+                used += len;
+                self.emit(&chunk)?;
+                if self.last_use[id as usize] > i {
+                    self.kept.insert(id, chunk);
+                }
+                self.next_unique += 1;
+            } else {
+                return Err(invalid("chunks out of order"));
+            }
+            self.next_ref += 1;
+        }
+        if self.next_ref == self.refs.len() && used < self.pending.len() {
+            return Err(invalid("trailing bytes"));
+        }
+        self.pending.drain(..used);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Rededup<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Rededup::None(out) => out.write(buf),
+            Rededup::Active(r) => {
+                r.pending.extend_from_slice(buf);
+                r.advance()?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Rededup::None(out) => out.flush(),
+            Rededup::Active(r) => r.out.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rebuild `container` in uneven writes, returning the output if it matches `info`.
+    fn rebuild(container: &[u8], info: &DedupInfo) -> io::Result<Vec<u8>> {
+        let mut r = Rededup::new(Some(info), Vec::new());
+        for piece in container.chunks(777) {
+            r.write_all(piece)?;
+        }
+        match r.finish()? {
+            (out, Some((_, true))) => Ok(out),
+            _ => Err(invalid("incomplete")),
+        }
+    }
+
+    fn repetitive(len: usize) -> Vec<u8> {
+        let block: Vec<u8> = (0..5000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        block.iter().cycle().take(len).copied().collect()
+    }
+
+    #[test]
+    fn with_average_clamps_then_rounds_down() {
+        assert_eq!(DedupParams::with_average(0).avg_chunk, 256);
+        assert_eq!(DedupParams::with_average(300).avg_chunk, 256);
+        assert_eq!(DedupParams::with_average(4096).avg_chunk, 4096);
+        assert_eq!(DedupParams::with_average(6000).avg_chunk, 4096);
+        assert_eq!(DedupParams::with_average(u32::MAX).avg_chunk, 1 << 26);
+        let p = DedupParams::with_average(u32::MAX);
+        assert_eq!((p.min_chunk, p.max_chunk), (1 << 24, 1 << 28));
+    }
+
+    #[test]
+    fn round_trip_shrinks_repeated_content() {
+        let p = DedupParams::with_average(1024);
+        for input in [Vec::new(), b"short".to_vec(), repetitive(200_000)] {
+            let (container, info) = dedup_bytes(&input, &p, true);
+            assert_eq!(info.stored_bytes, container.len() as u64);
+            assert_eq!(rebuild(&container, &info).unwrap(), input);
+        }
+        let (container, info) = dedup_bytes(&repetitive(200_000), &p, false);
+        assert!(info.ratio() > 5.0 && info.logical_sha256_hex.is_empty());
+        assert_eq!(rebuild(&container, &info).unwrap(), repetitive(200_000));
+    }
+
+    #[test]
+    fn hostile_manifest_counts_are_rejected() {
+        let (container, info) = dedup_bytes(&repetitive(50_000), &DedupParams::with_average(1024), true);
+        for (chunks, unique) in [(u64::MAX, 1), (1, u64::MAX), (u64::MAX / 4, u64::MAX / 4), (1 << 40, 0)] {
+            let bad = DedupInfo { chunks, unique_chunks: unique, ..info.clone() };
+            assert_eq!(rebuild(&container, &bad).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let bigger = DedupInfo { stored_bytes: info.stored_bytes + 1, ..info.clone() };
+        assert!(rebuild(&container, &bigger).is_err());
+    }
+
+    #[test]
+    fn malformed_containers_are_rejected() {
+        let (container, info) = dedup_bytes(&repetitive(50_000), &DedupParams::with_average(1024), true);
+        let index_end = HEADER_BYTES + 4 * (info.chunks + info.unique_chunks) as usize;
+
+        assert!(rebuild(&container[..container.len() - 1], &info).is_err());
+        let mut trailing = container.clone();
+        trailing.push(0);
+        assert!(rebuild(&trailing, &info).is_err());
+
+        let mut magic = container.clone();
+        magic[0] ^= 1;
+        assert!(rebuild(&magic, &info).is_err());
+
+        let mut bad_ref = container.clone();
+        bad_ref[index_end - 4..index_end].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(rebuild(&bad_ref, &info).is_err());
+
+        let mut bad_len = container.clone();
+        bad_len[HEADER_BYTES..HEADER_BYTES + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(rebuild(&bad_len, &info).is_err());
+
+        let mut data = container;
+        *data.last_mut().unwrap() ^= 1;
+        assert!(rebuild(&data, &info).is_err());
+    }
+}
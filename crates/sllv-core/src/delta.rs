@@ -0,0 +1,295 @@
+//! Delta archives: only what changed since a base archive.
+//!
+//! [`plan_delta`] compares a freshly packed tar with the base's table of contents (its
+//! [`Toc::state`], so the base may itself be a delta) and keeps new or changed members. The
+//! delta's TOC lists the unchanged base entries and the deleted paths as well, and the manifest
+//! records the base's state digest. [`restore_to_dir`] replays a base plus a chain of deltas.
+
+use crate::extract::{extract_tar_bytes_to_dir, ExtractError, ExtractOptions, ExtractReport};
+use crate::manifest::PayloadKind;
+use crate::raster::{decode_frames_dir_to_writer, read_manifest, RasterError, RasterParams};
+use crate::toc::{read_toc_with_manifest, Toc, TocEntry, TocEntryKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use tar::Archive;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DeltaError {
+    #[error("delta archives need a tar payload")]
+    NotTar,
+    #[error("{0} is a delta archive; a restore chain starts with a full archive")]
+    StartsWithDelta(PathBuf),
+    #[error("{0} is not a delta archive")]
+    NotADelta(PathBuf),
+    #[error("{0} was made against a different base than the archive before it in the chain")]
+    ChainBroken(PathBuf),
+    #[error("{0} is in the final state but in none of the archives")]
+    MissingEntry(String),
+    #[error("extract: {0}")]
+    Extract(#[from] ExtractError),
+}
+
+/// Summary of a delta archive, recorded in its manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaInfo {
+    /// [`Toc::state_sha256_hex`] of the base this delta applies to.
+    pub base_state_sha256_hex: String,
+    pub added: u64,
+    pub changed: u64,
+    pub deleted: u64,
+    pub unchanged: u64,
+    /// Bytes of regular files left out because the base already has them.
+    pub unchanged_bytes: u64,
+}
+
+/// What goes into a delta's table of contents besides its own members (encode side).
+#[derive(Debug, Clone)]
+pub struct DeltaPlan {
+    pub info: DeltaInfo,
+    pub unchanged: Vec<TocEntry>,
+    pub deleted: Vec<String>,
+}
+
+/// Whether `new` can be left out of a delta because the base has the same thing.
+///
+/// Contents, link targets and permissions count; mtimes don't, so re-touched files stay out.
+fn same_entry(base: &TocEntry, new: &TocEntry) -> bool {
+    base.kind == new.kind
+        && base.mode == new.mode
+        && match new.kind {
+            TocEntryKind::File => base.size == new.size && base.sha256_hex == new.sha256_hex,
+            TocEntryKind::Dir => true,
+            TocEntryKind::Symlink | TocEntryKind::Hardlink => base.link_target == new.link_target,
+            TocEntryKind::Other => false,
+        }
+}
+
+/// Reduce `tar_bytes` (a full pack of the current input) to the members that are new or
+/// changed compared to `base`, and list what stayed the same and what was deleted.
+pub fn plan_delta(tar_bytes: &[u8], base: &Toc) -> io::Result<(Vec<u8>, DeltaPlan)> {
+    let current = Toc::from_tar(tar_bytes)?;
+    let base_state = base.state();
+    let by_path: HashMap<&str, &TocEntry> = base_state.iter().map(|e| (e.path.as_str(), *e)).collect();
+
+    let mut keep = HashSet::new();
+    let mut unchanged = Vec::new();
+    let (mut added, mut changed, mut unchanged_bytes) = (0, 0, 0);
+    for e in &current.entries {
+        match by_path.get(e.path.as_str()) {
+            Some(b) if same_entry(b, e) => {
+                if b.kind == TocEntryKind::File {
+                    unchanged_bytes += b.size;
+                }
+                unchanged.push((*b).clone());
+            }
+            Some(_) => {
+                changed += 1;
+                keep.insert(e.path.as_str());
+            }
+            None => {
+                added += 1;
+                keep.insert(e.path.as_str());
+            }
+        }
+    }
+    let present: HashSet<&str> = current.entries.iter().map(|e| e.path.as_str()).collect();
+    let deleted: Vec<String> = base_state
+        .iter()
+        .filter(|e| !present.contains(e.path.as_str()))
+        .map(|e| e.path.clone())
+        .collect();
+
+    let tar = filter_tar(tar_bytes, |path| keep.contains(path))?;
+    let info = DeltaInfo {
+        base_state_sha256_hex: base.state_sha256_hex(),
+        added,
+        changed,
+        deleted: deleted.len() as u64,
+        unchanged: unchanged.len() as u64,
+        unchanged_bytes,
+    };
+    Ok((
+        tar,
+        DeltaPlan {
+            info,
+            unchanged,
+            deleted,
+        },
+    ))
+}
+
+/// Copy the members of `tar_bytes` whose path `keep` accepts into a new tar, byte for byte,
+/// extension headers (long names, PAX attributes) included.
+pub(crate) fn filter_tar(tar_bytes: &[u8], keep: impl Fn(&str) -> bool) -> io::Result<Vec<u8>> {
+    let mut kept = HashSet::new();
+    for entry in Archive::new(tar_bytes).entries()? {
+        let entry = entry?;
+        if keep(&entry.path()?.to_string_lossy()) {
+            kept.insert(entry.raw_header_position());
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut span_start = None;
+    for entry in Archive::new(tar_bytes).entries()?.raw(true) {
+        let entry = entry?;
+        let start = *span_start.get_or_insert(entry.raw_header_position());
+        // The size comes from the header: a hostile one must not overflow the span.
+        let end = entry.size().div_ceil(512).checked_mul(512).and_then(|n| n.checked_add(entry.raw_file_position()));
+        let t = entry.header().entry_type();
+        // Extension headers belong to the member that follows them.
+        if t.is_pax_local_extensions() || t.is_gnu_longname() || t.is_gnu_longlink() {
+            continue;
+        }
+        span_start = None;
+        if t.is_pax_global_extensions() || kept.contains(&entry.raw_header_position()) {
+            let span = end
+                .and_then(|end| tar_bytes.get(usize::try_from(start).ok()?..usize::try_from(end).ok()?))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "tar entry runs past the end"))?;
+            out.extend_from_slice(span);
+        }
+    }
+    out.extend_from_slice(&[0u8; 1024]);
+    Ok(out)
+}
+
+/// Rebuild the latest state from `dirs`: a full archive followed by deltas, each made against the
+/// one before it. Every archive needs a table of contents and the same key (`p.decryption`).
+///
+/// Each path is written once, from the newest archive that holds it; deleted paths are never
+/// written. Archives are decoded and verified one at a time.
+pub fn restore_to_dir(dirs: &[PathBuf], p: &RasterParams, out_dir: &Path, opts: &ExtractOptions) -> Result<ExtractReport, RasterError> {
+    let mut tocs: Vec<Toc> = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        let m = read_manifest(dir)?;
+        if m.payload != PayloadKind::Tar {
+            return Err(DeltaError::NotTar.into());
+        }
+        match (&m.delta, tocs.last()) {
+            (Some(_), None) => return Err(DeltaError::StartsWithDelta(dir.clone()).into()),
+            (None, Some(_)) => return Err(DeltaError::NotADelta(dir.clone()).into()),
+            (Some(d), Some(base)) if d.base_state_sha256_hex != base.state_sha256_hex() => {
+                return Err(DeltaError::ChainBroken(dir.clone()).into())
+            }
+            _ => {}
+        }
+        tocs.push(read_toc_with_manifest(dir, &m, p)?);
+    }
+    let Some(last) = tocs.last() else {
+        return Ok(ExtractReport::default());
+    };
+
+    // Newest archive holding each path of the final state.
+    let held: Vec<HashSet<&str>> = tocs.iter().map(|t| t.entries.iter().map(|e| e.path.as_str()).collect()).collect();
+    let mut source: HashMap<&str, usize> = HashMap::new();
+    for e in last.state() {
+        let i = held
+            .iter()
+            .rposition(|paths| paths.contains(e.path.as_str()))
+            .ok_or_else(|| DeltaError::MissingEntry(e.path.clone()))?;
+        source.insert(e.path.as_str(), i);
+    }
+
+    let mut report = ExtractReport::default();
+    for (i, dir) in dirs.iter().enumerate() {
+        if !source.values().any(|&s| s == i) {
+            continue;
+        }
+        let mut tar = Vec::new();
+        decode_frames_dir_to_writer(dir, p, &mut tar)?;
+        let tar = filter_tar(&tar, |path| source.get(path) == Some(&i))?;
+        let part = extract_tar_bytes_to_dir(&tar, out_dir, opts).map_err(DeltaError::from)?;
+        report.entries.extend(part.entries);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use crate::raster::encode_bytes_to_frames_dir;
+    use crate::toc::read_toc;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_delta_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn small_params() -> RasterParams {
+        RasterParams { grid_w: 64, grid_h: 64, sync_frames: 1, ..Profile::Archive.defaults() }
+    }
+
+    fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut b = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut h = tar::Header::new_gnu();
+            h.set_size(data.len() as u64);
+            h.set_mode(0o644);
+            b.append_data(&mut h, name, *data).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    fn member_paths(tar_bytes: &[u8]) -> Vec<String> {
+        Toc::from_tar(tar_bytes).unwrap().entries.into_iter().map(|e| e.path).collect()
+    }
+
+    #[test]
+    fn plan_keeps_only_new_and_changed_members() {
+        let base = Toc::from_tar(&tar_of(&[("a", b"same"), ("b", b"old"), ("c", b"gone")])).unwrap();
+        let (tar, plan) = plan_delta(&tar_of(&[("a", b"same"), ("b", b"new"), ("d", b"added")]), &base).unwrap();
+        assert_eq!(member_paths(&tar), ["b", "d"]);
+        let d = &plan.info;
+        assert_eq!((d.added, d.changed, d.deleted, d.unchanged, d.unchanged_bytes), (1, 1, 1, 1, 4));
+        assert_eq!(plan.deleted, ["c"]);
+        assert_eq!(d.base_state_sha256_hex, base.state_sha256_hex());
+    }
+
+    #[test]
+    fn filter_tar_keeps_long_names_and_rejects_damage() {
+        let long = format!("{}/file.txt", "d".repeat(150));
+        let tar = tar_of(&[("short", b"1"), (&long, b"2")]);
+        assert_eq!(member_paths(&filter_tar(&tar, |p| p != "short").unwrap()), [long]);
+        assert_eq!(member_paths(&filter_tar(&tar, |_| false).unwrap()), Vec::<String>::new());
+
+        assert!(filter_tar(&tar[..700], |_| true).is_err());
+        let mut huge = tar_of(&[("x", b"1")]);
+        let mut h = tar::Header::new_gnu();
+        h.set_size(u64::MAX);
+        h.set_path("x").unwrap();
+        h.set_cksum();
+        huge[..512].copy_from_slice(h.as_bytes());
+        assert!(filter_tar(&huge, |_| true).is_err());
+    }
+
+    #[test]
+    fn chain_restores_the_latest_state() {
+        let dir = scratch_dir("chain");
+        let (full, delta, out) = (dir.join("full"), dir.join("delta"), dir.join("out"));
+        let p = small_params();
+        encode_bytes_to_frames_dir(&tar_of(&[("r/a", b"same"), ("r/b", b"old"), ("r/c", b"gone")]), "r", &full, &p).unwrap();
+        let base = read_toc(&full, &p).unwrap();
+        let (tar, plan) = plan_delta(&tar_of(&[("r/a", b"same"), ("r/b", b"new"), ("r/d", b"added")]), &base).unwrap();
+        let dp = RasterParams { delta: Some(plan), ..small_params() };
+        encode_bytes_to_frames_dir(&tar, "r", &delta, &dp).unwrap();
+
+        restore_to_dir(&[full.clone(), delta.clone()], &p, &out, &ExtractOptions::default()).unwrap();
+        let read = |name: &str| std::fs::read(out.join("r").join(name)).ok();
+        assert_eq!(read("a").as_deref(), Some(&b"same"[..]));
+        assert_eq!(read("b").as_deref(), Some(&b"new"[..]));
+        assert_eq!(read("d").as_deref(), Some(&b"added"[..]));
+        assert_eq!(read("c"), None);
+
+        let err = |dirs: &[PathBuf]| restore_to_dir(dirs, &p, &dir.join("bad"), &ExtractOptions::default()).unwrap_err();
+        assert!(matches!(err(std::slice::from_ref(&delta)), RasterError::Delta(DeltaError::StartsWithDelta(_))));
+        assert!(matches!(err(&[full.clone(), full.clone()]), RasterError::Delta(DeltaError::NotADelta(_))));
+        assert!(matches!(err(&[full, delta.clone(), delta]), RasterError::Delta(DeltaError::ChainBroken(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
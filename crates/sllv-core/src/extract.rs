@@ -0,0 +1,594 @@
+use crate::manifest::{EncodeManifest, PayloadKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("unsafe path in archive: {0}")]
+    UnsafePath(String),
+    #[error("symlink {path} points outside the output directory ({target})")]
+    SymlinkEscape { path: String, target: String },
+}
+
+/// What to do when an extracted file already exists in the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    /// Leave the existing file alone.
+    #[default]
+    Skip,
+    Overwrite,
+    /// Write next to it as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+}
+
+impl CollisionPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Skip => "skip",
+            CollisionPolicy::Overwrite => "overwrite",
+            CollisionPolicy::Rename => "rename",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub on_collision: CollisionPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtractAction {
+    Written,
+    Overwritten,
+    /// Written under a new name because the original already existed.
+    Renamed,
+    /// Already existed and was left alone.
+    Skipped,
+    /// Entry type this platform/extractor doesn't create (devices, fifos, symlinks on Windows).
+    Unsupported,
+}
+
+/// One line of the extraction report.
+#[derive(Debug, Clone)]
+pub struct ExtractedEntry {
+    /// Path as stored in the archive.
+    pub archive_path: PathBuf,
+    /// Where it ended up on disk.
+    pub dest: PathBuf,
+    pub bytes: u64,
+    pub action: ExtractAction,
+}
+
+impl std::fmt::Display for ExtractedEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self.archive_path.display();
+        match self.action {
+            ExtractAction::Written => write!(f, "wrote       {path} ({} bytes)", self.bytes),
+            ExtractAction::Overwritten => write!(f, "overwrote   {path} ({} bytes)", self.bytes),
+            ExtractAction::Renamed => write!(f, "renamed     {path} -> {} ({} bytes)", self.dest.display(), self.bytes),
+            ExtractAction::Skipped => write!(f, "skipped     {path} (already exists)"),
+            ExtractAction::Unsupported => write!(f, "unsupported {path} (entry type not extracted)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtractReport {
+    pub entries: Vec<ExtractedEntry>,
+}
+
+impl ExtractReport {
+    pub fn files_written(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.action, ExtractAction::Written | ExtractAction::Overwritten | ExtractAction::Renamed))
+            .count()
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter(|e| e.action != ExtractAction::Skipped)
+            .map(|e| e.bytes)
+            .sum()
+    }
+}
+
+/// Unpack a tar archive (as produced by `pack_path_to_tar_bytes`) into `out_dir`.
+///
+/// Every entry is checked before anything is written: absolute paths, `..` components and
+/// link targets that leave `out_dir` or pass through another symlink in the archive fail the
+/// whole extraction. While writing, existing symlinks on the way to a destination (or a hard
+/// link source) are never followed, and symlinks themselves are only created once every file
+/// is in place.
+pub fn extract_tar_bytes_to_dir(tar_bytes: &[u8], out_dir: &Path, opts: &ExtractOptions) -> Result<ExtractReport, ExtractError> {
+    extract_tar_to_dir(io::Cursor::new(tar_bytes), out_dir, opts)
+}
+
+/// Like [`extract_tar_bytes_to_dir`], reading the archive from `tar` (twice: once to validate,
+/// once to extract) instead of holding it in memory.
+pub fn extract_tar_to_dir<R: Read + Seek>(mut tar: R, out_dir: &Path, opts: &ExtractOptions) -> Result<ExtractReport, ExtractError> {
+    // Pass 1: validate.
+    let mut links: Vec<(PathBuf, PathBuf, bool)> = Vec::new();
+    let mut symlinks: HashSet<PathBuf> = HashSet::new();
+    for entry in Archive::new(&mut tar).entries()? {
+        let entry = entry?;
+        let rel = safe_relative_path(&entry.path()?)?;
+        let kind = entry.header().entry_type();
+        if kind == EntryType::Symlink || kind == EntryType::Link {
+            let target = entry
+                .link_name()?
+                .ok_or_else(|| ExtractError::UnsafePath(format!("{} has no link target", rel.display())))?;
+            if kind == EntryType::Symlink {
+                symlinks.insert(rel.clone());
+            }
+            links.push((rel, target.into_owned(), kind == EntryType::Link));
+        }
+    }
+    for (rel, target, hard) in &links {
+        check_link_target(rel, target, *hard, &symlinks)?;
+    }
+
+    // Pass 2: extract.
+    tar.seek(SeekFrom::Start(0))?;
+    fs::create_dir_all(out_dir)?;
+    let mut report = ExtractReport::default();
+    // Directory metadata is applied last so read-only modes don't block their contents.
+    let mut dir_meta: Vec<(PathBuf, u32, u64)> = Vec::new();
+    // Symlinks are created after everything else so nothing in the archive is written through them.
+    let mut pending_symlinks: Vec<(PathBuf, PathBuf, PathBuf)> = Vec::new();
+    for entry in Archive::new(&mut tar).entries()? {
+        let mut entry = entry?;
+        let rel = safe_relative_path(&entry.path()?)?;
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        let dest = out_dir.join(&rel);
+        ensure_parent_dirs(out_dir, &rel)?;
+
+        let record = |dest: PathBuf, bytes, action| ExtractedEntry {
+            archive_path: rel.clone(),
+            dest,
+            bytes,
+            action,
+        };
+
+        match kind {
+            EntryType::Directory => {
+                if fs::symlink_metadata(&dest).is_ok_and(|m| !m.is_dir()) {
+                    return Err(ExtractError::UnsafePath(format!("{} exists and is not a directory", rel.display())));
+                }
+                fs::create_dir_all(&dest)?;
+                let h = entry.header();
+                dir_meta.push((dest, h.mode().unwrap_or(0o755), h.mtime().unwrap_or(0)));
+            }
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                let Some((dest, action)) = resolve_collision(&dest, opts.on_collision)? else {
+                    report.entries.push(record(dest, entry.size(), ExtractAction::Skipped));
+                    continue;
+                };
+                let mut f = fs::OpenOptions::new().write(true).create_new(true).open(&dest)?;
+                let bytes = io::copy(&mut entry, &mut f)?;
+                let h = entry.header();
+                apply_metadata(&f, h.mode().unwrap_or(0o644), h.mtime().unwrap_or(0))?;
+                report.entries.push(record(dest, bytes, action));
+            }
+            EntryType::Symlink => {
+                let target = entry.link_name()?.map(|t| t.into_owned()).unwrap_or_default();
+                pending_symlinks.push((rel.clone(), dest, target));
+            }
+            EntryType::Link => {
+                let target = entry.link_name()?.map(|t| t.into_owned()).unwrap_or_default();
+                let source = hard_link_source(out_dir, &target)?;
+                let Some((dest, action)) = resolve_collision(&dest, opts.on_collision)? else {
+                    report.entries.push(record(dest, 0, ExtractAction::Skipped));
+                    continue;
+                };
+                fs::hard_link(&source, &dest)?;
+                let bytes = fs::metadata(&dest)?.len();
+                report.entries.push(record(dest, bytes, action));
+            }
+            _ => report.entries.push(record(dest, 0, ExtractAction::Unsupported)),
+        }
+    }
+    for (rel, dest, target) in pending_symlinks {
+        // Re-checked here: a file or directory written since pass 2 began may now sit on the path.
+        ensure_parent_dirs(out_dir, &rel)?;
+        let record = |dest: PathBuf, action| ExtractedEntry { archive_path: rel.clone(), dest, bytes: 0, action };
+        let Some((dest, action)) = resolve_collision(&dest, opts.on_collision)? else {
+            report.entries.push(record(dest, ExtractAction::Skipped));
+            continue;
+        };
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&target, &dest)?;
+            report.entries.push(record(dest, action));
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (target, action);
+            report.entries.push(record(dest, ExtractAction::Unsupported));
+        }
+    }
+    for (dir, mode, mtime) in dir_meta.into_iter().rev() {
+        apply_metadata(&fs::File::open(&dir)?, mode, mtime)?;
+    }
+    Ok(report)
+}
+
+/// Extract a decoded payload into `out_dir` the way `m` says it was packed: a tar is unpacked with
+/// [`extract_tar_bytes_to_dir`], a raw payload is written as one file named after the manifest.
+pub fn extract_payload_bytes_to_dir(
+    bytes: &[u8],
+    m: &EncodeManifest,
+    out_dir: &Path,
+    opts: &ExtractOptions,
+) -> Result<ExtractReport, ExtractError> {
+    extract_payload_to_dir(io::Cursor::new(bytes), m, out_dir, opts)
+}
+
+/// Like [`extract_payload_bytes_to_dir`], reading the payload from `payload`.
+pub fn extract_payload_to_dir<R: Read + Seek>(
+    payload: R,
+    m: &EncodeManifest,
+    out_dir: &Path,
+    opts: &ExtractOptions,
+) -> Result<ExtractReport, ExtractError> {
+    match m.payload {
+        PayloadKind::Tar => extract_tar_to_dir(payload, out_dir, opts),
+        PayloadKind::Raw => extract_raw_to_dir(payload, &m.file_name, out_dir, opts),
+    }
+}
+
+/// Write a raw (non-tar) payload into `out_dir` as a single file called `name`.
+///
+/// `name` comes from the manifest, so it gets the same path checks as a tar entry.
+pub fn extract_raw_bytes_to_dir(
+    bytes: &[u8],
+    name: &str,
+    out_dir: &Path,
+    opts: &ExtractOptions,
+) -> Result<ExtractReport, ExtractError> {
+    extract_raw_to_dir(io::Cursor::new(bytes), name, out_dir, opts)
+}
+
+/// Like [`extract_raw_bytes_to_dir`], copying the file from `raw`.
+pub fn extract_raw_to_dir<R: Read + Seek>(
+    mut raw: R,
+    name: &str,
+    out_dir: &Path,
+    opts: &ExtractOptions,
+) -> Result<ExtractReport, ExtractError> {
+    let rel = safe_relative_path(Path::new(name))?;
+    if rel.as_os_str().is_empty() {
+        return Err(ExtractError::UnsafePath(name.to_string()));
+    }
+    fs::create_dir_all(out_dir)?;
+    ensure_parent_dirs(out_dir, &rel)?;
+    let dest = out_dir.join(&rel);
+    let mut report = ExtractReport::default();
+    let entry = match resolve_collision(&dest, opts.on_collision)? {
+        Some((dest, action)) => {
+            let mut f = fs::OpenOptions::new().write(true).create_new(true).open(&dest)?;
+            let bytes = io::copy(&mut raw, &mut f)?;
+            ExtractedEntry { archive_path: rel, dest, bytes, action }
+        }
+        None => {
+            let bytes = raw.seek(SeekFrom::End(0))?;
+            ExtractedEntry { archive_path: rel, dest, bytes, action: ExtractAction::Skipped }
+        }
+    };
+    report.entries.push(entry);
+    Ok(report)
+}
+
+/// Restore permission bits (never setuid/setgid/sticky) and mtime.
+fn apply_metadata(f: &fs::File, mode: u32, mtime: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        f.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    f.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+}
+
+/// Strip `.` components and reject anything that could land outside the output directory.
+fn safe_relative_path(p: &Path) -> Result<PathBuf, ExtractError> {
+    let mut out = PathBuf::new();
+    for c in p.components() {
+        match c {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(ExtractError::UnsafePath(p.display().to_string()));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Symlink targets are relative to the link's directory; hard link targets to the archive root.
+///
+/// `..` is only meaningful textually if nothing before it is a symlink, so a target that walks
+/// through any symlink from the same archive is refused outright.
+fn check_link_target(rel: &Path, target: &Path, hard: bool, symlinks: &HashSet<PathBuf>) -> Result<(), ExtractError> {
+    let escape = || ExtractError::SymlinkEscape {
+        path: rel.display().to_string(),
+        target: target.display().to_string(),
+    };
+    let mut depth: Vec<&std::ffi::OsStr> = Vec::new();
+    if !hard {
+        if let Some(parent) = rel.parent() {
+            depth.extend(parent.components().map(|c| c.as_os_str()));
+        }
+    }
+    for c in target.components() {
+        match c {
+            Component::Normal(part) => {
+                depth.push(part);
+                if symlinks.contains(&depth.iter().collect::<PathBuf>()) {
+                    return Err(escape());
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth.pop().is_none() {
+                    return Err(escape());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(escape()),
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a hard link target inside `out_dir`, refusing any path that goes through a symlink
+/// or ends at something other than a regular file.
+fn hard_link_source(out_dir: &Path, target: &Path) -> Result<PathBuf, ExtractError> {
+    let rel = safe_relative_path(target)?;
+    let mut cur = out_dir.to_path_buf();
+    for c in rel.components() {
+        cur.push(c);
+        let m = fs::symlink_metadata(&cur)?;
+        if m.file_type().is_symlink() {
+            return Err(ExtractError::UnsafePath(format!(
+                "hard link source {} goes through symlink {}",
+                target.display(),
+                cur.display()
+            )));
+        }
+    }
+    if !fs::symlink_metadata(&cur)?.is_file() {
+        return Err(ExtractError::UnsafePath(format!("hard link source {} is not a regular file", target.display())));
+    }
+    Ok(cur)
+}
+
+/// Create the directories leading to `rel`, refusing to walk through an existing symlink.
+fn ensure_parent_dirs(out_dir: &Path, rel: &Path) -> Result<(), ExtractError> {
+    let mut cur = out_dir.to_path_buf();
+    let Some(parent) = rel.parent() else {
+        return Ok(());
+    };
+    for c in parent.components() {
+        cur.push(c);
+        match fs::symlink_metadata(&cur) {
+            Ok(m) if m.file_type().is_symlink() => {
+                return Err(ExtractError::UnsafePath(format!(
+                    "{} goes through symlink {}",
+                    rel.display(),
+                    cur.display()
+                )));
+            }
+            Ok(m) if m.is_dir() => {}
+            Ok(_) => {
+                return Err(ExtractError::UnsafePath(format!("{} exists and is not a directory", cur.display())));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => fs::create_dir(&cur)?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Returns where to write (and how to report it), or `None` to skip.
+fn resolve_collision(dest: &Path, policy: CollisionPolicy) -> Result<Option<(PathBuf, ExtractAction)>, ExtractError> {
+    let exists = match fs::symlink_metadata(dest) {
+        Ok(_) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(e.into()),
+    };
+    if !exists {
+        return Ok(Some((dest.to_path_buf(), ExtractAction::Written)));
+    }
+    match policy {
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::Overwrite => {
+            let m = fs::symlink_metadata(dest)?;
+            if m.is_dir() {
+                return Err(ExtractError::UnsafePath(format!("{} exists and is a directory", dest.display())));
+            }
+            // Removing (rather than truncating) means an existing symlink is replaced, not followed.
+            fs::remove_file(dest)?;
+            Ok(Some((dest.to_path_buf(), ExtractAction::Overwritten)))
+        }
+        CollisionPolicy::Rename => {
+            let stem = dest.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            let ext = dest.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+            for n in 1u32.. {
+                let candidate = dest.with_file_name(format!("{stem} ({n}){ext}"));
+                if fs::symlink_metadata(&candidate).is_err() {
+                    return Ok(Some((candidate, ExtractAction::Renamed)));
+                }
+            }
+            unreachable!("u32 range exhausted")
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_extract_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    enum Item<'a> {
+        Dir(&'a str),
+        File(&'a str, &'a [u8]),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    fn tar_of(items: &[Item]) -> Vec<u8> {
+        let mut b = tar::Builder::new(Vec::new());
+        for item in items {
+            let mut h = tar::Header::new_gnu();
+            h.set_mode(0o755);
+            let (path, data): (&str, &[u8]) = match *item {
+                Item::Dir(p) => {
+                    h.set_entry_type(EntryType::Directory);
+                    (p, &[])
+                }
+                Item::File(p, data) => {
+                    h.set_entry_type(EntryType::Regular);
+                    (p, data)
+                }
+                Item::Symlink(p, target) | Item::HardLink(p, target) => {
+                    let kind = if matches!(item, Item::Symlink(..)) { EntryType::Symlink } else { EntryType::Link };
+                    h.set_entry_type(kind);
+                    h.set_link_name_literal(target).unwrap();
+                    (p, &[])
+                }
+            };
+            h.set_size(data.len() as u64);
+            b.append_data(&mut h, path, data).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_in_tree_links() {
+        let dir = scratch_dir("roundtrip");
+        let out = dir.join("out");
+        let tar = tar_of(&[
+            Item::Dir("sub"),
+            Item::File("sub/a.txt", b"hello"),
+            Item::Symlink("link", "sub/a.txt"),
+            Item::HardLink("hard", "sub/a.txt"),
+        ]);
+        let report = extract_tar_bytes_to_dir(&tar, &out, &ExtractOptions::default()).unwrap();
+        assert_eq!(fs::read(out.join("sub/a.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read_link(out.join("link")).unwrap(), Path::new("sub/a.txt"));
+        assert_eq!(fs::read(out.join("hard")).unwrap(), b"hello");
+        assert_eq!(report.files_written(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parent_dir_entry_is_rejected() {
+        let dir = scratch_dir("dotdot");
+        let mut h = tar::Header::new_gnu();
+        h.set_entry_type(EntryType::Regular);
+        h.set_size(1);
+        h.as_old_mut().name[..10].copy_from_slice(b"../escaped");
+        h.set_cksum();
+        let mut b = tar::Builder::new(Vec::new());
+        b.append(&h, &b"x"[..]).unwrap();
+        let tar = b.into_inner().unwrap();
+        assert!(matches!(
+            extract_tar_bytes_to_dir(&tar, &dir.join("out"), &ExtractOptions::default()),
+            Err(ExtractError::UnsafePath(_))
+        ));
+        assert!(!dir.join("escaped").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chained_parent_symlink_is_rejected() {
+        let dir = scratch_dir("chain");
+        let out = dir.join("out");
+        // Textually `x/l1/..` is the archive root, but x/l1 itself already points at the root.
+        let tar = tar_of(&[Item::Dir("x"), Item::Symlink("x/l1", ".."), Item::Symlink("l2", "x/l1/..")]);
+        assert!(matches!(
+            extract_tar_bytes_to_dir(&tar, &out, &ExtractOptions::default()),
+            Err(ExtractError::SymlinkEscape { .. })
+        ));
+        assert!(!out.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_is_not_written_through_archive_symlink() {
+        let dir = scratch_dir("through");
+        let out = dir.join("out");
+        let tar = tar_of(&[Item::Dir("sub"), Item::Symlink("d", "sub"), Item::File("d/evil", b"x")]);
+        let report = extract_tar_bytes_to_dir(&tar, &out, &ExtractOptions::default()).unwrap();
+        assert!(!out.join("sub/evil").exists());
+        assert!(fs::symlink_metadata(out.join("d")).unwrap().is_dir());
+        assert!(report.entries.iter().any(|e| e.archive_path == Path::new("d") && e.action == ExtractAction::Skipped));
+
+        let escaping = tar_of(&[Item::Symlink("e", "../outside"), Item::File("e/evil", b"x")]);
+        assert!(extract_tar_bytes_to_dir(&escaping, &out, &ExtractOptions::default()).is_err());
+        assert!(!dir.join("outside").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hard_link_through_symlink_is_rejected() {
+        let dir = scratch_dir("hardlink");
+        let out = dir.join("out");
+        let outside = dir.join("outside");
+        fs::create_dir_all(&out).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink("../outside", out.join("esc")).unwrap();
+
+        let tar = tar_of(&[Item::HardLink("h", "esc/secret")]);
+        assert!(matches!(
+            extract_tar_bytes_to_dir(&tar, &out, &ExtractOptions::default()),
+            Err(ExtractError::UnsafePath(_))
+        ));
+        assert!(!out.join("h").exists());
+
+        let tar = tar_of(&[Item::Dir("sub"), Item::File("sub/f", b"f"), Item::Symlink("s", "sub"), Item::HardLink("h", "s/f")]);
+        assert!(matches!(
+            extract_tar_bytes_to_dir(&tar, &out, &ExtractOptions::default()),
+            Err(ExtractError::SymlinkEscape { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn payload_is_extracted_by_its_manifest_kind() {
+        let dir = scratch_dir("payload_kind");
+        let tar = tar_of(&[Item::File("a.txt", b"hello")]);
+
+        let m = EncodeManifest { file_name: "data.bin".into(), payload: PayloadKind::Raw, ..Default::default() };
+        let report = extract_payload_bytes_to_dir(&tar, &m, &dir.join("raw"), &ExtractOptions::default()).unwrap();
+        assert_eq!(fs::read(dir.join("raw/data.bin")).unwrap(), tar);
+        assert!(!dir.join("raw/a.txt").exists());
+        assert_eq!(report.files_written(), 1);
+
+        let m = EncodeManifest { payload: PayloadKind::Tar, ..m };
+        extract_payload_bytes_to_dir(&tar, &m, &dir.join("tar"), &ExtractOptions::default()).unwrap();
+        assert_eq!(fs::read(dir.join("tar/a.txt")).unwrap(), b"hello");
+        assert!(!dir.join("tar/data.bin").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
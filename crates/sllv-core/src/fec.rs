@@ -22,6 +22,8 @@ impl Default for FecParams {
 pub enum FecError {
     #[error("invalid params")]
     InvalidParams,
+    #[error("group {stuck} is still missing data shards while group {group} arrived ({window} groups ahead at most)")]
+    WindowExceeded { stuck: u32, group: u32, window: u32 },
 }
 
 #[derive(Debug, Clone)]
@@ -112,10 +114,8 @@ pub fn fec_decode_collect(packets: Vec<ShardPacket>, total_bytes: usize, p: &Fec
 
     // Fallback decode: concatenate data shards in order.
     for (_g, shards) in by_group {
-        for i in 0..p.data_shards {
-            if let Some(bytes) = &shards[i] {
-                out.extend_from_slice(bytes);
-            }
+        for bytes in shards.iter().take(p.data_shards).flatten() {
+            out.extend_from_slice(bytes);
         }
         if out.len() >= total_bytes {
             break;
@@ -125,3 +125,166 @@ pub fn fec_decode_collect(packets: Vec<ShardPacket>, total_bytes: usize, p: &Fec
     out.truncate(total_bytes);
     Ok(out)
 }
+
+/// How many groups past the next one to emit an assembler buffers by default.
+pub const DEFAULT_WINDOW_GROUPS: u32 = 64;
+
+/// Incremental counterpart to [`fec_decode_collect`].
+///
+/// Packets may be pushed in any order; completed groups are handed back strictly in group order
+/// so callers can stream recovered bytes without holding the whole payload in memory. Only groups
+/// within a window past the next one to emit are buffered, so memory stays bounded even when an
+/// early group never completes.
+pub struct FecGroupAssembler {
+    p: FecParams,
+    total_bytes: u64,
+    total_groups: u32,
+    next_group: u32,
+    emitted_bytes: u64,
+    window: u32,
+    pending: std::collections::BTreeMap<u32, Vec<Option<Vec<u8>>>>,
+}
+
+impl FecGroupAssembler {
+    pub fn new(p: &FecParams, total_bytes: u64) -> Result<Self, FecError> {
+        if p.data_shards == 0 || p.shard_bytes == 0 {
+            return Err(FecError::InvalidParams);
+        }
+        let group_data_bytes = (p.data_shards * p.shard_bytes) as u64;
+        Ok(Self {
+            p: p.clone(),
+            total_bytes,
+            total_groups: total_bytes.div_ceil(group_data_bytes) as u32,
+            next_group: 0,
+            emitted_bytes: 0,
+            window: DEFAULT_WINDOW_GROUPS,
+            pending: std::collections::BTreeMap::new(),
+        })
+    }
+
+    /// Buffer at most `groups` groups (at least one) past the next one to emit.
+    pub fn with_window(mut self, groups: u32) -> Self {
+        self.window = groups.max(1);
+        self
+    }
+
+    /// Store a packet. Duplicates and packets for groups already emitted are ignored.
+    ///
+    /// A packet for a group beyond the window is not stored and returns
+    /// [`FecError::WindowExceeded`]: the next group is stuck, which is fatal when frames are read
+    /// in order but only means "try again later" for a looping live capture.
+    pub fn push(&mut self, pkt: ShardPacket) -> Result<(), FecError> {
+        if pkt.group_index < self.next_group || pkt.group_index >= self.total_groups {
+            return Ok(());
+        }
+        if pkt.group_index - self.next_group >= self.window {
+            return Err(FecError::WindowExceeded {
+                stuck: self.next_group,
+                group: pkt.group_index,
+                window: self.window,
+            });
+        }
+        let total_shards = self.p.data_shards + self.p.parity_shards;
+        let entry = self
+            .pending
+            .entry(pkt.group_index)
+            .or_insert_with(|| (0..total_shards).map(|_| None).collect());
+        let idx = pkt.shard_index as usize;
+        if idx < entry.len() && entry[idx].is_none() {
+            entry[idx] = Some(pkt.shard_bytes);
+        }
+        Ok(())
+    }
+
+    /// Take the next group's data bytes if every data shard for it has arrived.
+    pub fn pop_ready(&mut self) -> Option<Vec<u8>> {
+        let shards = self.pending.get(&self.next_group)?;
+        if shards.iter().take(self.p.data_shards).any(|s| s.is_none()) {
+            return None;
+        }
+        let shards = self.pending.remove(&self.next_group)?;
+
+        let remaining = (self.total_bytes - self.emitted_bytes) as usize;
+        let mut out: Vec<u8> = Vec::with_capacity(self.p.data_shards * self.p.shard_bytes);
+        for bytes in shards.into_iter().take(self.p.data_shards).flatten() {
+            out.extend_from_slice(&bytes);
+        }
+        out.truncate(remaining);
+
+        self.emitted_bytes += out.len() as u64;
+        self.next_group += 1;
+        Some(out)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_group >= self.total_groups
+    }
+
+    /// Index of the first group that has not been emitted yet.
+    pub fn next_group(&self) -> u32 {
+        self.next_group
+    }
+
+    pub fn total_groups(&self) -> u32 {
+        self.total_groups
+    }
+
+    pub fn emitted_bytes(&self) -> u64 {
+        self.emitted_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> FecParams {
+        FecParams {
+            data_shards: 2,
+            parity_shards: 1,
+            shard_bytes: 4,
+        }
+    }
+
+    fn packet(group_index: u32, shard_index: u16, byte: u8) -> ShardPacket {
+        ShardPacket {
+            group_index,
+            shard_index,
+            shard_bytes: vec![byte; 4],
+            shard_sha256: [0; 32],
+        }
+    }
+
+    #[test]
+    fn assembler_emits_groups_in_order() {
+        let mut a = FecGroupAssembler::new(&params(), 20).unwrap();
+        a.push(packet(1, 1, 4)).unwrap();
+        a.push(packet(1, 0, 3)).unwrap();
+        a.push(packet(0, 1, 2)).unwrap();
+        assert!(a.pop_ready().is_none());
+        a.push(packet(0, 0, 1)).unwrap();
+        assert_eq!(a.pop_ready().unwrap(), [1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(a.pop_ready().unwrap(), [3, 3, 3, 3, 4, 4, 4, 4]);
+        assert!(a.pop_ready().is_none());
+        a.push(packet(2, 0, 5)).unwrap();
+        a.push(packet(2, 1, 6)).unwrap();
+        assert_eq!(a.pop_ready().unwrap(), [5, 5, 5, 5]);
+        assert!(a.is_complete());
+        assert_eq!(a.emitted_bytes(), 20);
+    }
+
+    #[test]
+    fn assembler_rejects_groups_past_the_window() {
+        let mut a = FecGroupAssembler::new(&params(), 8 * 10).unwrap().with_window(3);
+        a.push(packet(2, 0, 1)).unwrap();
+        let err = a.push(packet(3, 0, 1)).unwrap_err();
+        assert!(matches!(err, FecError::WindowExceeded { stuck: 0, group: 3, window: 3 }));
+
+        // Once group 0 is out, group 3 fits again.
+        a.push(packet(0, 0, 1)).unwrap();
+        a.push(packet(0, 1, 1)).unwrap();
+        assert!(a.pop_ready().is_some());
+        a.push(packet(3, 0, 1)).unwrap();
+        assert_eq!(a.pending.len(), 2);
+    }
+}
@@ -1,10 +1,22 @@
+use crate::anim::{decode_anim_to_writer, AnimFormat, AnimReader};
+use crate::frames::{DecodeStatus, FrameDecoder, FrameEncoder};
+use crate::manifest::EncodeManifest;
+use crate::framefile::{frame_path, read_frame_file};
+use crate::raster::{frame_size_px, read_manifest, sample_cell, ProgressMsg, RasterParams};
+use crate::y4m::{decode_y4m_to_writer, is_y4m, Y4mReader};
 use anyhow::{bail, Context};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc;
 
 fn probe_ffmpeg(ffmpeg: &Path) -> anyhow::Result<()> {
     let status = Command::new(ffmpeg)
         .arg("-version")
+        .stdout(Stdio::null())
         .status()
         .context("spawn ffmpeg -version")?;
     if !status.success() {
@@ -13,7 +25,9 @@ fn probe_ffmpeg(ffmpeg: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn resolve_ffmpeg(ffmpeg_path: Option<&Path>) -> anyhow::Result<PathBuf> {
+/// The ffmpeg to run: `ffmpeg_path` if given, else `ffmpeg` from PATH, after checking that
+/// `ffmpeg -version` succeeds.
+pub fn resolve_ffmpeg(ffmpeg_path: Option<&Path>) -> anyhow::Result<PathBuf> {
     if let Some(p) = ffmpeg_path {
         probe_ffmpeg(p)?;
         return Ok(p.to_path_buf());
@@ -27,46 +41,450 @@ fn resolve_ffmpeg(ffmpeg_path: Option<&Path>) -> anyhow::Result<PathBuf> {
     })
 }
 
+/// Video codec for exported frames; see [`VideoExportParams`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VideoCodec {
+    /// FFV1, lossless.
+    #[default]
+    Ffv1,
+    /// H.264 lossless RGB (libx264rgb at `-qp 0`); plays in more tools than FFV1.
+    X264rgb,
+    /// H.264 4:2:0, lossy (libx264). For [`Profile::Video`](crate::Profile::Video) uploads.
+    Libx264,
+    /// H.265 4:2:0, lossy (libx265).
+    Libx265,
+    /// AV1 4:2:0, lossy (libaom-av1).
+    Av1,
+    /// VP9 lossless in planar RGB (libvpx-vp9 `-lossless 1`).
+    Vp9Lossless,
+}
+
+impl VideoCodec {
+    pub const ALL: [VideoCodec; 6] = [
+        VideoCodec::Ffv1,
+        VideoCodec::X264rgb,
+        VideoCodec::Libx264,
+        VideoCodec::Libx265,
+        VideoCodec::Av1,
+        VideoCodec::Vp9Lossless,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoCodec::Ffv1 => "ffv1",
+            VideoCodec::X264rgb => "x264rgb",
+            VideoCodec::Libx264 => "libx264",
+            VideoCodec::Libx265 => "libx265",
+            VideoCodec::Av1 => "av1",
+            VideoCodec::Vp9Lossless => "vp9-lossless",
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, VideoCodec::Ffv1 | VideoCodec::X264rgb | VideoCodec::Vp9Lossless)
+    }
+
+    /// CRF used by lossy codecs when none is given. Well below each encoder's default, so a
+    /// platform's own re-encode still has margin left to eat.
+    pub fn default_crf(&self) -> Option<u8> {
+        match self {
+            VideoCodec::Libx264 => Some(18),
+            VideoCodec::Libx265 => Some(20),
+            VideoCodec::Av1 => Some(24),
+            _ => None,
+        }
+    }
+
+    /// Output pixel format unless [`VideoExportParams::pix_fmt`] overrides it.
+    pub fn default_pix_fmt(&self) -> &'static str {
+        match self {
+            VideoCodec::Ffv1 | VideoCodec::X264rgb => "rgb24",
+            VideoCodec::Vp9Lossless => "gbrp",
+            VideoCodec::Libx264 | VideoCodec::Libx265 | VideoCodec::Av1 => "yuv420p",
+        }
+    }
+
+    /// Containers ffmpeg can mux this codec into.
+    pub fn containers(&self) -> &'static [VideoContainer] {
+        use VideoContainer::*;
+        match self {
+            VideoCodec::Ffv1 => &[Mkv, Avi],
+            VideoCodec::X264rgb | VideoCodec::Libx264 => &[Mkv, Mp4, Avi],
+            VideoCodec::Libx265 => &[Mkv, Mp4],
+            VideoCodec::Av1 | VideoCodec::Vp9Lossless => &[Mkv, Mp4, Webm],
+        }
+    }
+
+    fn ffmpeg_args(&self, crf: Option<u8>) -> Vec<String> {
+        let crf = crf.or(self.default_crf()).unwrap_or_default().to_string();
+        let args: Vec<&str> = match self {
+            VideoCodec::Ffv1 => vec!["-c:v", "ffv1", "-level", "3"],
+            VideoCodec::X264rgb => vec!["-c:v", "libx264rgb", "-qp", "0", "-preset", "medium"],
+            VideoCodec::Libx264 => vec!["-c:v", "libx264", "-preset", "slow", "-crf", &crf],
+            VideoCodec::Libx265 => vec!["-c:v", "libx265", "-preset", "medium", "-crf", &crf],
+            VideoCodec::Av1 => vec!["-c:v", "libaom-av1", "-crf", &crf, "-b:v", "0", "-cpu-used", "6", "-row-mt", "1"],
+            VideoCodec::Vp9Lossless => vec!["-c:v", "libvpx-vp9", "-lossless", "1", "-row-mt", "1"],
+        };
+        args.into_iter().map(String::from).collect()
+    }
+}
+
+/// Container format for exported video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoContainer {
+    #[default]
+    Mkv,
+    Mp4,
+    Webm,
+    Avi,
+}
+
+impl VideoContainer {
+    pub const ALL: [VideoContainer; 4] = [VideoContainer::Mkv, VideoContainer::Mp4, VideoContainer::Webm, VideoContainer::Avi];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoContainer::Mkv => "mkv",
+            VideoContainer::Mp4 => "mp4",
+            VideoContainer::Webm => "webm",
+            VideoContainer::Avi => "avi",
+        }
+    }
+
+    /// From a file name's extension, if it is one of ours.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        VideoContainer::ALL.into_iter().find(|c| c.extension() == ext)
+    }
+
+    fn muxer(&self) -> &'static str {
+        match self {
+            VideoContainer::Mkv => "matroska",
+            VideoContainer::Mp4 => "mp4",
+            VideoContainer::Webm => "webm",
+            VideoContainer::Avi => "avi",
+        }
+    }
+}
+
+/// How frames are written to a video file: codec, container, pixel format, and timing for
+/// playback in front of a camera.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoExportParams {
+    pub codec: VideoCodec,
+    pub container: VideoContainer,
+    /// ffmpeg `-pix_fmt` for the output; `None` uses [`VideoCodec::default_pix_fmt`].
+    pub pix_fmt: Option<String>,
+    /// Quality for lossy codecs; `None` uses [`VideoCodec::default_crf`].
+    pub crf: Option<u8>,
+    pub fps: u32,
+    /// Show each code frame this many times in a row, so a camera filming the playback catches
+    /// every frame. Needs an FEC profile: FEC frames carry their own index, plain ones do not.
+    pub repeat: u32,
+    /// Seconds of solid sync colour before the first frame.
+    pub lead_in_secs: f32,
+    /// Seconds of solid sync colour after the last frame.
+    pub lead_out_secs: f32,
+}
+
+impl Default for VideoExportParams {
+    fn default() -> Self {
+        Self {
+            codec: VideoCodec::Ffv1,
+            container: VideoContainer::Mkv,
+            pix_fmt: None,
+            crf: None,
+            fps: 24,
+            repeat: 1,
+            lead_in_secs: 0.0,
+            lead_out_secs: 0.0,
+        }
+    }
+}
+
+impl VideoExportParams {
+    /// Lossless FFV1 in Matroska at `fps`: what `--out-mkv` has always written.
+    pub fn ffv1(fps: u32) -> Self {
+        Self { fps, ..Self::default() }
+    }
+
+    /// Reject combinations ffmpeg or the decoder can't handle, before anything is rendered.
+    pub fn validate(&self, p: &RasterParams) -> anyhow::Result<()> {
+        if !self.codec.containers().contains(&self.container) {
+            let names: Vec<_> = self.codec.containers().iter().map(|c| c.extension()).collect();
+            bail!(
+                "{} can't be stored in {}; use {}",
+                self.codec.name(),
+                self.container.extension(),
+                names.join(", ")
+            );
+        }
+        if self.crf.is_some() && self.codec.is_lossless() {
+            bail!("crf only applies to lossy codecs, not {}", self.codec.name());
+        }
+        if self.fps == 0 {
+            bail!("fps must be at least 1");
+        }
+        if self.repeat == 0 {
+            bail!("repeat must be at least 1");
+        }
+        if self.repeat > 1 && p.fec.is_none() {
+            bail!("repeating frames needs a profile with FEC (scan or video): frames without FEC carry no index");
+        }
+        for secs in [self.lead_in_secs, self.lead_out_secs] {
+            if !secs.is_finite() || secs < 0.0 {
+                bail!("lead-in/lead-out must be zero or more seconds");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lead_in_frames(&self) -> u32 {
+        (self.lead_in_secs * self.fps as f32).round() as u32
+    }
+
+    pub fn lead_out_frames(&self) -> u32 {
+        (self.lead_out_secs * self.fps as f32).round() as u32
+    }
+
+    fn output_args(&self) -> Vec<String> {
+        let mut args = self.codec.ffmpeg_args(self.crf);
+        let pix_fmt = self.pix_fmt.as_deref().unwrap_or(self.codec.default_pix_fmt());
+        args.extend(["-pix_fmt", pix_fmt, "-f", self.container.muxer()].map(String::from));
+        args
+    }
+
+    /// Full ffmpeg command line (after the program) reading `width` x `height` raw rgb24 frames
+    /// on stdin and writing `out_video`.
+    fn sink_args(&self, (width, height): (u32, u32), out_video: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["-y", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s"].map(OsString::from).into();
+        args.push(format!("{width}x{height}").into());
+        args.extend(["-framerate".into(), self.fps.to_string().into(), "-i".into(), "-".into()]);
+        args.extend(self.output_args().into_iter().map(OsString::from));
+        args.push(out_video.into());
+        args
+    }
+}
+
 /// Convert a frames directory to Matroska/FFV1 via ffmpeg.
-///
-/// Uses image2 demuxer with numbered frame filenames `frame_%06d.png`.
 pub fn frames_to_ffv1_mkv(
     frames_dir: &Path,
     out_mkv: &Path,
     fps: u32,
     ffmpeg_path: Option<&Path>,
 ) -> anyhow::Result<()> {
-    let ffmpeg = resolve_ffmpeg(ffmpeg_path)?;
-    let input_pattern = frames_dir.join("frame_%06d.png");
+    frames_to_video(frames_dir, out_mkv, &RasterParams::default(), &VideoExportParams::ffv1(fps), ffmpeg_path)
+}
 
-    let status = Command::new(ffmpeg)
-        .arg("-y")
-        .arg("-f")
-        .arg("image2")
-        .arg("-framerate")
-        .arg(format!("{fps}"))
-        .arg("-i")
-        .arg(input_pattern)
-        .arg("-c:v")
-        .arg("ffv1")
-        .arg("-level")
-        .arg("3")
-        .arg("-pix_fmt")
-        .arg("rgb24")
-        .arg(out_mkv)
-        .status()
-        .context("spawn ffmpeg")?;
+/// Convert a frames directory (`frame_%06d.png` or another [`FrameFormat`](crate::framefile::FrameFormat),
+/// plus `manifest.json`) to a video via ffmpeg.
+///
+/// The frame files are decoded here and piped as raw rgb24, so lead-in, repetition and lead-out are
+/// applied exactly as for [`pipe_frames_to_video`]. `p` is the profile the frames were made with.
+pub fn frames_to_video(
+    frames_dir: &Path,
+    out_video: &Path,
+    p: &RasterParams,
+    v: &VideoExportParams,
+    ffmpeg_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    v.validate(p)?;
+    let m = read_manifest(frames_dir).context("read manifest")?;
+    let load = |i: u32| -> anyhow::Result<RgbImage> {
+        let path = frame_path(frames_dir, i, &m);
+        read_frame_file(&path).with_context(|| format!("read {}", path.display()))
+    };
+    let first = load(0)?;
+    let mut sink = VideoSink::spawn(out_video, first.dimensions(), v, p, ffmpeg_path)?;
+    let written = (|| {
+        sink.push(0, &first)?;
+        for i in 1..m.frames {
+            sink.push(i, &load(i)?)?;
+        }
+        Ok(())
+    })();
+    sink.finish(written)
+}
 
-    if !status.success() {
-        bail!("ffmpeg failed: {status}");
+/// Manifest written next to a video encoded without a frames directory: `out.mkv` -> `out.manifest.json`.
+pub fn video_manifest_path(video: &Path) -> PathBuf {
+    video.with_extension("manifest.json")
+}
+
+/// Encode `input_bytes` straight to Matroska/FFV1, with no frames directory.
+pub fn encode_bytes_to_ffv1_mkv(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_mkv: &Path,
+    fps: u32,
+    p: &RasterParams,
+    ffmpeg_path: Option<&Path>,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> anyhow::Result<EncodeManifest> {
+    encode_bytes_to_video(input_bytes, file_name, out_mkv, p, &VideoExportParams::ffv1(fps), ffmpeg_path, progress_tx)
+}
+
+/// Encode `input_bytes` straight to a video, with no frames directory.
+///
+/// Frames are rendered in memory and streamed to ffmpeg as raw rgb24 on stdin; the manifest goes
+/// to [`video_manifest_path`].
+pub fn encode_bytes_to_video(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_video: &Path,
+    p: &RasterParams,
+    v: &VideoExportParams,
+    ffmpeg_path: Option<&Path>,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> anyhow::Result<EncodeManifest> {
+    v.validate(p)?;
+    let encoder = FrameEncoder::new(input_bytes, file_name, p).context("plan frames")?;
+    pipe_frames_to_video(&encoder, out_video, v, ffmpeg_path, progress_tx)?;
+    let manifest = encoder.manifest().clone();
+    std::fs::write(video_manifest_path(out_video), serde_json::to_vec_pretty(&manifest)?)
+        .context("write video manifest")?;
+    Ok(manifest)
+}
+
+/// Stream every frame of `encoder` into ffmpeg (`-f rawvideo -pix_fmt rgb24 -i -`) as Matroska/FFV1.
+pub fn pipe_frames_to_ffv1_mkv(
+    encoder: &FrameEncoder,
+    out_mkv: &Path,
+    fps: u32,
+    ffmpeg_path: Option<&Path>,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> anyhow::Result<()> {
+    pipe_frames_to_video(encoder, out_mkv, &VideoExportParams::ffv1(fps), ffmpeg_path, progress_tx)
+}
+
+/// Stream every frame of `encoder` into ffmpeg (`-f rawvideo -pix_fmt rgb24 -i -`), encoded as `v`
+/// says.
+///
+/// Rendering runs ahead of ffmpeg by at most a few frames, so memory stays bounded.
+pub fn pipe_frames_to_video(
+    encoder: &FrameEncoder,
+    out_video: &Path,
+    v: &VideoExportParams,
+    ffmpeg_path: Option<&Path>,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> anyhow::Result<()> {
+    v.validate(encoder.params())?;
+    let first = encoder.frame(0).context("render frame 0")?;
+    let mut sink = VideoSink::spawn(
+        out_video,
+        first.dimensions(),
+        v,
+        encoder.params(),
+        ffmpeg_path,
+    )?;
+    let total = encoder.total_frames() as u64;
+    let written = encoder.render_in_order(|i, img| -> anyhow::Result<()> {
+        sink.push(i, &img)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "encode".into(),
+                done: i as u64 + 1,
+                total,
+            });
+        }
+        Ok(())
+    });
+    sink.finish(written)
+}
+
+/// ffmpeg reading raw rgb24 frames on stdin, with [`VideoExportParams`] lead-in, repetition and
+/// lead-out applied on the way in.
+struct VideoSink {
+    child: Child,
+    stdin: ChildStdin,
+    width: u32,
+    height: u32,
+    repeat: u32,
+    lead_out: u32,
+    /// Solid sync-colour frame for lead-in and lead-out.
+    solid: RgbImage,
+}
+
+impl VideoSink {
+    fn spawn(
+        out_video: &Path,
+        (width, height): (u32, u32),
+        v: &VideoExportParams,
+        p: &RasterParams,
+        ffmpeg_path: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let c = p.palette.color(p.sync_color_symbol)?;
+        let ffmpeg = resolve_ffmpeg(ffmpeg_path)?;
+        let mut child = Command::new(ffmpeg)
+            .args(v.sink_args((width, height), out_video))
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("spawn ffmpeg")?;
+        let Some(stdin) = child.stdin.take() else {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("ffmpeg stdin not captured");
+        };
+        let mut sink = Self {
+            child,
+            stdin,
+            width,
+            height,
+            repeat: v.repeat.max(1),
+            lead_out: v.lead_out_frames(),
+            solid: RgbImage::from_pixel(width, height, image::Rgb([c.r, c.g, c.b])),
+        };
+        let lead_in = (0..v.lead_in_frames()).try_for_each(|_| sink.stdin.write_all(sink.solid.as_raw()));
+        if let Err(e) = lead_in {
+            // No `finish` will run for this sink, so don't leave ffmpeg behind.
+            let _ = sink.child.kill();
+            let _ = sink.child.wait();
+            return Err(e).context("write lead-in to ffmpeg");
+        }
+        Ok(sink)
     }
 
-    Ok(())
+    fn push(&mut self, i: u32, img: &RgbImage) -> anyhow::Result<()> {
+        if img.dimensions() != (self.width, self.height) {
+            bail!(
+                "frame {i} is {}x{}, expected {}x{}",
+                img.width(),
+                img.height(),
+                self.width,
+                self.height
+            );
+        }
+        for _ in 0..self.repeat {
+            self.stdin.write_all(img.as_raw()).context("write frame to ffmpeg")?;
+        }
+        Ok(())
+    }
+
+    /// Write the lead-out, close the stream and wait for ffmpeg.
+    fn finish(mut self, written: anyhow::Result<()>) -> anyhow::Result<()> {
+        let written = written.and_then(|()| {
+            for _ in 0..self.lead_out {
+                self.stdin.write_all(self.solid.as_raw()).context("write lead-out to ffmpeg")?;
+            }
+            Ok(())
+        });
+        // Closing stdin ends the stream; ffmpeg's status explains a broken pipe better than EPIPE.
+        drop(self.stdin);
+        let status = self.child.wait().context("wait for ffmpeg")?;
+        if !status.success() {
+            bail!("ffmpeg failed: {status}");
+        }
+        written
+    }
 }
 
 /// Extract `frame_%06d.png` into `out_frames_dir` from a video file.
 ///
 /// Uses ffmpeg `-vsync 0` to avoid frame duplication and `-start_number 0` to match our naming.
+/// Nothing is checked here; [`probe_video`] catches lossy or rescaled input beforehand.
 pub fn mkv_to_frames(
     in_video: &Path,
     out_frames_dir: &Path,
@@ -95,3 +513,768 @@ pub fn mkv_to_frames(
 
     Ok(())
 }
+
+/// `ffprobe` next to an explicit ffmpeg path (same extension, e.g. `.exe`), else from PATH.
+fn resolve_ffprobe(ffmpeg_path: Option<&Path>) -> PathBuf {
+    match ffmpeg_path {
+        Some(p) => {
+            let mut name = std::ffi::OsString::from("ffprobe");
+            if let Some(ext) = p.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            p.with_file_name(name)
+        }
+        None => PathBuf::from("ffprobe"),
+    }
+}
+
+/// Width and height of the first video stream, from ffprobe.
+pub fn probe_dimensions(video: &Path, ffmpeg_path: Option<&Path>) -> anyhow::Result<(u32, u32)> {
+    let ffprobe = resolve_ffprobe(ffmpeg_path);
+    let out = Command::new(&ffprobe)
+        .arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg("v:0")
+        .arg("-show_entries")
+        .arg("stream=width,height")
+        .arg("-of")
+        .arg("csv=s=x:p=0")
+        .arg(video)
+        .output()
+        .with_context(|| format!("spawn {}", ffprobe.display()))?;
+    if !out.status.success() {
+        bail!("ffprobe failed: {}: {}", out.status, String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    parse_dimensions(&text).with_context(|| {
+        let dims = text.lines().next().unwrap_or_default().trim();
+        format!("ffprobe reported no video size for {} (got {dims:?})", video.display())
+    })
+}
+
+/// `WIDTHxHEIGHT` on the first line of `ffprobe -of csv=s=x:p=0`; `None` unless both are positive.
+fn parse_dimensions(text: &str) -> Option<(u32, u32)> {
+    let (w, h) = text.lines().next()?.trim().split_once('x')?;
+    let (w, h) = (w.parse::<u32>().ok()?, h.parse::<u32>().ok()?);
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+/// Stream properties of a video, from ffprobe (or the header of a `.y4m`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VideoProbe {
+    /// ffprobe codec name, e.g. `ffv1`, `h264`; `rawvideo` for a `.y4m`.
+    pub codec: String,
+    /// ffmpeg pixel format, e.g. `rgb24`, `yuv420p`.
+    pub pix_fmt: String,
+    pub width: u32,
+    pub height: u32,
+    /// Video frames in the file, if the container says or ffprobe could count them.
+    pub frames: Option<u64>,
+    pub fps: Option<f64>,
+}
+
+/// Codecs that never lose information.
+const LOSSLESS_CODECS: &[&str] = &["ffv1", "rawvideo", "png", "apng", "qtrle", "utvideo", "huffyuv", "ffvhuff", "magicyuv", "gif", "webp"];
+
+impl VideoProbe {
+    /// False for codecs that are lossy unless told otherwise. H.264, VP9 and friends in an RGB
+    /// pixel format are taken to be lossless (x264rgb, vp9-lossless); in YUV they are not.
+    pub fn is_lossless(&self) -> bool {
+        LOSSLESS_CODECS.contains(&self.codec.as_str()) || self.is_rgb()
+    }
+
+    fn is_rgb(&self) -> bool {
+        ["rgb", "bgr", "gbr", "argb", "abgr"].iter().any(|p| self.pix_fmt.starts_with(p))
+    }
+
+    /// True if the pixel format stores chroma at less than full resolution.
+    pub fn subsamples_chroma(&self) -> bool {
+        ["420", "422", "411", "410", "440", "nv12", "nv21", "nv16", "yuyv", "uyvy"]
+            .iter()
+            .any(|p| self.pix_fmt.contains(p))
+    }
+
+    /// One line for logs: `h264 yuv420p 1920x1080, 240 frames at 24 fps`.
+    pub fn summary(&self) -> String {
+        let frames = self.frames.map_or(String::new(), |n| format!(", {n} frames"));
+        let fps = self.fps.map_or(String::new(), |f| format!(" at {} fps", (f * 1000.0).round() / 1000.0));
+        format!("{} {} {}x{}{frames}{fps}", self.codec, self.pix_fmt, self.width, self.height)
+    }
+
+    /// Reasons this video may not decode with manifest `m` and profile `p`, to show before
+    /// decoding rather than after a SHA-256 mismatch. Empty if nothing looks wrong.
+    ///
+    /// Lossy codecs and chroma subsampling are expected with [`RasterParams::center_sample`]
+    /// (the video profile), and any frame size is expected with [`RasterParams::deskew`].
+    pub fn warnings(&self, m: &EncodeManifest, p: &RasterParams) -> Vec<String> {
+        let mut out = Vec::new();
+        if !self.is_lossless() && !p.center_sample {
+            out.push(format!(
+                "{} in {} is lossy; cells only survive exact colours unless encoded with the video profile (re-export with ffv1 or x264rgb)",
+                self.codec, self.pix_fmt
+            ));
+        } else if self.subsamples_chroma() && !p.center_sample {
+            out.push(format!(
+                "{} stores colour at reduced resolution, which blurs colours across cell edges; use rgb24 or yuv444p",
+                self.pix_fmt
+            ));
+        }
+        match frame_size_px(m, p) {
+            Ok((want_w, want_h)) if !p.deskew && (self.width, self.height) != (want_w, want_h) => out.push(format!(
+                "video is {}x{} but the frames were rendered at {want_w}x{want_h}; it was scaled or cropped, which only a deskew profile can read",
+                self.width, self.height
+            )),
+            Ok(_) => {}
+            Err(e) => out.push(format!("the manifest is unusable: {e}")),
+        }
+        if let Some(n) = self.frames {
+            if n < m.frames as u64 {
+                out.push(format!("video has {n} frames but the archive has {}; it may be cut short", m.frames));
+            }
+        }
+        out
+    }
+}
+
+/// Codec, pixel format, size, frame count and frame rate of the first video stream.
+///
+/// A `.y4m` is read from its header (its frame count is not known up front) and an animated
+/// image by decoding its frames; anything else goes through ffprobe with `-count_packets`, which reads the whole file once but does not decode it.
+pub fn probe_video(video: &Path, ffmpeg_path: Option<&Path>) -> anyhow::Result<VideoProbe> {
+    if let Some(format) = AnimFormat::detect(video) {
+        let mut reader = AnimReader::open(video).context("read animated image")?;
+        let (width, height) = reader.dimensions();
+        let delay_ms = reader.delay_ms();
+        let mut frames = 0u64;
+        while reader.read_frame().context("read animated image")?.is_some() {
+            frames += 1;
+        }
+        return Ok(VideoProbe {
+            codec: format.name().into(),
+            pix_fmt: if format == AnimFormat::Gif { "pal8" } else { "rgb24" }.into(),
+            width,
+            height,
+            frames: Some(frames),
+            fps: (delay_ms > 0).then(|| 1000.0 / delay_ms as f64),
+        });
+    }
+    if is_y4m(video) {
+        let reader = Y4mReader::open(video).context("read y4m header")?;
+        let (width, height) = reader.dimensions();
+        let (n, d) = reader.fps();
+        return Ok(VideoProbe {
+            codec: "rawvideo".into(),
+            pix_fmt: reader.pix_fmt().into(),
+            width,
+            height,
+            frames: None,
+            fps: (d > 0).then(|| n as f64 / d as f64),
+        });
+    }
+    let ffprobe = resolve_ffprobe(ffmpeg_path);
+    let out = Command::new(&ffprobe)
+        .arg("-v")
+        .arg("error")
+        .arg("-select_streams")
+        .arg("v:0")
+        .arg("-count_packets")
+        .arg("-show_entries")
+        .arg("stream=codec_name,pix_fmt,width,height,avg_frame_rate,nb_frames,nb_read_packets")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1")
+        .arg(video)
+        .output()
+        .with_context(|| format!("spawn {}", ffprobe.display()))?;
+    if !out.status.success() {
+        bail!("ffprobe failed: {}: {}", out.status, String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    VideoProbe::from_ffprobe(&text).with_context(|| format!("ffprobe reported no video stream in {}", video.display()))
+}
+
+impl VideoProbe {
+    /// Parse `key=value` lines from `ffprobe -of default=noprint_wrappers=1`. Empty and `N/A`
+    /// values count as missing; `None` without a width and height.
+    fn from_ffprobe(text: &str) -> Option<Self> {
+        let field = |key: &str| {
+            text.lines()
+                .filter_map(|l| l.split_once('='))
+                .find(|(k, _)| k.trim() == key)
+                .map(|(_, v)| v.trim())
+                .filter(|v| !v.is_empty() && *v != "N/A")
+        };
+        let width = field("width")?.parse::<u32>().ok()?;
+        let height = field("height")?.parse::<u32>().ok()?;
+        let fps = field("avg_frame_rate").and_then(|v| {
+            let (n, d) = v.split_once('/').unwrap_or((v, "1"));
+            let (n, d) = (n.parse::<f64>().ok()?, d.parse::<f64>().ok()?);
+            (n > 0.0 && d > 0.0).then_some(n / d)
+        });
+        Some(VideoProbe {
+            codec: field("codec_name").unwrap_or("unknown").to_string(),
+            pix_fmt: field("pix_fmt").unwrap_or("unknown").to_string(),
+            width,
+            height,
+            frames: field("nb_frames").or(field("nb_read_packets")).and_then(|v| v.parse().ok()),
+            fps,
+        })
+    }
+}
+
+/// Frames of a video decoded by ffmpeg and read from its stdout as raw rgb24
+/// (`-f rawvideo -pix_fmt rgb24 pipe:1`), one [`RgbImage`] at a time.
+///
+/// Dropping the reader stops ffmpeg.
+pub struct VideoFrames {
+    child: Child,
+    stdout: ChildStdout,
+    width: u32,
+    height: u32,
+    read: u64,
+    done: bool,
+}
+
+impl VideoFrames {
+    pub fn open(video: &Path, ffmpeg_path: Option<&Path>) -> anyhow::Result<Self> {
+        let ffmpeg = resolve_ffmpeg(ffmpeg_path)?;
+        let (width, height) = probe_dimensions(video, ffmpeg_path)?;
+        let mut child = Command::new(ffmpeg)
+            .args(Self::args(video))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("spawn ffmpeg")?;
+        let Some(stdout) = child.stdout.take() else {
+            bail!("ffmpeg stdout not captured");
+        };
+        Ok(Self {
+            child,
+            stdout,
+            width,
+            height,
+            read: 0,
+            done: false,
+        })
+    }
+
+    /// ffmpeg command line decoding every frame of `video`, undropped and unduplicated, as raw
+    /// rgb24 on stdout.
+    fn args(video: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["-v", "error", "-i"].map(OsString::from).into();
+        args.push(video.into());
+        args.extend(["-vsync", "0", "-f", "rawvideo", "-pix_fmt", "rgb24", "pipe:1"].map(OsString::from));
+        args
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frames returned so far.
+    pub fn frames_read(&self) -> u64 {
+        self.read
+    }
+
+    fn read_frame(&mut self) -> anyhow::Result<Option<RgbImage>> {
+        let mut buf = vec![0u8; self.width as usize * self.height as usize * 3];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.stdout.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e).context("read frame from ffmpeg"),
+            }
+        }
+        if filled == 0 {
+            let status = self.child.wait().context("wait for ffmpeg")?;
+            if !status.success() {
+                bail!("ffmpeg failed: {status}");
+            }
+            return Ok(None);
+        }
+        if filled < buf.len() {
+            bail!("ffmpeg output ended inside frame {}", self.read);
+        }
+        self.read += 1;
+        RgbImage::from_raw(self.width, self.height, buf).map(Some).context("frame buffer size")
+    }
+}
+
+impl Iterator for VideoFrames {
+    type Item = anyhow::Result<RgbImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.read_frame().transpose();
+        if !matches!(frame, Some(Ok(_))) {
+            self.done = true;
+        }
+        frame
+    }
+}
+
+impl Drop for VideoFrames {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Load the manifest written next to a video by [`encode_bytes_to_ffv1_mkv`] (or `encode -o DIR --out-mkv`).
+///
+/// For a `.y4m` or an animated image the embedded manifest wins; that file is only a fallback.
+pub fn read_video_manifest(video: &Path) -> anyhow::Result<EncodeManifest> {
+    let path = video_manifest_path(video);
+    if AnimFormat::detect(video).is_some() {
+        let reader = AnimReader::open(video).context("read animated image")?;
+        if let Some(manifest) = reader.manifest() {
+            return Ok(manifest.clone());
+        }
+    }
+    if is_y4m(video) {
+        let reader = Y4mReader::open(video).context("read y4m header")?;
+        if let Some(manifest) = reader.manifest() {
+            return Ok(manifest.clone());
+        }
+    }
+    let bytes = std::fs::read(&path).with_context(|| format!("read video manifest {}", path.display()))?;
+    let manifest: EncodeManifest = serde_json::from_slice(&bytes).context("parse video manifest")?;
+    if !manifest.is_supported() {
+        bail!("unsupported manifest {}", path.display());
+    }
+    Ok(manifest)
+}
+
+/// Decode a video straight from ffmpeg's output into `out`: no frames are written to disk.
+///
+/// Frames go through [`FrameDecoder`], so decryption, decompression and the SHA-256 check work
+/// as for a frames directory; reading stops as soon as the payload is complete. A `.y4m` and
+/// animated PNG, WebP or GIF files are read natively (see [`crate::y4m`], [`crate::anim`]) and
+/// need no ffmpeg.
+pub fn decode_video_to_writer<W: Write>(
+    video: &Path,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    out: W,
+    ffmpeg_path: Option<&Path>,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> anyhow::Result<W> {
+    if is_y4m(video) {
+        return Ok(decode_y4m_to_writer(video, Some(m), p, out, progress_tx)?);
+    }
+    if AnimFormat::detect(video).is_some() {
+        return Ok(decode_anim_to_writer(video, Some(m), p, out, progress_tx)?);
+    }
+    let mut decoder = FrameDecoder::with_writer(p, out).with_manifest(m.clone());
+    let mut frames = VideoFrames::open(video, ffmpeg_path)?;
+    let total = m.frames as u64;
+    while let Some(frame) = frames.next() {
+        let status = decoder.push_frame(&frame?)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "decode".into(),
+                done: frames.frames_read(),
+                total,
+            });
+        }
+        if status == DecodeStatus::Complete {
+            break;
+        }
+    }
+    Ok(decoder.finish()?)
+}
+
+/// What [`lossy_video_selftest`] measured.
+#[derive(Debug, Clone)]
+pub struct LossySelfTest {
+    /// Frames read back and compared with the rendered originals.
+    pub frames: u64,
+    /// Payload cells compared.
+    pub cells: u64,
+    /// Cells that read back as the wrong colour.
+    pub symbol_errors: u64,
+    /// Frames with at least one wrong cell. Their shard checksum fails and nothing can replace
+    /// them, so any damaged frame fails the decode.
+    pub damaged_frames: u64,
+    /// Worst cell margin: 1.0 is the exact palette colour, 0.0 the midpoint to the nearest wrong
+    /// colour, below zero a wrong symbol.
+    pub min_margin: f64,
+    pub mean_margin: f64,
+    /// Size of the video that was decoded (the transcoded one, if any).
+    pub video_bytes: u64,
+    /// `None` if the full decode, shard checksums and SHA-256 check included, returned the original bytes.
+    pub decode_error: Option<String>,
+}
+
+/// Encode `payload_bytes` of pseudo-random data with `p` and `v`, optionally re-encode the result
+/// the way a video platform would (libx264, 4:2:0, `transcode_crf`), then compare every cell with
+/// the rendered frames and decode the video in full. Temporary videos go to the system temp dir.
+pub fn lossy_video_selftest(
+    p: &RasterParams,
+    v: &VideoExportParams,
+    transcode_crf: Option<u8>,
+    payload_bytes: usize,
+    ffmpeg_path: Option<&Path>,
+) -> anyhow::Result<LossySelfTest> {
+    let mut state = 0x5eed_u64;
+    let input: Vec<u8> = (0..payload_bytes)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect();
+    v.validate(p)?;
+    let encoder = FrameEncoder::new(&input, "selftest.bin", p).context("plan frames")?;
+    let m = encoder.manifest().clone();
+
+    let tmp = std::env::temp_dir().join(format!("sllv-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&tmp).context("create temp dir")?;
+    let result = (|| {
+        let upload = tmp.join(format!("upload.{}", v.container.extension()));
+        pipe_frames_to_video(&encoder, &upload, v, ffmpeg_path, None)?;
+        let video = match transcode_crf {
+            Some(crf) => {
+                let platform = tmp.join("platform.mp4");
+                let status = Command::new(resolve_ffmpeg(ffmpeg_path)?)
+                    .arg("-y")
+                    .arg("-v")
+                    .arg("error")
+                    .arg("-i")
+                    .arg(&upload)
+                    .args(VideoCodec::Libx264.ffmpeg_args(Some(crf)))
+                    .arg("-pix_fmt")
+                    .arg("yuv420p")
+                    .arg(&platform)
+                    .stdin(Stdio::null())
+                    .status()
+                    .context("spawn ffmpeg")?;
+                if !status.success() {
+                    bail!("ffmpeg transcode failed: {status}");
+                }
+                platform
+            }
+            None => upload,
+        };
+
+        let mut report = LossySelfTest {
+            frames: 0,
+            cells: 0,
+            symbol_errors: 0,
+            damaged_frames: 0,
+            min_margin: 1.0,
+            mean_margin: 0.0,
+            video_bytes: std::fs::metadata(&video).map(|md| md.len()).unwrap_or_default(),
+            decode_error: None,
+        };
+        let palette = crate::palette::Palette8::Basic;
+        let colors: Vec<[f64; 3]> = (0..8u8)
+            .map(|s| palette.color(s).map(|c| [c.r as f64, c.g as f64, c.b as f64]))
+            .collect::<Result<_, _>>()?;
+        let mut margin_sum = 0.0;
+        let lead_in = v.lead_in_frames() as usize;
+        for (j, frame) in VideoFrames::open(&video, ffmpeg_path)?.enumerate() {
+            let frame = frame?;
+            // Lead-in and lead-out frames carry no cells; repeats are each compared with the original.
+            let Some(i) = j.checked_sub(lead_in).map(|k| k / v.repeat.max(1) as usize) else {
+                continue;
+            };
+            let Ok(original) = encoder.frame(i as u32) else {
+                break;
+            };
+            if frame.dimensions() != original.dimensions() {
+                bail!(
+                    "frame {i} came back {}x{}, expected {}x{}",
+                    frame.width(),
+                    frame.height(),
+                    original.width(),
+                    original.height()
+                );
+            }
+            let mut damaged = false;
+            for y in 0..m.grid_h {
+                for x in 0..m.grid_w {
+                    let (gx, gy) = (x + p.border_cells, y + p.border_cells);
+                    let want = sample_cell(&original, gx, gy, m.cell_px, false);
+                    let want = palette.symbol_from_rgb_nearest(want[0], want[1], want[2]) as usize;
+                    let got = sample_cell(&frame, gx, gy, m.cell_px, p.center_sample);
+                    let got = [got[0] as f64, got[1] as f64, got[2] as f64];
+                    let margin = cell_margin(&colors, want, got);
+                    report.min_margin = report.min_margin.min(margin);
+                    margin_sum += margin;
+                    report.cells += 1;
+                    if margin < 0.0 {
+                        report.symbol_errors += 1;
+                        damaged = true;
+                    }
+                }
+            }
+            report.frames += 1;
+            report.damaged_frames += u64::from(damaged);
+        }
+        report.mean_margin = margin_sum / report.cells.max(1) as f64;
+
+        match decode_video_to_writer(&video, &m, p, Vec::new(), ffmpeg_path, None) {
+            Ok(out) if out == input => {}
+            Ok(_) => report.decode_error = Some("decoded bytes differ from the input".into()),
+            Err(e) => report.decode_error = Some(format!("{e:#}")),
+        }
+        Ok(report)
+    })();
+    let _ = std::fs::remove_dir_all(&tmp);
+    result
+}
+
+/// Where `got` sits between palette colour `want` and the closest other colour: the signed
+/// distance from their midpoint plane, as a fraction of half the distance between the two.
+fn cell_margin(colors: &[[f64; 3]], want: usize, got: [f64; 3]) -> f64 {
+    let c = colors[want];
+    colors
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != want)
+        .map(|(_, o)| {
+            let axis = [c[0] - o[0], c[1] - o[1], c[2] - o[2]];
+            let len2 = axis.iter().map(|a| a * a).sum::<f64>();
+            let mid = [(c[0] + o[0]) / 2.0, (c[1] + o[1]) / 2.0, (c[2] + o[2]) / 2.0];
+            let along = (0..3).map(|k| (got[k] - mid[k]) * axis[k]).sum::<f64>();
+            2.0 * along / len2
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_ffmpeg_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn strings(args: &[OsString]) -> Vec<&str> {
+        args.iter().map(|a| a.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn margin_is_one_on_the_colour_and_negative_past_the_midpoint() {
+        let colors = [[0.0, 0.0, 0.0], [255.0, 255.0, 255.0], [255.0, 0.0, 0.0]];
+        assert!((cell_margin(&colors, 0, [0.0, 0.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!(cell_margin(&colors, 0, [127.0, 0.0, 0.0]).abs() < 0.01);
+        assert!(cell_margin(&colors, 0, [200.0, 0.0, 0.0]) < 0.0);
+        assert!(cell_margin(&colors, 1, [255.0, 200.0, 200.0]) > 0.0);
+    }
+
+    #[test]
+    fn sink_args_read_rgb24_and_write_the_codec() {
+        let v = VideoExportParams::ffv1(30);
+        let args = v.sink_args((640, 360), Path::new("out.mkv"));
+        assert_eq!(
+            strings(&args),
+            [
+                "-y", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s", "640x360", "-framerate", "30", "-i", "-", "-c:v", "ffv1",
+                "-level", "3", "-pix_fmt", "rgb24", "-f", "matroska", "out.mkv"
+            ]
+        );
+
+        let v = VideoExportParams {
+            codec: VideoCodec::Libx264,
+            container: VideoContainer::Mp4,
+            pix_fmt: Some("yuv444p".into()),
+            ..Default::default()
+        };
+        let args = v.sink_args((64, 48), Path::new("out.mp4"));
+        let args = strings(&args);
+        assert!(args.windows(2).any(|w| w == ["-crf", "18"]));
+        assert!(args.ends_with(&["-pix_fmt", "yuv444p", "-f", "mp4", "out.mp4"]));
+
+        let v = VideoExportParams {
+            codec: VideoCodec::Av1,
+            container: VideoContainer::Webm,
+            crf: Some(40),
+            ..Default::default()
+        };
+        let args = v.sink_args((64, 48), Path::new("out.webm"));
+        let args = strings(&args);
+        assert!(args.windows(2).any(|w| w == ["-c:v", "libaom-av1"]));
+        assert!(args.windows(2).any(|w| w == ["-crf", "40"]));
+        assert!(args.windows(2).any(|w| w == ["-pix_fmt", "yuv420p"]));
+    }
+
+    #[test]
+    fn validate_checks_codec_container_and_timing() {
+        let plain = RasterParams { fec: None, ..Default::default() };
+        let fec = crate::Profile::Scan.defaults();
+        let ok = |v: VideoExportParams, p: &RasterParams| v.validate(p).map_err(|e| e.to_string());
+
+        for codec in VideoCodec::ALL {
+            for container in VideoContainer::ALL {
+                let v = VideoExportParams { codec, container, ..Default::default() };
+                assert_eq!(
+                    ok(v, &plain).is_ok(),
+                    codec.containers().contains(&container),
+                    "{} in {}",
+                    codec.name(),
+                    container.extension()
+                );
+            }
+        }
+        let err = ok(VideoExportParams { container: VideoContainer::Mp4, ..Default::default() }, &plain).unwrap_err();
+        assert!(err.contains("mkv, avi"), "{err}");
+
+        assert!(ok(VideoExportParams { crf: Some(20), ..Default::default() }, &plain).is_err());
+        let lossy = VideoExportParams { codec: VideoCodec::Libx265, crf: Some(20), ..Default::default() };
+        assert!(ok(lossy, &plain).is_ok());
+        // Any pixel format is passed through for ffmpeg to accept or reject.
+        assert!(ok(VideoExportParams { pix_fmt: Some("yuv444p".into()), ..Default::default() }, &plain).is_ok());
+
+        assert!(ok(VideoExportParams { fps: 0, ..Default::default() }, &plain).is_err());
+        assert!(ok(VideoExportParams { repeat: 0, ..Default::default() }, &fec).is_err());
+        let repeated = VideoExportParams { repeat: 3, ..Default::default() };
+        assert!(ok(repeated.clone(), &plain).unwrap_err().contains("FEC"));
+        assert!(ok(repeated, &fec).is_ok());
+
+        for secs in [-1.0, f32::NAN, f32::INFINITY] {
+            assert!(ok(VideoExportParams { lead_in_secs: secs, ..Default::default() }, &plain).is_err());
+            assert!(ok(VideoExportParams { lead_out_secs: secs, ..Default::default() }, &plain).is_err());
+        }
+        let v = VideoExportParams { lead_in_secs: 1.5, lead_out_secs: 0.1, ..VideoExportParams::ffv1(30) };
+        assert!(ok(v.clone(), &plain).is_ok());
+        assert_eq!((v.lead_in_frames(), v.lead_out_frames()), (45, 3));
+    }
+
+    #[test]
+    fn ffprobe_fields_are_parsed() {
+        let text = "codec_name=h264\npix_fmt=yuv420p\nwidth=1920\nheight=1080\n\
+                    avg_frame_rate=30000/1001\nnb_frames=N/A\nnb_read_packets=240\n";
+        let probe = VideoProbe::from_ffprobe(text).unwrap();
+        assert_eq!((probe.codec.as_str(), probe.pix_fmt.as_str()), ("h264", "yuv420p"));
+        assert_eq!((probe.width, probe.height, probe.frames), (1920, 1080, Some(240)));
+        assert!((probe.fps.unwrap() - 29.97).abs() < 0.001);
+        assert_eq!(probe.summary(), "h264 yuv420p 1920x1080, 240 frames at 29.97 fps");
+        assert!(!probe.is_lossless());
+        assert!(probe.subsamples_chroma());
+
+        // Order doesn't matter, the container's count wins, and missing values stay missing.
+        let text = "height = 48\nnb_read_packets=9\nnb_frames=10\nwidth=64\navg_frame_rate=0/0\n";
+        let probe = VideoProbe::from_ffprobe(text).unwrap();
+        assert_eq!(
+            (probe.codec.as_str(), probe.width, probe.height, probe.frames, probe.fps),
+            ("unknown", 64, 48, Some(10), None)
+        );
+        assert_eq!(VideoProbe::from_ffprobe("width=64\navg_frame_rate=24\nheight=48").unwrap().fps, Some(24.0));
+
+        for bad in ["", "codec_name=ffv1\n", "width=64\nheight=N/A\n", "width=-1\nheight=48\n", "width 64\nheight 48\n"] {
+            assert_eq!(VideoProbe::from_ffprobe(bad), None, "{bad:?}");
+        }
+    }
+
+    fn probe(codec: &str, pix_fmt: &str, (width, height): (u32, u32), frames: Option<u64>) -> VideoProbe {
+        VideoProbe { codec: codec.into(), pix_fmt: pix_fmt.into(), width, height, frames, fps: Some(24.0) }
+    }
+
+    #[test]
+    fn probe_warnings() {
+        let p = RasterParams { grid_w: 64, grid_h: 48, ..crate::Profile::Archive.defaults() };
+        let m = EncodeManifest { grid_w: 64, grid_h: 48, cell_px: p.cell_px, frames: 10, ..Default::default() };
+        let size = frame_size_px(&m, &p).unwrap();
+
+        assert!(probe("ffv1", "rgb24", size, Some(10)).warnings(&m, &p).is_empty());
+        assert!(probe("h264", "rgb24", size, None).warnings(&m, &p).is_empty());
+
+        let w = probe("h264", "yuv420p", size, Some(10)).warnings(&m, &p);
+        assert!(w.len() == 1 && w[0].contains("lossy"), "{w:?}");
+        let w = probe("ffv1", "yuv420p", size, Some(10)).warnings(&m, &p);
+        assert!(w.len() == 1 && w[0].contains("reduced resolution"), "{w:?}");
+        let w = probe("ffv1", "rgb24", (size.0 / 2, size.1 / 2), Some(10)).warnings(&m, &p);
+        assert!(w.len() == 1 && w[0].contains("scaled or cropped"), "{w:?}");
+        let w = probe("ffv1", "rgb24", size, Some(7)).warnings(&m, &p);
+        assert!(w.len() == 1 && w[0].contains("cut short"), "{w:?}");
+        let hostile = EncodeManifest { cell_px: u32::MAX, ..m.clone() };
+        let w = probe("ffv1", "rgb24", size, Some(10)).warnings(&hostile, &p);
+        assert!(w.len() == 1 && w[0].contains("unusable"), "{w:?}");
+
+        // The video profile expects lossy 4:2:0, but still at the rendered size.
+        let video = crate::Profile::Video.defaults();
+        let m = EncodeManifest { grid_w: video.grid_w, grid_h: video.grid_h, cell_px: video.cell_px, frames: 10, ..m };
+        let size = frame_size_px(&m, &video).unwrap();
+        assert_eq!(size, (1920, 1080));
+        assert!(probe("h264", "yuv420p", size, Some(10)).warnings(&m, &video).is_empty());
+        let w = probe("h264", "yuv420p", (1280, 720), Some(3)).warnings(&m, &video);
+        assert!(w.len() == 2 && w[0].contains("scaled") && w[1].contains("cut short"), "{w:?}");
+    }
+
+    #[test]
+    fn frames_are_read_as_rgb24_from_stdout() {
+        let args = VideoFrames::args(Path::new("in put.mkv"));
+        assert_eq!(
+            strings(&args),
+            ["-v", "error", "-i", "in put.mkv", "-vsync", "0", "-f", "rawvideo", "-pix_fmt", "rgb24", "pipe:1"]
+        );
+    }
+
+    #[test]
+    fn dimensions_from_ffprobe_csv() {
+        assert_eq!(parse_dimensions("1920x1080\n"), Some((1920, 1080)));
+        assert_eq!(parse_dimensions("  64x48  \nignored\n"), Some((64, 48)));
+        for bad in ["", "\n1920x1080", "0x1080", "1920x", "x", "N/A", "1920x1080x2", "-1x5"] {
+            assert_eq!(parse_dimensions(bad), None, "{bad:?}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn resolve_ffmpeg_runs_the_binary() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("resolve");
+        let script = |name: &str, status: u8| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\nexit {status}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+        let good = script("good", 0);
+        assert_eq!(resolve_ffmpeg(Some(&good)).unwrap(), good);
+        let err = resolve_ffmpeg(Some(&script("broken", 1))).unwrap_err();
+        assert!(err.to_string().contains("failed to run"), "{err:#}");
+        assert!(resolve_ffmpeg(Some(&dir.join("missing"))).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_lead_in_stops_ffmpeg() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("lead_in");
+        let pid_file = dir.join("pid");
+        // Answers `-version`, then closes stdin and lingers like a wedged encoder.
+        let fake = dir.join("ffmpeg");
+        std::fs::write(
+            &fake,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = -version ] && exit 0\necho $$ > '{}'\nexec 0<&-\nexec sleep 30\n",
+                pid_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let v = VideoExportParams { lead_in_secs: 1.0, ..VideoExportParams::ffv1(24) };
+        let err = VideoSink::spawn(&dir.join("out.mkv"), (64, 64), &v, &RasterParams::default(), Some(&fake))
+            .err()
+            .expect("lead-in into a closed pipe");
+        assert!(format!("{err:#}").contains("lead-in"), "{err:#}");
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        assert!(!Path::new(&format!("/proc/{}", pid.trim())).exists(), "ffmpeg {} still running", pid.trim());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+//! Image formats for the frame files of a frames directory.
+//!
+//! | format | file | notes |
+//! |--------|------|-------|
+//! | PNG (RGB) | `.png` | 24-bit, the default; opens anywhere |
+//! | PNG (indexed) | `.png` | 1, 2, 4 or 8 bits per pixel from the frame's own colours; smaller |
+//! | QOI | `.qoi` | no entropy coding, so faster to write and read than PNG, and a little larger |
+//! | WebP | `.webp` | lossless VP8L (see [`crate::webp`]); the smallest, and the slowest to write |
+//! | PPM | `.ppm` | raw binary `P6`; no compression at all |
+//!
+//! The manifest records the format the frames were written in. Readers go by the file's
+//! signature rather than its name, and look for other extensions when the expected file is
+//! missing, so a directory converted to another format (or frames extracted from a video as PNG)
+//! still decodes.
+
+use crate::manifest::EncodeManifest;
+use crate::webp::{encode_still, WebpError, WebpReader};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const QOI_MAGIC: &[u8; 4] = b"qoif";
+/// QOI images above this many pixels are rejected rather than allocated.
+const QOI_MAX_PIXELS: u64 = 400_000_000;
+const QOI_END: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+#[derive(Debug, Error)]
+pub enum FrameFileError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("png: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("webp: {0}")]
+    Webp(#[from] WebpError),
+    #[error("qoi: {0}")]
+    Qoi(String),
+    #[error("webp file has no frame")]
+    EmptyWebp,
+}
+
+/// File format of each frame in a frames directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrameFormat {
+    /// 24-bit RGB PNG.
+    #[default]
+    Png,
+    /// Palette PNG at the smallest bit depth that holds the frame's colours (RGB if over 256).
+    PngIndexed,
+    Qoi,
+    /// Lossless WebP.
+    Webp,
+    /// Binary PPM (`P6`).
+    Ppm,
+}
+
+impl FrameFormat {
+    pub const ALL: [FrameFormat; 5] =
+        [FrameFormat::Png, FrameFormat::PngIndexed, FrameFormat::Qoi, FrameFormat::Webp, FrameFormat::Ppm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::PngIndexed => "png-indexed",
+            FrameFormat::Qoi => "qoi",
+            FrameFormat::Webp => "webp",
+            FrameFormat::Ppm => "ppm",
+        }
+    }
+
+    /// File extension without the dot; both PNG flavours use `.png`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Png | FrameFormat::PngIndexed => "png",
+            FrameFormat::Qoi => "qoi",
+            FrameFormat::Webp => "webp",
+            FrameFormat::Ppm => "ppm",
+        }
+    }
+
+    pub fn is_png(&self) -> bool {
+        *self == FrameFormat::Png
+    }
+
+    /// Format of encoded frame bytes, from their signature. PNGs are reported as [`FrameFormat::Png`]
+    /// whatever their colour type.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(PNG_SIGNATURE) {
+            Some(FrameFormat::Png)
+        } else if bytes.starts_with(QOI_MAGIC) {
+            Some(FrameFormat::Qoi)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(FrameFormat::Webp)
+        } else if bytes.starts_with(b"P6") {
+            Some(FrameFormat::Ppm)
+        } else {
+            None
+        }
+    }
+}
+
+/// File name of frame `index` in `format`.
+pub fn frame_file_name(index: u32, format: FrameFormat) -> String {
+    format!("frame_{:06}.{}", index, format.extension())
+}
+
+/// Index and extension of a file named like [`frame_file_name`] in any format.
+pub(crate) fn parse_frame_file_name(name: &str) -> Option<(u32, &str)> {
+    let (index, ext) = name.strip_prefix("frame_")?.split_once('.')?;
+    if index.len() < 6 || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    FrameFormat::ALL.iter().any(|f| f.extension() == ext).then_some((index.parse().ok()?, ext))
+}
+
+/// Path of frame `index` in `dir`: in the format `m` records, or else whichever other format's
+/// file exists. If none does, the expected path (for the error message).
+pub fn frame_path(dir: &Path, index: u32, m: &EncodeManifest) -> PathBuf {
+    let expected = dir.join(frame_file_name(index, m.frame_format));
+    if expected.exists() {
+        return expected;
+    }
+    FrameFormat::ALL
+        .iter()
+        .map(|&f| dir.join(frame_file_name(index, f)))
+        .find(|p| p.exists())
+        .unwrap_or(expected)
+}
+
+/// Encode one frame image as a file in `format`.
+pub fn encode_frame_file(img: &RgbImage, format: FrameFormat) -> Result<Vec<u8>, FrameFileError> {
+    match format {
+        FrameFormat::Png => encode_png_rgb(img),
+        FrameFormat::PngIndexed => encode_png_indexed(img),
+        FrameFormat::Qoi => Ok(encode_qoi(img)),
+        FrameFormat::Webp => Ok(encode_still(img)?),
+        FrameFormat::Ppm => {
+            let mut out = format!("P6\n{} {}\n255\n", img.width(), img.height()).into_bytes();
+            out.extend_from_slice(img.as_raw());
+            Ok(out)
+        }
+    }
+}
+
+/// Decode a frame file in any of the [`FrameFormat`]s (or any other image `image` reads, such as
+/// JPEG), going by its signature.
+pub fn decode_frame_file(bytes: &[u8]) -> Result<RgbImage, FrameFileError> {
+    match FrameFormat::detect(bytes) {
+        Some(FrameFormat::Qoi) => decode_qoi(bytes),
+        Some(FrameFormat::Webp) => WebpReader::new(Cursor::new(bytes))?.read_frame()?.ok_or(FrameFileError::EmptyWebp),
+        _ => Ok(image::load_from_memory(bytes)?.to_rgb8()),
+    }
+}
+
+/// Read and decode the frame file at `path`.
+pub fn read_frame_file(path: &Path) -> Result<RgbImage, FrameFileError> {
+    decode_frame_file(&fs::read(path)?)
+}
+
+fn encode_png_rgb(img: &RgbImage) -> Result<Vec<u8>, FrameFileError> {
+    let mut out = Vec::new();
+    let mut enc = png::Encoder::new(&mut out, img.width(), img.height());
+    enc.set_color(png::ColorType::Rgb);
+    enc.set_depth(png::BitDepth::Eight);
+    enc.write_header()?.write_image_data(img.as_raw())?;
+    Ok(out)
+}
+
+/// Palette PNG of `img`, packing pixels at 1, 2, 4 or 8 bits. Frames use at most the eight
+/// palette colours plus white, so this is 4 bits at most (1 bit for a solid sync frame).
+fn encode_png_indexed(img: &RgbImage) -> Result<Vec<u8>, FrameFileError> {
+    let mut palette: Vec<Rgb<u8>> = Vec::new();
+    let mut indices = Vec::with_capacity(img.as_raw().len() / 3);
+    let mut last = None;
+    for &px in img.pixels() {
+        let i = match last {
+            Some((c, i)) if c == px => i,
+            _ => match palette.iter().position(|&c| c == px) {
+                Some(i) => i as u8,
+                None if palette.len() < 256 => {
+                    palette.push(px);
+                    (palette.len() - 1) as u8
+                }
+                None => return encode_png_rgb(img),
+            },
+        };
+        last = Some((px, i));
+        indices.push(i);
+    }
+
+    let (depth, bits) = match palette.len() {
+        0..=2 => (png::BitDepth::One, 1),
+        3..=4 => (png::BitDepth::Two, 2),
+        5..=16 => (png::BitDepth::Four, 4),
+        _ => (png::BitDepth::Eight, 8),
+    };
+    let w = img.width() as usize;
+    let row_bytes = (w * bits).div_ceil(8);
+    let mut packed = vec![0u8; row_bytes * img.height() as usize];
+    for (row, out) in indices.chunks(w.max(1)).zip(packed.chunks_mut(row_bytes.max(1))) {
+        for (x, &i) in row.iter().enumerate() {
+            let bit = x * bits;
+            out[bit / 8] |= i << (8 - bits - bit % 8);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut enc = png::Encoder::new(&mut out, img.width(), img.height());
+    enc.set_color(png::ColorType::Indexed);
+    enc.set_depth(depth);
+    enc.set_palette(palette.iter().flat_map(|c| c.0).collect::<Vec<u8>>());
+    enc.write_header()?.write_image_data(&packed)?;
+    Ok(out)
+}
+
+fn qoi_hash([r, g, b, a]: [u8; 4]) -> usize {
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+/// QOI ("Quite OK Image") with three channels, as in the spec at qoiformat.org.
+fn encode_qoi(img: &RgbImage) -> Vec<u8> {
+    let mut out = Vec::with_capacity(img.as_raw().len() / 4 + 32);
+    out.extend_from_slice(QOI_MAGIC);
+    out.extend_from_slice(&img.width().to_be_bytes());
+    out.extend_from_slice(&img.height().to_be_bytes());
+    out.extend_from_slice(&[3, 0]);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0, 0, 0, 255];
+    let mut run = 0u8;
+    for &Rgb([r, g, b]) in img.pixels() {
+        let px = [r, g, b, 255];
+        if px == prev {
+            run += 1;
+            if run == 62 {
+                out.push(0xc0 | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(0xc0 | (run - 1));
+            run = 0;
+        }
+        let h = qoi_hash(px);
+        if index[h] == px {
+            out.push(h as u8);
+        } else {
+            index[h] = px;
+            let dr = r.wrapping_sub(prev[0]) as i8;
+            let dg = g.wrapping_sub(prev[1]) as i8;
+            let db = b.wrapping_sub(prev[2]) as i8;
+            let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+            if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                out.push(0x40 | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+            } else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+                out.push(0x80 | (dg + 32) as u8);
+                out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+            } else {
+                out.extend_from_slice(&[0xfe, r, g, b]);
+            }
+        }
+        prev = px;
+    }
+    if run > 0 {
+        out.push(0xc0 | (run - 1));
+    }
+    out.extend_from_slice(&QOI_END);
+    out
+}
+
+fn decode_qoi(bytes: &[u8]) -> Result<RgbImage, FrameFileError> {
+    let err = |msg: &str| FrameFileError::Qoi(msg.into());
+    if bytes.len() < 14 + QOI_END.len() || !bytes.starts_with(QOI_MAGIC) {
+        return Err(err("truncated header"));
+    }
+    let w = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+    let h = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
+    if !matches!(bytes[12], 3 | 4) {
+        return Err(err("channels must be 3 or 4"));
+    }
+    let pixels = w as u64 * h as u64;
+    if w == 0 || h == 0 || pixels > QOI_MAX_PIXELS {
+        return Err(FrameFileError::Qoi(format!("size {w}x{h} out of range")));
+    }
+    // No op yields more than a 62-pixel run, so a header promising more is a truncated or
+    // hostile file; checking before allocating keeps a few bytes from reserving gigabytes.
+    let data = &bytes[14..bytes.len() - QOI_END.len()];
+    if pixels > data.len() as u64 * 62 {
+        return Err(err("truncated data"));
+    }
+
+    let mut rgb = Vec::with_capacity(pixels as usize * 3);
+    let mut index = [[0u8; 4]; 64];
+    let mut px = [0u8, 0, 0, 255];
+    let mut pos = 0;
+    let mut take = |n: usize| -> Result<&[u8], FrameFileError> {
+        let s = data.get(pos..pos + n).ok_or_else(|| err("truncated data"))?;
+        pos += n;
+        Ok(s)
+    };
+    while rgb.len() < pixels as usize * 3 {
+        let op = take(1)?[0];
+        let mut run = 1;
+        match op {
+            0xfe => px[..3].copy_from_slice(take(3)?),
+            0xff => px.copy_from_slice(take(4)?),
+            _ => match op >> 6 {
+                0 => px = index[op as usize],
+                1 => {
+                    px[0] = px[0].wrapping_add((op >> 4 & 3).wrapping_sub(2));
+                    px[1] = px[1].wrapping_add((op >> 2 & 3).wrapping_sub(2));
+                    px[2] = px[2].wrapping_add((op & 3).wrapping_sub(2));
+                }
+                2 => {
+                    let dg = (op & 0x3f).wrapping_sub(32);
+                    let b2 = take(1)?[0];
+                    px[0] = px[0].wrapping_add(dg.wrapping_add((b2 >> 4).wrapping_sub(8)));
+                    px[1] = px[1].wrapping_add(dg);
+                    px[2] = px[2].wrapping_add(dg.wrapping_add((b2 & 0xf).wrapping_sub(8)));
+                }
+                _ => run = (op & 0x3f) as usize + 1,
+            },
+        }
+        index[qoi_hash(px)] = px;
+        for _ in 0..run.min(pixels as usize - rgb.len() / 3) {
+            rgb.extend_from_slice(&px[..3]);
+        }
+    }
+    RgbImage::from_raw(w, h, rgb).ok_or_else(|| err("pixel count mismatch"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame-like image with `colours` distinct colours in runs, gradients and noise.
+    fn test_image(colours: u32) -> RgbImage {
+        RgbImage::from_fn(97, 61, |x, y| {
+            let k = (x / 3 + y * 7 + (x * y) % 5) % colours;
+            Rgb([(k * 37) as u8, (k * 91 / 3) as u8, (k * 13 + k / 256) as u8])
+        })
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        for colours in [1, 2, 4, 9, 16, 200, 1000] {
+            let img = test_image(colours);
+            for format in FrameFormat::ALL {
+                let bytes = encode_frame_file(&img, format).unwrap();
+                let detected = FrameFormat::detect(&bytes).unwrap();
+                assert_eq!(detected.extension(), format.extension(), "{format:?}");
+                assert!(decode_frame_file(&bytes).unwrap() == img, "{format:?} with {colours} colours");
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_png_uses_the_smallest_depth() {
+        let depth = |colours| {
+            let bytes = encode_frame_file(&test_image(colours), FrameFormat::PngIndexed).unwrap();
+            let reader = png::Decoder::new(Cursor::new(bytes)).read_info().unwrap();
+            let info = reader.info();
+            (info.color_type, info.bit_depth)
+        };
+        assert_eq!(depth(2), (png::ColorType::Indexed, png::BitDepth::One));
+        assert_eq!(depth(4), (png::ColorType::Indexed, png::BitDepth::Two));
+        assert_eq!(depth(9), (png::ColorType::Indexed, png::BitDepth::Four));
+        assert_eq!(depth(200), (png::ColorType::Indexed, png::BitDepth::Eight));
+        assert_eq!(depth(1000), (png::ColorType::Rgb, png::BitDepth::Eight));
+    }
+
+    #[test]
+    fn frame_path_falls_back_to_other_formats() {
+        let dir = std::env::temp_dir().join(format!("sllv_framefile_path_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let m = EncodeManifest { frame_format: FrameFormat::Qoi, ..Default::default() };
+        assert_eq!(frame_path(&dir, 3, &m), dir.join("frame_000003.qoi"));
+        fs::write(dir.join("frame_000003.png"), encode_frame_file(&test_image(4), FrameFormat::Png).unwrap()).unwrap();
+        assert_eq!(frame_path(&dir, 3, &m), dir.join("frame_000003.png"));
+        assert!(read_frame_file(&frame_path(&dir, 3, &m)).unwrap() == test_image(4));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hostile_qoi_headers_are_rejected() {
+        let header = |w: u32, h: u32, channels: u8| {
+            let mut b = QOI_MAGIC.to_vec();
+            b.extend_from_slice(&w.to_be_bytes());
+            b.extend_from_slice(&h.to_be_bytes());
+            b.extend_from_slice(&[channels, 0, 0xc0 | 61]);
+            b.extend_from_slice(&QOI_END);
+            b
+        };
+        assert!(decode_qoi(&header(62, 1, 3)).is_ok());
+        for bad in [header(63, 1, 3), header(20_000, 20_000, 3), header(u32::MAX, u32::MAX, 3), header(0, 5, 3), header(1, 1, 5)] {
+            assert!(matches!(decode_frame_file(&bad), Err(FrameFileError::Qoi(_))));
+        }
+    }
+
+    #[test]
+    fn truncated_or_corrupted_files_fail_cleanly() {
+        let img = test_image(9);
+        for format in FrameFormat::ALL {
+            let bytes = encode_frame_file(&img, format).unwrap();
+            for len in (0..bytes.len()).step_by(7) {
+                let _ = decode_frame_file(&bytes[..len]);
+            }
+            for pos in (0..bytes.len()).step_by(5) {
+                let mut bad = bytes.clone();
+                bad[pos] ^= 0x5a;
+                let _ = decode_frame_file(&bad);
+            }
+        }
+        assert!(decode_frame_file(b"not an image at all").is_err());
+        assert!(decode_frame_file(b"P6\n99999 99999\n255\n").is_err());
+    }
+}
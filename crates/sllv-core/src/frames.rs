@@ -0,0 +1,773 @@
+use crate::compress::{compress_bytes, CompressionInfo};
+use crate::crypto::{encrypt_bytes, EncryptionInfo};
+use crate::sign::sign_manifest;
+use crate::toc::{toc_frame_payloads, Toc, TocInfo};
+use crate::fec::{fec_encode_stream, FecGroupAssembler, ShardPacket, DEFAULT_WINDOW_GROUPS};
+use crate::manifest::{EncodeManifest, PayloadKind};
+use crate::palette::Palette8;
+use crate::raster::{
+    check_frame_size, compose_tiles, decode_rgb_frame_bytes, frame_payload_capacity, layout_manifest, max_shard_bytes,
+    render_calibration_frame, render_payload_frame, finish_payload, payload_io_error, payload_sink, render_solid_frame,
+    rgb_frame_symbol_count, shard_frame_payload, shard_packet_from_frame_bytes, tile_payloads, PayloadSink, RasterError,
+    RasterParams, MAX_TILES,
+};
+use crate::dedup::{dedup_bytes, DedupInfo};
+use crate::volume::{header_frame_payload, VolumeInfo};
+use image::RgbImage;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+
+/// In-memory frame source: renders sync, calibration and data frames one at a time.
+///
+/// This is the same frame sequence `encode_bytes_to_frames_dir` writes to disk, for callers that
+/// want to push frames straight to a display or video pipeline.
+pub struct FrameEncoder {
+    p: RasterParams,
+    manifest: EncodeManifest,
+    data: FrameData,
+    /// Volume header frame payload, for one volume of a split encode.
+    volume_frame: Option<Vec<u8>>,
+    /// One copy of the table-of-contents frame payloads; repeated `TocInfo::copies` times.
+    toc_frames: Vec<Vec<u8>>,
+    next: u32,
+}
+
+enum FrameData {
+    Shards {
+        packets: Vec<ShardPacket>,
+        orig_total_bytes: u64,
+        frame_payload: usize,
+    },
+    Chunks {
+        bytes: Vec<u8>,
+        per_frame: usize,
+    },
+}
+
+impl FrameEncoder {
+    /// Plan the frames for `input_bytes`, compressing and then encrypting them first if
+    /// `p.compression` / `p.encryption` ask for it. With `p.toc`, a table of contents of the
+    /// (uncompressed) payload follows the data frames; it is skipped if a tar payload won't parse.
+    pub fn new(input_bytes: &[u8], file_name: &str, p: &RasterParams) -> Result<Self, RasterError> {
+        let toc = match table_of_contents(input_bytes, file_name, p) {
+            Some(toc) => Some(toc_frame_payloads(&toc, p.encryption.as_ref(), frame_payload_capacity(p) as usize)?),
+            None => None,
+        };
+        let carried = carry_payload(input_bytes, p)?;
+        Self::plan(carried, file_name, p, toc, None)
+    }
+
+    /// Lay out frames for bytes that already went through [`carry_payload`], followed by an
+    /// optional volume header frame and the table-of-contents frames from [`toc_frame_payloads`].
+    pub(crate) fn plan(
+        carried: Carried<'_>,
+        file_name: &str,
+        p: &RasterParams,
+        toc: Option<(TocInfo, Vec<Vec<u8>>)>,
+        volume: Option<VolumeInfo>,
+    ) -> Result<Self, RasterError> {
+        let input_bytes = &carried.bytes[..];
+        let mut hasher = Sha256::new();
+        hasher.update(input_bytes);
+        let sha256_hex = hex::encode(hasher.finalize());
+
+        let max_frame_payload = frame_payload_capacity(p);
+        if max_frame_payload == 0 {
+            return Err(RasterError::Fec("frame too small for payload".into()));
+        }
+
+        if p.tiles.iter().any(|n| !(1..=MAX_TILES).contains(n)) {
+            return Err(RasterError::Fec(format!("tiles must be between 1x1 and {MAX_TILES}x{MAX_TILES}")));
+        }
+        let tiles = p.tiles[0] * p.tiles[1];
+        if tiles > 1 && (p.fec.is_none() || !p.border_fiducials) {
+            return Err(RasterError::Fec("tiled frames need FEC and border_fiducials".into()));
+        }
+
+        let (data, chunk_bytes, data_frames) = if let Some(fecp) = &p.fec {
+            // Each frame holds a shard header and the shard (see `shard_frame_payload`), and the
+            // header records the shard length in 16 bits.
+            let max_shard = max_shard_bytes(p).min(u16::MAX as usize);
+            if fecp.shard_bytes > max_shard {
+                return Err(RasterError::Fec(format!(
+                    "fec shard_bytes {} exceeds the {} bytes a frame can hold",
+                    fecp.shard_bytes, max_shard
+                )));
+            }
+            let packets = fec_encode_stream(input_bytes, fecp).map_err(|e| RasterError::Fec(e.to_string()))?;
+            let n = (packets.len() as u32).div_ceil(tiles);
+            let data = FrameData::Shards {
+                packets,
+                orig_total_bytes: input_bytes.len() as u64,
+                frame_payload: max_frame_payload as usize,
+            };
+            (data, max_frame_payload, n)
+        } else {
+            let per_frame = std::cmp::min(max_frame_payload, p.chunk_bytes) as usize;
+            let n = input_bytes.len().div_ceil(per_frame) as u32;
+            let data = FrameData::Chunks {
+                bytes: input_bytes.to_vec(),
+                per_frame,
+            };
+            (data, per_frame as u32, n)
+        };
+
+        let data_end = p.sync_frames + p.calibration_frames + data_frames;
+        let (volume, volume_frame) = match volume {
+            Some(mut info) => {
+                info.header_frame = data_end;
+                let frame = header_frame_payload(&info, max_frame_payload as usize)?;
+                (Some(info), Some(frame))
+            }
+            None => (None, None),
+        };
+        let toc_start = data_end + volume_frame.is_some() as u32;
+        let (toc, toc_frames) = match toc {
+            Some((mut info, frames)) => {
+                info.first_frame = toc_start;
+                info.copies = TocInfo::COPIES;
+                (Some(info), frames)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let mut manifest = EncodeManifest {
+            magic: EncodeManifest::MAGIC.to_string(),
+            version: EncodeManifest::VERSION,
+            file_name: file_name.to_string(),
+            total_bytes: input_bytes.len() as u64,
+            chunk_bytes,
+            grid_w: p.grid_w,
+            grid_h: p.grid_h,
+            cell_px: p.cell_px,
+            palette: p.palette.id().to_string(),
+            sha256_hex,
+            frames: toc_start + toc.as_ref().map_or(0, |t| t.frames * t.copies),
+            dedup: carried.dedup,
+            compression: carried.compression,
+            encryption: carried.encryption,
+            payload: p.payload,
+            filter: p.filter.clone(),
+            delta: p.delta.as_ref().map(|d| d.info.clone()),
+            toc,
+            volume,
+            tiles: (tiles > 1).then_some(p.tiles),
+            frame_format: p.frame_format,
+            ..Default::default()
+        };
+        if let Some(signer) = &p.signing {
+            sign_manifest(&mut manifest, signer)?;
+        }
+
+        Ok(Self {
+            p: p.clone(),
+            manifest,
+            data,
+            volume_frame,
+            toc_frames,
+            next: 0,
+        })
+    }
+
+    pub fn manifest(&self) -> &EncodeManifest {
+        &self.manifest
+    }
+
+    pub fn params(&self) -> &RasterParams {
+        &self.p
+    }
+
+    pub fn total_frames(&self) -> u32 {
+        self.manifest.frames
+    }
+
+    /// Index of the first data frame (after sync and calibration).
+    pub fn data_start(&self) -> u32 {
+        self.p.sync_frames + self.p.calibration_frames
+    }
+
+    pub fn frames_emitted(&self) -> u32 {
+        self.next
+    }
+
+    /// Render the next frame in sequence, or `None` once every frame has been produced.
+    pub fn next_frame(&mut self) -> Result<Option<RgbImage>, RasterError> {
+        if self.next >= self.total_frames() {
+            return Ok(None);
+        }
+        let img = self.frame(self.next)?;
+        self.next += 1;
+        Ok(Some(img))
+    }
+
+    /// Render every frame on worker threads and hand them to `f` in order.
+    ///
+    /// Workers stay at most four frames each ahead of `f`, so a slow consumer (a pipe, a file) or
+    /// a slow frame holds back rendering instead of buffering the whole sequence. If `f` fails,
+    /// the workers stop and the error is returned.
+    pub fn render_in_order<E: From<RasterError>>(
+        &self,
+        mut f: impl FnMut(u32, RgbImage) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let total = self.total_frames();
+        let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(8);
+        let ahead = 4 * num_workers as u32;
+        let (tx_img, rx_img) = mpsc::sync_channel::<(u32, Result<RgbImage, RasterError>)>(ahead as usize);
+        let counter = AtomicU32::new(0);
+        // Next frame `f` needs; `u32::MAX` once the consumer has stopped.
+        let consumed = (Mutex::new(0u32), Condvar::new());
+
+        std::thread::scope(|s| {
+            for _ in 0..num_workers {
+                let tx = tx_img.clone();
+                let (counter, consumed) = (&counter, &consumed);
+                s.spawn(move || loop {
+                    let idx = counter.fetch_add(1, Ordering::Relaxed);
+                    if idx >= total {
+                        break;
+                    }
+                    let mut next = consumed.0.lock().unwrap_or_else(|e| e.into_inner());
+                    while idx >= next.saturating_add(ahead) {
+                        next = consumed.1.wait(next).unwrap_or_else(|e| e.into_inner());
+                    }
+                    drop(next);
+                    if tx.send((idx, self.frame(idx))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx_img);
+
+            // Runs however the loop below ends, so workers waiting for room see the stop and
+            // those blocked in `send` find the receiver gone.
+            let _stop = StopOnDrop(&consumed);
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (idx, img) in rx_img {
+                pending.insert(idx, img?);
+                while let Some(img) = pending.remove(&next) {
+                    f(next, img)?;
+                    next += 1;
+                    *consumed.0.lock().unwrap_or_else(|e| e.into_inner()) = next;
+                    consumed.1.notify_all();
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Render an arbitrary frame by index. Useful for looping a display or re-sending a frame.
+    ///
+    /// With `p.tiles`, data frames carry one shard per tile; every other frame repeats in each tile.
+    pub fn frame(&self, index: u32) -> Result<RgbImage, RasterError> {
+        let p = &self.p;
+        if index >= self.total_frames() {
+            return Err(RasterError::FrameOutOfRange(index));
+        }
+        compose_tiles(self.tile_images(index)?, p)
+    }
+
+    /// The single-tile frame images that make up frame `index`.
+    fn tile_images(&self, index: u32) -> Result<Vec<RgbImage>, RasterError> {
+        let p = &self.p;
+        if index < p.sync_frames {
+            return Ok(vec![render_solid_frame(p, p.sync_color_symbol)?]);
+        }
+        if index < self.data_start() {
+            return Ok(vec![render_calibration_frame(p)?]);
+        }
+
+        if let Some(toc) = self.manifest.toc.as_ref().filter(|t| index >= t.first_frame) {
+            let i = (index - toc.first_frame) as usize % self.toc_frames.len().max(1);
+            return Ok(vec![render_payload_frame(&self.toc_frames[i], p)?]);
+        }
+        if let Some(header) = self.volume_frame.as_ref().filter(|_| index >= self.manifest.data_end()) {
+            return Ok(vec![render_payload_frame(header, p)?]);
+        }
+
+        let i = (index - self.data_start()) as usize;
+        match &self.data {
+            FrameData::Shards {
+                packets,
+                orig_total_bytes,
+                frame_payload,
+            } => {
+                let tiles = (p.tiles[0] * p.tiles[1]) as usize;
+                let end = ((i + 1) * tiles).min(packets.len());
+                packets[i * tiles..end]
+                    .iter()
+                    .map(|pkt| render_payload_frame(&shard_frame_payload(pkt, *orig_total_bytes, *frame_payload), p))
+                    .collect()
+            }
+            FrameData::Chunks { bytes, per_frame } => {
+                let start = i * per_frame;
+                let end = std::cmp::min(start + per_frame, bytes.len());
+                let mut frame_payload = vec![0u8; *per_frame];
+                frame_payload[..end - start].copy_from_slice(&bytes[start..end]);
+                Ok(vec![render_payload_frame(&frame_payload, p)?])
+            }
+        }
+    }
+}
+
+/// Payload after the optional dedup, compression and encryption stages: the bytes the frames carry.
+pub(crate) struct Carried<'a> {
+    pub bytes: Cow<'a, [u8]>,
+    pub dedup: Option<DedupInfo>,
+    pub compression: Option<CompressionInfo>,
+    pub encryption: Option<EncryptionInfo>,
+}
+
+/// Deduplicate, compress and then encrypt `input_bytes` as `p.dedup` / `p.compression` /
+/// `p.encryption` ask.
+pub(crate) fn carry_payload<'a>(input_bytes: &'a [u8], p: &RasterParams) -> Result<Carried<'a>, RasterError> {
+    let mut bytes = Cow::Borrowed(input_bytes);
+    let mut dedup = None;
+    if let Some(dp) = &p.dedup {
+        // A plaintext hash next to ciphertext would let anyone confirm a guessed input.
+        let (container, info) = dedup_bytes(input_bytes, dp, p.encryption.is_none());
+        bytes = Cow::Owned(container);
+        dedup = Some(info);
+    }
+
+    let mut compression = None;
+    if !p.compression.is_none() {
+        compression = Some(CompressionInfo {
+            method: p.compression,
+            uncompressed_bytes: bytes.len() as u64,
+            uncompressed_sha256_hex: if p.encryption.is_some() {
+                String::new()
+            } else {
+                hex::encode(Sha256::digest(&bytes))
+            },
+        });
+        bytes = Cow::Owned(compress_bytes(&bytes, p.compression)?);
+    }
+
+    let mut encryption = None;
+    if let Some(key) = &p.encryption {
+        let (ct, info) = encrypt_bytes(&bytes, key)?;
+        bytes = Cow::Owned(ct);
+        encryption = Some(info);
+    }
+    Ok(Carried {
+        bytes,
+        dedup,
+        compression,
+        encryption,
+    })
+}
+
+/// Table of contents for the (uncompressed) payload, if `p.toc` asks for one and a tar payload
+/// parses, with the base entries and deletions of `p.delta`.
+pub(crate) fn table_of_contents(input_bytes: &[u8], file_name: &str, p: &RasterParams) -> Option<Toc> {
+    let mut toc = match p.payload {
+        _ if !p.toc => None,
+        PayloadKind::Tar => Toc::from_tar(input_bytes).ok(),
+        PayloadKind::Raw => Some(Toc::from_raw(file_name, input_bytes)),
+    }?;
+    if let Some(delta) = &p.delta {
+        toc.unchanged = delta.unchanged.clone();
+        toc.deleted = delta.deleted.clone();
+    }
+    Some(toc)
+}
+
+/// Outcome of handing one frame to [`FrameDecoder::push_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeStatus {
+    /// The frame carried nothing new (sync, calibration, duplicate or unreadable).
+    Ignored,
+    /// The frame was accepted. `done`/`total` count FEC groups (or chunks without FEC).
+    Accepted { done: u64, total: u64 },
+    /// Every byte has been recovered; call [`FrameDecoder::finish`].
+    Complete,
+}
+
+/// Marks the consumer of [`FrameEncoder::render_in_order`] as stopped when dropped.
+struct StopOnDrop<'a>(&'a (Mutex<u32>, Condvar));
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        *self.0 .0.lock().unwrap_or_else(|e| e.into_inner()) = u32::MAX;
+        self.0 .1.notify_all();
+    }
+}
+
+/// In-memory frame sink: demodulates frames pushed one at a time (e.g. from a camera callback).
+///
+/// With FEC, frames may arrive in any order and repeat; the shard headers carry everything needed
+/// to reassemble, so a manifest is optional and only adds the final SHA-256 check. Without FEC the
+/// frames carry no headers, so a manifest is required and frames must be pushed in order.
+pub struct FrameDecoder<W: Write = Vec<u8>> {
+    p: RasterParams,
+    manifest: Option<EncodeManifest>,
+    layout: EncodeManifest,
+    out: Option<W>,
+    sink: Option<PayloadSink<W>>,
+    hasher: Sha256,
+    assembler: Option<FecGroupAssembler>,
+    window: u32,
+    chunks_done: u64,
+    calibration_seen: u32,
+}
+
+impl FrameDecoder<Vec<u8>> {
+    pub fn new(p: &RasterParams) -> Self {
+        Self::with_writer(p, Vec::new())
+    }
+}
+
+impl<W: Write> FrameDecoder<W> {
+    /// Decode into `out`, writing each FEC group as soon as it completes.
+    pub fn with_writer(p: &RasterParams, out: W) -> Self {
+        Self {
+            p: p.clone(),
+            manifest: None,
+            layout: layout_manifest(p),
+            out: Some(out),
+            sink: None,
+            hasher: Sha256::new(),
+            assembler: None,
+            window: DEFAULT_WINDOW_GROUPS,
+            chunks_done: 0,
+            calibration_seen: 0,
+        }
+    }
+
+    /// Buffer shards for at most `groups` FEC groups past the next one to write. Shards further
+    /// ahead are ignored until the groups before them complete (a looping display repeats them).
+    pub fn with_window(mut self, groups: u32) -> Self {
+        self.window = groups;
+        self
+    }
+
+    /// Attach the encoder's manifest so the result can be verified, decrypted and decompressed
+    /// (required without FEC). Without a manifest, the carried bytes are written out as-is.
+    pub fn with_manifest(mut self, m: EncodeManifest) -> Self {
+        self.layout = m.clone();
+        self.manifest = Some(m);
+        self
+    }
+
+    pub fn is_complete(&self) -> bool {
+        match (&self.assembler, &self.p.fec, &self.manifest) {
+            (Some(a), Some(_), _) => a.is_complete(),
+            (None, None, Some(m)) => self.chunks_done >= m.total_bytes.div_ceil(m.chunk_bytes.max(1) as u64),
+            _ => false,
+        }
+    }
+
+    /// Push the demodulated bytes of one frame, e.g. a frame already located on a scanned page.
+    /// Only FEC archives are self-describing enough to decode this way.
+    pub(crate) fn push_frame_bytes(&mut self, bytes: &[u8]) -> Result<DecodeStatus, RasterError> {
+        if self.is_complete() {
+            return Ok(DecodeStatus::Complete);
+        }
+        let Some(fecp) = self.p.fec.clone() else {
+            return Err(RasterError::Fec("frame bytes can only be pushed for FEC archives".into()));
+        };
+        let Some((pkt, orig_total_bytes)) = shard_packet_from_frame_bytes(bytes) else {
+            return Ok(DecodeStatus::Ignored);
+        };
+
+        if self.assembler.is_none() {
+            let total = match &self.manifest {
+                Some(m) => m.total_bytes,
+                None => orig_total_bytes,
+            };
+            let assembler = FecGroupAssembler::new(&fecp, total).map_err(|e| RasterError::Fec(e.to_string()))?;
+            self.assembler = Some(assembler.with_window(self.window));
+        }
+        let Some(assembler) = self.assembler.as_mut() else {
+            return Ok(DecodeStatus::Ignored);
+        };
+
+        // Too far ahead of a group that is still incomplete: a looping capture shows it again.
+        if assembler.push(pkt).is_err() {
+            return Ok(DecodeStatus::Ignored);
+        }
+        while let Some(group) = assembler.pop_ready() {
+            self.hasher.update(&group);
+            Self::sink(&mut self.sink, &mut self.out, &self.manifest, &self.p)?
+                .write_all(&group)
+                .map_err(payload_io_error)?;
+        }
+        if assembler.is_complete() {
+            return Ok(DecodeStatus::Complete);
+        }
+        Ok(DecodeStatus::Accepted {
+            done: assembler.next_group() as u64,
+            total: assembler.total_groups() as u64,
+        })
+    }
+
+    pub fn push_frame(&mut self, img: &RgbImage) -> Result<DecodeStatus, RasterError> {
+        if self.is_complete() {
+            return Ok(DecodeStatus::Complete);
+        }
+        let palette = Palette8::Basic;
+
+        if self.p.fec.is_some() {
+            let Ok(bytes) = decode_rgb_frame_bytes(img, &self.layout, &self.p, palette) else {
+                return Ok(DecodeStatus::Ignored);
+            };
+            // Each tile of a tiled frame is a shard of its own.
+            let mut status = DecodeStatus::Ignored;
+            for tile in tile_payloads(&bytes, &self.layout) {
+                match self.push_frame_bytes(tile)? {
+                    DecodeStatus::Ignored => {}
+                    s => status = s,
+                }
+            }
+            return Ok(status);
+        }
+
+        let Some(m) = self.manifest.clone() else {
+            return Err(RasterError::ManifestMissing);
+        };
+
+        check_frame_size(img, &m, &self.p)?;
+        // Mirror `detect_data_start`: skip solid sync frames, then the calibration frame(s).
+        if self.chunks_done == 0 {
+            if rgb_frame_symbol_count(img, &m, &self.p, palette) <= 1 {
+                return Ok(DecodeStatus::Ignored);
+            }
+            if self.calibration_seen < self.p.calibration_frames {
+                self.calibration_seen += 1;
+                return Ok(DecodeStatus::Ignored);
+            }
+        }
+
+        let bytes = decode_rgb_frame_bytes(img, &m, &self.p, palette)?;
+        let per_frame = m.chunk_bytes as u64;
+        let total_chunks = m.total_bytes.div_ceil(per_frame.max(1));
+        let remaining = m.total_bytes - self.chunks_done * per_frame;
+        let take = std::cmp::min(std::cmp::min(bytes.len() as u64, per_frame), remaining) as usize;
+        self.hasher.update(&bytes[..take]);
+        Self::sink(&mut self.sink, &mut self.out, &self.manifest, &self.p)?
+            .write_all(&bytes[..take])
+            .map_err(payload_io_error)?;
+        self.chunks_done += 1;
+
+        if self.chunks_done >= total_chunks {
+            return Ok(DecodeStatus::Complete);
+        }
+        Ok(DecodeStatus::Accepted {
+            done: self.chunks_done,
+            total: total_chunks,
+        })
+    }
+
+    /// Flush and return the output once complete, verifying the SHA-256 when a manifest is known.
+    pub fn finish(mut self) -> Result<W, RasterError> {
+        if !self.is_complete() {
+            let msg = match &self.assembler {
+                Some(a) => format!("group {} of {} is missing data shards", a.next_group(), a.total_groups()),
+                None => "no data frames decoded".into(),
+            };
+            return Err(RasterError::Fec(msg));
+        }
+        Self::sink(&mut self.sink, &mut self.out, &self.manifest, &self.p)?;
+        let Some(sink) = self.sink.take() else {
+            return Err(RasterError::Fec("decoder output already taken".into()));
+        };
+        let (out, _) = finish_payload(sink, self.manifest.as_ref(), &hex::encode(self.hasher.finalize()))?;
+        Ok(out)
+    }
+
+    /// The output stage is created on first use so a manifest attached up front decides whether
+    /// it decrypts and decompresses.
+    fn sink<'a>(
+        sink: &'a mut Option<PayloadSink<W>>,
+        out: &mut Option<W>,
+        manifest: &Option<EncodeManifest>,
+        p: &RasterParams,
+    ) -> Result<&'a mut PayloadSink<W>, RasterError> {
+        if sink.is_none() {
+            let Some(out) = out.take() else {
+                return Err(RasterError::Fec("decoder output already taken".into()));
+            };
+            *sink = Some(payload_sink(manifest.as_ref(), p, out)?);
+        }
+        match sink.as_mut() {
+            Some(s) => Ok(s),
+            None => Err(RasterError::Fec("decoder output already taken".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compression;
+    use crate::crypto::{DecryptionKey, EncryptionKey};
+    use crate::fec::FecParams;
+    use crate::profile::Profile;
+
+    fn plain_params() -> RasterParams {
+        RasterParams { grid_w: 64, grid_h: 64, sync_frames: 2, payload: PayloadKind::Raw, toc: false, ..Profile::Archive.defaults() }
+    }
+
+    fn fec_params() -> RasterParams {
+        RasterParams {
+            fec: Some(FecParams { data_shards: 4, parity_shards: 2, shard_bytes: 256 }),
+            ..plain_params()
+        }
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 300) as u8).collect()
+    }
+
+    fn frames_of(enc: &FrameEncoder) -> Vec<RgbImage> {
+        (0..enc.total_frames()).map(|i| enc.frame(i).unwrap()).collect()
+    }
+
+    #[test]
+    fn round_trip_without_fec_needs_the_manifest() {
+        let data = test_data(5000);
+        let p = plain_params();
+        let mut enc = FrameEncoder::new(&data, "data.bin", &p).unwrap();
+        let frames = frames_of(&enc);
+        let mut streamed = Vec::new();
+        while let Some(img) = enc.next_frame().unwrap() {
+            streamed.push(img);
+        }
+        assert!(streamed == frames);
+
+        let mut dec = FrameDecoder::new(&p).with_manifest(enc.manifest().clone());
+        let mut last = DecodeStatus::Ignored;
+        for img in &frames {
+            last = dec.push_frame(img).unwrap();
+        }
+        assert_eq!(last, DecodeStatus::Complete);
+        assert_eq!(dec.finish().unwrap(), data);
+
+        assert!(matches!(FrameDecoder::new(&p).push_frame(&frames[3]), Err(RasterError::ManifestMissing)));
+    }
+
+    #[test]
+    fn render_in_order_delivers_every_frame_in_order() {
+        let enc = FrameEncoder::new(&test_data(200_000), "data.bin", &plain_params()).unwrap();
+        let mut seen = Vec::new();
+        enc.render_in_order(|i, img| -> Result<(), RasterError> {
+            assert!(img == enc.frame(i).unwrap());
+            seen.push(i);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, (0..enc.total_frames()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn render_in_order_stops_when_the_consumer_fails() {
+        let enc = FrameEncoder::new(&test_data(500_000), "data.bin", &plain_params()).unwrap();
+        assert!(enc.total_frames() > 300);
+        for fail_at in [0, 40] {
+            let mut calls = 0;
+            let err = enc.render_in_order(|i, _| {
+                calls += 1;
+                if i == fail_at {
+                    return Err(RasterError::Fec("consumer gone".into()));
+                }
+                Ok(())
+            });
+            assert!(matches!(err, Err(RasterError::Fec(_))));
+            assert_eq!(calls, fail_at + 1);
+        }
+    }
+
+    #[test]
+    fn fec_frames_decode_in_any_order_with_losses() {
+        let data = test_data(9000);
+        let p = RasterParams { compression: Compression::Zstd { level: 3 }, ..fec_params() };
+        let enc = FrameEncoder::new(&data, "data.bin", &p).unwrap();
+        let mut frames = frames_of(&enc);
+        frames.drain(..enc.data_start() as usize);
+        frames.reverse();
+        // Lose one shard of every group, see another twice, and mix in a blank frame.
+        let mut pushed: Vec<RgbImage> = frames.iter().enumerate().filter(|(i, _)| i % 6 != 1).map(|(_, f)| f.clone()).collect();
+        pushed.insert(2, frames[0].clone());
+        pushed.insert(0, RgbImage::new(frames[0].width(), frames[0].height()));
+
+        let mut dec = FrameDecoder::new(&p).with_manifest(enc.manifest().clone()).with_window(64);
+        for img in &pushed {
+            dec.push_frame(img).unwrap();
+        }
+        assert!(dec.is_complete());
+        assert_eq!(dec.finish().unwrap(), data);
+    }
+
+    #[test]
+    fn shard_size_is_checked_against_the_frame() {
+        let with_shard = |shard_bytes| RasterParams {
+            fec: Some(FecParams { data_shards: 4, parity_shards: 2, shard_bytes }),
+            ..plain_params()
+        };
+        let max = max_shard_bytes(&with_shard(0));
+        let data = test_data(5000);
+        let enc = FrameEncoder::new(&data, "data.bin", &with_shard(max)).unwrap();
+        let mut dec = FrameDecoder::new(&with_shard(max)).with_manifest(enc.manifest().clone());
+        for img in frames_of(&enc) {
+            dec.push_frame(&img).unwrap();
+        }
+        assert_eq!(dec.finish().unwrap(), data);
+
+        // Up to the payload capacity, which used to pass the check and then panic while rendering.
+        let capacity = frame_payload_capacity(&with_shard(0)) as usize;
+        for shard_bytes in [max + 1, (max + capacity) / 2, capacity] {
+            assert!(matches!(FrameEncoder::new(&data, "data.bin", &with_shard(shard_bytes)), Err(RasterError::Fec(_))));
+        }
+    }
+
+    #[test]
+    fn encrypted_round_trip_needs_the_key() {
+        let data = test_data(3000);
+        let (secret, public) = crate::crypto::generate_x25519_keypair().unwrap();
+        let p = RasterParams { encryption: Some(EncryptionKey::Recipient(public)), ..fec_params() };
+        let enc = FrameEncoder::new(&data, "data.bin", &p).unwrap();
+        let decode = |p: &RasterParams| {
+            let mut dec = FrameDecoder::new(p).with_manifest(enc.manifest().clone());
+            for img in frames_of(&enc) {
+                dec.push_frame(&img)?;
+            }
+            dec.finish()
+        };
+        let keyed = RasterParams { decryption: Some(DecryptionKey::Identity(secret)), ..fec_params() };
+        assert_eq!(decode(&keyed).unwrap(), data);
+        assert!(decode(&fec_params()).is_err());
+    }
+
+    #[test]
+    fn incomplete_or_mismatched_input_is_rejected() {
+        let data = test_data(5000);
+        let p = fec_params();
+        let enc = FrameEncoder::new(&data, "data.bin", &p).unwrap();
+        let frames = frames_of(&enc);
+
+        let mut partial = FrameDecoder::new(&p).with_manifest(enc.manifest().clone());
+        partial.push_frame(&frames[enc.data_start() as usize]).unwrap();
+        assert!(matches!(partial.finish(), Err(RasterError::Fec(_))));
+        assert!(matches!(FrameDecoder::new(&p).finish(), Err(RasterError::Fec(_))));
+
+        let mut wrong = enc.manifest().clone();
+        wrong.sha256_hex = "00".repeat(32);
+        let mut dec = FrameDecoder::new(&p).with_manifest(wrong);
+        for img in &frames {
+            dec.push_frame(img).unwrap();
+        }
+        assert!(dec.finish().is_err());
+
+        let plain = plain_params();
+        let m = FrameEncoder::new(&data, "data.bin", &plain).unwrap().manifest().clone();
+        let mut dec = FrameDecoder::new(&plain).with_manifest(EncodeManifest { grid_w: u32::MAX, ..m.clone() });
+        assert!(matches!(dec.push_frame(&frames[0]), Err(RasterError::FrameTooSmall { .. })));
+        let mut dec = FrameDecoder::new(&plain).with_manifest(m);
+        assert!(matches!(dec.push_frame(&RgbImage::new(8, 8)), Err(RasterError::FrameTooSmall { .. })));
+    }
+}
@@ -0,0 +1,681 @@
+//! GIF89a animation: LZW-compressed indexed frames, at most 256 colours each.
+//!
+//! The writer gives every frame its own colour table, so only the colours of a single frame have
+//! to fit in 256; SLLV frames use a handful. It loops forever (NETSCAPE2.0) and can store a
+//! comment block before the first frame, which is where the manifest goes.
+//!
+//! The reader handles any GIF87a/89a: global and local colour tables, interlacing, transparency,
+//! sub-rectangle frames and all disposal methods, returning each frame composited on the canvas.
+//!
+//! Why not `image`'s `gif` feature: neither its decoder nor the `gif` crate's frame reader it is
+//! built on hands back comment extensions, so the manifest would be lost on the way in.
+
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use thiserror::Error;
+
+const MAX_CODE: u16 = 4096;
+/// Largest canvas or frame side the reader accepts; the format allows 65535.
+const MAX_SIDE: u32 = 16384;
+
+#[derive(Debug, Error)]
+pub enum GifError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a GIF file")]
+    NotGif,
+    #[error("format: {0}")]
+    Format(String),
+    #[error("frame {index} has {colors} colours; GIF allows 256")]
+    TooManyColors { index: u64, colors: usize },
+    #[error("frame {index}: {msg}")]
+    Frame { index: u64, msg: String },
+}
+
+/// Writes RGB frames as an endlessly looping GIF.
+pub struct GifWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+    delay_cs: u16,
+    frames: u64,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Write the header; `delay_ms` is rounded to GIF's hundredths of a second. `comment` (e.g.
+    /// the manifest) is stored in a comment extension before the first frame.
+    pub fn new(mut out: W, width: u32, height: u32, delay_ms: u32, comment: Option<&[u8]>) -> Result<Self, GifError> {
+        if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(GifError::Format(format!("size {width}x{height} outside 1..=65535")));
+        }
+        out.write_all(b"GIF89a")?;
+        out.write_all(&(width as u16).to_le_bytes())?;
+        out.write_all(&(height as u16).to_le_bytes())?;
+        // No global colour table, background index 0, square pixels.
+        out.write_all(&[0, 0, 0])?;
+        out.write_all(&[0x21, 0xff, 0x0b])?;
+        out.write_all(b"NETSCAPE2.0")?;
+        out.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+        if let Some(comment) = comment {
+            out.write_all(&[0x21, 0xfe])?;
+            write_sub_blocks(&mut out, comment)?;
+        }
+        Ok(Self {
+            out,
+            width,
+            height,
+            delay_cs: (delay_ms.div_ceil(10)).min(u16::MAX as u32) as u16,
+            frames: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, img: &RgbImage) -> Result<(), GifError> {
+        if img.dimensions() != (self.width, self.height) {
+            return Err(GifError::Frame {
+                index: self.frames,
+                msg: format!("{}x{}, expected {}x{}", img.width(), img.height(), self.width, self.height),
+            });
+        }
+        let mut colors: HashMap<[u8; 3], u8> = HashMap::new();
+        let mut table: Vec<[u8; 3]> = Vec::new();
+        let mut indices = Vec::with_capacity(img.len() / 3);
+        for px in img.pixels() {
+            let index = match colors.get(&px.0) {
+                Some(&i) => i,
+                None => {
+                    if table.len() == 256 {
+                        return Err(GifError::TooManyColors {
+                            index: self.frames,
+                            colors: img.pixels().map(|p| p.0).collect::<std::collections::HashSet<_>>().len(),
+                        });
+                    }
+                    let i = table.len() as u8;
+                    colors.insert(px.0, i);
+                    table.push(px.0);
+                    i
+                }
+            };
+            indices.push(index);
+        }
+        // Colour tables hold 2^(size+1) entries.
+        let size = (table.len().max(2).next_power_of_two().trailing_zeros() - 1) as u8;
+        table.resize(2 << size, [0, 0, 0]);
+
+        // Graphic control: keep the frame on screen for the delay, no transparency.
+        self.out.write_all(&[0x21, 0xf9, 0x04, 0x04])?;
+        self.out.write_all(&self.delay_cs.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+
+        self.out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.out.write_all(&(self.width as u16).to_le_bytes())?;
+        self.out.write_all(&(self.height as u16).to_le_bytes())?;
+        self.out.write_all(&[0x80 | size])?;
+        for c in &table {
+            self.out.write_all(c)?;
+        }
+        let min_code_size = (size + 1).max(2);
+        self.out.write_all(&[min_code_size])?;
+        write_sub_blocks(&mut self.out, &lzw_encode(&indices, min_code_size))?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Write the trailer and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, GifError> {
+        self.out.write_all(&[0x3b])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// What the last frame asked to be done with its area before the next one is drawn.
+#[derive(Clone, Copy)]
+enum Disposal {
+    Keep,
+    Background { x: u32, y: u32, w: u32, h: u32 },
+    Previous,
+}
+
+/// Reads a GIF as a sequence of composited RGB frames.
+pub struct GifReader<R: Read> {
+    input: BufReader<R>,
+    global: Option<Vec<[u8; 3]>>,
+    background: [u8; 3],
+    canvas: RgbImage,
+    saved: Option<RgbImage>,
+    disposal: Disposal,
+    /// From the graphic control extension that applies to the next image.
+    next_disposal: u8,
+    next_transparent: Option<u8>,
+    next_delay_cs: u16,
+    delay_cs: u16,
+    comments: Vec<Vec<u8>>,
+    /// The image descriptor introducer was read while looking for comments.
+    pending_image: bool,
+    done: bool,
+    read: u64,
+}
+
+impl<R: Read> GifReader<R> {
+    /// Parse the header and everything up to the first frame.
+    pub fn new(input: R) -> Result<Self, GifError> {
+        let mut input = BufReader::new(input);
+        let mut header = [0u8; 13];
+        input.read_exact(&mut header).map_err(|_| GifError::NotGif)?;
+        if &header[..6] != b"GIF87a" && &header[..6] != b"GIF89a" {
+            return Err(GifError::NotGif);
+        }
+        let width = u16::from_le_bytes([header[6], header[7]]) as u32;
+        let height = u16::from_le_bytes([header[8], header[9]]) as u32;
+        if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+            return Err(GifError::Format(format!("size {width}x{height} outside 1..={MAX_SIDE}")));
+        }
+        let flags = header[10];
+        let global = if flags & 0x80 != 0 {
+            Some(read_color_table(&mut input, flags & 0x07)?)
+        } else {
+            None
+        };
+        let background = global
+            .as_ref()
+            .and_then(|t| t.get(header[11] as usize).copied())
+            .unwrap_or([0, 0, 0]);
+        let mut reader = Self {
+            input,
+            global,
+            background,
+            canvas: RgbImage::from_pixel(width, height, Rgb(background)),
+            saved: None,
+            disposal: Disposal::Keep,
+            next_disposal: 0,
+            next_transparent: None,
+            next_delay_cs: 0,
+            delay_cs: 0,
+            comments: Vec::new(),
+            pending_image: false,
+            done: false,
+            read: 0,
+        };
+        reader.pending_image = reader.read_until_image()?;
+        Ok(reader)
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.canvas.dimensions()
+    }
+
+    /// Comment extensions seen so far (all those before the first frame once [`Self::new`] returns).
+    pub fn comments(&self) -> &[Vec<u8>] {
+        &self.comments
+    }
+
+    /// Delay of the last frame returned, in milliseconds.
+    pub fn delay_ms(&self) -> u32 {
+        self.delay_cs as u32 * 10
+    }
+
+    /// Frames returned so far.
+    pub fn frames_read(&self) -> u64 {
+        self.read
+    }
+
+    /// The next frame as shown on screen, or `None` after the trailer.
+    pub fn read_frame(&mut self) -> Result<Option<RgbImage>, GifError> {
+        if self.done {
+            return Ok(None);
+        }
+        if !self.pending_image && !self.read_until_image()? {
+            return Ok(None);
+        }
+        self.pending_image = false;
+        self.read_image()?;
+        self.read += 1;
+        Ok(Some(self.canvas.clone()))
+    }
+
+    /// Process blocks until an image descriptor (true) or the trailer (false).
+    fn read_until_image(&mut self) -> Result<bool, GifError> {
+        loop {
+            match read_u8(&mut self.input)? {
+                0x2c => return Ok(true),
+                0x3b => {
+                    self.done = true;
+                    return Ok(false);
+                }
+                0x21 => {
+                    let label = read_u8(&mut self.input)?;
+                    let data = read_sub_blocks(&mut self.input)?;
+                    match label {
+                        0xf9 if data.len() >= 4 => {
+                            self.next_disposal = (data[0] >> 2) & 0x07;
+                            self.next_transparent = (data[0] & 0x01 != 0).then_some(data[3]);
+                            self.next_delay_cs = u16::from_le_bytes([data[1], data[2]]);
+                        }
+                        0xfe => self.comments.push(data),
+                        _ => {}
+                    }
+                }
+                other => return Err(GifError::Format(format!("unexpected block 0x{other:02x}"))),
+            }
+        }
+    }
+
+    fn read_image(&mut self) -> Result<(), GifError> {
+        let index = self.read;
+        let mut desc = [0u8; 9];
+        self.input.read_exact(&mut desc)?;
+        let x = u16::from_le_bytes([desc[0], desc[1]]) as u32;
+        let y = u16::from_le_bytes([desc[2], desc[3]]) as u32;
+        let w = u16::from_le_bytes([desc[4], desc[5]]) as u32;
+        let h = u16::from_le_bytes([desc[6], desc[7]]) as u32;
+        if w > MAX_SIDE || h > MAX_SIDE {
+            return Err(GifError::Frame {
+                index,
+                msg: format!("frame {w}x{h} exceeds {MAX_SIDE}"),
+            });
+        }
+        let flags = desc[8];
+        let local = if flags & 0x80 != 0 {
+            Some(read_color_table(&mut self.input, flags & 0x07)?)
+        } else {
+            None
+        };
+        let min_code_size = read_u8(&mut self.input)?;
+        let data = read_sub_blocks(&mut self.input)?;
+        let Some(table) = local.as_ref().or(self.global.as_ref()) else {
+            return Err(GifError::Frame {
+                index,
+                msg: "no colour table".into(),
+            });
+        };
+        let pixels = lzw_decode(&data, min_code_size, w as usize * h as usize).map_err(|msg| GifError::Frame { index, msg })?;
+
+        match self.disposal {
+            Disposal::Keep => {}
+            Disposal::Background { x, y, w, h } => fill_rect(&mut self.canvas, x, y, w, h, self.background),
+            Disposal::Previous => {
+                if let Some(saved) = self.saved.take() {
+                    self.canvas = saved;
+                }
+            }
+        }
+        self.disposal = match self.next_disposal {
+            2 => Disposal::Background { x, y, w, h },
+            3 => {
+                self.saved = Some(self.canvas.clone());
+                Disposal::Previous
+            }
+            _ => Disposal::Keep,
+        };
+        self.delay_cs = self.next_delay_cs;
+        let transparent = self.next_transparent.take();
+        self.next_disposal = 0;
+
+        let rows: Vec<u32> = if flags & 0x40 != 0 { interlaced_rows(h) } else { (0..h).collect() };
+        let (cw, ch) = self.canvas.dimensions();
+        for (row, &dy) in rows.iter().enumerate() {
+            for dx in 0..w {
+                let i = pixels[row * w as usize + dx as usize];
+                if Some(i) == transparent {
+                    continue;
+                }
+                let (px, py) = (x + dx, y + dy);
+                if px < cw && py < ch {
+                    let c = table.get(i as usize).copied().unwrap_or([0, 0, 0]);
+                    self.canvas.put_pixel(px, py, Rgb(c));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for GifReader<R> {
+    type Item = Result<RgbImage, GifError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Row order of an interlaced image: every 8th row from 0, every 8th from 4, every 4th from 2,
+/// then every 2nd from 1.
+fn interlaced_rows(h: u32) -> Vec<u32> {
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(|(start, step)| (start..h).step_by(step))
+        .collect()
+}
+
+fn fill_rect(img: &mut RgbImage, x: u32, y: u32, w: u32, h: u32, c: [u8; 3]) {
+    for py in y..(y + h).min(img.height()) {
+        for px in x..(x + w).min(img.width()) {
+            img.put_pixel(px, py, Rgb(c));
+        }
+    }
+}
+
+fn read_u8<R: Read>(input: &mut R) -> Result<u8, GifError> {
+    let mut b = [0u8; 1];
+    input
+        .read_exact(&mut b)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => GifError::Format("file ends without a trailer".into()),
+            _ => GifError::Io(e),
+        })?;
+    Ok(b[0])
+}
+
+fn read_color_table<R: Read>(input: &mut R, size: u8) -> Result<Vec<[u8; 3]>, GifError> {
+    let mut raw = vec![0u8; 3 << (size + 1)];
+    input.read_exact(&mut raw)?;
+    Ok(raw.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect())
+}
+
+fn read_sub_blocks<R: Read>(input: &mut R) -> Result<Vec<u8>, GifError> {
+    let mut data = Vec::new();
+    loop {
+        let len = read_u8(input)? as usize;
+        if len == 0 {
+            return Ok(data);
+        }
+        let start = data.len();
+        data.resize(start + len, 0);
+        input.read_exact(&mut data[start..])?;
+    }
+}
+
+fn write_sub_blocks<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(255) {
+        out.write_all(&[chunk.len() as u8])?;
+        out.write_all(chunk)?;
+    }
+    out.write_all(&[0])
+}
+
+/// LSB-first bit packing, as GIF's LZW codes are stored.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += size as u32;
+        while self.bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Variable-width LZW as GIF uses it: codes start at `min_code_size + 1` bits and grow to 12,
+/// with a clear code when the table is full.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let eoi = clear + 1;
+    let mut out = BitWriter {
+        bytes: Vec::with_capacity(indices.len() / 2),
+        acc: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_code_size + 1;
+    let mut next = eoi + 1;
+    out.write(clear, size);
+    let Some((&first, rest)) = indices.split_first() else {
+        out.write(eoi, size);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &k in rest {
+        if let Some(&code) = table.get(&(prefix, k)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        if next < MAX_CODE {
+            table.insert((prefix, k), next);
+            // The decoder adds its entry one code later, so it widens when `next` itself no
+            // longer fits; match that here.
+            if next == (1 << size) && size < 12 {
+                size += 1;
+            }
+            next += 1;
+        } else {
+            out.write(clear, size);
+            table.clear();
+            size = min_code_size + 1;
+            next = eoi + 1;
+        }
+        prefix = k as u16;
+    }
+    out.write(prefix, size);
+    out.write(eoi, size);
+    out.finish()
+}
+
+fn lzw_decode(data: &[u8], min_code_size: u8, expect: usize) -> Result<Vec<u8>, String> {
+    if !(2..=11).contains(&min_code_size) {
+        return Err(format!("invalid LZW code size {min_code_size}"));
+    }
+    let clear = 1u16 << min_code_size;
+    let eoi = clear + 1;
+    let mut prefix = [0u16; MAX_CODE as usize];
+    let mut suffix = [0u8; MAX_CODE as usize];
+    for i in 0..clear {
+        suffix[i as usize] = i as u8;
+    }
+    let mut out = Vec::new();
+    let mut size = min_code_size + 1;
+    let mut next = eoi + 1;
+    let mut prev: Option<u16> = None;
+    let (mut acc, mut bits, mut pos) = (0u32, 0u32, 0usize);
+    let mut string = Vec::new();
+    while out.len() < expect {
+        while bits < size as u32 {
+            let Some(&b) = data.get(pos) else {
+                // Truncated data: keep what decoded, pad the rest like most viewers do.
+                out.resize(expect, 0);
+                return Ok(out);
+            };
+            acc |= (b as u32) << bits;
+            bits += 8;
+            pos += 1;
+        }
+        let code = (acc & ((1 << size) - 1)) as u16;
+        acc >>= size;
+        bits -= size as u32;
+
+        if code == clear {
+            size = min_code_size + 1;
+            next = eoi + 1;
+            prev = None;
+            continue;
+        }
+        if code == eoi {
+            break;
+        }
+        let Some(p) = prev else {
+            if code >= clear {
+                return Err(format!("LZW code {code} before any literal"));
+            }
+            out.push(code as u8);
+            prev = Some(code);
+            continue;
+        };
+        let known = code < next;
+        if !known && code != next {
+            return Err(format!("LZW code {code} beyond table ({next})"));
+        }
+        // Expand `code` (or, for the not-yet-defined code, `p` plus its own first byte).
+        let mut c = if known { code } else { p };
+        string.clear();
+        while c >= clear {
+            string.push(suffix[c as usize]);
+            c = prefix[c as usize];
+        }
+        string.push(c as u8);
+        string.reverse();
+        let first = string[0];
+        if !known {
+            string.push(first);
+        }
+        out.extend_from_slice(&string);
+        if next < MAX_CODE {
+            prefix[next as usize] = p;
+            suffix[next as usize] = first;
+            next += 1;
+            if next == (1 << size) && size < 12 {
+                size += 1;
+            }
+        }
+        prev = Some(code);
+    }
+    out.resize(expect, 0);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(w: u32, h: u32, colors: u32) -> RgbImage {
+        RgbImage::from_fn(w, h, |x, y| {
+            let c = (x / 3 + y) % colors;
+            Rgb([(c * 37) as u8, (c * 11) as u8, (c * 5) as u8])
+        })
+    }
+
+    fn animation(frames: &[RgbImage], comment: Option<&[u8]>) -> Vec<u8> {
+        let (w, h) = frames[0].dimensions();
+        let mut writer = GifWriter::new(Vec::new(), w, h, 40, comment).unwrap();
+        for f in frames {
+            writer.write_frame(f).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_all(bytes: &[u8]) -> Result<Vec<RgbImage>, GifError> {
+        GifReader::new(bytes)?.collect()
+    }
+
+    /// Seeded xorshift, so a failing fuzz case replays.
+    fn rng(mut state: u64) -> impl FnMut() -> u64 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    #[test]
+    fn animation_round_trip() {
+        // 256 colours over 64x64 pixels grows the LZW table past 4096 codes and forces clears.
+        let frames = [test_image(64, 64, 2), test_image(64, 64, 17), test_image(64, 64, 256)];
+        let bytes = animation(&frames, Some(b"{\"manifest\":1}"));
+        let mut reader = GifReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.dimensions(), (64, 64));
+        assert_eq!(reader.comments(), [b"{\"manifest\":1}".to_vec()]);
+        reader.read_frame().unwrap();
+        assert_eq!(reader.delay_ms(), 40);
+        assert_eq!(read_all(&bytes).unwrap(), frames);
+    }
+
+    #[test]
+    fn lzw_round_trip_at_every_code_size() {
+        for min_code_size in 2..=8u8 {
+            let n = 1u32 << min_code_size;
+            let indices: Vec<u8> = (0..20_000u32).map(|i| ((i * i / 7) % n) as u8).collect();
+            let packed = lzw_encode(&indices, min_code_size);
+            assert_eq!(lzw_decode(&packed, min_code_size, indices.len()).unwrap(), indices);
+        }
+    }
+
+    #[test]
+    fn too_many_colours_is_an_error() {
+        let mut writer = GifWriter::new(Vec::new(), 32, 32, 0, None).unwrap();
+        assert!(matches!(
+            writer.write_frame(&RgbImage::from_fn(32, 32, |x, y| Rgb([x as u8, y as u8, 0]))),
+            Err(GifError::TooManyColors { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_canvas_and_frame_are_rejected() {
+        assert!(matches!(GifReader::new(&b"GIF89a\xff\xff\xff\xff\0\0\0;"[..]), Err(GifError::Format(_))));
+        let frame = b"GIF89a\x01\0\x01\0\x80\0\0\0\0\0\xff\xff\xff,\0\0\0\0\xff\xff\xff\xff\0\x02\x02\x4c\x01\0;";
+        assert!(matches!(read_all(&frame[..]), Err(GifError::Frame { index: 0, .. })));
+    }
+
+    #[test]
+    fn foreign_and_corrupted_files_fail_cleanly() {
+        assert!(matches!(GifReader::new(&b"GIF90a\x01\0\x01\0\0\0\0"[..]), Err(GifError::NotGif)));
+        assert!(matches!(GifReader::new(&b"GIF8"[..]), Err(GifError::NotGif)));
+        let bytes = animation(&[test_image(10, 7, 3), test_image(10, 7, 9)], Some(b"m"));
+        for len in 0..bytes.len() {
+            let _ = read_all(&bytes[..len]);
+        }
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut bad = bytes.clone();
+                bad[i] ^= flip;
+                let _ = read_all(&bad);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzzed_files_fail_cleanly() {
+        let bytes = animation(&[test_image(16, 11, 4), test_image(16, 11, 40), test_image(16, 11, 2)], Some(b"m"));
+        let mut next = rng(0x5eed_0047);
+        // The canvas size stays put: any size up to `MAX_SIDE` is valid and only costs memory.
+        let at = |bad: &[u8], r: u64| 10 + (r % (bad.len() - 10) as u64) as usize;
+        for _ in 0..4000 {
+            let mut bad = bytes.clone();
+            for _ in 0..1 + next() % 6 {
+                let i = at(&bad, next());
+                match next() % 4 {
+                    0 => bad[i] = next() as u8,
+                    1 => bad.insert(i, next() as u8),
+                    2 => drop(bad.remove(i)),
+                    _ => {
+                        let run: Vec<u8> = bad[i..].iter().take(1 + (next() % 32) as usize).copied().collect();
+                        let j = at(&bad, next());
+                        bad.splice(j..j, run);
+                    }
+                }
+            }
+            let _ = read_all(&bad);
+        }
+    }
+
+    #[test]
+    fn random_lzw_streams_fail_cleanly() {
+        let mut next = rng(0x5eed_0012);
+        for _ in 0..20_000 {
+            let min_code_size = 2 + (next() % 7) as u8;
+            let data: Vec<u8> = (0..next() % 256).map(|_| next() as u8).collect();
+            if let Ok(out) = lzw_decode(&data, min_code_size, 500) {
+                assert_eq!(out.len(), 500);
+            }
+        }
+    }
+}
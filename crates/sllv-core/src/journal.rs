@@ -0,0 +1,327 @@
+//! Decode journal: a sidecar file remembering what each frame file decoded to.
+//!
+//! Every record is keyed by a SHA-256 over the manifest, the decode parameters, the frame's file
+//! name and the file's contents, so a re-run only demodulates frames that are new or changed and
+//! one journal can serve several archives (e.g. the volumes of a set). Records are appended as
+//! frames are decoded, so an interrupted decode keeps its progress:
+//!
+//! ```text
+//! "SLVJ" | version: u8 | records: id [32] | status: u8 | len: u32 | frame bytes | crc32
+//! ```
+//!
+//! Status 1 stores the demodulated frame bytes (with FEC, only if the shard header and hash
+//! verified); status 0 records a frame that yielded nothing. A damaged tail is dropped on open.
+//! Only an index of the records is kept in memory; frame bytes are read back when a frame hits.
+
+use crate::manifest::EncodeManifest;
+use crate::raster::RasterParams;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"SLVJ";
+const VERSION: u8 = 1;
+/// Record header: id, status and payload length.
+const RECORD_HEAD: usize = 37;
+
+pub(crate) type FrameId = [u8; 32];
+
+/// Record offset and payload length by frame id; `None` for frames recorded as unusable.
+type RecordIndex = HashMap<FrameId, Option<(u64, u32)>>;
+
+/// What earlier runs recorded. Payloads stay on disk until [`Self::get`].
+pub(crate) struct JournalEntries {
+    file: Mutex<File>,
+    index: RecordIndex,
+}
+
+impl JournalEntries {
+    /// The recorded bytes for `id`: `None` if there is no record (or it no longer reads back
+    /// intact), `Some(None)` for a frame recorded as unusable.
+    pub fn get(&self, id: &FrameId) -> Option<Option<Vec<u8>>> {
+        let (offset, len) = match self.index.get(id)? {
+            None => return Some(None),
+            Some(at) => *at,
+        };
+        let mut rec = vec![0u8; RECORD_HEAD + len as usize + 4];
+        let mut file = self.file.lock().ok()?;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut rec).ok()?;
+        let (body, crc) = rec.split_at(RECORD_HEAD + len as usize);
+        if crc32fast::hash(body).to_le_bytes() != crc || &body[..32] != id {
+            return None;
+        }
+        Some(Some(body[RECORD_HEAD..].to_vec()))
+    }
+}
+
+pub(crate) struct DecodeJournal {
+    /// Hash of the manifest and decode parameters; the prefix of every frame id.
+    pub key: [u8; 32],
+    pub entries: JournalEntries,
+    pub writer: JournalWriter,
+}
+
+/// Append side of a [`DecodeJournal`], kept separate so decode workers can read `entries`
+/// while the collecting thread appends.
+pub(crate) struct JournalWriter {
+    file: File,
+    pub hits: u64,
+    pub added: u64,
+}
+
+impl DecodeJournal {
+    /// Open the journal at `path` for decoding the archive described by `m`, creating it if it
+    /// doesn't exist. An existing file that isn't a journal of this version is an error and is
+    /// left untouched.
+    pub fn open(path: &Path, m: &EncodeManifest, p: &RasterParams) -> io::Result<Self> {
+        let mut h = Sha256::new();
+        h.update(serde_json::to_vec(m)?);
+        h.update(serde_json::to_vec(p)?);
+        let key = h.finalize().into();
+
+        let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                OpenOptions::new().read(true).write(true).create_new(true).open(path)?
+            }
+            Err(e) => return Err(e),
+        };
+        let (index, good) = if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.write_all(&[VERSION])?;
+            (HashMap::new(), 5)
+        } else {
+            let mut head = [0u8; 5];
+            let is_journal = file.read_exact(&mut head).is_ok() && &head[..4] == MAGIC;
+            if !is_journal {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} exists and is not a decode journal", path.display()),
+                ));
+            }
+            if head[4] != VERSION {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is a version {} journal; this build writes version {VERSION}", path.display(), head[4]),
+                ));
+            }
+            scan_records(&mut BufReader::new(&file))?
+        };
+        // Drop a torn record left by an interrupted run.
+        file.set_len(good)?;
+        file.seek(SeekFrom::Start(good))?;
+        // A separate handle, so lookups seeking around don't move where records are appended.
+        let reader = File::open(path)?;
+        Ok(Self {
+            key,
+            entries: JournalEntries { file: Mutex::new(reader), index },
+            writer: JournalWriter { file, hits: 0, added: 0 },
+        })
+    }
+}
+
+/// Id of the frame file `name` with contents `file`, for the archive hashed into `key`.
+pub(crate) fn frame_id(key: &[u8; 32], name: &str, file: &[u8]) -> FrameId {
+    let mut h = Sha256::new();
+    h.update(key);
+    h.update((name.len() as u64).to_le_bytes());
+    h.update(name.as_bytes());
+    h.update(file);
+    h.finalize().into()
+}
+
+/// Index the records after the 5-byte header, checking each CRC without keeping payloads, and
+/// return the length of the undamaged prefix.
+fn scan_records<R: Read>(input: &mut R) -> io::Result<(RecordIndex, u64)> {
+    let mut index = HashMap::new();
+    let mut pos = 5u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let mut head = [0u8; RECORD_HEAD];
+        if read_full(input, &mut head)? < RECORD_HEAD {
+            break;
+        }
+        let len = u32::from_le_bytes([head[33], head[34], head[35], head[36]]);
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&head);
+        let mut left = len as usize;
+        while left > 0 {
+            let n = read_full(input, &mut buf[..left.min(64 * 1024)])?;
+            crc.update(&buf[..n]);
+            left -= n;
+            if n == 0 {
+                break;
+            }
+        }
+        let mut stored = [0u8; 4];
+        if left > 0 || read_full(input, &mut stored)? < 4 || crc.finalize().to_le_bytes() != stored {
+            break;
+        }
+        let id: FrameId = head[..32].try_into().unwrap_or_default();
+        index.insert(id, (head[32] == 1).then_some((pos, len)));
+        pos += (RECORD_HEAD + 4) as u64 + len as u64;
+    }
+    Ok((index, pos))
+}
+
+/// Like `read_exact`, but returns how much was read when the input ends early.
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl JournalWriter {
+    /// Append a record; `bytes: None` marks a frame that decoded to nothing usable.
+    pub fn record(&mut self, id: &FrameId, bytes: Option<&[u8]>) -> io::Result<()> {
+        let payload = bytes.unwrap_or_default();
+        let mut rec = Vec::with_capacity(41 + payload.len());
+        rec.extend_from_slice(id);
+        rec.push(u8::from(bytes.is_some()));
+        rec.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        rec.extend_from_slice(payload);
+        rec.extend_from_slice(&crc32fast::hash(&rec).to_le_bytes());
+        // One write per record, so an interrupted run leaves at most one torn record behind.
+        self.file.write_all(&rec)?;
+        self.added += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scratch_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_journal_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("decode.journal")
+    }
+
+    fn open(path: &Path) -> io::Result<DecodeJournal> {
+        DecodeJournal::open(path, &EncodeManifest::default(), &RasterParams::default())
+    }
+
+    #[test]
+    fn records_survive_reopen() {
+        let path = scratch_file("reopen");
+        let (a, b) = {
+            let mut j = open(&path).unwrap();
+            let a = frame_id(&j.key, "f_000001.png", b"one");
+            let b = frame_id(&j.key, "f_000002.png", b"two");
+            j.writer.record(&a, Some(b"frame bytes")).unwrap();
+            j.writer.record(&b, None).unwrap();
+            (a, b)
+        };
+        let j = open(&path).unwrap();
+        assert_eq!(j.entries.get(&a), Some(Some(b"frame bytes".to_vec())));
+        assert_eq!(j.entries.get(&b), Some(None));
+        assert_eq!(j.entries.get(&frame_id(&j.key, "f_000003.png", b"three")), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn torn_tail_is_dropped_and_appended_over() {
+        let path = scratch_file("torn");
+        let id = {
+            let mut j = open(&path).unwrap();
+            let id = frame_id(&j.key, "f", b"x");
+            j.writer.record(&id, Some(&[7; 100])).unwrap();
+            id
+        };
+        let good = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[1; 50]).unwrap();
+
+        let mut j = open(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), good);
+        let later = frame_id(&j.key, "g", b"y");
+        // Lookups in between must not move the append position.
+        assert_eq!(j.entries.get(&id), Some(Some(vec![7; 100])));
+        j.writer.record(&later, Some(b"later")).unwrap();
+        drop(j);
+        let j = open(&path).unwrap();
+        assert_eq!(j.entries.get(&id), Some(Some(vec![7; 100])));
+        assert_eq!(j.entries.get(&later), Some(Some(b"later".to_vec())));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn other_files_are_left_alone() {
+        let path = scratch_file("foreign");
+        std::fs::write(&path, b"precious user data").unwrap();
+        assert_eq!(open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), b"precious user data");
+
+        std::fs::write(&path, b"SLVJ\x09rest").unwrap();
+        assert_eq!(open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), b"SLVJ\x09rest");
+
+        // An empty file (e.g. from mktemp) becomes a journal.
+        std::fs::write(&path, b"").unwrap();
+        open(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"SLVJ\x01");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn changed_record_reads_as_a_miss() {
+        let path = scratch_file("changed");
+        let id = {
+            let mut j = open(&path).unwrap();
+            let id = frame_id(&j.key, "f", b"x");
+            j.writer.record(&id, Some(b"abcdef")).unwrap();
+            id
+        };
+        let j = open(&path).unwrap();
+        let mut data = std::fs::read(&path).unwrap();
+        data[5 + RECORD_HEAD] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(j.entries.get(&id), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn second_decode_is_served_from_the_journal() {
+        use crate::profile::Profile;
+        use crate::raster::{decode_frames_dir_to_bytes_with_progress, encode_bytes_to_frames_dir, ProgressMsg};
+
+        let path = scratch_file("decode");
+        let frames = path.with_file_name("frames");
+        let data: Vec<u8> = (0..6000u32).map(|i| (i % 253) as u8).collect();
+        for fec in [false, true] {
+            let base = if fec { Profile::Scan } else { Profile::Archive }.defaults();
+            let mut p = RasterParams { grid_w: 64, grid_h: 64, sync_frames: 1, toc: false, ..base };
+            p.fec = p.fec.map(|f| crate::fec::FecParams { shard_bytes: 256, ..f });
+            encode_bytes_to_frames_dir(&data, "data.bin", &frames, &p).unwrap();
+            let _ = std::fs::remove_file(&path);
+            p.journal = Some(path.clone());
+            let mut logs = Vec::new();
+            for _ in 0..2 {
+                let (tx, rx) = std::sync::mpsc::channel();
+                let out = decode_frames_dir_to_bytes_with_progress(&frames, &p, Some(tx)).unwrap();
+                assert_eq!(out, data);
+                logs.extend(rx.try_iter().filter_map(|m| match m {
+                    ProgressMsg::Info(line) if line.starts_with("journal") => Some(line),
+                    _ => None,
+                }));
+            }
+            assert!(logs[0].starts_with("journal: 0 frames from earlier runs"), "{logs:?}");
+            assert!(logs[1].ends_with(", 0 decoded"), "{logs:?}");
+            std::fs::remove_dir_all(&frames).unwrap();
+        }
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
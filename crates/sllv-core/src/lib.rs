@@ -1,23 +1,80 @@
 pub mod manifest;
+pub mod dedup;
+pub mod compress;
+pub mod crypto;
+pub mod sign;
 pub mod palette;
 pub mod raster;
 pub mod pack;
+pub mod extract;
 pub mod fec;
 pub mod warp;
 pub mod profile;
 pub mod ffmpeg;
+pub mod y4m;
+pub mod gif;
+pub mod webp;
+pub mod anim;
+pub mod print;
+pub mod frames;
+pub mod framefile;
+pub mod toc;
+pub mod journal;
+pub mod volume;
+pub mod delta;
+pub mod output;
 
-pub use manifest::{DecodeManifest, EncodeManifest};
+pub use manifest::{DecodeManifest, EncodeManifest, PayloadKind};
+pub use dedup::{dedup_bytes, DedupInfo, DedupParams};
+pub use compress::{compress_bytes, Compression, CompressionInfo, CompressError};
+pub use crypto::{CryptoError, DecryptionKey, EncryptionInfo, EncryptionKey};
+pub use sign::{ManifestSigner, SignError, SignatureInfo, SignatureStatus};
 pub use palette::{Palette8, PaletteError};
 pub use raster::{
     decode_frames_dir_to_bytes,
     decode_frames_dir_to_bytes_with_params,
+    decode_frames_dir_to_writer,
+    decode_frames_dir_to_writer_with_progress,
+    decode_carried_ranges,
+    decode_frame_set,
+    frames_for_carried_ranges,
     encode_bytes_to_frames_dir,
+    read_manifest,
+    verify_frames_dir_payload,
     RasterParams,
     RasterError,
 };
-pub use pack::{pack_path_to_tar_bytes, PackError};
-pub use fec::{fec_encode_stream, fec_decode_collect, FecParams, FecError, ShardPacket};
+pub use pack::{
+    pack_inputs_to_tar_bytes, pack_path_to_tar_bytes, pack_path_to_tar_bytes_with_options, FilterInfo, PackError, PackFilter,
+    PackInput, PackOptions, PackedInput, SkipReason, SkippedEntry,
+};
+pub use extract::{
+    extract_payload_bytes_to_dir, extract_payload_to_dir, extract_raw_bytes_to_dir, extract_raw_to_dir,
+    extract_tar_bytes_to_dir, extract_tar_to_dir, CollisionPolicy, ExtractAction, ExtractError, ExtractOptions, ExtractReport,
+    ExtractedEntry,
+};
+pub use fec::{fec_encode_stream, fec_decode_collect, FecGroupAssembler, FecParams, FecError, ShardPacket};
 pub use warp::{homography_from_4, warp_perspective_nearest, Pt2, WarpError};
 pub use profile::Profile;
-pub use ffmpeg::{frames_to_ffv1_mkv, mkv_to_frames};
+pub use ffmpeg::{
+    decode_video_to_writer, encode_bytes_to_ffv1_mkv, encode_bytes_to_video, frames_to_ffv1_mkv, frames_to_video,
+    lossy_video_selftest, mkv_to_frames, pipe_frames_to_ffv1_mkv, pipe_frames_to_video, probe_dimensions,
+    probe_video, read_video_manifest, resolve_ffmpeg, video_manifest_path, LossySelfTest, VideoCodec, VideoContainer, VideoExportParams,
+    VideoFrames, VideoProbe,
+};
+pub use frames::{DecodeStatus, FrameDecoder, FrameEncoder};
+pub use framefile::{decode_frame_file, encode_frame_file, FrameFileError, FrameFormat};
+pub use toc::{read_entries, read_toc, EntryContents, Toc, TocEntry, TocEntryKind, TocError, TocInfo};
+pub use volume::{
+    decode_volumes_to_writer, encode_bytes_to_volumes, scan_volumes, VolumeDecodeReport, VolumeError, VolumeInfo, VolumeParams,
+    VolumeScan, VolumeSet,
+};
+pub use delta::{plan_delta, restore_to_dir, DeltaError, DeltaInfo, DeltaPlan};
+pub use output::PartialFile;
+pub use y4m::{decode_y4m_to_writer, encode_bytes_to_y4m, is_y4m, Y4mError, Y4mReader, Y4mWriter};
+pub use anim::{decode_anim_to_writer, encode_bytes_to_anim, AnimError, AnimFormat, AnimReader, AnimWriter};
+pub use print::{
+    decode_scans_to_writer, encode_bytes_to_print, read_scans_manifest, scan_files, PaperSize, PrintError, PrintParams,
+};
+pub use gif::{GifError, GifReader, GifWriter};
+pub use webp::{WebpError, WebpReader, WebpWriter};
@@ -1,6 +1,15 @@
+use crate::compress::CompressionInfo;
+use crate::crypto::EncryptionInfo;
+use crate::dedup::DedupInfo;
+use crate::framefile::FrameFormat;
+use crate::delta::DeltaInfo;
+use crate::pack::FilterInfo;
+use crate::sign::SignatureInfo;
+use crate::toc::TocInfo;
+use crate::volume::VolumeInfo;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EncodeManifest {
     pub magic: String,
     pub version: u16,
@@ -16,11 +25,164 @@ pub struct EncodeManifest {
     pub palette: String,
     pub sha256_hex: String,
     pub frames: u32,
+
+    /// Tiles per frame image, columns and rows, each a complete grid of `grid_w` x `grid_h`
+    /// cells; absent means one. See [`RasterParams::tiles`](crate::raster::RasterParams::tiles).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiles: Option<[u32; 2]>,
+
+    /// Image format of the frame files in a frames directory; absent means RGB PNG.
+    #[serde(default, skip_serializing_if = "FrameFormat::is_png")]
+    pub frame_format: FrameFormat,
+
+    /// What the (decompressed, decrypted) payload is; absent in older manifests, meaning a tar.
+    #[serde(default, skip_serializing_if = "PayloadKind::is_tar")]
+    pub payload: PayloadKind,
+
+    /// Chunk-level deduplication before compression; see [`crate::dedup`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<DedupInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionInfo>,
+    /// Include/exclude/size filter the input was packed with; absent means everything was packed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<FilterInfo>,
+    /// Table-of-contents frames after the data frames; see [`crate::toc`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toc: Option<TocInfo>,
+    /// Present when this is a delta against an earlier archive; see [`crate::delta`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<DeltaInfo>,
+    /// Set membership when this directory is one volume of a split encode; see [`crate::volume`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<VolumeInfo>,
+    /// Ed25519 signature over all other fields; see [`crate::sign`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureInfo>,
+}
+
+/// Format of the recovered payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadKind {
+    /// A tar archive from `pack`; `file_name` is its top-level entry.
+    #[default]
+    Tar,
+    /// Bytes stored as-is (e.g. a stream read from stdin); `file_name` is the name to restore it as.
+    Raw,
+}
+
+impl PayloadKind {
+    pub fn is_tar(&self) -> bool {
+        *self == PayloadKind::Tar
+    }
 }
 
 pub type DecodeManifest = EncodeManifest;
 
 impl EncodeManifest {
+    /// One past the last data frame: the volume header and table-of-contents frames (if any) come after it.
+    pub fn data_end(&self) -> u32 {
+        match (&self.volume, &self.toc) {
+            (Some(v), _) => v.header_frame,
+            (None, Some(t)) => t.first_frame,
+            (None, None) => self.frames,
+        }
+    }
+
+    /// Size of the payload before dedup, compression and encryption, which table-of-contents
+    /// offsets refer to. For an archive that is only encrypted this is an upper bound.
+    pub fn payload_bytes(&self) -> u64 {
+        match (&self.dedup, &self.compression) {
+            (Some(d), _) => d.logical_bytes,
+            (None, Some(c)) => c.uncompressed_bytes,
+            (None, None) => self.total_bytes,
+        }
+    }
+
     pub const MAGIC: &'static str = "SLLV";
-    pub const VERSION: u16 = 1;
+    /// Version 2 added optional payload stages (compression, encryption, signature); version 1 manifests still decode.
+    pub const VERSION: u16 = 2;
+
+    pub fn is_supported(&self) -> bool {
+        self.magic == Self::MAGIC && (1..=Self::VERSION).contains(&self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION_1: &str = r#"{"magic":"SLLV","version":1,"file_name":"a.txt","total_bytes":5,"chunk_bytes":100,
+        "grid_w":8,"grid_h":8,"cell_px":2,"palette":"basic8","sha256_hex":"00","frames":3}"#;
+
+    #[test]
+    fn version_1_manifests_still_decode() {
+        let m: EncodeManifest = serde_json::from_str(VERSION_1).unwrap();
+        assert!(m.is_supported());
+        assert!(m.payload.is_tar() && m.frame_format.is_png());
+        assert!(m.compression.is_none() && m.encryption.is_none() && m.signature.is_none());
+        for (magic, version) in [("SLLV", 0), ("SLLV", EncodeManifest::VERSION + 1), ("SLLX", 1)] {
+            assert!(!EncodeManifest { magic: magic.into(), version, ..m.clone() }.is_supported());
+        }
+    }
+
+    #[test]
+    fn absent_stages_are_not_written() {
+        let m: EncodeManifest = serde_json::from_str(VERSION_1).unwrap();
+        let written = serde_json::to_value(&m).unwrap();
+        assert_eq!(written, serde_json::from_str::<serde_json::Value>(VERSION_1).unwrap());
+        let raw = serde_json::to_value(EncodeManifest { payload: PayloadKind::Raw, ..m }).unwrap();
+        assert_eq!(raw["payload"], "raw");
+    }
+
+    #[test]
+    fn data_ends_before_the_volume_header_and_toc_frames() {
+        let mut m = EncodeManifest { frames: 10, ..Default::default() };
+        assert_eq!(m.data_end(), 10);
+        m.toc = Some(TocInfo {
+            format: String::new(),
+            first_frame: 8,
+            frames: 1,
+            copies: 2,
+            entries: 0,
+            stored_bytes: 0,
+            sha256_hex: String::new(),
+            encryption: None,
+        });
+        assert_eq!(m.data_end(), 8);
+        m.volume = Some(VolumeInfo {
+            set_id: String::new(),
+            index: 0,
+            data_volumes: 2,
+            parity_volumes: 1,
+            stripe_bytes: 0,
+            header_frame: 7,
+        });
+        assert_eq!(m.data_end(), 7);
+    }
+
+    #[test]
+    fn payload_bytes_are_counted_before_dedup_and_compression() {
+        let mut m = EncodeManifest { total_bytes: 100, ..Default::default() };
+        assert_eq!(m.payload_bytes(), 100);
+        m.compression = Some(CompressionInfo {
+            method: crate::compress::Compression::Zstd { level: 3 },
+            uncompressed_bytes: 400,
+            uncompressed_sha256_hex: String::new(),
+        });
+        assert_eq!(m.payload_bytes(), 400);
+        m.dedup = Some(DedupInfo {
+            chunker: DedupInfo::CHUNKER.into(),
+            params: Default::default(),
+            chunks: 9,
+            unique_chunks: 3,
+            logical_bytes: 1000,
+            stored_bytes: 400,
+            logical_sha256_hex: String::new(),
+        });
+        assert_eq!(m.payload_bytes(), 1000);
+    }
 }
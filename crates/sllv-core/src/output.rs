@@ -0,0 +1,149 @@
+//! Output files that only appear once they are complete.
+//!
+//! Decoders stream into a temporary file next to the destination and rename it into place after
+//! the payload hash has been checked. If anything fails (or the writer is simply dropped), the
+//! temporary file is removed, so a failed decode never leaves a half-written or unverified file
+//! under the name the user asked for.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub struct PartialFile {
+    /// `None` once committed.
+    out: Option<BufWriter<File>>,
+    tmp: PathBuf,
+    dest: PathBuf,
+}
+
+impl PartialFile {
+    /// Create a hidden temporary file in the same directory as `dest`, so the final rename
+    /// never crosses a filesystem.
+    pub fn create(dest: &Path) -> io::Result<Self> {
+        let name = dest
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".partial-{}", std::process::id()));
+        let tmp = dest.with_file_name(tmp_name);
+        let file = File::create(&tmp)?;
+        Ok(Self {
+            out: Some(BufWriter::new(file)),
+            tmp,
+            dest: dest.to_path_buf(),
+        })
+    }
+
+    /// Scratch space inside `dir` (created if missing) for a payload that must be verified before
+    /// anything is extracted from it. It is never committed: read it back with
+    /// [`reopen`](Self::reopen) and drop it to remove it.
+    pub fn scratch_in(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Self::create(&dir.join("payload"))
+    }
+
+    /// Flush what has been written so far and open the temporary file for reading.
+    pub fn reopen(&mut self) -> io::Result<File> {
+        self.writer()?.flush()?;
+        File::open(&self.tmp)
+    }
+
+    /// Flush, sync and move the file to its destination, replacing whatever was there.
+    pub fn commit(mut self) -> io::Result<()> {
+        let Some(out) = self.out.take() else { return Ok(()) };
+        let res = out
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(|file| file.sync_all())
+            .and_then(|()| fs::rename(&self.tmp, &self.dest));
+        if res.is_err() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+        res
+    }
+
+    fn writer(&mut self) -> io::Result<&mut BufWriter<File>> {
+        self.out
+            .as_mut()
+            .ok_or_else(|| io::Error::other("output already committed"))
+    }
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer()?.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer()?.flush()
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_output_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn commit_replaces_destination() {
+        let dir = scratch_dir("commit");
+        let dest = dir.join("out.tar");
+        fs::write(&dest, b"old").unwrap();
+
+        let mut f = PartialFile::create(&dest).unwrap();
+        f.write_all(b"new contents").unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"old");
+        f.commit().unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), b"new contents");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn drop_without_commit_leaves_nothing() {
+        let dir = scratch_dir("drop");
+        let dest = dir.join("out.tar");
+        {
+            let mut f = PartialFile::create(&dest).unwrap();
+            f.write_all(b"half written").unwrap();
+        }
+        assert!(!dest.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scratch_reads_back_and_is_removed() {
+        let dir = scratch_dir("scratch");
+        let out = dir.join("out");
+        {
+            let mut f = PartialFile::scratch_in(&out).unwrap();
+            f.write_all(b"decoded payload").unwrap();
+            let mut back = Vec::new();
+            io::Read::read_to_end(&mut f.reopen().unwrap(), &mut back).unwrap();
+            assert_eq!(back, b"decoded payload");
+        }
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,56 +1,880 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use tar::Builder;
+use std::sync::{Arc, Mutex};
+use tar::{Builder, EntryType, Header};
 use thiserror::Error;
-use walkdir::{WalkDir, Error as WalkDirError};
 
 #[derive(Debug, Error)]
 pub enum PackError {
     #[error("io: {0}")]
     Io(#[from] io::Error),
-    #[error("walkdir: {0}")]
-    WalkDir(#[from] WalkDirError),
+    #[error("walk: {0}")]
+    Walk(#[from] ignore::Error),
+    #[error("invalid glob {pattern}: {reason}")]
+    InvalidGlob { pattern: String, reason: String },
     #[error("invalid input path")]
     InvalidInput,
+    #[error("two inputs would both be stored as {0}")]
+    DuplicateName(String),
 }
 
-/// Package a file or directory (recursively) into a tar byte stream.
+/// How files are recorded in the tar stream.
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// Store the files symlinks point to instead of the links themselves.
+    pub follow_symlinks: bool,
+    /// Record extended attributes as `SCHILY.xattr.*` PAX records (Unix only).
+    pub xattrs: bool,
+    /// Sorted entries, uid/gid 0 with no owner names, mtime [`PackOptions::REPRODUCIBLE_MTIME`] and
+    /// modes reduced to 0755 (folders and executables) or 0644, so identical input gives a
+    /// byte-identical tar (and frames, unless encrypted) whatever the umask or checkout time.
+    pub reproducible: bool,
+    /// Which files under a directory input are packed.
+    pub filter: PackFilter,
+}
+
+impl PackOptions {
+    pub const REPRODUCIBLE_MTIME: u64 = 0;
+}
+
+/// Selects the entries of a directory input. Globs are matched against the path relative to
+/// the input (`src/**/*.rs`) and against the bare file name (`*.log`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackFilter {
+    /// If non-empty, only files matching one of these are packed (plus the folders leading to them).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Files and folders matching any of these are left out; excluding a folder skips its contents.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Honor `.gitignore` and `.sllvignore` files inside the input, and skip `.git/`.
+    #[serde(default)]
+    pub ignore_files: bool,
+    /// Regular files larger than this many bytes are skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+}
+
+impl PackFilter {
+    pub const IGNORE_FILE_NAME: &'static str = ".sllvignore";
+
+    pub fn is_empty(&self) -> bool {
+        *self == PackFilter::default()
+    }
+}
+
+/// Filter settings and their effect, recorded in the manifest when a filter was used.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilterInfo {
+    #[serde(flatten)]
+    pub filter: PackFilter,
+    /// Entries left out by `exclude`, `include` or `max_file_size`; a skipped folder counts once.
+    /// Paths hidden by ignore files are not counted.
+    pub skipped_entries: u64,
+    /// Size of the skipped regular files that were counted.
+    pub skipped_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Excluded,
+    NotIncluded,
+    TooLarge,
+}
+
+#[derive(Debug, Clone)]
+pub struct SkippedEntry {
+    /// Path as it would have appeared in the archive.
+    pub path: PathBuf,
+    /// File size (0 for folders and links).
+    pub bytes: u64,
+    pub reason: SkipReason,
+}
+
+impl std::fmt::Display for SkippedEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self.path.display();
+        match self.reason {
+            SkipReason::Excluded => write!(f, "excluded     {path}"),
+            SkipReason::NotIncluded => write!(f, "not included {path}"),
+            SkipReason::TooLarge => write!(f, "too large    {path} ({} bytes)", self.bytes),
+        }
+    }
+}
+
+/// One top-level entry of the archive.
+#[derive(Debug, Clone)]
+pub enum PackInput {
+    /// A file or folder on disk, stored under its own file name.
+    Path(PathBuf),
+    /// In-memory bytes (e.g. read from stdin), stored as a regular file called `name`.
+    Bytes { name: String, data: Vec<u8> },
+}
+
+impl PackInput {
+    /// Name of the top-level entry this input becomes.
+    pub fn entry_name(&self) -> String {
+        match self {
+            PackInput::Path(p) => p.file_name().and_then(|s| s.to_str()).unwrap_or("input").to_string(),
+            PackInput::Bytes { name, .. } => name.clone(),
+        }
+    }
+}
+
+/// Result of [`pack_path_to_tar_bytes_with_options`] and [`pack_inputs_to_tar_bytes`].
+#[derive(Debug, Clone)]
+pub struct PackedInput {
+    pub tar: Vec<u8>,
+    /// Top-level name: the input's file name, or [`PackedInput::MULTI_NAME`] for several inputs.
+    pub name: String,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl PackedInput {
+    pub const MULTI_NAME: &'static str = "archive";
+
+    /// What to put in `RasterParams::filter`; `None` if no filter was configured.
+    pub fn filter_info(&self, filter: &PackFilter) -> Option<FilterInfo> {
+        if filter.is_empty() {
+            return None;
+        }
+        Some(FilterInfo {
+            filter: filter.clone(),
+            skipped_entries: self.skipped.len() as u64,
+            skipped_bytes: self.skipped.iter().map(|s| s.bytes).sum(),
+        })
+    }
+}
+
+/// Package a file or directory (recursively) into a tar byte stream with default options.
 ///
-/// This is deliberately *not compressed* here; a later step can compress before FEC/encoding.
+/// This is deliberately *not compressed* here; see `RasterParams::compression` for the optional
+/// compression stage that runs before FEC/encoding.
 pub fn pack_path_to_tar_bytes(input: &Path) -> Result<(Vec<u8>, String), PackError> {
-    if !input.exists() {
+    let packed = pack_path_to_tar_bytes_with_options(input, &PackOptions::default())?;
+    Ok((packed.tar, packed.name))
+}
+
+/// Like [`pack_path_to_tar_bytes`], with explicit control over metadata and filtering.
+///
+/// Directories (including empty ones), regular files and symlinks are stored with their mtime
+/// and Unix mode. Names that don't fit a ustar header, or aren't UTF-8, go in PAX records.
+/// Sockets, fifos and devices are skipped. The input path itself is always followed and never
+/// filtered out, except by `max_file_size`.
+pub fn pack_path_to_tar_bytes_with_options(input: &Path, opts: &PackOptions) -> Result<PackedInput, PackError> {
+    pack_inputs_to_tar_bytes(&[PackInput::Path(input.to_path_buf())], opts)
+}
+
+/// Pack several inputs into one tar, each as a top-level entry named by [`PackInput::entry_name`].
+///
+/// Two inputs with the same entry name are rejected rather than merged.
+pub fn pack_inputs_to_tar_bytes(inputs: &[PackInput], opts: &PackOptions) -> Result<PackedInput, PackError> {
+    if inputs.is_empty() {
         return Err(PackError::InvalidInput);
     }
+    let mut names: Vec<String> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let name = input.entry_name();
+        let simple = Path::new(&name).components().all(|c| matches!(c, std::path::Component::Normal(_)));
+        if name.is_empty() || !simple {
+            return Err(PackError::InvalidInput);
+        }
+        if names.contains(&name) {
+            return Err(PackError::DuplicateName(name));
+        }
+        names.push(name);
+    }
 
-    let file_name = input
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("input")
-        .to_string();
-
+    let mut skipped = Vec::new();
     let mut out: Vec<u8> = Vec::new();
     {
         let mut builder = Builder::new(&mut out);
-        if input.is_file() {
-            // Store as a single entry named after the file.
-            builder.append_path_with_name(input, &file_name)?;
-        } else if input.is_dir() {
-            let base = input;
-            for entry in WalkDir::new(input) {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    continue;
+        for (input, name) in inputs.iter().zip(&names) {
+            match input {
+                PackInput::Path(path) => append_path(&mut builder, path, name, opts, &mut skipped)?,
+                PackInput::Bytes { data, .. } => {
+                    if opts.filter.max_file_size.is_some_and(|max| data.len() as u64 > max) {
+                        skipped.push(SkippedEntry { path: PathBuf::from(name), bytes: data.len() as u64, reason: SkipReason::TooLarge });
+                    } else {
+                        append_bytes(&mut builder, name, data, opts)?;
+                    }
                 }
-                let rel: PathBuf = path.strip_prefix(base).unwrap().to_path_buf();
-                let name = Path::new(&file_name).join(rel);
-                builder.append_path_with_name(path, name)?;
             }
-        } else {
-            return Err(PackError::InvalidInput);
         }
         builder.finish()?;
     }
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let name = match names.len() {
+        1 => names.remove(0),
+        _ => PackedInput::MULTI_NAME.to_string(),
+    };
+    Ok(PackedInput { tar: out, name, skipped })
+}
+
+fn append_path<W: io::Write>(
+    builder: &mut Builder<W>,
+    input: &Path,
+    file_name: &str,
+    opts: &PackOptions,
+    skipped: &mut Vec<SkippedEntry>,
+) -> Result<(), PackError> {
+    if !input.exists() {
+        return Err(PackError::InvalidInput);
+    }
+    let filter = &opts.filter;
+    let too_large = |meta: &fs::Metadata| meta.is_file() && filter.max_file_size.is_some_and(|max| meta.len() > max);
+    let top = Path::new(file_name);
+
+    if input.is_file() {
+        // Store as a single entry named after the file.
+        let meta = fs::metadata(input)?;
+        if too_large(&meta) {
+            skipped.push(SkippedEntry { path: top.to_path_buf(), bytes: meta.len(), reason: SkipReason::TooLarge });
+        } else {
+            append_entry(builder, input, top, &meta, opts)?;
+        }
+        return Ok(());
+    }
+    if !input.is_dir() {
+        return Err(PackError::InvalidInput);
+    }
+
+    let include = build_globset(&filter.include)?;
+    let exclude = build_globset(&filter.exclude)?;
+    let excluded = Arc::new(Mutex::new(Vec::new()));
+
+    let mut walk = WalkBuilder::new(input);
+    walk.standard_filters(false)
+        .follow_links(opts.follow_symlinks)
+        .git_ignore(filter.ignore_files)
+        .require_git(false);
+    if filter.ignore_files {
+        walk.add_custom_ignore_filename(PackFilter::IGNORE_FILE_NAME);
+    }
+    if opts.reproducible {
+        walk.sort_by_file_name(|a, b| a.cmp(b));
+    }
+    {
+        let root = input.to_path_buf();
+        let top = top.to_path_buf();
+        let ignore_files = filter.ignore_files;
+        let excluded = Arc::clone(&excluded);
+        walk.filter_entry(move |e| {
+            if e.depth() == 0 {
+                return true;
+            }
+            if ignore_files && e.file_name() == ".git" {
+                return false;
+            }
+            let rel = e.path().strip_prefix(&root).unwrap_or(e.path());
+            if glob_matches(&exclude, rel) {
+                let bytes = e.metadata().ok().filter(|m| m.is_file()).map_or(0, |m| m.len());
+                excluded.lock().unwrap().push(SkippedEntry { path: top.join(rel), bytes, reason: SkipReason::Excluded });
+                return false;
+            }
+            true
+        });
+    }
+
+    // Collect first: with `include`, a folder is only kept if something inside it is.
+    let mut entries: Vec<(PathBuf, PathBuf, fs::Metadata)> = Vec::new();
+    for entry in walk.build() {
+        let entry = entry?;
+        let path = entry.path().to_path_buf();
+        let rel: PathBuf = path.strip_prefix(input).unwrap().to_path_buf();
+        // With follow_links the walker already resolved symlinks for us.
+        let meta = entry.metadata()?;
+        if too_large(&meta) {
+            skipped.push(SkippedEntry { path: top.join(rel), bytes: meta.len(), reason: SkipReason::TooLarge });
+            continue;
+        }
+        entries.push((path, rel, meta));
+    }
+    if !include.is_empty() {
+        // A file is included if it, or a folder it's in, matches.
+        let included = |rel: &Path| rel.ancestors().any(|a| !a.as_os_str().is_empty() && glob_matches(&include, a));
+        // Folders that lead to a kept file; each file adds its ancestors until one is already there.
+        let mut kept_dirs: HashSet<PathBuf> = HashSet::new();
+        for (_, rel, meta) in &entries {
+            if meta.is_dir() || !included(rel) {
+                continue;
+            }
+            for dir in rel.ancestors().skip(1) {
+                if !kept_dirs.insert(dir.to_path_buf()) {
+                    break;
+                }
+            }
+        }
+        let (kept, dropped): (Vec<_>, Vec<_>) = entries.into_iter().partition(|(_, rel, meta)| {
+            if meta.is_dir() {
+                rel.as_os_str().is_empty() || included(rel) || kept_dirs.contains(rel.as_path())
+            } else {
+                included(rel)
+            }
+        });
+        // Report a dropped folder once, not everything in it.
+        let mut dropped_dirs: HashSet<PathBuf> = HashSet::new();
+        for (_, rel, meta) in dropped {
+            if rel.ancestors().any(|a| dropped_dirs.contains(a)) {
+                continue;
+            }
+            if meta.is_dir() {
+                dropped_dirs.insert(rel.clone());
+            }
+            let bytes = if meta.is_file() { meta.len() } else { 0 };
+            skipped.push(SkippedEntry { path: top.join(rel), bytes, reason: SkipReason::NotIncluded });
+        }
+        entries = kept;
+    }
+    for (path, rel, meta) in &entries {
+        append_entry(builder, path, &top.join(rel), meta, opts)?;
+    }
+    skipped.extend(excluded.lock().unwrap().drain(..));
+    Ok(())
+}
+
+/// A regular file with mode 0644, stamped with the current time (or the reproducible mtime).
+fn append_bytes<W: io::Write>(builder: &mut Builder<W>, name: &str, data: &[u8], opts: &PackOptions) -> Result<(), PackError> {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    let mtime = if opts.reproducible {
+        PackOptions::REPRODUCIBLE_MTIME
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    };
+    header.set_mtime(mtime);
+    let mut pax: Vec<u8> = Vec::new();
+    set_name(&mut header, &mut pax, "path", Path::new(name), false)?;
+    append_pax(builder, &pax, mtime)?;
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append(&header, data)?;
+    Ok(())
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, PackError> {
+    let mut b = GlobSetBuilder::new();
+    for p in patterns {
+        let glob = Glob::new(p).map_err(|e| PackError::InvalidGlob { pattern: p.clone(), reason: e.kind().to_string() })?;
+        b.add(glob);
+    }
+    b.build().map_err(|e| PackError::InvalidGlob { pattern: patterns.join(","), reason: e.to_string() })
+}
+
+fn glob_matches(set: &GlobSet, rel: &Path) -> bool {
+    !set.is_empty() && (set.is_match(rel) || rel.file_name().is_some_and(|n| set.is_match(n)))
+}
+
+fn append_entry<W: io::Write>(
+    builder: &mut Builder<W>,
+    path: &Path,
+    name: &Path,
+    meta: &fs::Metadata,
+    opts: &PackOptions,
+) -> Result<(), PackError> {
+    let ft = meta.file_type();
+    let kind = if ft.is_dir() {
+        EntryType::Directory
+    } else if ft.is_symlink() {
+        EntryType::Symlink
+    } else if ft.is_file() {
+        EntryType::Regular
+    } else {
+        return Ok(());
+    };
+
+    let mut header = Header::new_ustar();
+    header.set_entry_type(kind);
+    if opts.reproducible {
+        header.set_mode(reproducible_mode(file_mode(meta, kind), kind));
+        header.set_mtime(PackOptions::REPRODUCIBLE_MTIME);
+        header.set_uid(0);
+        header.set_gid(0);
+    } else {
+        header.set_mode(file_mode(meta, kind));
+        header.set_mtime(mtime_secs(meta));
+        set_owner(&mut header, meta);
+    }
+
+    let mut pax: Vec<u8> = Vec::new();
+    set_name(&mut header, &mut pax, "path", name, kind == EntryType::Directory)?;
+    if kind == EntryType::Symlink {
+        let target = fs::read_link(path)?;
+        set_name(&mut header, &mut pax, "linkpath", &target, false)?;
+    }
+    if opts.xattrs {
+        append_xattr_records(&mut pax, path)?;
+    }
 
-    Ok((out, file_name))
+    append_pax(builder, &pax, header.mtime().unwrap_or(0))?;
+
+    if kind == EntryType::Regular {
+        header.set_size(meta.len());
+        header.set_cksum();
+        builder.append(&header, fs::File::open(path)?)?;
+    } else {
+        header.set_size(0);
+        header.set_cksum();
+        builder.append(&header, io::empty())?;
+    }
+    Ok(())
+}
+
+/// Write the extended header entry that precedes an entry, if it has any PAX records.
+fn append_pax<W: io::Write>(builder: &mut Builder<W>, pax: &[u8], mtime: u64) -> io::Result<()> {
+    if pax.is_empty() {
+        return Ok(());
+    }
+    let mut xh = Header::new_ustar();
+    xh.set_entry_type(EntryType::XHeader);
+    xh.set_path("PaxHeader")?;
+    xh.set_mode(0o644);
+    xh.set_mtime(mtime);
+    xh.set_size(pax.len() as u64);
+    xh.set_cksum();
+    builder.append(&xh, pax)
+}
+
+/// Put `value` in the ustar header when it fits as UTF-8, otherwise in a PAX record.
+fn set_name(header: &mut Header, pax: &mut Vec<u8>, key: &str, value: &Path, dir: bool) -> io::Result<()> {
+    let bytes = path_bytes(value, dir);
+    let fits = match std::str::from_utf8(&bytes) {
+        Ok(s) if key == "path" => header.set_path(s).is_ok(),
+        Ok(s) => header.set_link_name(s).is_ok(),
+        Err(_) => false,
+    };
+    if fits {
+        return Ok(());
+    }
+    if std::str::from_utf8(&bytes).is_err() {
+        pax_record(pax, "hdrcharset", b"BINARY");
+    }
+    pax_record(pax, key, &bytes);
+    // Truncated fallback for readers that ignore PAX.
+    let field = if key == "path" {
+        &mut header.as_old_mut().name[..]
+    } else {
+        &mut header.as_old_mut().linkname[..]
+    };
+    let n = bytes.len().min(field.len());
+    field[..n].copy_from_slice(&bytes[..n]);
+    Ok(())
+}
+
+/// One `"<len> <key>=<value>\n"` record, where `<len>` counts the whole record including itself.
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let rest = key.len() + value.len() + 3; // ' ', '=', '\n'
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{len} {key}=").as_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+#[cfg(unix)]
+fn path_bytes(p: &Path, dir: bool) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut b = p.as_os_str().as_bytes().to_vec();
+    if dir && !b.ends_with(b"/") {
+        b.push(b'/');
+    }
+    b
+}
+
+#[cfg(not(unix))]
+fn path_bytes(p: &Path, dir: bool) -> Vec<u8> {
+    // Tar names always use '/'.
+    let mut s = p.to_string_lossy().replace('\\', "/");
+    if dir && !s.ends_with('/') {
+        s.push('/');
+    }
+    s.into_bytes()
+}
+
+fn mtime_secs(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata, _kind: EntryType) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(meta: &fs::Metadata, kind: EntryType) -> u32 {
+    match kind {
+        EntryType::Directory | EntryType::Symlink => 0o755,
+        _ if meta.permissions().readonly() => 0o444,
+        _ => 0o644,
+    }
+}
+
+/// Keep only whether the owner could execute the entry; the rest of the mode depends on the umask.
+fn reproducible_mode(mode: u32, kind: EntryType) -> u32 {
+    match kind {
+        EntryType::Symlink => 0o777,
+        EntryType::Directory => 0o755,
+        _ if mode & 0o100 != 0 => 0o755,
+        _ => 0o644,
+    }
+}
+
+#[cfg(unix)]
+fn set_owner(header: &mut Header, meta: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    header.set_uid(meta.uid() as u64);
+    header.set_gid(meta.gid() as u64);
+}
+
+#[cfg(not(unix))]
+fn set_owner(header: &mut Header, _meta: &fs::Metadata) {
+    header.set_uid(0);
+    header.set_gid(0);
+}
+
+#[cfg(unix)]
+fn append_xattr_records(pax: &mut Vec<u8>, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    // `xattr::list`/`get` don't follow symlinks, so a link's own attributes are recorded.
+    let mut names: Vec<_> = xattr::list(path)?.collect();
+    names.sort();
+    for name in names {
+        if let Some(value) = xattr::get(path, &name)? {
+            let key = format!("SCHILY.xattr.{}", String::from_utf8_lossy(name.as_bytes()));
+            pax_record(pax, &key, &value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn append_xattr_records(_pax: &mut Vec<u8>, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_pack_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    struct Listed {
+        path: PathBuf,
+        kind: EntryType,
+        mode: u32,
+        mtime: u64,
+        link: Option<PathBuf>,
+        pax: Vec<(String, Vec<u8>)>,
+    }
+
+    fn list(tar: &[u8]) -> Vec<Listed> {
+        let mut archive = tar::Archive::new(tar);
+        let mut out = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let pax = match entry.pax_extensions().unwrap() {
+                Some(exts) => exts
+                    .map(|e| e.unwrap())
+                    .map(|e| (e.key().unwrap().to_string(), e.value_bytes().to_vec()))
+                    .collect(),
+                None => Vec::new(),
+            };
+            let h = entry.header();
+            out.push(Listed {
+                path: entry.path().unwrap().into_owned(),
+                kind: h.entry_type(),
+                mode: h.mode().unwrap(),
+                mtime: h.mtime().unwrap(),
+                link: entry.link_name().unwrap().map(|l| l.into_owned()),
+                pax,
+            });
+        }
+        out
+    }
+
+    fn find<'a>(entries: &'a [Listed], path: &str) -> &'a Listed {
+        entries
+            .iter()
+            .find(|e| e.path == Path::new(path))
+            .unwrap_or_else(|| panic!("{path} not in {:?}", entries.iter().map(|e| &e.path).collect::<Vec<_>>()))
+    }
+
+    fn set_mtime(path: &Path, secs: u64) {
+        fs::File::open(path).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
+
+    #[test]
+    fn empty_dirs_and_symlinks_are_stored() {
+        let dir = scratch_dir("links");
+        let tree = dir.join("tree");
+        fs::create_dir_all(tree.join("empty")).unwrap();
+        fs::write(tree.join("file.txt"), b"hello").unwrap();
+        symlink("file.txt", tree.join("link")).unwrap();
+
+        let packed = pack_path_to_tar_bytes_with_options(&tree, &PackOptions::default()).unwrap();
+        let entries = list(&packed.tar);
+        assert_eq!(find(&entries, "tree/empty").kind, EntryType::Directory);
+        let link = find(&entries, "tree/link");
+        assert_eq!(link.kind, EntryType::Symlink);
+        assert_eq!(link.link.as_deref(), Some(Path::new("file.txt")));
+
+        let opts = PackOptions { follow_symlinks: true, ..Default::default() };
+        let entries = list(&pack_path_to_tar_bytes_with_options(&tree, &opts).unwrap().tar);
+        assert_eq!(find(&entries, "tree/link").kind, EntryType::Regular);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mtime_and_mode_are_kept() {
+        let dir = scratch_dir("meta");
+        let tree = dir.join("tree");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        let file = tree.join("sub/run.sh");
+        fs::write(&file, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o750)).unwrap();
+        fs::set_permissions(tree.join("sub"), fs::Permissions::from_mode(0o711)).unwrap();
+        set_mtime(&file, 1_600_000_000);
+        set_mtime(&tree.join("sub"), 1_500_000_000);
+
+        let entries = list(&pack_path_to_tar_bytes_with_options(&tree, &PackOptions::default()).unwrap().tar);
+        let f = find(&entries, "tree/sub/run.sh");
+        assert_eq!((f.mode, f.mtime), (0o750, 1_600_000_000));
+        let d = find(&entries, "tree/sub");
+        assert_eq!((d.mode, d.mtime), (0o711, 1_500_000_000));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_and_non_utf8_names_use_pax() {
+        let dir = scratch_dir("pax");
+        let tree = dir.join("tree");
+        let long = "d".repeat(80);
+        fs::create_dir_all(tree.join(&long)).unwrap();
+        // A file name over 100 bytes fits neither the ustar name field nor the prefix split.
+        let long_file = format!("{long}/{}.txt", "f".repeat(120));
+        fs::write(tree.join(&long_file), b"long").unwrap();
+        let odd = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(tree.join(odd), b"latin-1").unwrap();
+        symlink(&long_file, tree.join("to_long")).unwrap();
+
+        let entries = list(&pack_path_to_tar_bytes_with_options(&tree, &PackOptions::default()).unwrap().tar);
+        let f = find(&entries, &format!("tree/{long_file}"));
+        assert!(f.pax.iter().any(|(k, _)| k == "path"));
+        assert_eq!(find(&entries, "tree/to_long").link.as_deref(), Some(Path::new(&long_file)));
+
+        let odd_entry = entries.iter().find(|e| e.path == Path::new("tree").join(odd)).expect("non-UTF-8 name");
+        assert!(odd_entry.pax.iter().any(|(k, v)| k == "hdrcharset" && v == b"BINARY"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn xattrs_become_pax_records() {
+        let dir = scratch_dir("xattr");
+        let tree = dir.join("tree");
+        fs::create_dir_all(&tree).unwrap();
+        let file = tree.join("tagged");
+        fs::write(&file, b"x").unwrap();
+        if xattr::set(&file, "user.sllv", b"yes").is_err() {
+            // The temp filesystem doesn't support user xattrs; nothing to check.
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+
+        let opts = PackOptions { xattrs: true, ..Default::default() };
+        let entries = list(&pack_path_to_tar_bytes_with_options(&tree, &opts).unwrap().tar);
+        let pax = &find(&entries, "tree/tagged").pax;
+        assert!(pax.contains(&("SCHILY.xattr.user.sllv".to_string(), b"yes".to_vec())));
+
+        let entries = list(&pack_path_to_tar_bytes_with_options(&tree, &PackOptions::default()).unwrap().tar);
+        assert!(find(&entries, "tree/tagged").pax.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A small tree: `tree/{keep.rs, notes.log, big.bin, src/{a.rs, b.txt}, docs/{x.md, y.md}, target/out.o}`.
+    fn filter_tree(dir: &Path) -> PathBuf {
+        let tree = dir.join("tree");
+        for d in ["src", "docs", "target"] {
+            fs::create_dir_all(tree.join(d)).unwrap();
+        }
+        for f in ["keep.rs", "notes.log", "src/a.rs", "src/b.txt", "docs/x.md", "docs/y.md", "target/out.o"] {
+            fs::write(tree.join(f), f.as_bytes()).unwrap();
+        }
+        fs::write(tree.join("big.bin"), vec![0u8; 4096]).unwrap();
+        tree
+    }
+
+    fn pack_filtered(tree: &Path, filter: PackFilter) -> (Vec<String>, Vec<SkippedEntry>) {
+        let opts = PackOptions { filter, reproducible: true, ..Default::default() };
+        let packed = pack_path_to_tar_bytes_with_options(tree, &opts).unwrap();
+        let names = list(&packed.tar)
+            .into_iter()
+            .map(|e| e.path.to_string_lossy().trim_end_matches('/').to_string())
+            .collect();
+        (names, packed.skipped)
+    }
+
+    fn skipped_as(skipped: &[SkippedEntry]) -> Vec<(String, SkipReason)> {
+        skipped.iter().map(|s| (s.path.to_string_lossy().into_owned(), s.reason)).collect()
+    }
+
+    #[test]
+    fn include_keeps_matches_and_the_folders_leading_to_them() {
+        let dir = scratch_dir("include");
+        let tree = filter_tree(&dir);
+        let filter = PackFilter { include: vec!["*.rs".into(), "docs".into()], ..Default::default() };
+        let (names, skipped) = pack_filtered(&tree, filter.clone());
+        assert_eq!(
+            names,
+            ["tree", "tree/docs", "tree/docs/x.md", "tree/docs/y.md", "tree/keep.rs", "tree/src", "tree/src/a.rs"]
+        );
+        // The dropped folder is reported once, not once per file in it.
+        assert_eq!(
+            skipped_as(&skipped),
+            [
+                ("tree/big.bin".to_string(), SkipReason::NotIncluded),
+                ("tree/notes.log".to_string(), SkipReason::NotIncluded),
+                ("tree/src/b.txt".to_string(), SkipReason::NotIncluded),
+                ("tree/target".to_string(), SkipReason::NotIncluded),
+            ]
+        );
+        let info = PackedInput { tar: Vec::new(), name: "tree".into(), skipped }.filter_info(&filter).unwrap();
+        assert_eq!(info.skipped_entries, 4);
+        assert_eq!(info.skipped_bytes, 4096 + "notes.log".len() as u64 + "src/b.txt".len() as u64);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exclude_skips_files_and_whole_folders() {
+        let dir = scratch_dir("exclude");
+        let tree = filter_tree(&dir);
+        let exclude = vec!["*.log".into(), "target".into(), "src/*.txt".into()];
+        let (names, skipped) = pack_filtered(&tree, PackFilter { exclude, ..Default::default() });
+        assert!(names.iter().all(|n| !n.ends_with(".log") && !n.contains("target") && !n.ends_with(".txt")));
+        assert!(names.contains(&"tree/src/a.rs".to_string()));
+        assert_eq!(
+            skipped_as(&skipped),
+            [
+                ("tree/notes.log".to_string(), SkipReason::Excluded),
+                ("tree/src/b.txt".to_string(), SkipReason::Excluded),
+                ("tree/target".to_string(), SkipReason::Excluded),
+            ]
+        );
+        let bad = PackFilter { exclude: vec!["a{".into()], ..Default::default() };
+        assert!(matches!(
+            pack_path_to_tar_bytes_with_options(&tree, &PackOptions { filter: bad, ..Default::default() }),
+            Err(PackError::InvalidGlob { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignore_files_are_honored_only_when_asked() {
+        let dir = scratch_dir("ignore");
+        let tree = filter_tree(&dir);
+        fs::write(tree.join(".gitignore"), "target/\n").unwrap();
+        fs::write(tree.join("docs").join(PackFilter::IGNORE_FILE_NAME), "y.md\n").unwrap();
+        fs::create_dir_all(tree.join(".git")).unwrap();
+        fs::write(tree.join(".git/HEAD"), "ref").unwrap();
+
+        let (names, skipped) = pack_filtered(&tree, PackFilter { ignore_files: true, ..Default::default() });
+        assert!(names.iter().all(|n| !n.contains("target") && !n.ends_with("y.md") && !n.contains(".git/")));
+        assert!(names.contains(&"tree/docs/x.md".to_string()));
+        assert!(names.contains(&"tree/.gitignore".to_string()));
+        // Ignored paths aren't reported as skipped.
+        assert!(skipped.is_empty());
+
+        let (names, _) = pack_filtered(&tree, PackFilter::default());
+        for n in ["tree/target/out.o", "tree/docs/y.md", "tree/.git/HEAD"] {
+            assert!(names.contains(&n.to_string()), "{n} missing without ignore_files");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn max_file_size_skips_large_files() {
+        let dir = scratch_dir("maxsize");
+        let tree = filter_tree(&dir);
+        let (names, skipped) = pack_filtered(&tree, PackFilter { max_file_size: Some(1024), ..Default::default() });
+        assert!(!names.contains(&"tree/big.bin".to_string()));
+        assert!(names.contains(&"tree/keep.rs".to_string()));
+        assert_eq!(skipped_as(&skipped), [("tree/big.bin".to_string(), SkipReason::TooLarge)]);
+        assert_eq!(skipped[0].bytes, 4096);
+
+        // The input itself is also subject to the limit.
+        let packed = pack_path_to_tar_bytes_with_options(
+            &tree.join("big.bin"),
+            &PackOptions { filter: PackFilter { max_file_size: Some(1024), ..Default::default() }, ..Default::default() },
+        )
+        .unwrap();
+        assert!(list(&packed.tar).is_empty());
+        assert_eq!(skipped_as(&packed.skipped), [("big.bin".to_string(), SkipReason::TooLarge)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reproducible_ignores_mtime_and_umask() {
+        let dir = scratch_dir("repro");
+        // The same tree checked out twice, at different times and under different umasks.
+        let make = |root: &Path, secs: u64, file_mode: u32, dir_mode: u32| {
+            let tree = root.join("tree");
+            for d in ["a", "b/c", "empty"] {
+                fs::create_dir_all(tree.join(d)).unwrap();
+            }
+            for (f, data) in [("a/1.txt", &b"one"[..]), ("b/c/2.txt", b"two"), ("z.txt", b"zzz")] {
+                fs::write(tree.join(f), data).unwrap();
+                fs::set_permissions(tree.join(f), fs::Permissions::from_mode(file_mode)).unwrap();
+                set_mtime(&tree.join(f), secs);
+            }
+            fs::write(tree.join("run.sh"), b"#!/bin/sh\n").unwrap();
+            fs::set_permissions(tree.join("run.sh"), fs::Permissions::from_mode(file_mode | 0o100)).unwrap();
+            symlink("z.txt", tree.join("link")).unwrap();
+            for d in ["a", "b/c", "b", "empty", ""] {
+                set_mtime(&tree.join(d), secs);
+                fs::set_permissions(tree.join(d), fs::Permissions::from_mode(dir_mode)).unwrap();
+            }
+            tree
+        };
+        let first = make(&dir.join("one"), 1_000_000_000, 0o644, 0o755);
+        let second = make(&dir.join("two"), 1_700_000_000, 0o600, 0o700);
+
+        let opts = PackOptions { reproducible: true, ..Default::default() };
+        let a = pack_path_to_tar_bytes_with_options(&first, &opts).unwrap().tar;
+        let b = pack_path_to_tar_bytes_with_options(&second, &opts).unwrap().tar;
+        assert!(a == b, "reproducible tars differ");
+
+        let entries = list(&a);
+        assert_eq!(find(&entries, "tree/run.sh").mode, 0o755);
+        assert_eq!(find(&entries, "tree/z.txt").mode, 0o644);
+        assert!(entries.iter().all(|e| e.mtime == PackOptions::REPRODUCIBLE_MTIME));
+
+        let plain = pack_path_to_tar_bytes_with_options(&second, &PackOptions::default()).unwrap().tar;
+        assert_eq!(find(&list(&plain), "tree/z.txt").mode, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -63,9 +63,9 @@ impl Palette8 {
         let mut best_d = u32::MAX;
         for sym in 0u8..8u8 {
             let c = self.color(sym).unwrap();
-            let dr = (c.r as i32 - r as i32) as i32;
-            let dg = (c.g as i32 - g as i32) as i32;
-            let db = (c.b as i32 - b as i32) as i32;
+            let dr = c.r as i32 - r as i32;
+            let dg = c.g as i32 - g as i32;
+            let db = c.b as i32 - b as i32;
             let d = (dr * dr + dg * dg + db * db) as u32;
             if d < best_d {
                 best_d = d;
@@ -0,0 +1,873 @@
+//! Paper backups: one frame per page on A4 or Letter at a chosen DPI and cell size, written as a
+//! multi-page PDF or as page PNGs, and decoded back from flatbed scans of those pages.
+//!
+//! The first page carries the manifest; every other page carries one frame. Each page has a header
+//! with the archive name, page number and short hash, and a footer with the settings decode needs.
+//! Frames are drawn with [`RasterParams::border_fiducials`], so decode finds the frame anywhere on
+//! a scan, at any rotation, and samples it through a homography (see
+//! [`rectify_frame`](crate::raster::rectify_frame)).
+
+use crate::fec::FecParams;
+use crate::frames::{DecodeStatus, FrameDecoder, FrameEncoder};
+use crate::manifest::EncodeManifest;
+use crate::palette::Palette8;
+use crate::raster::{
+    decode_rgb_frame_bytes, frame_payload_capacity, layout_manifest, max_shard_bytes, rectify_frame, render_payload_frame,
+    ProgressMsg, RasterError, RasterParams,
+};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use thiserror::Error;
+
+const MANIFEST_MAGIC: &[u8; 4] = b"SLMF";
+/// Magic, JSON length and CRC-32 of both.
+const MANIFEST_HEADER_BYTES: usize = 12;
+/// Border cells around the grid: the two-cell fiducials plus one checkerboard cell inside them.
+const PRINT_BORDER_CELLS: u32 = 3;
+/// Fiducial arm length in cells (about 10 mm at the default cell size).
+const PRINT_FIDUCIAL_CELLS: u32 = 16;
+/// Height of a text line's capitals.
+const TEXT_MM: f64 = 2.0;
+/// Image extensions picked up when a scans directory is given.
+const SCAN_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "tif", "tiff"];
+
+#[derive(Debug, Error)]
+pub enum PrintError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("png: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("invalid print settings: {0}")]
+    InvalidParams(String),
+    #[error("{width}x{height} px frames don't fit the {area_w}x{area_h} px printable area; use --profile print with the same paper, dpi and margins")]
+    FrameTooLarge { width: u32, height: u32, area_w: u32, area_h: u32 },
+    #[error("manifest does not fit on a page")]
+    ManifestTooLarge,
+    #[error("no manifest page found in the scans; check --paper, --dpi, --cell-mm and --margin-mm match the printout")]
+    ManifestPageMissing,
+    #[error("no scanned images found")]
+    NoScans,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    pub const ALL: [PaperSize; 2] = [PaperSize::A4, PaperSize::Letter];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaperSize::A4 => "a4",
+            PaperSize::Letter => "letter",
+        }
+    }
+
+    /// Portrait width and height in millimetres.
+    pub fn size_mm(&self) -> (f64, f64) {
+        match self {
+            PaperSize::A4 => (210.0, 297.0),
+            PaperSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+/// Page geometry for `encode --out-print` and `decode --scans`. Decode needs the same values as
+/// encode, since they fix the grid size; every page's footer repeats them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintParams {
+    pub paper: PaperSize,
+    pub dpi: u32,
+    /// Cell edge length, rounded to whole pixels at `dpi`.
+    pub cell_mm: f64,
+    /// Blank margin on every side; most printers can't print the outer few millimetres.
+    pub margin_mm: f64,
+}
+
+impl Default for PrintParams {
+    fn default() -> Self {
+        Self {
+            paper: PaperSize::A4,
+            dpi: 300,
+            cell_mm: 0.6,
+            margin_mm: 10.0,
+        }
+    }
+}
+
+/// Where things go on a page, in pixels at the print DPI.
+#[derive(Debug, Clone, Copy)]
+struct PageLayout {
+    width: u32,
+    height: u32,
+    margin: u32,
+    text_scale: u32,
+    area_x: u32,
+    area_y: u32,
+    area_w: u32,
+    area_h: u32,
+}
+
+impl PageLayout {
+    fn line_height(&self) -> u32 {
+        10 * self.text_scale
+    }
+
+    /// Characters that fit on one line between the margins.
+    fn line_chars(&self) -> usize {
+        (self.width.saturating_sub(2 * self.margin) / (GLYPH_ADVANCE * self.text_scale)) as usize
+    }
+}
+
+impl PrintParams {
+    pub fn validate(&self) -> Result<(), PrintError> {
+        if !(72..=2400).contains(&self.dpi) {
+            return Err(PrintError::InvalidParams(format!("dpi {} is outside 72-2400", self.dpi)));
+        }
+        if !(0.05..=10.0).contains(&self.cell_mm) {
+            return Err(PrintError::InvalidParams(format!("cell size {} mm is outside 0.05-10", self.cell_mm)));
+        }
+        if !(0.0..=50.0).contains(&self.margin_mm) {
+            return Err(PrintError::InvalidParams(format!("margin {} mm is outside 0-50", self.margin_mm)));
+        }
+        if self.cell_px() < 2 {
+            return Err(PrintError::InvalidParams(format!(
+                "{} mm cells are under 2 pixels at {} dpi",
+                self.cell_mm, self.dpi
+            )));
+        }
+        let p = self.raster_params();
+        if p.grid_w < PRINT_FIDUCIAL_CELLS || p.grid_h < PRINT_FIDUCIAL_CELLS {
+            return Err(PrintError::InvalidParams(format!(
+                "only a {}x{} grid fits on {} paper at {} mm cells",
+                p.grid_w,
+                p.grid_h,
+                self.paper.name(),
+                self.cell_mm
+            )));
+        }
+        Ok(())
+    }
+
+    fn dots_per_mm(&self) -> f64 {
+        self.dpi as f64 / 25.4
+    }
+
+    pub fn cell_px(&self) -> u32 {
+        (self.cell_mm * self.dots_per_mm()).round() as u32
+    }
+
+    fn layout(&self) -> PageLayout {
+        let dpmm = self.dots_per_mm();
+        let (w_mm, h_mm) = self.paper.size_mm();
+        let width = (w_mm * dpmm).round() as u32;
+        let height = (h_mm * dpmm).round() as u32;
+        let margin = ((self.margin_mm * dpmm).round() as u32).min(width / 4).min(height / 4);
+        let text_scale = ((TEXT_MM * dpmm / 7.0).round() as u32).max(1);
+        let line = 10 * text_scale;
+        // Two header lines above the frame, one footer line below it.
+        let area_y = margin + 2 * line;
+        PageLayout {
+            width,
+            height,
+            margin,
+            text_scale,
+            area_x: margin,
+            area_y,
+            area_w: width - 2 * margin,
+            area_h: height.saturating_sub(area_y + line + margin),
+        }
+    }
+
+    /// Frame geometry filling the printable area: one FEC shard per page, fiducials in the border.
+    ///
+    /// Parity is left at zero since parity shards don't carry recovery data yet; printing them
+    /// would only cost paper.
+    pub fn raster_params(&self) -> RasterParams {
+        let l = self.layout();
+        let cell_px = self.cell_px().max(1);
+        let b = PRINT_BORDER_CELLS;
+        let mut p = RasterParams {
+            grid_w: (l.area_w / cell_px).saturating_sub(2 * b).max(1),
+            grid_h: (l.area_h / cell_px).saturating_sub(2 * b).max(1),
+            cell_px,
+            sync_frames: 0,
+            calibration_frames: 0,
+            border_cells: b,
+            fiducial_size_cells: PRINT_FIDUCIAL_CELLS,
+            deskew: true,
+            border_fiducials: true,
+            fec: Some(FecParams {
+                data_shards: 1,
+                parity_shards: 0,
+                shard_bytes: 0,
+            }),
+            ..Default::default()
+        };
+        let shard_bytes = max_shard_bytes(&p).min(u16::MAX as usize);
+        if let Some(fec) = p.fec.as_mut() {
+            fec.shard_bytes = shard_bytes;
+        }
+        p
+    }
+
+    /// The decode command line printed in every page's footer.
+    fn decode_hint(&self) -> String {
+        format!(
+            "Print at 100%. Decode: sllv decode --profile print --paper {} --dpi {} --cell-mm {} --margin-mm {} --scans DIR",
+            self.paper.name(),
+            self.dpi,
+            self.cell_mm,
+            self.margin_mm
+        )
+    }
+}
+
+/// The manifest as a frame payload: magic, JSON length, CRC-32, JSON, zero padding.
+fn manifest_frame_payload(m: &EncodeManifest, frame_payload: usize) -> Result<Vec<u8>, RasterError> {
+    let json = serde_json::to_vec(m)?;
+    if MANIFEST_HEADER_BYTES + json.len() > frame_payload {
+        return Err(PrintError::ManifestTooLarge.into());
+    }
+    let mut frame = vec![0u8; frame_payload];
+    frame[0..4].copy_from_slice(MANIFEST_MAGIC);
+    frame[4..8].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    frame[MANIFEST_HEADER_BYTES..MANIFEST_HEADER_BYTES + json.len()].copy_from_slice(&json);
+    let crc = manifest_crc(&frame[4..8], &json);
+    frame[8..12].copy_from_slice(&crc.to_le_bytes());
+    Ok(frame)
+}
+
+fn manifest_crc(len: &[u8], json: &[u8]) -> u32 {
+    let mut h = crc32fast::Hasher::new();
+    h.update(len);
+    h.update(json);
+    h.finalize()
+}
+
+fn parse_manifest_frame(bytes: &[u8]) -> Option<EncodeManifest> {
+    if bytes.len() < MANIFEST_HEADER_BYTES || &bytes[0..4] != MANIFEST_MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes(bytes[4..8].try_into().ok()?) as usize;
+    let json = bytes.get(MANIFEST_HEADER_BYTES..MANIFEST_HEADER_BYTES.checked_add(len)?)?;
+    if manifest_crc(&bytes[4..8], json) != u32::from_le_bytes(bytes[8..12].try_into().ok()?) {
+        return None;
+    }
+    serde_json::from_slice(json).ok()
+}
+
+/// Encode into printable pages: a PDF if `out_path` ends in `.pdf`, otherwise a directory of
+/// `page_0001.png`, ... (tagged with the DPI) plus `manifest.json`.
+///
+/// `p` is normally [`PrintParams::raster_params`] with encode options added; its frames must fit
+/// the printable area of `print`.
+pub fn encode_bytes_to_print(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_path: &Path,
+    print: &PrintParams,
+    p: &RasterParams,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<EncodeManifest, RasterError> {
+    print.validate()?;
+    let layout = print.layout();
+    let encoder = FrameEncoder::new(input_bytes, file_name, p)?;
+    let manifest = encoder.manifest().clone();
+    let (width, height) = encoder.frame(0)?.dimensions();
+    if width > layout.area_w || height > layout.area_h {
+        return Err(PrintError::FrameTooLarge {
+            width,
+            height,
+            area_w: layout.area_w,
+            area_h: layout.area_h,
+        }
+        .into());
+    }
+
+    let pdf = out_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+    let mut sink = if pdf {
+        let mut w = PdfWriter::new(BufWriter::new(File::create(out_path)?))?;
+        w.set_title(file_name);
+        PageSink::Pdf(w)
+    } else {
+        fs::create_dir_all(out_path)?;
+        fs::write(out_path.join("manifest.json"), serde_json::to_vec_pretty(&manifest)?)?;
+        PageSink::Dir(out_path.to_path_buf())
+    };
+
+    let total = manifest.frames + 1;
+    let manifest_frame = render_payload_frame(&manifest_frame_payload(&manifest, frame_payload_capacity(p) as usize)?, p)?;
+    let page = render_page(&manifest_frame, &layout, print, &manifest, 1, total, "manifest");
+    sink.write_page(1, &page, print.dpi)?;
+    encoder.render_in_order(|i, img| -> Result<(), RasterError> {
+        let label = format!("frame {}", i + 1);
+        let page = render_page(&img, &layout, print, &manifest, i + 2, total, &label);
+        sink.write_page(i + 2, &page, print.dpi)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "print".into(),
+                done: i as u64 + 1,
+                total: manifest.frames as u64,
+            });
+        }
+        Ok(())
+    })?;
+    if let PageSink::Pdf(w) = sink {
+        w.finish()?.flush()?;
+    }
+    Ok(manifest)
+}
+
+enum PageSink {
+    Pdf(PdfWriter<BufWriter<File>>),
+    Dir(PathBuf),
+}
+
+impl PageSink {
+    fn write_page(&mut self, number: u32, page: &RgbImage, dpi: u32) -> Result<(), PrintError> {
+        match self {
+            PageSink::Pdf(w) => w.add_page(page, dpi)?,
+            PageSink::Dir(dir) => {
+                let out = BufWriter::new(File::create(dir.join(format!("page_{number:04}.png")))?);
+                let mut enc = png::Encoder::new(out, page.width(), page.height());
+                enc.set_color(png::ColorType::Rgb);
+                enc.set_depth(png::BitDepth::Eight);
+                let ppm = (dpi as f64 / 0.0254).round() as u32;
+                enc.set_pixel_dims(Some(png::PixelDimensions {
+                    xppu: ppm,
+                    yppu: ppm,
+                    unit: png::Unit::Meter,
+                }));
+                let mut w = enc.write_header()?;
+                w.write_image_data(page.as_raw())?;
+                w.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A white page with the frame centred in the printable area and the text lines around it.
+fn render_page(
+    frame: &RgbImage,
+    l: &PageLayout,
+    print: &PrintParams,
+    m: &EncodeManifest,
+    page: u32,
+    pages: u32,
+    label: &str,
+) -> RgbImage {
+    let mut img = RgbImage::from_pixel(l.width, l.height, Rgb([255, 255, 255]));
+    let x0 = l.area_x + (l.area_w - frame.width()) / 2;
+    let y0 = l.area_y + (l.area_h - frame.height()) / 2;
+    image::imageops::replace(&mut img, frame, x0 as i64, y0 as i64);
+
+    let short_hash = m.sha256_hex.get(..16).unwrap_or(&m.sha256_hex);
+    let right = format!("page {page}/{pages}");
+    let chars = l.line_chars();
+    let name = format!("SLLV  {}", m.file_name);
+    draw_text(&mut img, l, l.margin, l.margin, &fit(&name, chars.saturating_sub(right.len() + 2)));
+    draw_text_right(&mut img, l, l.margin, &right);
+    let info = format!("{} bytes  {} frames  sha256 {short_hash}", m.total_bytes, m.frames);
+    let y1 = l.margin + l.line_height();
+    draw_text(&mut img, l, l.margin, y1, &fit(&info, chars.saturating_sub(label.len() + 2)));
+    draw_text_right(&mut img, l, y1, label);
+    let footer_y = l.height - l.margin - 7 * l.text_scale;
+    draw_text(&mut img, l, l.margin, footer_y, &fit(&print.decode_hint(), chars));
+    img
+}
+
+/// `text` cut to `max` characters, ending in "..." if it was cut.
+fn fit(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(max.saturating_sub(3)).collect();
+    out.push_str("...");
+    out
+}
+
+fn draw_text_right(img: &mut RgbImage, l: &PageLayout, y: u32, text: &str) {
+    let width = text.chars().count() as u32 * GLYPH_ADVANCE * l.text_scale;
+    let x = (l.width - l.margin).saturating_sub(width);
+    draw_text(img, l, x, y, text);
+}
+
+fn draw_text(img: &mut RgbImage, l: &PageLayout, x: u32, y: u32, text: &str) {
+    let s = l.text_scale;
+    for (i, ch) in text.chars().enumerate() {
+        let gx = x + i as u32 * GLYPH_ADVANCE * s;
+        for (row, bits) in glyph(ch).iter().enumerate() {
+            for col in 0..5u32 {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..s {
+                    for dx in 0..s {
+                        let (px, py) = (gx + col * s + dx, y + row as u32 * s + dy);
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, Rgb([0, 0, 0]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Horizontal pitch of a glyph in font pixels: 5 columns plus a blank one.
+const GLYPH_ADVANCE: u32 = 6;
+
+/// 5x7 bitmap glyph, one byte per row with bit 4 as the leftmost column. Anything without a
+/// glyph prints as `?`.
+fn glyph(ch: char) -> [u8; 7] {
+    match ch {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        'a' => [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F],
+        'b' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E],
+        'c' => [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E],
+        'd' => [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F],
+        'e' => [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E],
+        'f' => [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08],
+        'g' => [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+        'h' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'i' => [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E],
+        'j' => [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C],
+        'k' => [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+        'l' => [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'm' => [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11],
+        'n' => [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'o' => [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E],
+        'p' => [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10],
+        'q' => [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01],
+        'r' => [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10],
+        's' => [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E],
+        't' => [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06],
+        'u' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D],
+        'v' => [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'w' => [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A],
+        'x' => [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11],
+        'y' => [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+        'z' => [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '\\' => [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '@' => [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E],
+        '~' => [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Minimal PDF 1.4 writer: each page is one full-bleed, deflated RGB image.
+struct PdfWriter<W: Write> {
+    out: W,
+    pos: u64,
+    /// Byte offset of object `n` at `[n - 1]`; 1 is the catalog, 2 the page tree, 3 the info dict.
+    offsets: Vec<u64>,
+    pages: Vec<u32>,
+    title: String,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(out: W) -> io::Result<Self> {
+        let mut w = Self {
+            out,
+            pos: 0,
+            offsets: vec![0; 3],
+            pages: Vec::new(),
+            title: String::new(),
+        };
+        // The binary comment marks the file as binary for transfer tools.
+        w.put(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(w)
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.pos += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn alloc(&mut self) -> u32 {
+        self.offsets.push(0);
+        self.offsets.len() as u32
+    }
+
+    fn object(&mut self, id: u32, dict: &str, stream: Option<&[u8]>) -> io::Result<()> {
+        self.offsets[id as usize - 1] = self.pos;
+        self.put(format!("{id} 0 obj\n{dict}\n").as_bytes())?;
+        if let Some(data) = stream {
+            self.put(b"stream\n")?;
+            self.put(data)?;
+            self.put(b"\nendstream\n")?;
+        }
+        self.put(b"endobj\n")
+    }
+
+    fn add_page(&mut self, img: &RgbImage, dpi: u32) -> io::Result<()> {
+        let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        z.write_all(img.as_raw())?;
+        let data = z.finish()?;
+        let (w, h) = img.dimensions();
+        let pt_w = w as f64 * 72.0 / dpi as f64;
+        let pt_h = h as f64 * 72.0 / dpi as f64;
+
+        let image = self.alloc();
+        self.object(
+            image,
+            &format!(
+                "<< /Type /XObject /Subtype /Image /Width {w} /Height {h} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>",
+                data.len()
+            ),
+            Some(&data),
+        )?;
+        let draw = format!("q {pt_w:.3} 0 0 {pt_h:.3} 0 0 cm /Im0 Do Q");
+        let content = self.alloc();
+        self.object(content, &format!("<< /Length {} >>", draw.len()), Some(draw.as_bytes()))?;
+        let page = self.alloc();
+        self.object(
+            page,
+            &format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {pt_w:.3} {pt_h:.3}] /Resources << /XObject << /Im0 {image} 0 R >> >> /Contents {content} 0 R >>"
+            ),
+            None,
+        )?;
+        self.pages.push(page);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.object(1, "<< /Type /Catalog /Pages 2 0 R >>", None)?;
+        let kids: Vec<String> = self.pages.iter().map(|p| format!("{p} 0 R")).collect();
+        let tree = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), self.pages.len());
+        self.object(2, &tree, None)?;
+        let info = format!("<< /Title ({}) /Producer (sllv) >>", pdf_string(&self.title));
+        self.object(3, &info, None)?;
+
+        let xref = self.pos;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len() + 1
+        ));
+        self.put(table.as_bytes())?;
+        Ok(self.out)
+    }
+}
+
+/// Body of a PDF literal string: printable ASCII with `\`, `(` and `)` escaped, anything else as `?`.
+fn pdf_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+/// Scanned page images: files as given, directories expanded to their PNG, JPEG and TIFF files
+/// in name order.
+pub fn scan_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, PrintError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut found: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| SCAN_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+            })
+            .collect();
+        found.sort();
+        files.extend(found);
+    }
+    if files.is_empty() {
+        return Err(PrintError::NoScans);
+    }
+    Ok(files)
+}
+
+/// Locate the frame on a scanned page and read its bytes, or `None` if no frame was found.
+fn read_scan(path: &Path, p: &RasterParams) -> Result<Option<Vec<u8>>, RasterError> {
+    let img = image::open(path)?.to_rgb8();
+    let layout = layout_manifest(p);
+    let Some(frame) = rectify_frame(&img, &layout, p, Palette8::Basic, 1) else {
+        return Ok(None);
+    };
+    // The rectified frame is exact: one pixel per cell, no deskew needed.
+    let flat = RasterParams {
+        cell_px: 1,
+        deskew: false,
+        border_fiducials: false,
+        center_sample: false,
+        ..p.clone()
+    };
+    let flat_layout = EncodeManifest { cell_px: 1, ..layout };
+    Ok(Some(decode_rgb_frame_bytes(&frame, &flat_layout, &flat, Palette8::Basic)?))
+}
+
+/// Read the manifest page from a set of scans (see [`scan_files`]); usually the first file.
+pub fn read_scans_manifest(files: &[PathBuf], p: &RasterParams) -> Result<EncodeManifest, RasterError> {
+    for path in files {
+        if let Some(m) = read_scan(path, p)?.as_deref().and_then(parse_manifest_frame) {
+            return Ok(m);
+        }
+    }
+    Err(PrintError::ManifestPageMissing.into())
+}
+
+/// Decode scanned pages, in any order and orientation, into `out`. Pages where no frame is found
+/// are reported as [`ProgressMsg::Info`] and skipped.
+pub fn decode_scans_to_writer<W: Write>(
+    files: &[PathBuf],
+    m: &EncodeManifest,
+    p: &RasterParams,
+    out: W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<W, RasterError> {
+    if p.fec.is_none() || !p.border_fiducials {
+        return Err(PrintError::InvalidParams("scans need --profile print".into()).into());
+    }
+    // Pages come in any order and are read once, so keep every shard: at most one per page and tile.
+    let mut decoder = FrameDecoder::with_writer(p, out).with_manifest(m.clone()).with_window(u32::MAX);
+    let total = files.len() as u64;
+    for (i, path) in files.iter().enumerate() {
+        let status = match read_scan(path, p)? {
+            Some(bytes) if parse_manifest_frame(&bytes).is_none() => decoder.push_frame_bytes(&bytes)?,
+            Some(_) => DecodeStatus::Ignored,
+            None => {
+                if let Some(ref tx) = progress_tx {
+                    let _ = tx.send(ProgressMsg::Info(format!("{}: no frame found", path.display())));
+                }
+                DecodeStatus::Ignored
+            }
+        };
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "decode".into(),
+                done: i as u64 + 1,
+                total,
+            });
+        }
+        if status == DecodeStatus::Complete {
+            break;
+        }
+    }
+    decoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_print_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Coarse pages so scans stay small.
+    fn coarse() -> PrintParams {
+        PrintParams { dpi: 100, cell_mm: 1.0, ..PrintParams::default() }
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 11 + i / 97) as u8).collect()
+    }
+
+    fn pages(dir: &Path) -> Vec<PathBuf> {
+        scan_files(&[dir.to_path_buf()]).unwrap()
+    }
+
+    #[test]
+    fn settings_outside_the_limits_are_rejected() {
+        assert!(PrintParams::default().validate().is_ok());
+        assert!(coarse().validate().is_ok());
+        for bad in [
+            PrintParams { dpi: 10, ..coarse() },
+            PrintParams { cell_mm: 0.01, ..coarse() },
+            PrintParams { cell_mm: 0.1, ..coarse() },
+            PrintParams { margin_mm: -1.0, ..coarse() },
+            PrintParams { cell_mm: 9.0, margin_mm: 50.0, ..coarse() },
+            PrintParams { cell_mm: f64::NAN, ..coarse() },
+        ] {
+            assert!(matches!(bad.validate(), Err(PrintError::InvalidParams(_))), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn manifest_frame_round_trip() {
+        let p = coarse().raster_params();
+        let m = FrameEncoder::new(b"hello", "hello.txt", &p).unwrap().manifest().clone();
+        let frame = manifest_frame_payload(&m, 4096).unwrap();
+        assert_eq!(parse_manifest_frame(&frame).unwrap().sha256_hex, m.sha256_hex);
+        assert!(matches!(manifest_frame_payload(&m, 64), Err(RasterError::Print(PrintError::ManifestTooLarge))));
+
+        let mut crc = frame.clone();
+        crc[MANIFEST_HEADER_BYTES + 3] ^= 1;
+        let mut len = frame.clone();
+        len[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut magic = frame.clone();
+        magic[0] = b'X';
+        for bad in [&crc[..], &len[..], &magic[..], &frame[..MANIFEST_HEADER_BYTES + 5], &frame[..3], &[0u8; 64][..]] {
+            assert!(parse_manifest_frame(bad).is_none());
+        }
+    }
+
+    #[test]
+    fn scanned_pages_decode_in_any_order_and_orientation() {
+        let dir = scratch_dir("pages");
+        let (pages_dir, scans_dir) = (dir.join("pages"), dir.join("scans"));
+        let print = coarse();
+        let p = print.raster_params();
+        let data = test_data(2 * p.fec.as_ref().unwrap().shard_bytes + 100);
+        let m = encode_bytes_to_print(&data, "data.bin", &pages_dir, &print, &p, None).unwrap();
+        let files = pages(&pages_dir);
+        assert_eq!(files.len() as u32, m.frames + 1);
+
+        // Rotate the pages different ways and reverse their order, as a sloppy scan would.
+        fs::create_dir_all(&scans_dir).unwrap();
+        for (i, file) in files.iter().enumerate() {
+            let img = image::open(file).unwrap().to_rgb8();
+            let img = match i % 3 {
+                0 => imageops::rotate180(&img),
+                1 => imageops::rotate90(&img),
+                _ => img,
+            };
+            img.save(scans_dir.join(format!("scan_{:02}.png", files.len() - i))).unwrap();
+        }
+        let scans = pages(&scans_dir);
+        assert_eq!(read_scans_manifest(&scans, &p).unwrap().sha256_hex, m.sha256_hex);
+        assert_eq!(decode_scans_to_writer(&scans, &m, &p, Vec::new(), None).unwrap(), data);
+
+        // Without parity a missing page can't be made up for.
+        assert!(decode_scans_to_writer(&scans[1..], &m, &p, Vec::new(), None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pdf_output_has_a_page_per_frame() {
+        let dir = scratch_dir("pdf");
+        let print = coarse();
+        let p = print.raster_params();
+        let out = dir.join("backup.pdf");
+        let m = encode_bytes_to_print(b"paper", "paper.txt", &out, &print, &p, None).unwrap();
+        let pdf = fs::read(&out).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(pdf.trim_ascii_end().ends_with(b"%%EOF"));
+        let page = b"/Type /Page ";
+        assert_eq!(pdf.windows(page.len()).filter(|w| w == page).count() as u32, m.frames + 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unusable_scans_are_reported() {
+        let dir = scratch_dir("unusable");
+        let p = coarse().raster_params();
+        assert!(matches!(scan_files(std::slice::from_ref(&dir)), Err(PrintError::NoScans)));
+
+        RgbImage::from_pixel(600, 800, Rgb([255, 255, 255])).save(dir.join("blank.png")).unwrap();
+        let files = pages(&dir);
+        assert!(matches!(read_scans_manifest(&files, &p), Err(RasterError::Print(PrintError::ManifestPageMissing))));
+
+        let m = FrameEncoder::new(b"x", "x", &p).unwrap().manifest().clone();
+        let (tx, rx) = mpsc::channel();
+        assert!(decode_scans_to_writer(&files, &m, &p, Vec::new(), Some(tx)).is_err());
+        assert!(rx.try_iter().any(|msg| matches!(msg, ProgressMsg::Info(line) if line.contains("no frame found"))));
+
+        let not_print = crate::profile::Profile::Archive.defaults();
+        assert!(matches!(
+            decode_scans_to_writer(&files, &m, &not_print, Vec::new(), None),
+            Err(RasterError::Print(PrintError::InvalidParams(_)))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,43 +1,108 @@
 use crate::fec::FecParams;
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Profile {
     Archive,
     Scan,
+    /// Survives lossy re-encoding to H.264/H.265/AV1 with 4:2:0 chroma (video platforms).
+    Video,
+    /// Paper: one frame per A4 page at 300 dpi and 0.6 mm cells, decoded from flatbed scans
+    /// (see [`crate::print`]).
+    Print,
 }
 
 impl Profile {
+    pub const ALL: [Profile; 4] = [Profile::Archive, Profile::Scan, Profile::Video, Profile::Print];
+
     pub fn name(&self) -> &'static str {
         match self {
             Profile::Archive => "archive",
             Profile::Scan => "scan",
+            Profile::Video => "video",
+            Profile::Print => "print",
         }
     }
 
     pub fn defaults(&self) -> crate::raster::RasterParams {
         match self {
             Profile::Archive => {
-                let mut p = crate::raster::RasterParams::default();
-                p.cell_px = 2;
-                p.border_cells = 2;
-                p.fiducial_size_cells = 12;
-                p.deskew = false; // archive expects exact pixels
-                p.fec = None;
-                p
+                crate::raster::RasterParams {
+                    cell_px: 2,
+                    border_cells: 2,
+                    fiducial_size_cells: 12,
+                    deskew: false, // archive expects exact pixels
+                    fec: None,
+                    ..Default::default()
+                }
             }
             Profile::Scan => {
-                let mut p = crate::raster::RasterParams::default();
-                p.cell_px = 6; // larger for camera robustness
-                p.border_cells = 4;
-                p.fiducial_size_cells = 18;
-                p.deskew = true;
-                p.fec = Some(FecParams {
-                    data_shards: 12,
-                    parity_shards: 12,
-                    shard_bytes: 768,
-                });
-                p
+                crate::raster::RasterParams {
+                    cell_px: 6, // larger for camera robustness
+                    border_cells: 4,
+                    fiducial_size_cells: 18,
+                    deskew: true,
+                    fec: Some(FecParams {
+                        data_shards: 12,
+                        parity_shards: 12,
+                        shard_bytes: 768,
+                    }),
+                    ..Default::default()
+                }
+            }
+            Profile::Video => {
+                // 1920x1080 frames. 8 px cells starting 16 px in line up with the codecs' 8x8
+                // transform blocks and 4:2:0 chroma, so every block is one flat colour and chroma
+                // subsampling never mixes two cells. The eight palette colours are the corners of
+                // the RGB cube, which stay far apart in luma and chroma after quantization;
+                // sampling the middle of each cell skips deblocking and ringing at cell edges.
+                crate::raster::RasterParams {
+                    grid_w: 236,
+                    grid_h: 131,
+                    cell_px: 8,
+                    border_cells: 2,
+                    fiducial_size_cells: 12,
+                    deskew: false, // platforms keep the geometry; a rescaled upload won't decode
+                    center_sample: true,
+                    // FEC framing gives every frame its index, so repeated or reordered frames decode.
+                    // There are no parity frames: parity shards are still zero-filled placeholders
+                    // (see `fec_encode_stream`) and would only make the video longer.
+                    fec: Some(FecParams {
+                        data_shards: 16,
+                        parity_shards: 0,
+                        shard_bytes: 11 * 1024, // one shard per frame: 11593 payload bytes
+                    }),
+                    ..Default::default()
+                }
+            }
+            // Other paper sizes, DPIs and cell sizes: `PrintParams::raster_params`.
+            Profile::Print => crate::print::PrintParams::default().raster_params(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::{frame_size_px, layout_manifest, max_shard_bytes};
+
+    #[test]
+    fn names_are_distinct_and_layouts_fit() {
+        for p in Profile::ALL {
+            assert_eq!(Profile::ALL.iter().filter(|q| q.name() == p.name()).count(), 1);
+            let params = p.defaults();
+            frame_size_px(&layout_manifest(&params), &params).unwrap();
+            if let Some(fec) = &params.fec {
+                assert!(fec.shard_bytes <= max_shard_bytes(&params), "{} shard overflows a frame", p.name());
             }
         }
     }
+
+    #[test]
+    fn video_frames_are_1080p_with_block_aligned_cells_and_no_parity() {
+        let p = Profile::Video.defaults();
+        assert_eq!(frame_size_px(&layout_manifest(&p), &p).unwrap(), (1920, 1080));
+        assert_eq!(p.cell_px % 8, 0);
+        assert_eq!(p.border_cells * p.cell_px % 8, 0);
+        assert_eq!(p.fec.unwrap().parity_shards, 0);
+    }
 }
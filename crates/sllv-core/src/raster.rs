@@ -1,13 +1,32 @@
-use crate::fec::{fec_decode_collect, fec_encode_stream, FecParams, ShardPacket};
-use crate::manifest::EncodeManifest;
+use crate::compress::{CompressError, Compression, Decompressor};
+use crate::dedup::{DedupParams, Rededup};
+use crate::crypto::{CryptoError, DecryptionKey, Decryptor, EncryptionKey};
+use crate::sign::{ManifestSigner, SignError};
+use crate::toc::TocError;
+use crate::volume::VolumeError;
+use crate::delta::{DeltaError, DeltaPlan};
+use crate::anim::AnimError;
+use crate::print::PrintError;
+use crate::y4m::Y4mError;
+use crate::fec::{FecGroupAssembler, FecParams, ShardPacket};
+use crate::frames::FrameEncoder;
+use crate::framefile::{
+    decode_frame_file, encode_frame_file, frame_file_name, frame_path, parse_frame_file_name, read_frame_file,
+    FrameFileError, FrameFormat,
+};
+use crate::journal::{frame_id, DecodeJournal, JournalEntries};
+use crate::manifest::{EncodeManifest, PayloadKind};
+use crate::pack::FilterInfo;
 use crate::palette::{Palette8, Rgb8};
-use crate::warp::{homography_from_4, warp_perspective_nearest, Pt2};
+use crate::warp::{apply_h, homography_from_4, warp_perspective_nearest, Pt2};
 use image::Rgb;
 use serde_json::json;
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::io;
-use std::path::Path;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::sync::Arc;
 use thiserror::Error;
@@ -31,6 +50,71 @@ pub struct RasterParams {
     pub fec: Option<FecParams>,
 
     pub deskew: bool,
+
+    /// Draw the corner fiducials as Ls hugging the frame's outer corners, inside the border,
+    /// instead of at the grid corners where payload cells cover them. Decode then finds the frame
+    /// by them anywhere in an image (see [`rectify_frame`]). Needs `border_cells >= 2`.
+    #[serde(default)]
+    pub border_fiducials: bool,
+
+    /// Tiles per frame image, columns and rows. Each tile is a complete frame with its own
+    /// border, fiducials and FEC shard, located and deskewed on its own, so a capture where glare
+    /// hides one tile still yields the others. Anything above 1x1 needs FEC and `border_fiducials`.
+    #[serde(default = "default_tiles")]
+    pub tiles: [u32; 2],
+
+    /// Read each cell as the average of its middle instead of its top-left pixel, so blurred or
+    /// ringing cell edges in lossy video don't flip symbols.
+    #[serde(default)]
+    pub center_sample: bool,
+
+    /// Deduplicate the payload with content-defined chunking before compression.
+    #[serde(default)]
+    pub dedup: Option<DedupParams>,
+
+    #[serde(default)]
+    pub compression: Compression,
+
+    /// Write table-of-contents frames after the data (tar and raw payloads).
+    #[serde(default = "default_toc")]
+    pub toc: bool,
+
+    /// Image format of the files a frames directory is written in; recorded in the manifest.
+    #[serde(default)]
+    pub frame_format: FrameFormat,
+
+    /// Encrypt the payload after compression (encode side). Never serialized.
+    #[serde(skip)]
+    pub encryption: Option<EncryptionKey>,
+    /// Key for encrypted archives (decode side). Never serialized.
+    #[serde(skip)]
+    pub decryption: Option<DecryptionKey>,
+    /// Sign the manifest with this Ed25519 key (encode side). Never serialized.
+    #[serde(skip)]
+    pub signing: Option<ManifestSigner>,
+    /// Recorded in the manifest so decode knows whether to unpack a tar (encode side). Never serialized.
+    #[serde(skip)]
+    pub payload: PayloadKind,
+    /// Pack filter report copied into the manifest (encode side). Never serialized.
+    #[serde(skip)]
+    pub filter: Option<FilterInfo>,
+    /// Encode a delta: base entries and deletions for the TOC, summary for the manifest (encode side). Never serialized.
+    #[serde(skip)]
+    pub delta: Option<DeltaPlan>,
+    /// Keep frames already in the output directory whose pixels match the plan (encode side). Never serialized.
+    #[serde(skip)]
+    pub resume: bool,
+    /// Journal file caching what each frame file decoded to; see [`crate::journal`] (decode side). Never serialized.
+    #[serde(skip)]
+    pub journal: Option<PathBuf>,
+}
+
+fn default_toc() -> bool {
+    true
+}
+
+fn default_tiles() -> [u32; 2] {
+    [1, 1]
 }
 
 impl Default for RasterParams {
@@ -53,6 +137,22 @@ impl Default for RasterParams {
             fec: Some(FecParams::default()),
 
             deskew: true,
+            border_fiducials: false,
+            tiles: default_tiles(),
+            center_sample: false,
+
+            dedup: None,
+            compression: Compression::None,
+            toc: true,
+            frame_format: FrameFormat::Png,
+            encryption: None,
+            decryption: None,
+            signing: None,
+            payload: PayloadKind::Tar,
+            filter: None,
+            delta: None,
+            resume: false,
+            journal: None,
         }
     }
 }
@@ -71,13 +171,102 @@ pub enum RasterError {
     ManifestInvalid,
     #[error("sha256 mismatch")]
     ShaMismatch,
+    #[error("sha256 mismatch after writing {bytes_written} bytes; the output is untrusted and must be discarded")]
+    UntrustedOutput { bytes_written: u64 },
     #[error("fec: {0}")]
     Fec(String),
+    #[error("frame index {0} out of range")]
+    FrameOutOfRange(u32),
+    #[error("frame is {width}x{height}, smaller than the {need_w}x{need_h} grid in the manifest")]
+    FrameTooSmall { width: u32, height: u32, need_w: u32, need_h: u32 },
+    #[error("compression: {0}")]
+    Compress(#[from] CompressError),
+    #[error("encryption: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("signature: {0}")]
+    Sign(#[from] SignError),
+    #[error("table of contents: {0}")]
+    Toc(#[from] TocError),
+    #[error("volumes: {0}")]
+    Volume(#[from] VolumeError),
+    #[error("delta: {0}")]
+    Delta(#[from] DeltaError),
+    #[error("y4m: {0}")]
+    Y4m(#[from] Y4mError),
+    #[error("animation: {0}")]
+    Anim(#[from] AnimError),
+    #[error("print: {0}")]
+    Print(#[from] PrintError),
+    #[error("frame file: {0}")]
+    FrameFile(#[from] FrameFileError),
+    #[error("none of the {0} tiles in the frame could be located")]
+    NoTiles(u32),
+    #[error("{}x{} tiles of {grid_w}x{grid_h} cells at {cell_px} px don't fit in a frame image", tiles[0], tiles[1])]
+    LayoutTooLarge { grid_w: u32, grid_h: u32, cell_px: u32, tiles: [u32; 2] },
+    #[error("bytes {start}..{end} are outside the {total}-byte payload")]
+    RangeOutOfPayload { start: u64, end: u64, total: u64 },
+}
+
+/// Output stages after FEC reassembly: decrypt, decompress, then rebuild deduplicated chunks.
+pub(crate) type PayloadSink<W> = Decryptor<Decompressor<Rededup<W>>>;
+
+pub(crate) fn payload_sink<W: Write>(
+    m: Option<&EncodeManifest>,
+    p: &RasterParams,
+    out: W,
+) -> Result<PayloadSink<W>, RasterError> {
+    let compression = m.and_then(|m| m.compression.as_ref());
+    let method = compression.map(|c| c.method).unwrap_or_default();
+    let limit = compression.map_or(u64::MAX, |c| c.uncompressed_bytes);
+    let encryption = m.and_then(|m| m.encryption.as_ref());
+    let dedup = Rededup::new(m.and_then(|m| m.dedup.as_ref()), out);
+    Ok(Decryptor::new(encryption, p.decryption.as_ref(), Decompressor::new(method, limit, dedup)?)?)
+}
+
+/// Flush every stage and, given the manifest, check the carried bytes (`carried_sha256_hex`)
+/// and each stage's output against it. Returns the writer and the bytes written to it.
+pub(crate) fn finish_payload<W: Write>(
+    sink: PayloadSink<W>,
+    m: Option<&EncodeManifest>,
+    carried_sha256_hex: &str,
+) -> Result<(W, u64), RasterError> {
+    let inner = sink.finish().map_err(payload_io_error)?;
+    let (dedup, decompressed, decompressed_sha256_hex) = inner.finish()?;
+    let (out, rebuilt) = dedup.finish()?;
+    let bytes_written = rebuilt.map_or(decompressed, |(n, _)| n);
+    let Some(m) = m else {
+        return Ok((out, bytes_written));
+    };
+    let untrusted = RasterError::UntrustedOutput { bytes_written };
+    if carried_sha256_hex != m.sha256_hex {
+        return Err(untrusted);
+    }
+    if let Some(c) = &m.compression {
+        // Encrypted archives omit the plaintext hash; the AEAD tags already authenticated it.
+        let sha_ok = c.uncompressed_sha256_hex.is_empty() || decompressed_sha256_hex == c.uncompressed_sha256_hex;
+        if !sha_ok || decompressed != c.uncompressed_bytes {
+            return Err(untrusted);
+        }
+    }
+    if matches!(rebuilt, Some((_, false))) {
+        return Err(untrusted);
+    }
+    Ok((out, bytes_written))
+}
+
+/// Surface decryption failures raised inside the writer chain as [`RasterError::Crypto`].
+pub(crate) fn payload_io_error(e: io::Error) -> RasterError {
+    match CryptoError::from_io(e) {
+        Ok(c) => RasterError::Crypto(c),
+        Err(e) => RasterError::Io(e),
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ProgressMsg {
     Stage { name: String, done: u64, total: u64 },
+    /// A line for the user-facing log (e.g. the signature status after decode).
+    Info(String),
     Done,
     Error(String),
 }
@@ -98,15 +287,57 @@ pub fn encode_bytes_to_frames_dir_with_progress(
     p: &RasterParams,
     progress_tx: Option<mpsc::Sender<ProgressMsg>>,
 ) -> Result<EncodeManifest, RasterError> {
-    fs::create_dir_all(out_dir)?;
+    let encoder = FrameEncoder::new(input_bytes, file_name, p)?;
+    write_encoder_frames(&encoder, out_dir, progress_tx)
+}
 
-    let mut hasher = Sha256::new();
-    hasher.update(input_bytes);
-    let sha256_hex = hex::encode(hasher.finalize());
+/// Delete frame files in `dir` with an index of `total` or more, or in a format other than `format`.
+/// Returns how many were removed.
+fn remove_stale_frames(dir: &Path, total: u32, format: FrameFormat) -> io::Result<u32> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some((index, ext)) = name.to_str().and_then(parse_frame_file_name) else {
+            continue;
+        };
+        if index >= total || ext != format.extension() {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Write every frame of `encoder` plus `manifest.json` (and `debug.json` with FEC) to `out_dir`.
+pub(crate) fn write_encoder_frames(
+    encoder: &FrameEncoder,
+    out_dir: &Path,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<EncodeManifest, RasterError> {
+    let p = encoder.params();
+    fs::create_dir_all(out_dir)?;
+    let kept = std::sync::atomic::AtomicU32::new(0);
+    // With `p.resume`, `None` means the frame on disk already matches the plan.
+    let render = |i: u32| -> Result<Option<image::RgbImage>, RasterError> {
+        let img = encoder.frame(i)?;
+        let path = out_dir.join(frame_file_name(i, p.frame_format));
+        if p.resume && read_frame_file(&path).is_ok_and(|on_disk| on_disk == img) {
+            kept.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            return Ok(None);
+        }
+        Ok(Some(img))
+    };
+    let save = |i: u32, img: Option<image::RgbImage>| -> Result<(), RasterError> {
+        if let Some(img) = img {
+            fs::write(out_dir.join(frame_file_name(i, p.frame_format)), encode_frame_file(&img, p.frame_format)?)?;
+        }
+        Ok(())
+    };
 
     // Sync frames
     for i in 0..p.sync_frames {
-        render_solid_frame(p, p.sync_color_symbol)?.save(out_dir.join(format!("frame_{:06}.png", i)))?;
+        save(i, render(i)?)?;
         if let Some(ref tx) = progress_tx {
             let _ = tx.send(ProgressMsg::Stage {
                 name: "sync".into(),
@@ -119,7 +350,7 @@ pub fn encode_bytes_to_frames_dir_with_progress(
     // Calibration frames
     for j in 0..p.calibration_frames {
         let idx = p.sync_frames + j;
-        render_calibration_frame(p)?.save(out_dir.join(format!("frame_{:06}.png", idx)))?;
+        save(idx, render(idx)?)?;
         if let Some(ref tx) = progress_tx {
             let _ = tx.send(ProgressMsg::Stage {
                 name: "calibration".into(),
@@ -129,126 +360,76 @@ pub fn encode_bytes_to_frames_dir_with_progress(
         }
     }
 
-    let payload_cells = (p.grid_w as usize) * (p.grid_h as usize);
-    let payload_bits = payload_cells * 3;
-    let payload_bytes_capacity = (payload_bits / 8) as u32;
-
-    let header_bytes = ShardHeader::BYTES as u32;
-    let max_frame_payload = if p.fec.is_some() {
-        payload_bytes_capacity.saturating_sub(header_bytes)
-    } else {
-        payload_bytes_capacity
-    };
-
-    if max_frame_payload == 0 {
-        return Err(RasterError::Fec("frame too small for payload".into()));
-    }
-
-    let mut frames_written = 0u32;
-
-    if let Some(fecp) = &p.fec {
-        let packets = fec_encode_stream(input_bytes, fecp).map_err(|e| RasterError::Fec(e.to_string()))?;
-
-        if fecp.shard_bytes as u32 > max_frame_payload {
-            return Err(RasterError::Fec(format!(
-                "fec shard_bytes {} exceeds frame payload capacity {}",
-                fecp.shard_bytes, max_frame_payload
-            )));
+    let data_start = encoder.data_start();
+    let data_frames = encoder.total_frames() - data_start;
+
+    // Parallel frame generation with bounded queue
+    let (tx_img, rx_img) = mpsc::sync_channel::<(u32, Result<Option<image::RgbImage>, RasterError>)>(16);
+    let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(8);
+    let counter = std::sync::atomic::AtomicU32::new(0);
+
+    std::thread::scope(|s| -> Result<(), RasterError> {
+        // Worker threads: render frames
+        for _ in 0..num_workers {
+            let tx = tx_img.clone();
+            let counter = &counter;
+            let render = &render;
+            s.spawn(move || loop {
+                let idx = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                if idx >= data_frames {
+                    break;
+                }
+                let frame_index = data_start + idx;
+                if tx.send((frame_index, render(frame_index))).is_err() {
+                    break;
+                }
+            });
         }
+        drop(tx_img);
 
-        let total_packets = packets.len() as u64;
-
-        // Parallel frame generation with bounded queue
-        let (tx_img, rx_img) = mpsc::sync_channel::<(u32, image::ImageBuffer<Rgb<u8>, Vec<u8>>)>(16);
-        let packets_arc = Arc::new(packets);
-        let p_clone = p.clone();
-        let max_payload = max_frame_payload;
-        let orig_total_bytes = input_bytes.len() as u64;
-
-        let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(8);
-        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-
-        std::thread::scope(|s| {
-            // Worker threads: render frames
-            for _ in 0..num_workers {
-                let tx = tx_img.clone();
-                let pkts = Arc::clone(&packets_arc);
-                let params = p_clone.clone();
-                let counter = Arc::clone(&counter);
-                s.spawn(move || {
-                    loop {
-                        let idx = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                        if idx >= pkts.len() {
-                            break;
-                        }
-                        let pkt = &pkts[idx];
-
-                        let hdr = ShardHeader {
-                            group_index: pkt.group_index,
-                            shard_index: pkt.shard_index,
-                            shard_len: pkt.shard_bytes.len() as u16,
-                            orig_total_bytes,
-                            shard_sha256: pkt.shard_sha256,
-                            header_crc32: 0,
-                        }
-                        .with_crc();
-
-                        let mut frame_bytes = Vec::with_capacity(ShardHeader::BYTES + pkt.shard_bytes.len());
-                        frame_bytes.extend_from_slice(&hdr.to_bytes());
-                        frame_bytes.extend_from_slice(&pkt.shard_bytes);
-
-                        let mut padded = vec![0u8; max_payload as usize];
-                        padded[..frame_bytes.len()].copy_from_slice(&frame_bytes);
-
-                        if let Ok(img) = render_payload_frame(&padded, &params) {
-                            let frame_index = params.sync_frames + params.calibration_frames + (idx as u32);
-                            let _ = tx.send((frame_index, img));
-                        }
-                    }
+        // Writer: save to disk
+        for (frames_written, (frame_idx, img)) in rx_img.into_iter().enumerate() {
+            save(frame_idx, img?)?;
+            if let Some(ref tx) = progress_tx {
+                let _ = tx.send(ProgressMsg::Stage {
+                    name: "encode".into(),
+                    done: frames_written as u64 + 1,
+                    total: data_frames as u64,
                 });
             }
-            drop(tx_img);
-
-            // Writer thread: save to disk
-            for (frame_idx, img) in rx_img {
-                img.save(out_dir.join(format!("frame_{:06}.png", frame_idx))).ok();
-                frames_written += 1;
-                if let Some(ref tx) = progress_tx {
-                    let _ = tx.send(ProgressMsg::Stage {
-                        name: "encode".into(),
-                        done: frames_written as u64,
-                        total: total_packets,
-                    });
-                }
+        }
+        Ok(())
+    })?;
+
+    if p.resume {
+        // A longer earlier run, or one in another frame format, leaves files this encode didn't write.
+        let removed = remove_stale_frames(out_dir, encoder.total_frames(), p.frame_format)?;
+        if let Some(tx) = &progress_tx {
+            let kept = kept.into_inner();
+            let mut msg = format!("resume: {kept} of {} frames already written", encoder.total_frames());
+            if removed > 0 {
+                msg += &format!(", {removed} stale frame files removed");
             }
-        });
-
-        let manifest = EncodeManifest {
-            magic: EncodeManifest::MAGIC.to_string(),
-            version: EncodeManifest::VERSION,
-            file_name: file_name.to_string(),
-            total_bytes: input_bytes.len() as u64,
-            chunk_bytes: max_frame_payload,
-            grid_w: p.grid_w,
-            grid_h: p.grid_h,
-            cell_px: p.cell_px,
-            palette: p.palette.id().to_string(),
-            sha256_hex,
-            frames: p.sync_frames + p.calibration_frames + frames_written,
-        };
+            let _ = tx.send(ProgressMsg::Info(msg));
+        }
+    }
 
-        fs::write(out_dir.join("manifest.json"), serde_json::to_vec_pretty(&manifest)?)?;
+    let manifest = encoder.manifest().clone();
+    fs::write(out_dir.join("manifest.json"), serde_json::to_vec_pretty(&manifest)?)?;
 
+    if p.fec.is_some() {
         let meta = json!({
-            "payload_bytes_capacity": payload_bytes_capacity,
+            "payload_bytes_capacity": payload_bytes_capacity(p),
             "header_bytes": ShardHeader::BYTES,
-            "frame_payload_bytes": max_frame_payload,
+            "frame_payload_bytes": manifest.chunk_bytes,
             "sync_frames": p.sync_frames,
             "calibration_frames": p.calibration_frames,
-            "data_frames": frames_written,
+            "data_frames": manifest.data_end() - data_start,
             "border_cells": p.border_cells,
             "fiducial_size_cells": p.fiducial_size_cells,
             "deskew": p.deskew,
+            "border_fiducials": p.border_fiducials,
+            "tiles": p.tiles,
             "fec": p.fec.as_ref().map(|fecp| json!({
               "data_shards": fecp.data_shards,
               "parity_shards": fecp.parity_shards,
@@ -256,45 +437,64 @@ pub fn encode_bytes_to_frames_dir_with_progress(
             }))
         });
         fs::write(out_dir.join("debug.json"), serde_json::to_vec_pretty(&meta)?)?;
+    }
+
+    Ok(manifest)
+}
+
+fn payload_bytes_capacity(p: &RasterParams) -> u32 {
+    let payload_cells = (p.grid_w as usize) * (p.grid_h as usize);
+    let payload_bits = payload_cells * 3;
+    (payload_bits / 8) as u32
+}
 
-        Ok(manifest)
+/// Bytes of data each frame can carry once the shard header (FEC only) is accounted for.
+pub(crate) fn frame_payload_capacity(p: &RasterParams) -> u32 {
+    let payload_bytes_capacity = payload_bytes_capacity(p);
+    if p.fec.is_some() {
+        payload_bytes_capacity.saturating_sub(ShardHeader::BYTES as u32)
     } else {
-        let max_payload = std::cmp::min(max_frame_payload, p.chunk_bytes) as usize;
-        let total_chunks = (input_bytes.len() + max_payload - 1) / max_payload;
+        payload_bytes_capacity
+    }
+}
 
-        for (i, chunk) in input_bytes.chunks(max_payload).enumerate() {
-            let mut frame_payload = vec![0u8; max_payload];
-            frame_payload[..chunk.len()].copy_from_slice(chunk);
-            let img = render_payload_frame(&frame_payload, p)?;
-            let frame_index = p.sync_frames + p.calibration_frames + (i as u32);
-            img.save(out_dir.join(format!("frame_{:06}.png", frame_index)))?;
-            frames_written += 1;
+/// Largest FEC shard that fits in one frame: [`shard_frame_payload`] puts the header in front of
+/// the shard within [`frame_payload_capacity`].
+pub(crate) fn max_shard_bytes(p: &RasterParams) -> usize {
+    (frame_payload_capacity(p) as usize).saturating_sub(ShardHeader::BYTES)
+}
 
-            if let Some(ref tx) = progress_tx {
-                let _ = tx.send(ProgressMsg::Stage {
-                    name: "encode".into(),
-                    done: frames_written as u64,
-                    total: total_chunks as u64,
-                });
-            }
-        }
+/// Geometry-only manifest for decoding frames that arrive without their `manifest.json`.
+pub(crate) fn layout_manifest(p: &RasterParams) -> EncodeManifest {
+    EncodeManifest {
+        magic: EncodeManifest::MAGIC.to_string(),
+        version: EncodeManifest::VERSION,
+        chunk_bytes: frame_payload_capacity(p),
+        grid_w: p.grid_w,
+        grid_h: p.grid_h,
+        cell_px: p.cell_px,
+        palette: p.palette.id().to_string(),
+        tiles: (p.tiles != [1, 1]).then_some(p.tiles),
+        ..Default::default()
+    }
+}
 
-        let manifest = EncodeManifest {
-            magic: EncodeManifest::MAGIC.to_string(),
-            version: EncodeManifest::VERSION,
-            file_name: file_name.to_string(),
-            total_bytes: input_bytes.len() as u64,
-            chunk_bytes: max_payload as u32,
-            grid_w: p.grid_w,
-            grid_h: p.grid_h,
-            cell_px: p.cell_px,
-            palette: p.palette.id().to_string(),
-            sha256_hex,
-            frames: p.sync_frames + p.calibration_frames + frames_written,
-        };
-        fs::write(out_dir.join("manifest.json"), serde_json::to_vec_pretty(&manifest)?)?;
-        Ok(manifest)
+/// Header + shard bytes for one FEC packet, zero-padded to a full frame payload.
+pub(crate) fn shard_frame_payload(pkt: &ShardPacket, orig_total_bytes: u64, frame_payload: usize) -> Vec<u8> {
+    let hdr = ShardHeader {
+        group_index: pkt.group_index,
+        shard_index: pkt.shard_index,
+        shard_len: pkt.shard_bytes.len() as u16,
+        orig_total_bytes,
+        shard_sha256: pkt.shard_sha256,
+        header_crc32: 0,
     }
+    .with_crc();
+
+    let mut padded = vec![0u8; frame_payload];
+    padded[..ShardHeader::BYTES].copy_from_slice(&hdr.to_bytes());
+    padded[ShardHeader::BYTES..ShardHeader::BYTES + pkt.shard_bytes.len()].copy_from_slice(&pkt.shard_bytes);
+    padded
 }
 
 pub fn decode_frames_dir_to_bytes(in_dir: &Path) -> Result<Vec<u8>, RasterError> {
@@ -305,27 +505,97 @@ pub fn decode_frames_dir_to_bytes_with_params(in_dir: &Path, p: &RasterParams) -
     decode_frames_dir_to_bytes_with_progress(in_dir, p, None)
 }
 
+/// Buffered convenience wrapper around [`decode_frames_dir_to_writer_with_progress`].
+///
+/// Needs RAM equal to the recovered size; prefer the writer variant for large archives.
 pub fn decode_frames_dir_to_bytes_with_progress(
     in_dir: &Path,
     p: &RasterParams,
     progress_tx: Option<mpsc::Sender<ProgressMsg>>,
 ) -> Result<Vec<u8>, RasterError> {
+    let mut out = Vec::new();
+    match decode_frames_dir_to_writer_with_progress(in_dir, p, &mut out, progress_tx) {
+        Ok(_) => Ok(out),
+        // The buffer is dropped here, so nothing untrusted escapes.
+        Err(RasterError::UntrustedOutput { .. }) => Err(RasterError::ShaMismatch),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn decode_frames_dir_to_writer<W: Write>(in_dir: &Path, p: &RasterParams, out: &mut W) -> Result<u64, RasterError> {
+    decode_frames_dir_to_writer_with_progress(in_dir, p, out, None)
+}
+
+/// Decode a frames directory, streaming recovered bytes into `out` as they become available.
+///
+/// With FEC enabled, each group is written as soon as all of its data shards have been decoded,
+/// so memory use is bounded by the groups in flight rather than the archive size. Compressed
+/// payloads are decompressed on the fly, and encrypted ones are decrypted first using
+/// `p.decryption`. The SHA-256 can only be checked once everything has been
+/// written; on mismatch this returns [`RasterError::UntrustedOutput`] and whatever reached `out`
+/// must be discarded.
+///
+/// Returns the number of bytes written.
+pub fn decode_frames_dir_to_writer_with_progress<W: Write>(
+    in_dir: &Path,
+    p: &RasterParams,
+    out: &mut W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<u64, RasterError> {
+    let manifest = read_manifest(in_dir)?;
+    decode_frames_with_manifest(in_dir, &manifest, p, out, progress_tx)
+}
+
+/// Load and sanity-check `manifest.json` from a frames directory.
+pub fn read_manifest(in_dir: &Path) -> Result<EncodeManifest, RasterError> {
     let manifest_path = in_dir.join("manifest.json");
     if !manifest_path.exists() {
         return Err(RasterError::ManifestMissing);
     }
     let manifest: EncodeManifest = serde_json::from_slice(&fs::read(manifest_path)?)?;
-    if manifest.magic != EncodeManifest::MAGIC || manifest.version != EncodeManifest::VERSION {
+    if !manifest.is_supported() {
         return Err(RasterError::ManifestInvalid);
     }
+    Ok(manifest)
+}
 
+/// Decode only the bytes carried by the frames (no decryption or decompression) and check them
+/// against `manifest.sha256_hex`. Needs no key, so signed encrypted archives can be verified.
+///
+/// Returns the number of bytes checked.
+pub fn verify_frames_dir_payload(in_dir: &Path, p: &RasterParams) -> Result<u64, RasterError> {
+    let mut carried = read_manifest(in_dir)?;
+    carried.compression = None;
+    carried.encryption = None;
+    carried.dedup = None;
+    decode_frames_with_manifest(in_dir, &carried, p, &mut io::sink(), None)
+}
+
+fn decode_frames_with_manifest<W: Write>(
+    in_dir: &Path,
+    manifest: &EncodeManifest,
+    p: &RasterParams,
+    out: &mut W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<u64, RasterError> {
     let palette = Palette8::Basic;
-    let start_index = detect_data_start(in_dir, &manifest, p, palette);
+    let start_index = detect_data_start(in_dir, manifest, p, palette);
 
-    let total_frames = (manifest.frames - start_index) as u64;
+    let data_end = manifest.data_end();
+    let total_frames = data_end.saturating_sub(start_index) as u64;
+    let mut hasher = Sha256::new();
+    let mut sink = payload_sink(Some(manifest), p, &mut *out)?;
+    let mut journal = p.journal.as_deref().map(|path| DecodeJournal::open(path, manifest, p)).transpose()?;
 
     if let Some(fecp) = &p.fec {
-        let (tx_pkt, rx_pkt) = mpsc::sync_channel::<Option<ShardPacket>>(16);
+        let mut assembler =
+            FecGroupAssembler::new(fecp, manifest.total_bytes).map_err(|e| RasterError::Fec(e.to_string()))?;
+        let (cache, mut writer) = match journal.as_mut() {
+            Some(j) => (Some((j.key, &j.entries)), Some(&mut j.writer)),
+            None => (None, None),
+        };
+
+        let (tx_pkt, rx_pkt) = mpsc::sync_channel::<DecodedFrame>(16);
         let in_dir_arc = Arc::new(in_dir.to_path_buf());
         let manifest_arc = Arc::new(manifest.clone());
         let p_arc = Arc::new(p.clone());
@@ -333,7 +603,7 @@ pub fn decode_frames_dir_to_bytes_with_progress(
         let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(8);
         let counter = Arc::new(std::sync::atomic::AtomicU32::new(0));
 
-        std::thread::scope(|s| {
+        std::thread::scope(|s| -> Result<(), RasterError> {
             // Worker threads: decode frames
             for _ in 0..num_workers {
                 let tx = tx_pkt.clone();
@@ -345,79 +615,91 @@ pub fn decode_frames_dir_to_bytes_with_progress(
                     loop {
                         let idx = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                         let i = start_index + idx;
-                        if i >= m.frames {
+                        if i >= data_end {
                             break;
                         }
 
-                        let path = dir.join(format!("frame_{:06}.png", i));
-                        let mut out_pkt: Option<ShardPacket> = None;
-
-                        if let Ok(bytes) = decode_frame_bytes_with_optional_deskew(&path, &m, &params, palette) {
-                            if bytes.len() >= ShardHeader::BYTES {
-                                let hdr = ShardHeader::from_bytes(&bytes[..ShardHeader::BYTES]);
-                                if hdr.crc_ok(&bytes[..ShardHeader::BYTES]) {
-                                    let shard_end = ShardHeader::BYTES + (hdr.shard_len as usize);
-                                    if shard_end <= bytes.len() {
-                                        let shard = bytes[ShardHeader::BYTES..shard_end].to_vec();
-                                        let mut h = Sha256::new();
-                                        h.update(&shard);
-                                        let sha: [u8; 32] = h.finalize().into();
-                                        if sha == hdr.shard_sha256 {
-                                            out_pkt = Some(ShardPacket {
-                                                group_index: hdr.group_index,
-                                                shard_index: hdr.shard_index,
-                                                shard_bytes: shard,
-                                                shard_sha256: hdr.shard_sha256,
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                        let name = frame_file_name(i, m.frame_format);
+                        let path = frame_path(&dir, i, &m);
+                        let decoded = match cache {
+                            Some((key, entries)) => decode_frame_cached(&path, &name, &key, entries, &m, &params, palette, true),
+                            None => DecodedFrame {
+                                bytes: decode_frame_bytes_with_optional_deskew(&path, &m, &params, palette).ok(),
+                                new_record: None,
+                                from_journal: false,
+                            },
+                        };
 
                         // Always send one message per processed frame so progress is accurate.
-                        let _ = tx.send(out_pkt);
+                        // A closed channel means the writer gave up; stop decoding.
+                        if tx.send(decoded).is_err() {
+                            break;
+                        }
                     }
                 });
             }
             drop(tx_pkt);
 
-            let mut packets = Vec::new();
-            let mut decoded = 0u64;
-            for maybe_pkt in rx_pkt {
-                if let Some(pkt) = maybe_pkt {
-                    packets.push(pkt);
+            for (decoded, frame) in rx_pkt.into_iter().enumerate() {
+                if let Some(w) = writer.as_mut() {
+                    if let Some(id) = &frame.new_record {
+                        w.record(id, frame.bytes.as_deref())?;
+                    }
+                    w.hits += u64::from(frame.from_journal);
+                }
+                let bytes = frame.bytes.unwrap_or_default();
+                for (pkt, _) in tile_payloads(&bytes, manifest).filter_map(shard_packet_from_frame_bytes) {
+                    assembler.push(pkt).map_err(|e| RasterError::Fec(e.to_string()))?;
+                    while let Some(group) = assembler.pop_ready() {
+                        hasher.update(&group);
+                        sink.write_all(&group).map_err(payload_io_error)?;
+                    }
                 }
-                decoded += 1;
                 if let Some(ref tx) = progress_tx {
                     let _ = tx.send(ProgressMsg::Stage {
                         name: "decode".into(),
-                        done: decoded,
+                        done: decoded as u64 + 1,
                         total: total_frames,
                     });
                 }
             }
+            Ok(())
+        })?;
 
-            let total = manifest.total_bytes as usize;
-            let out = fec_decode_collect(packets, total, fecp).map_err(|e| RasterError::Fec(format!("{e:?}")))?;
-
-            let mut hasher = Sha256::new();
-            hasher.update(&out);
-            let sha256_hex = hex::encode(hasher.finalize());
-            if sha256_hex != manifest.sha256_hex {
-                return Err(RasterError::ShaMismatch);
-            }
-            Ok(out)
-        })
+        if !assembler.is_complete() {
+            return Err(RasterError::Fec(format!(
+                "group {} of {} is missing data shards",
+                assembler.next_group(),
+                assembler.total_groups()
+            )));
+        }
     } else {
         let per_frame = manifest.chunk_bytes as usize;
-        let mut out = Vec::with_capacity(manifest.total_bytes as usize);
-
-        for i in start_index..manifest.frames {
-            let path = in_dir.join(format!("frame_{:06}.png", i));
-            let bytes = decode_frame_bytes_with_optional_deskew(&path, &manifest, p, palette)?;
-            let take = std::cmp::min(bytes.len(), per_frame);
-            out.extend_from_slice(&bytes[..take]);
+        let mut remaining = manifest.total_bytes;
+
+        for i in start_index..data_end {
+            let name = frame_file_name(i, manifest.frame_format);
+            let path = frame_path(in_dir, i, manifest);
+            let bytes = match journal.as_mut() {
+                Some(j) => {
+                    let frame = decode_frame_cached(&path, &name, &j.key, &j.entries, manifest, p, palette, false);
+                    j.writer.hits += u64::from(frame.from_journal);
+                    match (frame.bytes, frame.new_record) {
+                        // Unreadable: decode again for the error.
+                        (None, _) => decode_frame_bytes_with_optional_deskew(&path, manifest, p, palette)?,
+                        (Some(bytes), Some(id)) => {
+                            j.writer.record(&id, Some(&bytes))?;
+                            bytes
+                        }
+                        (Some(bytes), None) => bytes,
+                    }
+                }
+                None => decode_frame_bytes_with_optional_deskew(&path, manifest, p, palette)?,
+            };
+            let take = std::cmp::min(std::cmp::min(bytes.len(), per_frame) as u64, remaining) as usize;
+            hasher.update(&bytes[..take]);
+            sink.write_all(&bytes[..take]).map_err(payload_io_error)?;
+            remaining -= take as u64;
 
             if let Some(ref tx) = progress_tx {
                 let _ = tx.send(ProgressMsg::Stage {
@@ -427,34 +709,335 @@ pub fn decode_frames_dir_to_bytes_with_progress(
                 });
             }
         }
+    }
 
-        out.truncate(manifest.total_bytes as usize);
+    if let (Some(j), Some(tx)) = (&journal, &progress_tx) {
+        let _ = tx.send(ProgressMsg::Info(format!(
+            "journal: {} frames from earlier runs, {} decoded",
+            j.writer.hits, j.writer.added
+        )));
+    }
+
+    let (_, written) = finish_payload(sink, Some(manifest), &hex::encode(hasher.finalize()))?;
+    Ok(written)
+}
 
-        let mut hasher = Sha256::new();
-        hasher.update(&out);
-        let sha256_hex = hex::encode(hasher.finalize());
-        if sha256_hex != manifest.sha256_hex {
-            return Err(RasterError::ShaMismatch);
+/// One data frame as seen by the decode workers.
+struct DecodedFrame {
+    /// Demodulated frame bytes, if any.
+    bytes: Option<Vec<u8>>,
+    /// Journal id to record `bytes` under, for frames the journal didn't have.
+    new_record: Option<[u8; 32]>,
+    from_journal: bool,
+}
+
+/// Decode a frame file unless the journal already has it. With `fec`, only bytes whose shard
+/// header verifies are worth recording; anything else is recorded as unusable.
+#[allow(clippy::too_many_arguments)]
+fn decode_frame_cached(
+    path: &Path,
+    name: &str,
+    key: &[u8; 32],
+    entries: &JournalEntries,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    palette: Palette8,
+    fec: bool,
+) -> DecodedFrame {
+    let Ok(file) = fs::read(path) else {
+        return DecodedFrame { bytes: None, new_record: None, from_journal: false };
+    };
+    let id = frame_id(key, name, &file);
+    if let Some(bytes) = entries.get(&id) {
+        return DecodedFrame { bytes, new_record: None, from_journal: true };
+    }
+    let bytes = decode_frame_file(&file)
+        .map_err(RasterError::from)
+        .and_then(|img| decode_rgb_frame_bytes(&img, m, p, palette))
+        .ok()
+        .filter(|bytes| !fec || tile_payloads(bytes, m).any(|t| shard_packet_from_frame_bytes(t).is_some()));
+    DecodedFrame { bytes, new_record: Some(id), from_journal: false }
+}
+
+/// Frames holding bytes `ranges` of the carried payload (after compression/encryption).
+///
+/// With FEC this is every frame holding a data shard of each group a range touches (tiled frames
+/// hold several); without FEC, the chunk frames themselves. Frame indices assume
+/// `p.sync_frames + p.calibration_frames` leading frames, as written by [`FrameEncoder`].
+pub fn frames_for_carried_ranges(m: &EncodeManifest, p: &RasterParams, ranges: &[Range<u64>]) -> BTreeSet<u32> {
+    let data_start = p.sync_frames + p.calibration_frames;
+    let mut frames = BTreeSet::new();
+    for r in ranges {
+        let end = r.end.min(m.total_bytes);
+        if r.start >= end {
+            continue;
+        }
+        if let Some(fecp) = &p.fec {
+            let group_bytes = (fecp.data_shards * fecp.shard_bytes) as u64;
+            let per_group = (fecp.data_shards + fecp.parity_shards) as u64;
+            let [cols, rows] = manifest_tiles(m);
+            let tiles = u64::from((cols * rows).max(1));
+            // Parity shards are placeholders (see `fec_encode_stream`), so they can't stand in for a
+            // missing data shard and aren't worth decoding.
+            for g in r.start / group_bytes..=(end - 1) / group_bytes {
+                let first = u64::from(data_start) + g * per_group / tiles;
+                if first >= u64::from(m.data_end()) {
+                    break;
+                }
+                frames.extend((0..fecp.data_shards as u64).filter_map(|s| u32::try_from(u64::from(data_start) + (g * per_group + s) / tiles).ok()));
+            }
+        } else {
+            let chunk = m.chunk_bytes.max(1) as u64;
+            let frame = |offset: u64| u32::try_from(offset / chunk).unwrap_or(u32::MAX).saturating_add(data_start);
+            frames.extend(frame(r.start)..=frame(end - 1));
         }
-        Ok(out)
     }
+    frames.retain(|&i| i < m.data_end());
+    frames
 }
 
-fn decode_frame_bytes_with_optional_deskew(path: &Path, m: &EncodeManifest, p: &RasterParams, palette: Palette8) -> Result<Vec<u8>, RasterError> {
-    let dyn_img = image::open(path)?;
-    let img = dyn_img.to_rgb8();
+/// Decode only `frames` from a frames directory.
+///
+/// Returns the carried bytes recovered, keyed by their offset in the carried payload: one entry
+/// per complete FEC group, or per chunk frame without FEC. No SHA-256 check is possible on a
+/// partial payload; callers verify what they extract (e.g. against a TOC entry's hash).
+pub fn decode_frame_set(
+    in_dir: &Path,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    frames: &BTreeSet<u32>,
+) -> Result<BTreeMap<u64, Vec<u8>>, RasterError> {
+    let palette = Palette8::Basic;
+    let frames: Vec<u32> = frames.iter().copied().collect();
+    let decoded = std::sync::Mutex::new(Vec::with_capacity(frames.len()));
+    let num_workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).min(8);
+    let counter = std::sync::atomic::AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        for _ in 0..num_workers {
+            s.spawn(|| loop {
+                let k = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(&i) = frames.get(k) else { break };
+                let path = frame_path(in_dir, i, m);
+                let bytes = decode_frame_bytes_with_optional_deskew(&path, m, p, palette).ok();
+                decoded.lock().unwrap().push((i, bytes));
+            });
+        }
+    });
+    let decoded = decoded.into_inner().unwrap();
 
-    let payload_img = if p.deskew {
-        if let Some(warped) = deskew_with_fiducials(&img, m, p, palette) {
-            warped
-        } else {
-            img
+    let mut out = BTreeMap::new();
+    if let Some(fecp) = &p.fec {
+        let group_bytes = (fecp.data_shards * fecp.shard_bytes) as u64;
+        let mut groups: BTreeMap<u32, Vec<Option<Vec<u8>>>> = BTreeMap::new();
+        let packets = decoded
+            .iter()
+            .filter_map(|(_, bytes)| bytes.as_deref())
+            .flat_map(|bytes| tile_payloads(bytes, m).filter_map(shard_packet_from_frame_bytes));
+        for (pkt, _) in packets {
+            let shards = groups.entry(pkt.group_index).or_insert_with(|| vec![None; fecp.data_shards]);
+            if let Some(slot) = shards.get_mut(pkt.shard_index as usize) {
+                *slot = Some(pkt.shard_bytes);
+            }
+        }
+        for (g, shards) in groups {
+            let start = g as u64 * group_bytes;
+            if shards.iter().any(|s| s.is_none()) || start >= m.total_bytes {
+                continue;
+            }
+            let mut bytes: Vec<u8> = shards.into_iter().flatten().flatten().collect();
+            bytes.truncate((m.total_bytes - start) as usize);
+            out.insert(start, bytes);
         }
     } else {
-        img
+        let data_start = p.sync_frames + p.calibration_frames;
+        let chunk = m.chunk_bytes as u64;
+        for (i, bytes) in decoded {
+            let (Some(mut bytes), Some(k)) = (bytes, i.checked_sub(data_start)) else {
+                continue;
+            };
+            let start = k as u64 * chunk;
+            if start >= m.total_bytes {
+                continue;
+            }
+            bytes.truncate(chunk.min(m.total_bytes - start) as usize);
+            out.insert(start, bytes);
+        }
+    }
+    Ok(out)
+}
+
+/// Bytes `ranges` of the carried payload, decoding only the frames that hold them.
+pub fn decode_carried_ranges(
+    in_dir: &Path,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    ranges: &[Range<u64>],
+) -> Result<Vec<Vec<u8>>, RasterError> {
+    if let Some(r) = ranges.iter().find(|r| r.start > r.end || r.end > m.total_bytes) {
+        return Err(RasterError::RangeOutOfPayload { start: r.start, end: r.end, total: m.total_bytes });
+    }
+    let units = decode_frame_set(in_dir, m, p, &frames_for_carried_ranges(m, p, ranges))?;
+    ranges
+        .iter()
+        .map(|r| {
+            // Grown piece by piece rather than sized from the range up front.
+            let mut out = Vec::new();
+            let mut pos = r.start;
+            while pos < r.end {
+                let piece = units
+                    .range(..=pos)
+                    .next_back()
+                    .and_then(|(&start, bytes)| bytes.get((pos - start) as usize..))
+                    .filter(|rest| !rest.is_empty());
+                let Some(rest) = piece else {
+                    return Err(RasterError::Fec(format!("frames holding bytes {pos}..{} could not be decoded", r.end)));
+                };
+                let take = rest.len().min((r.end - pos) as usize);
+                out.extend_from_slice(&rest[..take]);
+                pos += take as u64;
+            }
+            Ok(out)
+        })
+        .collect()
+}
+
+/// Parse and verify a frame's shard header + shard bytes, along with the archive size recorded in
+/// the header. Returns `None` for anything that fails the CRC or the per-shard hash
+/// (sync/calibration frames, damaged captures).
+pub(crate) fn shard_packet_from_frame_bytes(bytes: &[u8]) -> Option<(ShardPacket, u64)> {
+    if bytes.len() < ShardHeader::BYTES {
+        return None;
+    }
+    let hdr = ShardHeader::from_bytes(&bytes[..ShardHeader::BYTES]);
+    if !hdr.crc_ok(&bytes[..ShardHeader::BYTES]) {
+        return None;
+    }
+    let shard_end = ShardHeader::BYTES + (hdr.shard_len as usize);
+    if shard_end > bytes.len() {
+        return None;
+    }
+    let shard = bytes[ShardHeader::BYTES..shard_end].to_vec();
+    let mut h = Sha256::new();
+    h.update(&shard);
+    let sha: [u8; 32] = h.finalize().into();
+    if sha != hdr.shard_sha256 {
+        return None;
+    }
+    let pkt = ShardPacket {
+        group_index: hdr.group_index,
+        shard_index: hdr.shard_index,
+        shard_bytes: shard,
+        shard_sha256: hdr.shard_sha256,
     };
+    Some((pkt, hdr.orig_total_bytes))
+}
 
-    decode_payload_from_rgb(&payload_img, m, p, palette)
+pub(crate) fn decode_frame_bytes_with_optional_deskew(path: &Path, m: &EncodeManifest, p: &RasterParams, palette: Palette8) -> Result<Vec<u8>, RasterError> {
+    let img = read_frame_file(path)?;
+    decode_rgb_frame_bytes(&img, m, p, palette)
+}
+
+pub(crate) fn decode_rgb_frame_bytes(
+    img: &image::RgbImage,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    palette: Palette8,
+) -> Result<Vec<u8>, RasterError> {
+    let [cols, rows] = manifest_tiles(m);
+    if cols * rows > 1 {
+        return decode_tiles(img, m, p, palette, cols * rows);
+    }
+    if p.border_fiducials {
+        if let Some(frame) = rectify_frame(img, m, p, palette, m.cell_px) {
+            return decode_payload_from_rgb(&frame, m, p, palette);
+        }
+    }
+    check_frame_size(img, m, p)?;
+    if p.deskew {
+        if let Some(warped) = deskew_with_fiducials(img, m, p, palette) {
+            return decode_payload_from_rgb(&warped, m, p, palette);
+        }
+    }
+    decode_payload_from_rgb(img, m, p, palette)
+}
+
+/// Payloads of every tile found in a tiled frame image, one after another.
+///
+/// An image of exactly the frame size (a rendered frame file) is cut along the tile layout first,
+/// and a cut tile whose shard header verifies is taken as is. Anything else has its tiles
+/// searched for by their fiducials with [`rectify_tiles`]. Tiles hidden by glare or cropped off
+/// are simply missing.
+fn decode_tiles(
+    img: &image::RgbImage,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    palette: Palette8,
+    count: u32,
+) -> Result<Vec<u8>, RasterError> {
+    let single = EncodeManifest { tiles: None, ..m.clone() };
+    let (tile_w, tile_h) = frame_size_px(&single, p)?;
+    let mut found = Vec::new();
+    if img.dimensions() == frame_size_px(m, p)? {
+        for k in 0..count {
+            let (x, y) = tile_origin(m, p, k)?;
+            let tile = image::imageops::crop_imm(img, x, y, tile_w, tile_h).to_image();
+            let bytes = decode_payload_from_rgb(&tile, &single, p, palette)?;
+            if shard_packet_from_frame_bytes(&bytes).is_some() {
+                found.push(bytes);
+            } else if let Some(frame) = rectify_frame(&tile, &single, p, palette, m.cell_px) {
+                found.push(decode_payload_from_rgb(&frame, &single, p, palette)?);
+            }
+        }
+    }
+    if found.len() < count as usize {
+        let searched = rectify_tiles(img, &single, p, palette, m.cell_px, count as usize);
+        if searched.len() > found.len() {
+            found = searched
+                .iter()
+                .map(|frame| decode_payload_from_rgb(frame, &single, p, palette))
+                .collect::<Result<_, _>>()?;
+        }
+    }
+    if found.is_empty() {
+        return Err(RasterError::NoTiles(count));
+    }
+    Ok(found.concat())
+}
+
+/// Reject images too small to hold every payload cell sampled for `m` (grid plus leading border).
+pub(crate) fn check_frame_size(img: &image::RgbImage, m: &EncodeManifest, p: &RasterParams) -> Result<(), RasterError> {
+    let need_w = m.grid_w.saturating_add(p.border_cells).saturating_mul(m.cell_px);
+    let need_h = m.grid_h.saturating_add(p.border_cells).saturating_mul(m.cell_px);
+    if img.width() < need_w || img.height() < need_h {
+        return Err(RasterError::FrameTooSmall {
+            width: img.width(),
+            height: img.height(),
+            need_w,
+            need_h,
+        });
+    }
+    Ok(())
+}
+
+/// Colour of cell (`gx`, `gy`): its top-left pixel, or with `center` the mean of its middle half.
+pub(crate) fn sample_cell(img: &image::RgbImage, gx: u32, gy: u32, cell_px: u32, center: bool) -> Rgb<u8> {
+    let (px, py) = (gx * cell_px, gy * cell_px);
+    if !center || cell_px < 2 {
+        return *img.get_pixel(px, py);
+    }
+    let lo = cell_px / 4;
+    let hi = (cell_px - lo).max(lo + 1);
+    let mut sum = [0u32; 3];
+    for y in py + lo..py + hi {
+        for x in px + lo..px + hi {
+            let c = img.get_pixel(x, y);
+            for (s, v) in sum.iter_mut().zip(c.0) {
+                *s += v as u32;
+            }
+        }
+    }
+    let n = (hi - lo) * (hi - lo);
+    Rgb(sum.map(|s| ((s + n / 2) / n) as u8))
 }
 
 fn deskew_with_fiducials(
@@ -546,6 +1129,431 @@ fn rgb_dist2(r: u8, g: u8, b: u8, rr: u8, gg: u8, bb: u8) -> u32 {
     (dr * dr + dg * dg + db * db) as u32
 }
 
+/// Fiducial symbols in the order `draw_corner_fiducials` places them: TL, TR, BL, BR.
+const FIDUCIAL_SYMBOLS: [u8; 4] = [2, 3, 4, 7];
+/// Border cells that must read back as the expected checkerboard for a located frame to count.
+const MIN_BORDER_MATCH: f64 = 0.85;
+
+/// A connected run of pixels closest to one fiducial colour; `pixels` indexes into a shared list.
+struct Blob {
+    symbol: u8,
+    pixels: Range<usize>,
+    min: (u32, u32),
+    max: (u32, u32),
+}
+
+impl Blob {
+    fn center(&self) -> Pt2 {
+        Pt2 {
+            x: (self.min.0 + self.max.0) as f64 / 2.0,
+            y: (self.min.1 + self.max.1) as f64 / 2.0,
+        }
+    }
+
+    /// Bounding box diagonal in pixels.
+    fn extent(&self) -> f64 {
+        ((self.max.0 - self.min.0 + 1) as f64).hypot((self.max.1 - self.min.1 + 1) as f64)
+    }
+
+    /// Whether the blob is shaped like a fiducial L of arm length `s`: a roughly square bounding
+    /// box that the L fills (4s - 4) / s^2 of.
+    fn is_l_like(&self, s: u32) -> bool {
+        let fill_l = (4 * s - 4) as f64 / (s * s) as f64;
+        let bw = (self.max.0 - self.min.0 + 1) as f64;
+        let bh = (self.max.1 - self.min.1 + 1) as f64;
+        let fill = self.pixels.len() as f64 / (bw * bh);
+        (0.6..=1.0 / 0.6).contains(&(bw / bh)) && fill > fill_l * 0.5 && fill < (fill_l * 1.8).min(0.8)
+    }
+}
+
+/// Every blob of a fiducial colour in an image, with the palette it was matched against.
+struct FiducialBlobs {
+    colors: Vec<[i32; 3]>,
+    pixels: Vec<u32>,
+    blobs: Vec<Blob>,
+}
+
+/// Label pixels nearest to a fiducial colour, then collect 4-connected blobs.
+fn find_fiducial_blobs(img: &image::RgbImage, palette: Palette8) -> Option<FiducialBlobs> {
+    let (w, h) = img.dimensions();
+    if w < 8 || h < 8 {
+        return None;
+    }
+    let colors: Vec<[i32; 3]> = (0..8u8)
+        .map(|sym| palette.color(sym).map(|c| [c.r as i32, c.g as i32, c.b as i32]))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let levels = channel_levels(img)?;
+
+    let mut labels: Vec<u8> = img
+        .pixels()
+        .map(|px| {
+            let c = levels.map_px(px);
+            let (sym, d2) = nearest_symbol(&colors, c);
+            if FIDUCIAL_SYMBOLS.contains(&sym) && d2 < 3 * 100 * 100 {
+                sym
+            } else {
+                u8::MAX
+            }
+        })
+        .collect();
+    let mut pixels: Vec<u32> = Vec::new();
+    let mut blobs: Vec<Blob> = Vec::new();
+    let mut stack: Vec<u32> = Vec::new();
+    for start in 0..labels.len() {
+        let symbol = labels[start];
+        if symbol == u8::MAX {
+            continue;
+        }
+        labels[start] = u8::MAX;
+        stack.push(start as u32);
+        let first = pixels.len();
+        let (mut min, mut max) = ((u32::MAX, u32::MAX), (0, 0));
+        while let Some(i) = stack.pop() {
+            pixels.push(i);
+            let (x, y) = (i % w, i / w);
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+            let mut visit = |j: u32| {
+                if labels[j as usize] == symbol {
+                    labels[j as usize] = u8::MAX;
+                    stack.push(j);
+                }
+            };
+            if x > 0 {
+                visit(i - 1);
+            }
+            if x + 1 < w {
+                visit(i + 1);
+            }
+            if y > 0 {
+                visit(i - w);
+            }
+            if y + 1 < h {
+                visit(i + w);
+            }
+        }
+        if pixels.len() - first < 16 {
+            pixels.truncate(first);
+            continue;
+        }
+        blobs.push(Blob { symbol, pixels: first..pixels.len(), min, max });
+    }
+
+    Some(FiducialBlobs { colors, pixels, blobs })
+}
+
+/// Find a frame anywhere in `img` (a camera photo or a flatbed scan of a printed page: any scale,
+/// rotation or flip, with margins and text around it) and resample it cell by cell.
+///
+/// Each corner fiducial is the largest L-shaped blob of its colour; see [`rectify_with`] for the
+/// rest. Returns `None` if no four fiducials were found or the frame didn't read back.
+pub(crate) fn rectify_frame(
+    img: &image::RgbImage,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    palette: Palette8,
+    out_cell_px: u32,
+) -> Option<image::RgbImage> {
+    let found = find_fiducial_blobs(img, palette)?;
+    let s = p.fiducial_size_cells;
+    let mut chosen = [0usize; 4];
+    for (c, sym) in chosen.iter_mut().zip(FIDUCIAL_SYMBOLS) {
+        *c = (0..found.blobs.len())
+            .filter(|&k| found.blobs[k].symbol == sym && found.blobs[k].is_l_like(s))
+            .max_by_key(|&k| found.blobs[k].pixels.len())?;
+    }
+    rectify_with(img, &found, chosen, m, p, out_cell_px)
+}
+
+/// Find up to `max_tiles` frames in `img`, e.g. the tiles of one tiled frame, each by its own four
+/// fiducials, and resample each as [`rectify_frame`] does. Order follows how well the fiducials
+/// line up, not the tile layout; tiles whose fiducials are missing or glared are left out.
+pub(crate) fn rectify_tiles(
+    img: &image::RgbImage,
+    m: &EncodeManifest,
+    p: &RasterParams,
+    palette: Palette8,
+    out_cell_px: u32,
+    max_tiles: usize,
+) -> Vec<image::RgbImage> {
+    let Some(found) = find_fiducial_blobs(img, palette) else {
+        return Vec::new();
+    };
+    let s = p.fiducial_size_cells;
+    let full_w = (m.grid_w + 2 * p.border_cells) as f64;
+    let full_h = (m.grid_h + 2 * p.border_cells) as f64;
+    // The largest L candidates of each colour; a few more than there are tiles, for stray blobs.
+    let candidates: Vec<Vec<usize>> = FIDUCIAL_SYMBOLS
+        .iter()
+        .map(|&sym| {
+            let mut c: Vec<usize> = (0..found.blobs.len())
+                .filter(|&k| found.blobs[k].symbol == sym && found.blobs[k].is_l_like(s))
+                .collect();
+            c.sort_by_key(|&k| std::cmp::Reverse(found.blobs[k].pixels.len()));
+            c.truncate((2 * max_tiles).clamp(2, 12));
+            c
+        })
+        .collect();
+
+    // Group one L of each colour into a frame: the four must sit on a parallelogram (a
+    // perspective view of a rectangle is close to one) whose sides are as many L sizes long as
+    // the frame is fiducials wide and high.
+    let dist = |a: Pt2, b: Pt2| (a.x - b.x).hypot(a.y - b.y);
+    let mut groups: Vec<(f64, [usize; 4])> = Vec::new();
+    for &tl in &candidates[0] {
+        for &tr in &candidates[1] {
+            for &bl in &candidates[2] {
+                for &br in &candidates[3] {
+                    let k = [tl, tr, bl, br];
+                    let [a, b, c, d] = k.map(|k| &found.blobs[k]);
+                    let areas = k.map(|k| found.blobs[k].pixels.len());
+                    if areas.iter().max() > areas.iter().min().map(|a| a * 3).as_ref() {
+                        continue;
+                    }
+                    let (ca, cb, cc, cd) = (a.center(), b.center(), c.center(), d.center());
+                    let diag = dist(ca, cd).max(dist(cb, cc));
+                    if diag < 1.0 {
+                        continue;
+                    }
+                    let skew = (cb.x + cc.x - ca.x - cd.x).hypot(cb.y + cc.y - ca.y - cd.y) / diag;
+                    // The L's bounding box spans s cells (its diagonal about s * 1.4), and the
+                    // centres of two Ls along an edge sit (full - s) cells apart.
+                    let cell = (a.extent() + b.extent() + c.extent() + d.extent()) / 4.0 / (s as f64 * 1.414);
+                    let ratio_w = (dist(ca, cb) + dist(cc, cd)) / 2.0 / (cell * (full_w - s as f64));
+                    let ratio_h = (dist(ca, cc) + dist(cb, cd)) / 2.0 / (cell * (full_h - s as f64));
+                    let plausible = |r: f64| (0.6..=1.0 / 0.6).contains(&r);
+                    if skew < 0.25 && plausible(ratio_w) && plausible(ratio_h) {
+                        groups.push((skew, k));
+                    }
+                }
+            }
+        }
+    }
+    groups.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut used = vec![false; found.blobs.len()];
+    let mut out = Vec::new();
+    for (_, k) in groups {
+        if out.len() >= max_tiles {
+            break;
+        }
+        if k.iter().any(|&k| used[k]) {
+            continue;
+        }
+        if let Some(tile) = rectify_with(img, &found, k, m, p, out_cell_px) {
+            k.iter().for_each(|&k| used[k] = true);
+            out.push(tile);
+        }
+    }
+    out
+}
+
+/// Resample the frame whose TL, TR, BL and BR fiducials are `chosen` (indices into
+/// `found.blobs`).
+///
+/// The fiducials' centroids give a homography from grid cells to image pixels, refined by
+/// dropping blob pixels that fall outside the L (payload cells of the same colour touching it).
+/// Every cell is then sampled around its centre and white-balanced against the border
+/// checkerboard. The result is a clean frame of `out_cell_px` pixels per cell, or `None` if the
+/// fiducials differ too much in size or fewer than [`MIN_BORDER_MATCH`] of the border cells read
+/// back correctly.
+fn rectify_with(
+    img: &image::RgbImage,
+    found: &FiducialBlobs,
+    chosen: [usize; 4],
+    m: &EncodeManifest,
+    p: &RasterParams,
+    out_cell_px: u32,
+) -> Option<image::RgbImage> {
+    let (w, h) = img.dimensions();
+    let s = p.fiducial_size_cells;
+    let b = p.border_cells;
+    let full_w = m.grid_w + 2 * b;
+    let full_h = m.grid_h + 2 * b;
+    if s < 3 || b < 2 || full_w < 2 * s || full_h < 2 * s || out_cell_px == 0 {
+        return None;
+    }
+    let FiducialBlobs { colors, pixels, blobs } = found;
+    let chosen: [&Blob; 4] = chosen.map(|k| &blobs[k]);
+    let areas = chosen.iter().map(|bl| bl.pixels.len());
+    let (lo, hi) = (areas.clone().min()?, areas.max()?);
+    if hi > lo * 3 {
+        return None;
+    }
+
+    // Each L in cell coordinates (border included): the frame corner it hugs, which way its arms
+    // run, and its centroid, `c` cells in from the corner along both axes.
+    let corners = [(0.0, 0.0, 1.0, 1.0), (full_w as f64, 0.0, -1.0, 1.0), (0.0, full_h as f64, 1.0, -1.0), (full_w as f64, full_h as f64, -1.0, -1.0)];
+    let c = (s * s + 2 * s - 4) as f64 / (4 * s - 4) as f64;
+    let cells: [Pt2; 4] = corners.map(|(x, y, dx, dy)| Pt2 { x: x + dx * c, y: y + dy * c });
+    let in_l = |k: usize, g: Pt2| {
+        let (x, y, dx, dy) = corners[k];
+        let (u, v) = ((g.x - x) * dx, (g.y - y) * dy);
+        let s = s as f64;
+        u >= 0.0 && v >= 0.0 && ((u < 2.0 && v < s) || (u < s && v < 2.0))
+    };
+    let centroid = |bl: &Blob, keep: &dyn Fn(Pt2) -> bool| -> Option<Pt2> {
+        let (mut sx, mut sy, mut n) = (0.0, 0.0, 0u64);
+        for &i in &pixels[bl.pixels.clone()] {
+            let pt = Pt2 { x: (i % w) as f64 + 0.5, y: (i / w) as f64 + 0.5 };
+            if keep(pt) {
+                sx += pt.x;
+                sy += pt.y;
+                n += 1;
+            }
+        }
+        (n > 0).then(|| Pt2 { x: sx / n as f64, y: sy / n as f64 })
+    };
+    let mut found = [Pt2 { x: 0.0, y: 0.0 }; 4];
+    for (f, bl) in found.iter_mut().zip(&chosen) {
+        *f = centroid(bl, &|_| true)?;
+    }
+    let mut hmat = homography_from_4(cells, found).ok()?;
+    for _ in 0..3 {
+        let inv = hmat.try_inverse()?;
+        for (k, bl) in chosen.iter().enumerate() {
+            found[k] = centroid(bl, &|pt| in_l(k, apply_h(&inv, pt)))?;
+        }
+        hmat = homography_from_4(cells, found).ok()?;
+    }
+
+    // Sample every cell around its centre, averaging over about half a cell.
+    let mid = Pt2 { x: full_w as f64 / 2.0, y: full_h as f64 / 2.0 };
+    let a = apply_h(&hmat, mid);
+    let e = apply_h(&hmat, Pt2 { x: mid.x + 1.0, y: mid.y });
+    let r = (((e.x - a.x).hypot(e.y - a.y)) / 4.0).floor() as i64;
+    let mut samples: Vec<[f64; 3]> = Vec::with_capacity((full_w * full_h) as usize);
+    for gy in 0..full_h {
+        for gx in 0..full_w {
+            let pt = apply_h(&hmat, Pt2 { x: gx as f64 + 0.5, y: gy as f64 + 0.5 });
+            let (cx, cy) = (pt.x.floor() as i64, pt.y.floor() as i64);
+            if cx < 0 || cy < 0 || cx >= w as i64 || cy >= h as i64 {
+                return None;
+            }
+            let mut sum = [0.0; 3];
+            let mut n = 0.0;
+            for y in (cy - r).max(0)..=(cy + r).min(h as i64 - 1) {
+                for x in (cx - r).max(0)..=(cx + r).min(w as i64 - 1) {
+                    let px = img.get_pixel(x as u32, y as u32);
+                    for (s, v) in sum.iter_mut().zip(px.0) {
+                        *s += v as f64;
+                    }
+                    n += 1.0;
+                }
+            }
+            samples.push(sum.map(|s| s / n));
+        }
+    }
+
+    // White balance: stretch each channel so the border's black and white cells read 0 and 255.
+    // Checkerboard cells of the border; the Ls cover some.
+    let in_border = |gx: u32, gy: u32| {
+        let g = Pt2 { x: gx as f64 + 0.5, y: gy as f64 + 0.5 };
+        (gx < b || gy < b || gx >= b + m.grid_w || gy >= b + m.grid_h) && !(0..4).any(|k| in_l(k, g))
+    };
+    let mut dark = [0.0; 3];
+    let mut light = [0.0; 3];
+    let (mut n_dark, mut n_light) = (0.0, 0.0);
+    for gy in 0..full_h {
+        for gx in 0..full_w {
+            if !in_border(gx, gy) {
+                continue;
+            }
+            let px = samples[(gy * full_w + gx) as usize];
+            if (gx ^ gy) & 1 == 0 {
+                dark.iter_mut().zip(px).for_each(|(d, v)| *d += v);
+                n_dark += 1.0;
+            } else {
+                light.iter_mut().zip(px).for_each(|(l, v)| *l += v);
+                n_light += 1.0;
+            }
+        }
+    }
+    if n_dark == 0.0 || n_light == 0.0 {
+        return None;
+    }
+    let dark = dark.map(|d| d / n_dark);
+    let light = light.map(|l| l / n_light);
+    if (0..3).any(|ch| light[ch] - dark[ch] < 32.0) {
+        return None;
+    }
+    let balance = |px: [f64; 3]| -> [u8; 3] {
+        std::array::from_fn(|ch| ((px[ch] - dark[ch]) / (light[ch] - dark[ch]) * 255.0).round().clamp(0.0, 255.0) as u8)
+    };
+
+    let mut out = image::RgbImage::new(full_w * out_cell_px, full_h * out_cell_px);
+    let (mut border, mut border_ok) = (0u32, 0u32);
+    for gy in 0..full_h {
+        for gx in 0..full_w {
+            let [r, g, bl] = balance(samples[(gy * full_w + gx) as usize]);
+            if in_border(gx, gy) {
+                border += 1;
+                let want = if (gx ^ gy) & 1 == 0 { 0 } else { 1 };
+                if nearest_symbol(colors, [r as i32, g as i32, bl as i32]).0 == want {
+                    border_ok += 1;
+                }
+            }
+            paint_cell(&mut out, gx, gy, out_cell_px, r, g, bl);
+        }
+    }
+    if (border_ok as f64) < border as f64 * MIN_BORDER_MATCH {
+        return None;
+    }
+    Some(out)
+}
+
+/// Per-channel black and white points (1st and 99th percentiles), to undo a scanner's or
+/// camera's exposure before pixels are matched against the palette.
+struct Levels {
+    lo: [i32; 3],
+    hi: [i32; 3],
+}
+
+impl Levels {
+    fn map_px(&self, px: &Rgb<u8>) -> [i32; 3] {
+        std::array::from_fn(|ch| ((px[ch] as i32 - self.lo[ch]) * 255 / (self.hi[ch] - self.lo[ch])).clamp(0, 255))
+    }
+}
+
+fn channel_levels(img: &image::RgbImage) -> Option<Levels> {
+    let mut hist = [[0u64; 256]; 3];
+    for px in img.pixels() {
+        for ch in 0..3 {
+            hist[ch][px[ch] as usize] += 1;
+        }
+    }
+    let n = img.width() as u64 * img.height() as u64;
+    let percentile = |hist: &[u64; 256], q: u64| {
+        let mut acc = 0;
+        hist.iter().position(|&c| {
+            acc += c;
+            acc * 100 > n * q
+        })
+    };
+    let mut levels = Levels { lo: [0; 3], hi: [255; 3] };
+    for (ch, hist) in hist.iter().enumerate() {
+        levels.lo[ch] = percentile(hist, 1)? as i32;
+        levels.hi[ch] = percentile(hist, 99)? as i32;
+        if levels.hi[ch] - levels.lo[ch] < 32 {
+            return None;
+        }
+    }
+    Some(levels)
+}
+
+/// Nearest palette symbol and its squared distance.
+fn nearest_symbol(colors: &[[i32; 3]], c: [i32; 3]) -> (u8, i32) {
+    let mut best = (0u8, i32::MAX);
+    for (sym, k) in colors.iter().enumerate() {
+        let d = (0..3).map(|ch| (k[ch] - c[ch]).pow(2)).sum::<i32>();
+        if d < best.1 {
+            best = (sym as u8, d);
+        }
+    }
+    best
+}
+
 fn decode_payload_from_rgb(
     img: &image::ImageBuffer<Rgb<u8>, Vec<u8>>,
     m: &EncodeManifest,
@@ -561,11 +1569,7 @@ fn decode_payload_from_rgb(
 
     for y in 0..m.grid_h {
         for x in 0..m.grid_w {
-            let gx = x + p.border_cells;
-            let gy = y + p.border_cells;
-            let px = gx * m.cell_px;
-            let py = gy * m.cell_px;
-            let p0 = img.get_pixel(px, py);
+            let p0 = sample_cell(img, x + p.border_cells, y + p.border_cells, m.cell_px, p.center_sample);
 
             let sym = palette.symbol_from_rgb_nearest(p0[0], p0[1], p0[2]);
             write_3bits(&mut payload, bit_i, sym);
@@ -585,7 +1589,7 @@ fn detect_data_start(in_dir: &Path, m: &EncodeManifest, p: &RasterParams, palett
     let mut saw_cal = false;
 
     for i in 0..limit {
-        let path = in_dir.join(format!("frame_{:06}.png", i));
+        let path = frame_path(in_dir, i, m);
         let Ok(stats) = frame_symbol_stats(&path, m, p, palette) else { continue };
 
         if stats.unique_symbols <= 1 {
@@ -608,17 +1612,22 @@ struct SymbolStats {
 }
 
 fn frame_symbol_stats(path: &Path, m: &EncodeManifest, p: &RasterParams, palette: Palette8) -> Result<SymbolStats, RasterError> {
-    let dyn_img = image::open(path)?;
-    let img = dyn_img.to_rgb8();
+    let img = read_frame_file(path)?;
 
+    Ok(SymbolStats {
+        unique_symbols: rgb_frame_symbol_count(&img, m, p, palette),
+    })
+}
+
+/// Number of distinct palette symbols in the payload area (1 for solid sync frames).
+pub(crate) fn rgb_frame_symbol_count(img: &image::RgbImage, m: &EncodeManifest, p: &RasterParams, palette: Palette8) -> usize {
     let mut seen = [false; 8];
+    if check_frame_size(img, m, p).is_err() {
+        return 0;
+    }
     for y in 0..m.grid_h {
         for x in 0..m.grid_w {
-            let gx = x + p.border_cells;
-            let gy = y + p.border_cells;
-            let px = gx * m.cell_px;
-            let py = gy * m.cell_px;
-            let p0 = img.get_pixel(px, py);
+            let p0 = sample_cell(img, x + p.border_cells, y + p.border_cells, m.cell_px, p.center_sample);
             let sym = palette.symbol_from_rgb_nearest(p0[0], p0[1], p0[2]) as usize;
             if sym < 8 {
                 seen[sym] = true;
@@ -626,9 +1635,7 @@ fn frame_symbol_stats(path: &Path, m: &EncodeManifest, p: &RasterParams, palette
         }
     }
 
-    Ok(SymbolStats {
-        unique_symbols: seen.iter().filter(|x| **x).count(),
-    })
+    seen.iter().filter(|x| **x).count()
 }
 
 #[derive(Clone, Copy)]
@@ -649,7 +1656,7 @@ impl ShardHeader {
         self
     }
 
-    fn to_bytes_no_crc(&self) -> [u8; Self::BYTES - 4] {
+    fn to_bytes_no_crc(self) -> [u8; Self::BYTES - 4] {
         let mut out = [0u8; Self::BYTES - 4];
         out[0..4].copy_from_slice(&self.group_index.to_le_bytes());
         out[4..6].copy_from_slice(&self.shard_index.to_le_bytes());
@@ -659,7 +1666,7 @@ impl ShardHeader {
         out
     }
 
-    fn to_bytes(&self) -> [u8; Self::BYTES] {
+    fn to_bytes(self) -> [u8; Self::BYTES] {
         let mut out = [0u8; Self::BYTES];
         out[0..Self::BYTES - 4].copy_from_slice(&self.to_bytes_no_crc());
         out[Self::BYTES - 4..Self::BYTES].copy_from_slice(&self.header_crc32.to_le_bytes());
@@ -706,7 +1713,72 @@ fn full_grid_h(p: &RasterParams) -> u32 {
     p.grid_h + 2 * p.border_cells
 }
 
-fn render_payload_frame(payload: &[u8], p: &RasterParams) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
+/// White cells between neighbouring tiles of a tiled frame, so their fiducials stay apart.
+const TILE_GAP_CELLS: u32 = 4;
+
+/// Most tile columns (or rows) in one frame image.
+pub const MAX_TILES: u32 = 8;
+
+/// Tile columns and rows of the frames `m` describes, each kept within 1..=[`MAX_TILES`] since
+/// the manifest may come from an untrusted file.
+pub(crate) fn manifest_tiles(m: &EncodeManifest) -> [u32; 2] {
+    m.tiles.map_or([1, 1], |t| t.map(|n| n.clamp(1, MAX_TILES)))
+}
+
+/// Pixel size of one frame image for `m`: every tile plus the gaps between them. Fails if that
+/// overflows, as it can for a hostile manifest.
+pub(crate) fn frame_size_px(m: &EncodeManifest, p: &RasterParams) -> Result<(u32, u32), RasterError> {
+    let [cols, rows] = manifest_tiles(m);
+    let side = |cells: u32, n: u32| {
+        let tile = cells.checked_add(p.border_cells.checked_mul(2)?)?;
+        n.checked_mul(tile)?.checked_add((n - 1).checked_mul(TILE_GAP_CELLS)?)?.checked_mul(m.cell_px)
+    };
+    side(m.grid_w, cols).zip(side(m.grid_h, rows)).ok_or_else(|| layout_too_large(m))
+}
+
+/// Top-left pixel of tile `k` (left to right, then top to bottom) in a frame image for `m`.
+fn tile_origin(m: &EncodeManifest, p: &RasterParams, k: u32) -> Result<(u32, u32), RasterError> {
+    let [cols, _] = manifest_tiles(m);
+    let offset = |cells: u32, i: u32| {
+        let step = cells.checked_add(p.border_cells.checked_mul(2)?)?.checked_add(TILE_GAP_CELLS)?;
+        i.checked_mul(step)?.checked_mul(m.cell_px)
+    };
+    offset(m.grid_w, k % cols).zip(offset(m.grid_h, k / cols)).ok_or_else(|| layout_too_large(m))
+}
+
+fn layout_too_large(m: &EncodeManifest) -> RasterError {
+    RasterError::LayoutTooLarge { grid_w: m.grid_w, grid_h: m.grid_h, cell_px: m.cell_px, tiles: manifest_tiles(m) }
+}
+
+/// Lay single-tile frames out as one frame image of `p.tiles`, left to right then top to bottom
+/// on a white background. Short of images, the last tiles repeat the first ones.
+pub(crate) fn compose_tiles(tiles: Vec<image::RgbImage>, p: &RasterParams) -> Result<image::RgbImage, RasterError> {
+    let m = layout_manifest(p);
+    let count = (p.tiles[0] * p.tiles[1]) as usize;
+    if count <= 1 || tiles.is_empty() {
+        return Ok(tiles.into_iter().next().unwrap_or_default());
+    }
+    let (w, h) = frame_size_px(&m, p)?;
+    let Rgb8 { r, g, b } = p.palette.color(1).unwrap();
+    let mut img = image::RgbImage::from_pixel(w, h, Rgb([r, g, b]));
+    for k in 0..count {
+        let (x, y) = tile_origin(&m, p, k as u32)?;
+        image::imageops::replace(&mut img, &tiles[k % tiles.len()], x as i64, y as i64);
+    }
+    Ok(img)
+}
+
+/// Payload bytes of each tile in bytes from [`decode_rgb_frame_bytes`].
+pub(crate) fn tile_payloads<'a>(bytes: &'a [u8], m: &EncodeManifest) -> impl Iterator<Item = &'a [u8]> {
+    let per_tile = if m.tiles.is_some() {
+        (m.grid_w as usize * m.grid_h as usize * 3 / 8).max(1)
+    } else {
+        bytes.len().max(1)
+    };
+    bytes.chunks(per_tile)
+}
+
+pub(crate) fn render_payload_frame(payload: &[u8], p: &RasterParams) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
     let w_px = full_grid_w(p) * p.cell_px;
     let h_px = full_grid_h(p) * p.cell_px;
 
@@ -753,6 +1825,25 @@ fn draw_corner_fiducials(img: &mut image::ImageBuffer<Rgb<u8>, Vec<u8>>, p: &Ras
     let s = p.fiducial_size_cells;
     let b = p.border_cells;
 
+    if p.border_fiducials {
+        // Two cells wide, arms along the outer edges, mirrored so each one hugs its own corner.
+        let (fw, fh) = (full_grid_w(p), full_grid_h(p));
+        let s = s.min(fw / 2).min(fh / 2);
+        for (sym, flip_x, flip_y) in [(2, false, false), (3, true, false), (4, false, true), (7, true, true)] {
+            let c = Palette8::Basic.color(sym).unwrap();
+            for i in 0..s {
+                for j in 0..2 {
+                    for (u, v) in [(i, j), (j, i)] {
+                        let x = if flip_x { fw - 1 - u } else { u };
+                        let y = if flip_y { fh - 1 - v } else { v };
+                        paint_cell(img, x, y, p.cell_px, c.r, c.g, c.b);
+                    }
+                }
+            }
+        }
+        return;
+    }
+
     draw_l(img, b, b, s, p.cell_px, 2);
     draw_l(img, b + p.grid_w - s, b, s, p.cell_px, 3);
     draw_l(img, b, b + p.grid_h - s, s, p.cell_px, 4);
@@ -773,7 +1864,7 @@ fn draw_l(img: &mut image::ImageBuffer<Rgb<u8>, Vec<u8>>, x0: u32, y0: u32, s: u
     }
 }
 
-fn render_solid_frame(p: &RasterParams, symbol: u8) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
+pub(crate) fn render_solid_frame(p: &RasterParams, symbol: u8) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
     let w_px = full_grid_w(p) * p.cell_px;
     let h_px = full_grid_h(p) * p.cell_px;
     let mut img: image::ImageBuffer<Rgb<u8>, Vec<u8>> = image::ImageBuffer::new(w_px, h_px);
@@ -786,7 +1877,7 @@ fn render_solid_frame(p: &RasterParams, symbol: u8) -> Result<image::ImageBuffer
     Ok(img)
 }
 
-fn render_calibration_frame(p: &RasterParams) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
+pub(crate) fn render_calibration_frame(p: &RasterParams) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, RasterError> {
     let w_px = full_grid_w(p) * p.cell_px;
     let h_px = full_grid_h(p) * p.cell_px;
     let mut img: image::ImageBuffer<Rgb<u8>, Vec<u8>> = image::ImageBuffer::new(w_px, h_px);
@@ -852,7 +1943,7 @@ fn read_3bits(bytes: &[u8], bit_i: usize) -> u8 {
         let i = bit_i + k;
         let b = bytes.get(i / 8).copied().unwrap_or(0);
         let bit = (b >> (i % 8)) & 1;
-        v |= (bit as u8) << k;
+        v |= bit << k;
     }
     v
 }
@@ -873,3 +1964,186 @@ fn write_3bits(bytes: &mut [u8], bit_i: usize, sym: u8) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_raster_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn small_params() -> RasterParams {
+        RasterParams { grid_w: 64, grid_h: 64, sync_frames: 1, payload: PayloadKind::Raw, toc: false, ..Profile::Archive.defaults() }
+    }
+
+    fn tiled_params() -> RasterParams {
+        RasterParams {
+            grid_w: 48,
+            grid_h: 48,
+            sync_frames: 1,
+            payload: PayloadKind::Raw,
+            toc: false,
+            tiles: [2, 2],
+            border_fiducials: true,
+            fec: Some(FecParams { data_shards: 4, parity_shards: 2, shard_bytes: 200 }),
+            ..Profile::Scan.defaults()
+        }
+    }
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + i / 7) as u8).collect()
+    }
+
+    #[test]
+    fn resume_removes_frames_past_the_new_end() {
+        let dir = scratch_dir("resume_stale");
+        let p = small_params();
+        let long = encode_bytes_to_frames_dir(&test_data(20_000), "data.bin", &dir, &p).unwrap();
+        std::fs::write(dir.join(frame_file_name(0, FrameFormat::Qoi)), b"old").unwrap();
+        std::fs::write(dir.join("frame_notes.txt"), b"keep").unwrap();
+
+        let data = test_data(3000);
+        let resumed = RasterParams { resume: true, ..p.clone() };
+        let (tx, rx) = mpsc::channel();
+        let m = encode_bytes_to_frames_dir_with_progress(&data, "data.bin", &dir, &resumed, Some(tx)).unwrap();
+        assert!(m.frames < long.frames);
+        let info: Vec<String> = rx
+            .try_iter()
+            .filter_map(|msg| if let ProgressMsg::Info(s) = msg { Some(s) } else { None })
+            .collect();
+        let removed = long.frames - m.frames + 1;
+        assert!(info[0].ends_with(&format!(", {removed} stale frame files removed")), "{info:?}");
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|n| n.starts_with("frame_"))
+            .collect();
+        names.sort();
+        let expected = (0..m.frames).map(|i| frame_file_name(i, p.frame_format)).chain(["frame_notes.txt".into()]);
+        assert_eq!(names, expected.collect::<Vec<_>>());
+        assert_eq!(decode_frames_dir_to_bytes_with_params(&dir, &p).unwrap(), data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn carried_ranges_round_trip() {
+        let dir = scratch_dir("ranges");
+        let p = small_params();
+        let data = test_data(20_000);
+        encode_bytes_to_frames_dir(&data, "data.bin", &dir, &p).unwrap();
+        let m = read_manifest(&dir).unwrap();
+        let ranges = [0..10, 1500..9000, 19_990..20_000, 7..7];
+        let got = decode_carried_ranges(&dir, &m, &p, &ranges).unwrap();
+        for (r, bytes) in ranges.iter().zip(got) {
+            assert_eq!(bytes, data[r.start as usize..r.end as usize]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn carried_ranges_outside_the_payload_are_rejected() {
+        let dir = scratch_dir("bad_ranges");
+        let p = small_params();
+        encode_bytes_to_frames_dir(&test_data(5000), "data.bin", &dir, &p).unwrap();
+        let m = read_manifest(&dir).unwrap();
+        #[allow(clippy::reversed_empty_ranges)]
+        for r in [10..5, 0..5001, u64::MAX - 1..u64::MAX] {
+            assert!(matches!(
+                decode_carried_ranges(&dir, &m, &p, &[r]),
+                Err(RasterError::RangeOutOfPayload { .. })
+            ));
+        }
+        let far = u64::MAX - 1..u64::MAX;
+        assert!(frames_for_carried_ranges(&m, &p, &[far]).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tiled_frames_round_trip() {
+        let dir = scratch_dir("tiles");
+        let p = tiled_params();
+        let data = test_data(3000);
+        let m = encode_bytes_to_frames_dir(&data, "data.bin", &dir, &p).unwrap();
+        assert_eq!(m.tiles, Some([2, 2]));
+        let img = read_frame_file(&frame_path(&dir, m.data_end() - 1, &m)).unwrap();
+        assert_eq!(img.dimensions(), frame_size_px(&m, &p).unwrap());
+        let got = decode_frames_dir_to_bytes_with_progress(&dir, &p, None).unwrap();
+        assert_eq!(got, data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_tiles_are_skipped() {
+        let p = tiled_params();
+        let enc = FrameEncoder::new(&test_data(3000), "data.bin", &p).unwrap();
+        let m = enc.manifest().clone();
+        let mut img = enc.frame(enc.data_start()).unwrap();
+        let shards = |img: &image::RgbImage| {
+            let bytes = decode_rgb_frame_bytes(img, &m, &p, Palette8::Basic).unwrap();
+            tile_payloads(&bytes, &m).filter_map(shard_packet_from_frame_bytes).count()
+        };
+        assert_eq!(shards(&img), 4);
+
+        // Glare over the top-right tile.
+        let (w, h) = frame_size_px(&m, &p).unwrap();
+        for (_, _, px) in img.enumerate_pixels_mut().filter(|(x, y, _)| *x >= w / 2 && *y < h / 2) {
+            *px = Rgb([255, 255, 255]);
+        }
+        assert_eq!(shards(&img), 3);
+
+        let white = image::RgbImage::from_pixel(w, h, Rgb([255, 255, 255]));
+        assert!(matches!(decode_rgb_frame_bytes(&white, &m, &p, Palette8::Basic), Err(RasterError::NoTiles(4))));
+    }
+
+    #[test]
+    fn bad_tile_layouts_are_rejected() {
+        for tiles in [[0, 1], [1, 0], [MAX_TILES + 1, 1], [u32::MAX, u32::MAX]] {
+            let p = RasterParams { tiles, ..tiled_params() };
+            assert!(FrameEncoder::new(b"data", "data.bin", &p).is_err(), "{tiles:?}");
+        }
+        let no_fec = RasterParams { fec: None, ..tiled_params() };
+        assert!(FrameEncoder::new(b"data", "data.bin", &no_fec).is_err());
+
+        // A manifest claiming an absurd layout decodes to nothing instead of overflowing.
+        let p = tiled_params();
+        let enc = FrameEncoder::new(b"data", "data.bin", &p).unwrap();
+        let hostile = EncodeManifest { tiles: Some([u32::MAX, u32::MAX]), ..enc.manifest().clone() };
+        assert_eq!(manifest_tiles(&hostile), [MAX_TILES, MAX_TILES]);
+        let img = enc.frame(enc.data_start()).unwrap();
+        let _ = decode_rgb_frame_bytes(&img, &hostile, &p, Palette8::Basic);
+    }
+
+    #[test]
+    fn oversized_layout_is_an_error() {
+        let p = tiled_params();
+        let enc = FrameEncoder::new(b"data", "data.bin", &p).unwrap();
+        let m = enc.manifest().clone();
+        assert!(frame_size_px(&m, &p).is_ok());
+        let last = m.tiles.unwrap()[0] * m.tiles.unwrap()[1] - 1;
+        assert!(tile_origin(&m, &p, last).is_ok());
+
+        let img = enc.frame(enc.data_start()).unwrap();
+        for hostile in [
+            EncodeManifest { grid_w: u32::MAX - 1, ..m.clone() },
+            EncodeManifest { grid_h: u32::MAX / 4, tiles: Some([MAX_TILES, MAX_TILES]), ..m.clone() },
+            EncodeManifest { cell_px: u32::MAX / 2, ..m.clone() },
+        ] {
+            assert!(matches!(frame_size_px(&hostile, &p), Err(RasterError::LayoutTooLarge { .. })), "{hostile:?}");
+            assert!(tile_origin(&hostile, &p, MAX_TILES * MAX_TILES - 1).is_err());
+            assert!(decode_rgb_frame_bytes(&img, &hostile, &p, Palette8::Basic).is_err());
+        }
+        let huge_border = RasterParams { border_cells: u32::MAX / 2 + 1, ..p.clone() };
+        assert!(frame_size_px(&m, &huge_border).is_err());
+        assert!(matches!(
+            compose_tiles(vec![image::RgbImage::new(1, 1)], &RasterParams { grid_w: u32::MAX, ..p }),
+            Err(RasterError::LayoutTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::manifest::EncodeManifest;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Prefix of the signed message so a manifest signature can't be replayed as anything else.
+const SIGNING_CONTEXT: &[u8] = b"SLLV manifest signature v1\0";
+
+/// Ed25519 secret key (seed) used to sign manifests. Never serialized or printed.
+#[derive(Clone)]
+pub struct ManifestSigner(pub [u8; 32]);
+
+impl std::fmt::Debug for ManifestSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ManifestSigner({})", key_id(&self.public_key()))
+    }
+}
+
+impl ManifestSigner {
+    pub fn public_key(&self) -> [u8; 32] {
+        SigningKey::from_bytes(&self.0).verifying_key().to_bytes()
+    }
+}
+
+/// Detached signature recorded in the manifest.
+///
+/// It covers every other manifest field, including `sha256_hex` (the hash of the bytes carried
+/// by the frames), so the payload can't be swapped without breaking it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureInfo {
+    pub algorithm: String,
+    /// First 8 bytes of SHA-256(public key), hex; for display and key lookup.
+    pub key_id: String,
+    pub public_key_hex: String,
+    pub signature_hex: String,
+}
+
+impl SignatureInfo {
+    pub const ALGORITHM: &'static str = "ed25519";
+}
+
+#[derive(Debug, Error)]
+pub enum SignError {
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("unsupported signature algorithm {0}")]
+    UnsupportedAlgorithm(String),
+}
+
+/// Outcome of checking a manifest's signature against a set of trusted public keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Unsigned,
+    /// Signature is valid and the key is in the trusted set.
+    Trusted { key_id: String },
+    /// Signature is valid but made by a key that isn't trusted.
+    UnknownSigner { key_id: String },
+    /// Signature doesn't match the manifest: it was modified after signing.
+    Invalid { key_id: String },
+}
+
+impl SignatureStatus {
+    pub fn is_trusted(&self) -> bool {
+        matches!(self, SignatureStatus::Trusted { .. })
+    }
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Unsigned => f.write_str("unsigned"),
+            SignatureStatus::Trusted { key_id } => write!(f, "valid, signed by trusted key {key_id}"),
+            SignatureStatus::UnknownSigner { key_id } => write!(f, "valid, but signed by untrusted key {key_id}"),
+            SignatureStatus::Invalid { key_id } => write!(f, "INVALID (claims key {key_id}); manifest was modified"),
+        }
+    }
+}
+
+pub fn key_id(public_key: &[u8; 32]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..8])
+}
+
+/// Fresh Ed25519 keypair as `(secret seed, public key)`.
+pub fn generate_ed25519_keypair() -> Result<([u8; 32], [u8; 32]), SignError> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| SignError::InvalidKey(e.to_string()))?;
+    let public = ManifestSigner(seed).public_key();
+    Ok((seed, public))
+}
+
+/// The bytes a signature covers: [`SIGNING_CONTEXT`] followed by the manifest as this version
+/// serializes it with `signature` cleared (compact JSON, fields in declaration order, absent
+/// optional fields omitted).
+///
+/// This is a canonical form rather than the bytes that were read, so it only covers the fields
+/// this version knows. A manifest signed with fields added by a newer version therefore fails to
+/// verify as `Invalid` instead of passing with those fields unchecked.
+fn signed_message(m: &EncodeManifest) -> Result<Vec<u8>, SignError> {
+    let mut unsigned = m.clone();
+    unsigned.signature = None;
+    let mut msg = SIGNING_CONTEXT.to_vec();
+    msg.extend(serde_json::to_vec(&unsigned)?);
+    Ok(msg)
+}
+
+/// Sign `m` in place, replacing any previous signature.
+pub fn sign_manifest(m: &mut EncodeManifest, signer: &ManifestSigner) -> Result<(), SignError> {
+    let key = SigningKey::from_bytes(&signer.0);
+    let sig = key.sign(&signed_message(m)?);
+    let public = key.verifying_key().to_bytes();
+    m.signature = Some(SignatureInfo {
+        algorithm: SignatureInfo::ALGORITHM.to_string(),
+        key_id: key_id(&public),
+        public_key_hex: hex::encode(public),
+        signature_hex: hex::encode(sig.to_bytes()),
+    });
+    Ok(())
+}
+
+/// Check the manifest signature and whether its key is one of `trusted` (public keys).
+pub fn verify_manifest(m: &EncodeManifest, trusted: &[[u8; 32]]) -> Result<SignatureStatus, SignError> {
+    let Some(info) = &m.signature else {
+        return Ok(SignatureStatus::Unsigned);
+    };
+    if info.algorithm != SignatureInfo::ALGORITHM {
+        return Err(SignError::UnsupportedAlgorithm(info.algorithm.clone()));
+    }
+    let public = crate::crypto::parse_key_hex(&info.public_key_hex).map_err(|e| SignError::InvalidKey(e.to_string()))?;
+    let key_id = key_id(&public);
+    let invalid = SignatureStatus::Invalid { key_id: key_id.clone() };
+
+    let Ok(vk) = VerifyingKey::from_bytes(&public) else {
+        return Ok(invalid);
+    };
+    let Ok(sig_bytes) = <[u8; 64]>::try_from(hex::decode(&info.signature_hex).unwrap_or_default()) else {
+        return Ok(invalid);
+    };
+    if vk.verify(&signed_message(m)?, &Signature::from_bytes(&sig_bytes)).is_err() {
+        return Ok(invalid);
+    }
+    if trusted.contains(&public) {
+        Ok(SignatureStatus::Trusted { key_id })
+    } else {
+        Ok(SignatureStatus::UnknownSigner { key_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::PayloadKind;
+
+    const SIGNER: ManifestSigner = ManifestSigner([7; 32]);
+
+    fn manifest() -> EncodeManifest {
+        EncodeManifest {
+            magic: EncodeManifest::MAGIC.into(),
+            version: EncodeManifest::VERSION,
+            file_name: "notes.txt".into(),
+            total_bytes: 1234,
+            chunk_bytes: 512,
+            grid_w: 64,
+            grid_h: 48,
+            cell_px: 4,
+            palette: "bw".into(),
+            sha256_hex: "ab".repeat(32),
+            frames: 3,
+            tiles: Some([2, 1]),
+            payload: PayloadKind::Raw,
+            ..Default::default()
+        }
+    }
+
+    fn signed() -> EncodeManifest {
+        let mut m = manifest();
+        sign_manifest(&mut m, &SIGNER).unwrap();
+        m
+    }
+
+    /// Change a JSON value so it still has the same shape; `None` if there is nothing to change.
+    fn tamper(v: &mut serde_json::Value) -> Option<()> {
+        use serde_json::Value;
+        match v {
+            Value::Bool(b) => *b = !*b,
+            Value::Number(n) => *v = Value::from(n.as_u64()? + 1),
+            Value::String(s) => s.push('0'),
+            Value::Array(a) => tamper(a.first_mut()?)?,
+            Value::Object(o) => tamper(o.values_mut().next()?)?,
+            Value::Null => return None,
+        }
+        Some(())
+    }
+
+    #[test]
+    fn sign_then_verify() {
+        let m = signed();
+        let public = SIGNER.public_key();
+        let key_id = key_id(&public);
+        assert_eq!(m.signature.as_ref().unwrap().key_id, key_id);
+        assert_eq!(verify_manifest(&m, &[public]).unwrap(), SignatureStatus::Trusted { key_id: key_id.clone() });
+
+        // The manifest is stored as JSON, so a round trip through it must keep the signature valid.
+        let back: EncodeManifest = serde_json::from_slice(&serde_json::to_vec_pretty(&m).unwrap()).unwrap();
+        assert_eq!(verify_manifest(&back, &[public]).unwrap(), SignatureStatus::Trusted { key_id });
+    }
+
+    #[test]
+    fn tampering_with_any_field_is_invalid() {
+        let m = signed();
+        let public = SIGNER.public_key();
+        let json = serde_json::to_value(&m).unwrap();
+        let mut checked = 0;
+        for key in json.as_object().unwrap().keys().filter(|k| *k != "signature") {
+            let mut changed = json.clone();
+            if tamper(&mut changed[key.as_str()]).is_none() {
+                continue;
+            }
+            // Some edits (an unknown `payload` kind, say) no longer parse at all.
+            let Ok(changed) = serde_json::from_value::<EncodeManifest>(changed) else { continue };
+            assert!(
+                matches!(verify_manifest(&changed, &[public]).unwrap(), SignatureStatus::Invalid { .. }),
+                "changing {key} kept the signature valid"
+            );
+            checked += 1;
+        }
+        assert!(checked >= 12, "only {checked} fields were tampered with");
+
+        let mut m = signed();
+        let sig = &mut m.signature.as_mut().unwrap().signature_hex;
+        let flipped = if sig.starts_with('0') { "1" } else { "0" };
+        sig.replace_range(0..1, flipped);
+        assert!(matches!(verify_manifest(&m, &[public]).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn another_key_is_an_unknown_signer() {
+        let m = signed();
+        let other = ManifestSigner([9; 32]).public_key();
+        assert_eq!(
+            verify_manifest(&m, &[other]).unwrap(),
+            SignatureStatus::UnknownSigner { key_id: key_id(&SIGNER.public_key()) }
+        );
+        assert!(!verify_manifest(&m, &[]).unwrap().is_trusted());
+
+        // Swapping in the other key's public half doesn't make the signature its own.
+        let mut swapped = m.clone();
+        swapped.signature.as_mut().unwrap().public_key_hex = hex::encode(other);
+        assert_eq!(verify_manifest(&swapped, &[other]).unwrap(), SignatureStatus::Invalid { key_id: key_id(&other) });
+    }
+
+    #[test]
+    fn unsigned_manifest() {
+        assert_eq!(verify_manifest(&manifest(), &[SIGNER.public_key()]).unwrap(), SignatureStatus::Unsigned);
+    }
+
+    #[test]
+    fn bad_algorithm_and_hex() {
+        let mut m = signed();
+        m.signature.as_mut().unwrap().algorithm = "rsa".into();
+        assert!(matches!(verify_manifest(&m, &[]), Err(SignError::UnsupportedAlgorithm(a)) if a == "rsa"));
+
+        let mut m = signed();
+        m.signature.as_mut().unwrap().public_key_hex = "not hex".into();
+        assert!(matches!(verify_manifest(&m, &[]), Err(SignError::InvalidKey(_))));
+
+        let mut m = signed();
+        m.signature.as_mut().unwrap().signature_hex = "not hex".into();
+        assert!(matches!(verify_manifest(&m, &[]).unwrap(), SignatureStatus::Invalid { .. }));
+        m.signature.as_mut().unwrap().signature_hex = "abcd".into();
+        assert!(matches!(verify_manifest(&m, &[]).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn fields_from_a_newer_version_fail_to_verify() {
+        // A newer writer signs a field this version drops when it reads the manifest back.
+        let mut json = serde_json::to_value(manifest()).unwrap();
+        json["future_field"] = serde_json::Value::from(1);
+        let mut msg = SIGNING_CONTEXT.to_vec();
+        msg.extend(serde_json::to_vec(&json).unwrap());
+        let key = SigningKey::from_bytes(&SIGNER.0);
+        let public = SIGNER.public_key();
+        json["signature"] = serde_json::to_value(SignatureInfo {
+            algorithm: SignatureInfo::ALGORITHM.into(),
+            key_id: key_id(&public),
+            public_key_hex: hex::encode(public),
+            signature_hex: hex::encode(key.sign(&msg).to_bytes()),
+        })
+        .unwrap();
+
+        let m: EncodeManifest = serde_json::from_value(json).unwrap();
+        assert!(matches!(verify_manifest(&m, &[public]).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+}
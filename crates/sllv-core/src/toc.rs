@@ -0,0 +1,554 @@
+use crate::compress::{compress_bytes, Compression, Decompressor};
+use crate::crypto::{encrypt_bytes, DecryptionKey, Decryptor, EncryptionInfo, EncryptionKey};
+use crate::framefile::frame_path;
+use crate::manifest::EncodeManifest;
+use crate::palette::Palette8;
+use crate::raster::{
+    decode_carried_ranges, decode_frame_bytes_with_optional_deskew, decode_frames_dir_to_writer, frames_for_carried_ranges,
+    payload_io_error, RasterError, RasterParams,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use tar::{Archive, EntryType};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TocError {
+    #[error("archive has no table of contents (encoded without one, or by an older version)")]
+    Missing,
+    #[error("table of contents frame {0} is unreadable in every copy")]
+    FrameUnreadable(u32),
+    #[error("table of contents sha256 mismatch")]
+    ShaMismatch,
+    #[error("unsupported table of contents format {0}")]
+    UnsupportedFormat(String),
+    #[error("no entry {0} in the archive")]
+    NotFound(String),
+    #[error("{0} does not match the sha256 in the table of contents")]
+    EntryShaMismatch(String),
+    #[error("{0} lies outside the payload according to the table of contents")]
+    EntryOutOfRange(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TocEntryKind {
+    File,
+    Dir,
+    Symlink,
+    Hardlink,
+    Other,
+}
+
+impl TocEntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TocEntryKind::File => "file",
+            TocEntryKind::Dir => "dir",
+            TocEntryKind::Symlink => "symlink",
+            TocEntryKind::Hardlink => "hardlink",
+            TocEntryKind::Other => "other",
+        }
+    }
+}
+
+/// One archive member.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TocEntry {
+    /// Path inside the archive (lossy UTF-8).
+    pub path: String,
+    pub kind: TocEntryKind,
+    pub size: u64,
+    /// SHA-256 of the file contents; empty for anything but regular files.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sha256_hex: String,
+    /// Byte offset of the contents in the uncompressed payload (the tar, or the raw bytes).
+    pub offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    #[serde(default)]
+    pub mode: u32,
+    #[serde(default)]
+    pub mtime: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Toc {
+    /// Members of this archive's payload.
+    pub entries: Vec<TocEntry>,
+    /// Delta archives: base entries that are still current but not in this payload (their
+    /// `offset` refers to whichever earlier archive holds them). See [`crate::delta`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchanged: Vec<TocEntry>,
+    /// Delta archives: base paths that no longer exist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+}
+
+impl Toc {
+    /// Every entry of the state this archive describes: its own entries plus, for a delta, the
+    /// unchanged ones from the base. Sorted by path.
+    pub fn state(&self) -> Vec<&TocEntry> {
+        let mut all: Vec<&TocEntry> = self.entries.iter().chain(&self.unchanged).collect();
+        all.sort_by(|a, b| a.path.cmp(&b.path));
+        all
+    }
+
+    /// Digest of [`Toc::state`] (paths, kinds, sizes, hashes, link targets and modes, but not
+    /// offsets or mtimes), so a delta can name its base whether that was a full or delta archive.
+    pub fn state_sha256_hex(&self) -> String {
+        let mut h = Sha256::new();
+        for e in self.state() {
+            let line = format!(
+                "{}\0{}\0{}\0{}\0{}\0{:o}\n",
+                e.kind.name(),
+                e.path,
+                e.size,
+                e.sha256_hex,
+                e.link_target.as_deref().unwrap_or(""),
+                e.mode
+            );
+            h.update(line.as_bytes());
+        }
+        hex::encode(h.finalize())
+    }
+
+    /// Total size of the regular files.
+    pub fn file_bytes(&self) -> u64 {
+        self.entries.iter().filter(|e| e.kind == TocEntryKind::File).map(|e| e.size).sum()
+    }
+
+    /// Regular files at `path`, or under it if it names a folder.
+    pub fn select(&self, path: &str) -> Vec<&TocEntry> {
+        let want = path.trim_matches('/');
+        self.entries
+            .iter()
+            .filter(|e| e.kind == TocEntryKind::File)
+            .filter(|e| {
+                let p = e.path.trim_end_matches('/');
+                p == want || p.strip_prefix(want).is_some_and(|rest| rest.starts_with('/'))
+            })
+            .collect()
+    }
+
+    /// List a tar payload. Fails if `tar_bytes` isn't a readable tar.
+    pub fn from_tar(tar_bytes: &[u8]) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for entry in Archive::new(tar_bytes).entries()? {
+            let entry = entry?;
+            let h = entry.header();
+            let kind = match h.entry_type() {
+                EntryType::Regular | EntryType::Continuous => TocEntryKind::File,
+                EntryType::Directory => TocEntryKind::Dir,
+                EntryType::Symlink => TocEntryKind::Symlink,
+                EntryType::Link => TocEntryKind::Hardlink,
+                _ => TocEntryKind::Other,
+            };
+            let offset = entry.raw_file_position();
+            let size = entry.size();
+            let sha256_hex = if kind == TocEntryKind::File {
+                let start = offset as usize;
+                let end = start
+                    .checked_add(size as usize)
+                    .filter(|&end| end <= tar_bytes.len())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "tar entry runs past the end"))?;
+                hex::encode(Sha256::digest(&tar_bytes[start..end]))
+            } else {
+                String::new()
+            };
+            entries.push(TocEntry {
+                path: entry.path()?.to_string_lossy().into_owned(),
+                kind,
+                size,
+                sha256_hex,
+                offset,
+                link_target: entry.link_name()?.map(|t| t.to_string_lossy().into_owned()),
+                mode: h.mode().unwrap_or(0),
+                mtime: h.mtime().unwrap_or(0),
+            });
+        }
+        Ok(Self {
+            entries,
+            ..Default::default()
+        })
+    }
+
+    /// A raw (non-tar) payload is a single file.
+    pub fn from_raw(name: &str, bytes: &[u8]) -> Self {
+        Self {
+            entries: vec![TocEntry {
+                path: name.to_string(),
+                kind: TocEntryKind::File,
+                size: bytes.len() as u64,
+                sha256_hex: hex::encode(Sha256::digest(bytes)),
+                offset: 0,
+                link_target: None,
+                mode: 0o644,
+                mtime: 0,
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+/// Where the table of contents lives, recorded in the manifest.
+///
+/// The TOC is zstd-compressed JSON (encrypted like the payload when the archive is), split
+/// across `frames` frames starting at `first_frame`, and written `copies` times in a row so a
+/// damaged frame can be read from another copy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TocInfo {
+    pub format: String,
+    pub first_frame: u32,
+    /// Frames per copy.
+    pub frames: u32,
+    pub copies: u32,
+    pub entries: u64,
+    /// Length and SHA-256 of the stored (compressed, possibly encrypted) TOC bytes.
+    pub stored_bytes: u64,
+    pub sha256_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionInfo>,
+}
+
+impl TocInfo {
+    pub const FORMAT: &'static str = "json+zstd";
+    pub const COPIES: u32 = 2;
+}
+
+/// Upper bound on the decompressed TOC, which the manifest does not record.
+const MAX_TOC_JSON_BYTES: u64 = 256 << 20;
+
+/// TOC frame header: magic, frame index, frame count, chunk length, CRC32 of all of it plus the chunk.
+const FRAME_MAGIC: &[u8; 4] = b"SLTC";
+const FRAME_HEADER_BYTES: usize = 4 + 2 + 2 + 4 + 4;
+
+/// Compress (and encrypt) `toc` and split it into frame payloads of `frame_payload` bytes.
+///
+/// Returns the info to record (with `first_frame` and `copies` left for the caller) and one
+/// payload per frame of a single copy.
+pub(crate) fn toc_frame_payloads(
+    toc: &Toc,
+    encryption: Option<&EncryptionKey>,
+    frame_payload: usize,
+) -> Result<(TocInfo, Vec<Vec<u8>>), RasterError> {
+    let json = serde_json::to_vec(toc)?;
+    let mut stored = compress_bytes(&json, Compression::Zstd { level: Compression::ZSTD_DEFAULT_LEVEL })?;
+    let mut enc_info = None;
+    if let Some(key) = encryption {
+        let (ct, info) = encrypt_bytes(&stored, key)?;
+        stored = ct;
+        enc_info = Some(info);
+    }
+
+    let chunk = frame_payload.saturating_sub(FRAME_HEADER_BYTES);
+    if chunk == 0 {
+        return Err(RasterError::Fec("frame too small for table of contents".into()));
+    }
+    let count = stored.len().div_ceil(chunk).max(1);
+    if count > u16::MAX as usize {
+        return Err(RasterError::Fec(format!("table of contents needs {count} frames (max {})", u16::MAX)));
+    }
+    let mut payloads = Vec::with_capacity(count);
+    for i in 0..count {
+        let part = &stored[(i * chunk).min(stored.len())..((i + 1) * chunk).min(stored.len())];
+        let mut frame = vec![0u8; frame_payload];
+        frame[0..4].copy_from_slice(FRAME_MAGIC);
+        frame[4..6].copy_from_slice(&(i as u16).to_le_bytes());
+        frame[6..8].copy_from_slice(&(count as u16).to_le_bytes());
+        frame[8..12].copy_from_slice(&(part.len() as u32).to_le_bytes());
+        frame[FRAME_HEADER_BYTES..FRAME_HEADER_BYTES + part.len()].copy_from_slice(part);
+        let crc = frame_crc(&frame[..12], part);
+        frame[12..16].copy_from_slice(&crc.to_le_bytes());
+        payloads.push(frame);
+    }
+
+    let info = TocInfo {
+        format: TocInfo::FORMAT.to_string(),
+        first_frame: 0,
+        frames: count as u32,
+        copies: 1,
+        entries: toc.entries.len() as u64,
+        stored_bytes: stored.len() as u64,
+        sha256_hex: hex::encode(Sha256::digest(&stored)),
+        encryption: enc_info,
+    };
+    Ok((info, payloads))
+}
+
+fn frame_crc(header: &[u8], chunk: &[u8]) -> u32 {
+    let mut h = crc32fast::Hasher::new();
+    h.update(header);
+    h.update(chunk);
+    h.finalize()
+}
+
+/// Check a decoded TOC frame and return its chunk if it is frame `index` of `count`.
+fn parse_toc_frame(bytes: &[u8], index: u32, count: u32) -> Option<&[u8]> {
+    if bytes.len() < FRAME_HEADER_BYTES || &bytes[0..4] != FRAME_MAGIC {
+        return None;
+    }
+    let le16 = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]) as u32;
+    let le32 = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let len = le32(&bytes[8..12]) as usize;
+    if le16(&bytes[4..6]) != index || le16(&bytes[6..8]) != count || FRAME_HEADER_BYTES + len > bytes.len() {
+        return None;
+    }
+    let chunk = &bytes[FRAME_HEADER_BYTES..FRAME_HEADER_BYTES + len];
+    (frame_crc(&bytes[..12], chunk) == le32(&bytes[12..16])).then_some(chunk)
+}
+
+/// Read the table of contents from a frames directory, decoding only its frames.
+///
+/// Needs `p.decryption` for encrypted archives.
+pub fn read_toc(in_dir: &Path, p: &RasterParams) -> Result<Toc, RasterError> {
+    let manifest = crate::raster::read_manifest(in_dir)?;
+    read_toc_with_manifest(in_dir, &manifest, p)
+}
+
+pub fn read_toc_with_manifest(in_dir: &Path, m: &EncodeManifest, p: &RasterParams) -> Result<Toc, RasterError> {
+    let info = m.toc.as_ref().ok_or(TocError::Missing)?;
+    if info.format != TocInfo::FORMAT {
+        return Err(TocError::UnsupportedFormat(info.format.clone()).into());
+    }
+    // Grown frame by frame: `stored_bytes` comes from the manifest and is only trusted once the
+    // frames add up to it.
+    let mut stored = Vec::new();
+    for i in 0..info.frames {
+        let chunk = (0..info.copies).find_map(|copy| {
+            let idx = info.first_frame + copy * info.frames + i;
+            let path = frame_path(in_dir, idx, m);
+            let bytes = decode_frame_bytes_with_optional_deskew(&path, m, p, Palette8::Basic).ok()?;
+            parse_toc_frame(&bytes, i, info.frames).map(|c| c.to_vec())
+        });
+        stored.extend(chunk.ok_or(TocError::FrameUnreadable(i))?);
+        if stored.len() as u64 > info.stored_bytes {
+            return Err(TocError::ShaMismatch.into());
+        }
+    }
+    if stored.len() as u64 != info.stored_bytes || hex::encode(Sha256::digest(&stored)) != info.sha256_hex {
+        return Err(TocError::ShaMismatch.into());
+    }
+    let json = open_stored(&stored, info.encryption.as_ref(), p.decryption.as_ref())?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+fn open_stored(stored: &[u8], enc: Option<&EncryptionInfo>, key: Option<&DecryptionKey>) -> Result<Vec<u8>, RasterError> {
+    let zstd = Compression::Zstd { level: Compression::ZSTD_DEFAULT_LEVEL };
+    let mut sink = Decryptor::new(enc, key, Decompressor::new(zstd, MAX_TOC_JSON_BYTES, Vec::new())?)?;
+    sink.write_all(stored).map_err(payload_io_error)?;
+    let (json, _, _) = sink.finish().map_err(payload_io_error)?.finish()?;
+    Ok(json)
+}
+
+/// Contents recovered by [`read_entries`].
+#[derive(Debug, Clone)]
+pub struct EntryContents {
+    /// One buffer per requested entry, in order.
+    pub data: Vec<Vec<u8>>,
+    /// Frames demodulated to get them; `None` if the whole payload had to be decoded.
+    pub frames_decoded: Option<u32>,
+}
+
+/// Whether entries can be read by decoding only the frames that hold them. Deduplicated,
+/// compressed or encrypted payloads can only be read from the start, so they fall back to a
+/// full decode.
+pub fn supports_selective_decode(m: &EncodeManifest) -> bool {
+    m.dedup.is_none() && m.compression.is_none() && m.encryption.is_none()
+}
+
+/// Recover the contents of `entries` (regular files from the archive's TOC), checking each
+/// against its SHA-256 before returning it. Entries reaching past the end of the payload are
+/// rejected up front.
+pub fn read_entries(in_dir: &Path, m: &EncodeManifest, p: &RasterParams, entries: &[&TocEntry]) -> Result<EntryContents, RasterError> {
+    let payload_bytes = m.payload_bytes();
+    let ranges = entries
+        .iter()
+        .map(|e| match e.offset.checked_add(e.size) {
+            Some(end) if end <= payload_bytes => Ok(e.offset..end),
+            _ => Err(TocError::EntryOutOfRange(e.path.clone())),
+        })
+        .collect::<Result<Vec<Range<u64>>, _>>()?;
+    let (data, frames_decoded) = if supports_selective_decode(m) {
+        let frames = frames_for_carried_ranges(m, p, &ranges).len() as u32;
+        (decode_carried_ranges(in_dir, m, p, &ranges)?, Some(frames))
+    } else {
+        let mut capture = RangeCapture::new(&ranges);
+        decode_frames_dir_to_writer(in_dir, p, &mut capture)?;
+        (capture.parts, None)
+    };
+    for (e, bytes) in entries.iter().zip(&data) {
+        if hex::encode(Sha256::digest(bytes)) != e.sha256_hex {
+            return Err(TocError::EntryShaMismatch(e.path.clone()).into());
+        }
+    }
+    Ok(EntryContents { data, frames_decoded })
+}
+
+/// Keeps the bytes of a full decode that fall inside `ranges` and discards the rest.
+struct RangeCapture<'a> {
+    ranges: &'a [Range<u64>],
+    parts: Vec<Vec<u8>>,
+    pos: u64,
+}
+
+impl<'a> RangeCapture<'a> {
+    fn new(ranges: &'a [Range<u64>]) -> Self {
+        Self {
+            ranges,
+            parts: vec![Vec::new(); ranges.len()],
+            pos: 0,
+        }
+    }
+}
+
+impl Write for RangeCapture<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.pos + buf.len() as u64;
+        for (r, part) in self.ranges.iter().zip(&mut self.parts) {
+            let (a, b) = (r.start.max(self.pos), r.end.min(end));
+            if a < b {
+                part.extend_from_slice(&buf[(a - self.pos) as usize..(b - self.pos) as usize]);
+            }
+        }
+        self.pos = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use crate::raster::{encode_bytes_to_frames_dir, read_manifest};
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_toc_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn small_params() -> RasterParams {
+        RasterParams { grid_w: 64, grid_h: 64, sync_frames: 1, ..Profile::Archive.defaults() }
+    }
+
+    /// A tar holding `files` under `root/`.
+    fn tar_of(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut b = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut h = tar::Header::new_gnu();
+            h.set_size(data.len() as u64);
+            h.set_mode(0o644);
+            b.append_data(&mut h, format!("root/{name}"), &data[..]).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    fn test_files() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("a.txt", b"hello".to_vec()),
+            ("b.bin", (0..3000u32).map(|i| (i * 7) as u8).collect()),
+            ("c.txt", b"third file".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn toc_round_trip() {
+        let dir = scratch_dir("roundtrip");
+        let tar = tar_of(&test_files());
+        encode_bytes_to_frames_dir(&tar, "root", &dir, &small_params()).unwrap();
+        let toc = read_toc(&dir, &small_params()).unwrap();
+        let files: Vec<&TocEntry> = toc.entries.iter().filter(|e| e.kind == TocEntryKind::File).collect();
+        assert_eq!(files.len(), 3);
+        for (e, (name, data)) in files.iter().zip(test_files()) {
+            assert_eq!(e.path, format!("root/{name}"));
+            assert_eq!(e.size, data.len() as u64);
+            assert_eq!(&tar[e.offset as usize..][..data.len()], &data[..]);
+            assert_eq!(e.sha256_hex, hex::encode(Sha256::digest(&data)));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hostile_toc_info_is_rejected() {
+        let dir = scratch_dir("hostile");
+        let p = small_params();
+        encode_bytes_to_frames_dir(&tar_of(&test_files()), "root", &dir, &p).unwrap();
+        let m = read_manifest(&dir).unwrap();
+
+        let mut huge = m.clone();
+        huge.toc.as_mut().unwrap().stored_bytes = u64::MAX;
+        assert!(matches!(read_toc_with_manifest(&dir, &huge, &p), Err(RasterError::Toc(TocError::ShaMismatch))));
+
+        let mut short = m.clone();
+        short.toc.as_mut().unwrap().stored_bytes = 1;
+        assert!(matches!(read_toc_with_manifest(&dir, &short, &p), Err(RasterError::Toc(TocError::ShaMismatch))));
+
+        let mut format = m.clone();
+        format.toc.as_mut().unwrap().format = "xml".into();
+        assert!(matches!(
+            read_toc_with_manifest(&dir, &format, &p),
+            Err(RasterError::Toc(TocError::UnsupportedFormat(_)))
+        ));
+
+        let mut none = m;
+        none.toc = None;
+        assert!(matches!(read_toc_with_manifest(&dir, &none, &p), Err(RasterError::Toc(TocError::Missing))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_are_read_selectively() {
+        let dir = scratch_dir("entries");
+        let p = small_params();
+        encode_bytes_to_frames_dir(&tar_of(&test_files()), "root", &dir, &p).unwrap();
+        let m = read_manifest(&dir).unwrap();
+        assert!(supports_selective_decode(&m));
+        let toc = read_toc_with_manifest(&dir, &m, &p).unwrap();
+        let wanted: Vec<&TocEntry> = toc.entries.iter().filter(|e| e.path.ends_with(".txt")).collect();
+        let got = read_entries(&dir, &m, &p, &wanted).unwrap();
+        assert_eq!(got.data, [b"hello".to_vec(), b"third file".to_vec()]);
+        assert!(got.frames_decoded.is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_outside_the_payload_are_rejected() {
+        let dir = scratch_dir("outside");
+        let p = small_params();
+        encode_bytes_to_frames_dir(&tar_of(&test_files()), "root", &dir, &p).unwrap();
+        let m = read_manifest(&dir).unwrap();
+        let toc = read_toc_with_manifest(&dir, &m, &p).unwrap();
+        let entry = toc.entries.iter().find(|e| e.kind == TocEntryKind::File).unwrap();
+        for (offset, size) in [(u64::MAX, 2), (1, u64::MAX), (m.total_bytes, 1), (0, m.total_bytes + 1)] {
+            let bad = TocEntry { offset, size, ..entry.clone() };
+            assert!(matches!(
+                read_entries(&dir, &m, &p, &[&bad]),
+                Err(RasterError::Toc(TocError::EntryOutOfRange(_)))
+            ));
+        }
+
+        let mut hash = entry.clone();
+        hash.sha256_hex = "00".repeat(32);
+        assert!(matches!(read_entries(&dir, &m, &p, &[&hash]), Err(RasterError::Toc(TocError::EntryShaMismatch(_)))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn range_capture_keeps_only_requested_bytes() {
+        let ranges = [2..5, 4..9, 100..u64::MAX];
+        let mut capture = RangeCapture::new(&ranges);
+        capture.write_all(b"0123").unwrap();
+        capture.write_all(b"456789abc").unwrap();
+        assert_eq!(capture.parts, [b"234".to_vec(), b"45678".to_vec(), Vec::new()]);
+    }
+}
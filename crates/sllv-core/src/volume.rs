@@ -0,0 +1,731 @@
+//! Split encodes: one payload spread over several frames directories ("volumes").
+//!
+//! The carried payload (after compression and encryption) is cut into `data_volumes` equal
+//! stripes, the last one zero-padded, and optionally `parity_volumes` Reed-Solomon stripes are
+//! computed across them. Each stripe is encoded as a complete frames directory `vol_NNN/` with its
+//! own sync and calibration frames, a volume header frame after the data and a copy of the
+//! table of contents, so any volume can be listed on its own. `volumes.json` (the set manifest)
+//! is written next to the volumes and copied into each one, so any subset is enough to start.
+//!
+//! Decoding needs any `data_volumes` of the `data_volumes + parity_volumes` volumes.
+
+use crate::framefile::frame_path;
+use crate::frames::{carry_payload, table_of_contents, Carried, FrameEncoder};
+use crate::manifest::{EncodeManifest, PayloadKind};
+use crate::palette::Palette8;
+use crate::raster::{
+    decode_frame_bytes_with_optional_deskew, decode_frames_dir_to_writer, finish_payload, frame_payload_capacity,
+    payload_io_error, payload_sink, read_manifest, write_encoder_frames, ProgressMsg, RasterError,
+    RasterParams,
+};
+use crate::sign::sign_manifest;
+use crate::toc::{toc_frame_payloads, TocInfo};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use thiserror::Error;
+
+/// File name of the set manifest, in the set directory and in every volume.
+pub const SET_MANIFEST: &str = "volumes.json";
+
+/// How to split an encode. Splitting happens if either limit is set or parity volumes are asked for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VolumeParams {
+    /// Most frames per volume, counting sync, calibration, header and TOC frames.
+    pub max_frames: Option<u32>,
+    /// Most carried payload bytes per volume (after compression/encryption, before framing).
+    pub max_bytes: Option<u64>,
+    /// Extra Reed-Solomon volumes; any this many volumes can be lost and rebuilt.
+    pub parity_volumes: u32,
+}
+
+impl VolumeParams {
+    /// Reed-Solomon over GF(2^8) allows at most 256 volumes in a set.
+    pub const MAX_VOLUMES: u32 = 256;
+
+    pub fn is_split(&self) -> bool {
+        self.max_frames.is_some() || self.max_bytes.is_some() || self.parity_volumes > 0
+    }
+}
+
+/// Where one volume sits in its set; recorded in the volume's manifest and its header frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    /// Shared by every volume of one encode.
+    pub set_id: String,
+    pub index: u32,
+    pub data_volumes: u32,
+    pub parity_volumes: u32,
+    /// Carried bytes per volume; the last data volume is zero-padded to this.
+    pub stripe_bytes: u64,
+    /// Frame holding this info, right after the data frames.
+    pub header_frame: u32,
+}
+
+impl VolumeInfo {
+    pub fn count(&self) -> u32 {
+        self.data_volumes + self.parity_volumes
+    }
+
+    pub fn is_parity(&self) -> bool {
+        self.index >= self.data_volumes
+    }
+}
+
+/// The set manifest (`volumes.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeSet {
+    pub magic: String,
+    pub version: u16,
+    pub set_id: String,
+    pub data_volumes: u32,
+    pub parity_volumes: u32,
+    pub stripe_bytes: u64,
+    pub volumes: Vec<VolumeEntry>,
+    /// The whole payload as one archive: carried size and SHA-256, compression, encryption,
+    /// payload kind and filter. `frames` is the total over all volumes. Signed when signing is on.
+    pub manifest: EncodeManifest,
+}
+
+/// One volume as listed in the set manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeEntry {
+    pub index: u32,
+    /// Directory name relative to the set directory.
+    pub dir: String,
+    pub frames: u32,
+    /// SHA-256 of the volume's stripe.
+    pub sha256_hex: String,
+}
+
+impl VolumeSet {
+    pub const MAGIC: &'static str = "SLLV-VOLUMES";
+    pub const VERSION: u16 = 1;
+
+    pub fn count(&self) -> u32 {
+        self.data_volumes + self.parity_volumes
+    }
+
+    pub fn dir_name(index: u32) -> String {
+        format!("vol_{index:03}")
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VolumeError {
+    #[error("{max_frames} frames per volume leaves no room for data (each volume needs {overhead} frames of overhead plus at least one FEC group)")]
+    FramesTooFew { max_frames: u32, overhead: u32 },
+    #[error("volume size must be at least 1 byte")]
+    BytesTooFew,
+    #[error("{0} volumes needed, but a set holds at most {max}", max = VolumeParams::MAX_VOLUMES)]
+    TooMany(u32),
+    #[error("{0} is not a volume or volume set directory")]
+    NotAVolume(PathBuf),
+    #[error("no {SET_MANIFEST} found next to the given volumes")]
+    SetManifestMissing,
+    #[error("{SET_MANIFEST} has an unsupported magic/version")]
+    SetManifestInvalid,
+    #[error("{0} belongs to a different volume set")]
+    SetMismatch(PathBuf),
+    #[error("volume {0}: header frame disagrees with manifest.json")]
+    HeaderMismatch(u32),
+    #[error("volume {0}: header frame unreadable")]
+    HeaderUnreadable(u32),
+    #[error("{have} of {count} volumes usable but {needed} are needed; missing or unreadable: {}", list(missing))]
+    Unrecoverable {
+        have: u32,
+        needed: u32,
+        count: u32,
+        missing: Vec<u32>,
+    },
+    #[error("reed-solomon: {0}")]
+    ReedSolomon(String),
+    #[error("volume {0} failed after part of it was written, and the rebuilt volume disagrees with that part")]
+    PartlyWritten(u32),
+}
+
+fn list(indices: &[u32]) -> String {
+    indices.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Volume header frame: magic, JSON length, CRC32 of the length and JSON, then the JSON.
+const HEADER_MAGIC: &[u8; 4] = b"SLVH";
+const HEADER_BYTES: usize = 4 + 4 + 4;
+
+pub(crate) fn header_frame_payload(info: &VolumeInfo, frame_payload: usize) -> Result<Vec<u8>, RasterError> {
+    let json = serde_json::to_vec(info)?;
+    if HEADER_BYTES + json.len() > frame_payload {
+        return Err(RasterError::Fec("frame too small for volume header".into()));
+    }
+    let mut frame = vec![0u8; frame_payload];
+    frame[0..4].copy_from_slice(HEADER_MAGIC);
+    frame[4..8].copy_from_slice(&(json.len() as u32).to_le_bytes());
+    frame[HEADER_BYTES..HEADER_BYTES + json.len()].copy_from_slice(&json);
+    let crc = header_crc(&frame[4..8], &json);
+    frame[8..12].copy_from_slice(&crc.to_le_bytes());
+    Ok(frame)
+}
+
+fn header_crc(len: &[u8], json: &[u8]) -> u32 {
+    let mut h = crc32fast::Hasher::new();
+    h.update(len);
+    h.update(json);
+    h.finalize()
+}
+
+fn parse_header_frame(bytes: &[u8]) -> Option<VolumeInfo> {
+    if bytes.len() < HEADER_BYTES || &bytes[0..4] != HEADER_MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes(bytes[4..8].try_into().ok()?) as usize;
+    let json = bytes.get(HEADER_BYTES..HEADER_BYTES + len)?;
+    if header_crc(&bytes[4..8], json) != u32::from_le_bytes(bytes[8..12].try_into().ok()?) {
+        return None;
+    }
+    serde_json::from_slice(json).ok()
+}
+
+/// Decode a volume's header frame (the frame after its data).
+pub fn read_volume_header(in_dir: &Path, m: &EncodeManifest, p: &RasterParams) -> Result<VolumeInfo, RasterError> {
+    let index = m.volume.as_ref().map_or(0, |v| v.index);
+    let path = frame_path(in_dir, m.data_end(), m);
+    let bytes = decode_frame_bytes_with_optional_deskew(&path, m, p, Palette8::Basic)?;
+    Ok(parse_header_frame(&bytes).ok_or(VolumeError::HeaderUnreadable(index))?)
+}
+
+/// Carried bytes one volume can hold within `max_frames`, given the frames every volume spends on
+/// sync, calibration, its header and the table of contents.
+fn stripe_capacity(p: &RasterParams, max_frames: u32, toc_frames: u32) -> Result<u64, VolumeError> {
+    let overhead = p.sync_frames + p.calibration_frames + 1 + toc_frames * TocInfo::COPIES;
+    let data_frames = max_frames.saturating_sub(overhead) as u64;
+    let bytes = match &p.fec {
+        Some(fecp) => {
+            let shards = data_frames * u64::from(p.tiles[0]) * u64::from(p.tiles[1]);
+            shards / (fecp.data_shards + fecp.parity_shards) as u64 * (fecp.data_shards * fecp.shard_bytes) as u64
+        }
+        None => data_frames * frame_payload_capacity(p).min(p.chunk_bytes) as u64,
+    };
+    if bytes == 0 {
+        return Err(VolumeError::FramesTooFew { max_frames, overhead });
+    }
+    Ok(bytes)
+}
+
+/// Encode `input_bytes` as a volume set under `out_dir` (`vol_000/`, `vol_001/`, ... and `volumes.json`).
+///
+/// Compression, encryption and signing apply to the payload as a whole, as for
+/// [`crate::raster::encode_bytes_to_frames_dir`]; the volumes carry stripes of the result.
+pub fn encode_bytes_to_volumes(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_dir: &Path,
+    p: &RasterParams,
+    vp: &VolumeParams,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<VolumeSet, RasterError> {
+    let toc = match table_of_contents(input_bytes, file_name, p) {
+        Some(toc) => Some(toc_frame_payloads(&toc, p.encryption.as_ref(), frame_payload_capacity(p) as usize)?),
+        None => None,
+    };
+    let carried = carry_payload(input_bytes, p)?;
+    let total = carried.bytes.len() as u64;
+
+    let mut data_volumes = 1u64;
+    if let Some(max_frames) = vp.max_frames {
+        let toc_frames = toc.as_ref().map_or(0, |(_, frames)| frames.len() as u32);
+        data_volumes = data_volumes.max(total.div_ceil(stripe_capacity(p, max_frames, toc_frames)?));
+    }
+    if let Some(max_bytes) = vp.max_bytes {
+        if max_bytes == 0 {
+            return Err(VolumeError::BytesTooFew.into());
+        }
+        data_volumes = data_volumes.max(total.div_ceil(max_bytes));
+    }
+    let count = data_volumes + vp.parity_volumes as u64;
+    if count > VolumeParams::MAX_VOLUMES as u64 {
+        return Err(VolumeError::TooMany(count.min(u32::MAX as u64) as u32).into());
+    }
+    let data_volumes = data_volumes as u32;
+    let stripe_bytes = total.div_ceil(data_volumes as u64);
+
+    let mut stripes: Vec<Vec<u8>> = (0..data_volumes as u64)
+        .map(|i| {
+            let start = (i * stripe_bytes).min(total) as usize;
+            let end = ((i + 1) * stripe_bytes).min(total) as usize;
+            let mut stripe = carried.bytes[start..end].to_vec();
+            stripe.resize(stripe_bytes as usize, 0);
+            stripe
+        })
+        .collect();
+    stripes.resize(count as usize, vec![0u8; stripe_bytes as usize]);
+    if vp.parity_volumes > 0 && stripe_bytes > 0 {
+        let rs = ReedSolomon::new(data_volumes as usize, vp.parity_volumes as usize)
+            .map_err(|e| VolumeError::ReedSolomon(format!("{e:?}")))?;
+        rs.encode(&mut stripes).map_err(|e| VolumeError::ReedSolomon(format!("{e:?}")))?;
+    }
+
+    let sha256_hex = hex::encode(Sha256::digest(&carried.bytes));
+    let set_id = {
+        let mut h = Sha256::new();
+        h.update(sha256_hex.as_bytes());
+        h.update(data_volumes.to_le_bytes());
+        h.update(vp.parity_volumes.to_le_bytes());
+        h.update(stripe_bytes.to_le_bytes());
+        hex::encode(&h.finalize()[..8])
+    };
+
+    // Each volume is a raw, unencrypted stripe; the set manifest describes the real payload.
+    let mut volume_p = p.clone();
+    volume_p.payload = PayloadKind::Raw;
+    volume_p.filter = None;
+    volume_p.delta = None;
+
+    let mut volumes = Vec::with_capacity(count as usize);
+    let mut manifests = Vec::with_capacity(count as usize);
+    for (index, stripe) in stripes.into_iter().enumerate() {
+        let index = index as u32;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Info(format!("volume {} of {count}", index + 1)));
+        }
+        let info = VolumeInfo {
+            set_id: set_id.clone(),
+            index,
+            data_volumes,
+            parity_volumes: vp.parity_volumes,
+            stripe_bytes,
+            header_frame: 0,
+        };
+        let stripe = Carried {
+            bytes: Cow::Owned(stripe),
+            dedup: None,
+            compression: None,
+            encryption: None,
+        };
+        let name = format!("{file_name}.{}", VolumeSet::dir_name(index));
+        let encoder = FrameEncoder::plan(stripe, &name, &volume_p, toc.clone(), Some(info))?;
+        let dir = VolumeSet::dir_name(index);
+        let m = write_encoder_frames(&encoder, &out_dir.join(&dir), progress_tx.clone())?;
+        volumes.push(VolumeEntry {
+            index,
+            dir,
+            frames: m.frames,
+            sha256_hex: m.sha256_hex.clone(),
+        });
+        manifests.push(m);
+    }
+
+    let mut manifest = EncodeManifest {
+        total_bytes: total,
+        sha256_hex,
+        frames: volumes.iter().map(|v| v.frames).sum(),
+        file_name: file_name.to_string(),
+        dedup: carried.dedup,
+        compression: carried.compression,
+        encryption: carried.encryption,
+        payload: p.payload,
+        filter: p.filter.clone(),
+        delta: p.delta.as_ref().map(|d| d.info.clone()),
+        signature: None,
+        toc: None,
+        volume: None,
+        ..manifests[0].clone()
+    };
+    if let Some(signer) = &p.signing {
+        sign_manifest(&mut manifest, signer)?;
+    }
+    let set = VolumeSet {
+        magic: VolumeSet::MAGIC.to_string(),
+        version: VolumeSet::VERSION,
+        set_id,
+        data_volumes,
+        parity_volumes: vp.parity_volumes,
+        stripe_bytes,
+        volumes,
+        manifest,
+    };
+    let json = serde_json::to_vec_pretty(&set)?;
+    fs::write(out_dir.join(SET_MANIFEST), &json)?;
+    for v in &set.volumes {
+        fs::write(out_dir.join(&v.dir).join(SET_MANIFEST), &json)?;
+    }
+    Ok(set)
+}
+
+/// Load `volumes.json` from a set directory or one of its volumes.
+pub fn read_volume_set(dir: &Path) -> Result<VolumeSet, RasterError> {
+    let path = dir.join(SET_MANIFEST);
+    if !path.exists() {
+        return Err(VolumeError::SetManifestMissing.into());
+    }
+    let set: VolumeSet = serde_json::from_slice(&fs::read(path)?)?;
+    if set.magic != VolumeSet::MAGIC || set.version != VolumeSet::VERSION || !set.manifest.is_supported() {
+        return Err(VolumeError::SetManifestInvalid.into());
+    }
+    Ok(set)
+}
+
+/// True if `dir` is a volume set directory or one volume of a set.
+pub fn is_volume_path(dir: &Path) -> bool {
+    dir.join(SET_MANIFEST).is_file()
+}
+
+/// The volumes of one set found on disk.
+#[derive(Debug, Clone)]
+pub struct VolumeScan {
+    pub set: VolumeSet,
+    /// Volume index -> its frames directory.
+    pub found: BTreeMap<u32, PathBuf>,
+}
+
+impl VolumeScan {
+    /// Indices of the volumes that weren't found, data and parity alike.
+    pub fn missing(&self) -> Vec<u32> {
+        (0..self.set.count()).filter(|i| !self.found.contains_key(i)).collect()
+    }
+}
+
+/// Gather the volumes among `paths`, each a set directory (its listed `vol_NNN/` subdirectories
+/// are picked up) or a single volume. Volumes from another set are rejected.
+pub fn scan_volumes(paths: &[PathBuf]) -> Result<VolumeScan, RasterError> {
+    let mut set: Option<VolumeSet> = None;
+    let mut found = BTreeMap::new();
+    for path in paths {
+        if path.join("manifest.json").is_file() {
+            let m = read_manifest(path)?;
+            let Some(v) = m.volume else {
+                return Err(VolumeError::NotAVolume(path.clone()).into());
+            };
+            adopt(&mut set, read_volume_set(path)?, path)?;
+            found.entry(v.index).or_insert_with(|| path.clone());
+        } else if is_volume_path(path) {
+            let s = read_volume_set(path)?;
+            let dirs: Vec<(u32, PathBuf)> = s.volumes.iter().map(|v| (v.index, path.join(&v.dir))).collect();
+            adopt(&mut set, s, path)?;
+            for (index, dir) in dirs {
+                let ok = read_manifest(&dir)
+                    .ok()
+                    .and_then(|m| m.volume)
+                    .is_some_and(|v| v.index == index && set.as_ref().is_some_and(|s| s.set_id == v.set_id));
+                if ok {
+                    found.entry(index).or_insert(dir);
+                }
+            }
+        } else {
+            return Err(VolumeError::NotAVolume(path.clone()).into());
+        }
+    }
+
+    let set = set.ok_or(VolumeError::SetManifestMissing)?;
+    Ok(VolumeScan { set, found })
+}
+
+/// Keep the first set seen and reject volumes from any other.
+fn adopt(set: &mut Option<VolumeSet>, candidate: VolumeSet, path: &Path) -> Result<(), RasterError> {
+    match set {
+        Some(s) if s.set_id != candidate.set_id => Err(VolumeError::SetMismatch(path.to_path_buf()).into()),
+        Some(_) => Ok(()),
+        None => {
+            *set = Some(candidate);
+            Ok(())
+        }
+    }
+}
+
+/// What [`decode_volumes_to_writer`] recovered and how.
+#[derive(Debug, Clone, Default)]
+pub struct VolumeDecodeReport {
+    /// Bytes written to the output.
+    pub bytes: u64,
+    /// Volumes found on disk that failed to decode, with the reason.
+    pub failed: Vec<(u32, String)>,
+    /// Data volumes rebuilt from parity.
+    pub rebuilt: Vec<u32>,
+}
+
+/// Decode a volume set, rebuilding missing or unreadable data volumes from parity volumes.
+///
+/// When every data volume is present they are decoded in order straight into `out`, so memory
+/// use is that of a single frames directory. Parity volumes are only decoded when a data volume
+/// is missing or fails; then the stripes needed for Reed-Solomon recovery are buffered, and the
+/// rebuilt ones are written after whatever already reached `out`. Decryption, decompression and
+/// the SHA-256 checks work as for a single frames directory.
+pub fn decode_volumes_to_writer<W: Write>(
+    scan: &VolumeScan,
+    p: &RasterParams,
+    out: &mut W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<VolumeDecodeReport, RasterError> {
+    let set = &scan.set;
+    let k = set.data_volumes;
+    let mut report = VolumeDecodeReport::default();
+
+    let mut stripe_p = p.clone();
+    stripe_p.decryption = None;
+    let decode_volume = |index: u32, dir: &Path, mut out: &mut dyn Write| -> Result<(), RasterError> {
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Info(format!("decoding volume {} of {}", index + 1, set.count())));
+        }
+        let m = read_manifest(dir)?;
+        let info = m.volume.clone().ok_or_else(|| VolumeError::NotAVolume(dir.to_path_buf()))?;
+        if info.index != index || info.set_id != set.set_id {
+            return Err(VolumeError::SetMismatch(dir.to_path_buf()).into());
+        }
+        // A readable header that disagrees means the manifest and frames were mixed up.
+        if let Ok(header) = read_volume_header(dir, &m, &stripe_p) {
+            if header != info {
+                return Err(VolumeError::HeaderMismatch(index).into());
+            }
+        }
+        if decode_frames_dir_to_writer(dir, &stripe_p, &mut out)? != set.stripe_bytes {
+            return Err(VolumeError::SetMismatch(dir.to_path_buf()).into());
+        }
+        Ok(())
+    };
+
+    let mut stream = StripeStream::new(payload_sink(Some(&set.manifest), p, &mut *out)?, set.manifest.total_bytes);
+    // Data volumes before `next` are completely written.
+    let mut next = 0;
+    let mut stream_failed = None;
+    if (0..k).all(|i| scan.found.contains_key(&i)) {
+        while next < k {
+            stream.start_stripe();
+            match decode_volume(next, &scan.found[&next], &mut stream) {
+                Ok(()) => next += 1,
+                // The output itself failed (or rejected the key); no other volume helps.
+                Err(e) if stream.sink_failed => return Err(e),
+                Err(e) => {
+                    report.failed.push((next, e.to_string()));
+                    stream_failed = Some(next);
+                    break;
+                }
+            }
+        }
+    } else {
+        stream.start_stripe();
+    }
+
+    if next < k {
+        let mut stripes: Vec<Option<Vec<u8>>> = vec![None; set.count() as usize];
+        let mut usable = 0;
+        for (&index, dir) in &scan.found {
+            // Parity volumes only matter once every data volume has been tried.
+            if index >= k && usable >= k {
+                break;
+            }
+            if stream_failed == Some(index) {
+                continue;
+            }
+            let mut stripe = Vec::new();
+            match decode_volume(index, dir, &mut stripe) {
+                Ok(()) => {
+                    stripes[index as usize] = Some(stripe);
+                    usable += 1;
+                }
+                Err(e) => report.failed.push((index, e.to_string())),
+            }
+        }
+        let lost: Vec<u32> = (0..k).filter(|&i| stripes[i as usize].is_none()).collect();
+        if !lost.is_empty() {
+            if usable < k {
+                let missing = (0..set.count()).filter(|&i| stripes[i as usize].is_none()).collect();
+                return Err(VolumeError::Unrecoverable {
+                    have: usable,
+                    needed: k,
+                    count: set.count(),
+                    missing,
+                }
+                .into());
+            }
+            let rs = ReedSolomon::new(k as usize, set.parity_volumes as usize)
+                .map_err(|e| VolumeError::ReedSolomon(format!("{e:?}")))?;
+            rs.reconstruct_data(&mut stripes).map_err(|e| VolumeError::ReedSolomon(format!("{e:?}")))?;
+            report.rebuilt = lost;
+        }
+
+        // Part of volume `next` may already be in `out`; it has to agree with the rebuilt stripe.
+        let first = stripes[next as usize].as_deref().unwrap_or_default();
+        let (written, written_sha) = stream.stripe_progress();
+        let agrees = first
+            .get(..written as usize)
+            .is_some_and(|head| Sha256::digest(head)[..] == written_sha[..]);
+        if !agrees {
+            return Err(VolumeError::PartlyWritten(next).into());
+        }
+        stream.write_all(&first[written as usize..]).map_err(payload_io_error)?;
+        for stripe in stripes.iter().take(k as usize).skip(next as usize + 1).flatten() {
+            stream.write_all(stripe).map_err(payload_io_error)?;
+        }
+    }
+
+    let (_, written) = finish_payload(stream.sink, Some(&set.manifest), &hex::encode(stream.hasher.finalize()))?;
+    report.bytes = written;
+    Ok(report)
+}
+
+/// Feeds data stripes, in order, into the payload sink: hashes what it passes on and drops the
+/// zero padding past the end of the payload.
+struct StripeStream<S: Write> {
+    sink: S,
+    hasher: Sha256,
+    remaining: u64,
+    /// Bytes of the current stripe received so far, and their hash.
+    stripe_len: u64,
+    stripe_hasher: Sha256,
+    /// The sink returned an error, as opposed to a volume failing to decode.
+    sink_failed: bool,
+}
+
+impl<S: Write> StripeStream<S> {
+    fn new(sink: S, total_bytes: u64) -> Self {
+        Self {
+            sink,
+            hasher: Sha256::new(),
+            remaining: total_bytes,
+            stripe_len: 0,
+            stripe_hasher: Sha256::new(),
+            sink_failed: false,
+        }
+    }
+
+    fn start_stripe(&mut self) {
+        self.stripe_len = 0;
+        self.stripe_hasher = Sha256::new();
+    }
+
+    fn stripe_progress(&self) -> (u64, [u8; 32]) {
+        (self.stripe_len, self.stripe_hasher.clone().finalize().into())
+    }
+}
+
+impl<S: Write> Write for StripeStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = self.remaining.min(buf.len() as u64) as usize;
+        if let Err(e) = self.sink.write_all(&buf[..take]) {
+            self.sink_failed = true;
+            return Err(e);
+        }
+        self.hasher.update(&buf[..take]);
+        self.remaining -= take as u64;
+        self.stripe_hasher.update(buf);
+        self.stripe_len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush().inspect_err(|_| self.sink_failed = true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sllv_volume_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn small_params() -> RasterParams {
+        RasterParams {
+            grid_w: 64,
+            grid_h: 48,
+            sync_frames: 1,
+            payload: PayloadKind::Raw,
+            toc: false,
+            ..Profile::Archive.defaults()
+        }
+    }
+
+    fn encode(dir: &Path, data: &[u8], p: &RasterParams) -> VolumeSet {
+        let vp = VolumeParams { max_bytes: Some(3000), parity_volumes: 2, ..Default::default() };
+        encode_bytes_to_volumes(data, "data.bin", dir, p, &vp, None).unwrap()
+    }
+
+    fn decode(dir: &Path, p: &RasterParams) -> Result<(Vec<u8>, VolumeDecodeReport), RasterError> {
+        let scan = scan_volumes(&[dir.to_path_buf()])?;
+        let mut out = Vec::new();
+        let report = decode_volumes_to_writer(&scan, p, &mut out, None)?;
+        Ok((out, report))
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i * 31 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn all_volumes_stream_without_parity() {
+        let dir = scratch_dir("all");
+        let p = small_params();
+        let set = encode(&dir, &test_data(), &p);
+        assert_eq!((set.data_volumes, set.parity_volumes), (4, 2));
+        let (out, report) = decode(&dir, &p).unwrap();
+        assert_eq!(out, test_data());
+        assert!(report.failed.is_empty() && report.rebuilt.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_volumes_are_rebuilt_from_parity() {
+        let dir = scratch_dir("missing");
+        let p = small_params();
+        encode(&dir, &test_data(), &p);
+        fs::remove_dir_all(dir.join(VolumeSet::dir_name(1))).unwrap();
+        fs::remove_dir_all(dir.join(VolumeSet::dir_name(3))).unwrap();
+        let (out, report) = decode(&dir, &p).unwrap();
+        assert_eq!(out, test_data());
+        assert_eq!(report.rebuilt, [1, 3]);
+
+        fs::remove_dir_all(dir.join(VolumeSet::dir_name(4))).unwrap();
+        match decode(&dir, &p) {
+            Err(RasterError::Volume(VolumeError::Unrecoverable { have: 3, needed: 4, missing, .. })) => {
+                assert_eq!(missing, [1, 3, 4]);
+            }
+            other => panic!("expected Unrecoverable, got {:?}", other.map(|(_, r)| r)),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn volume_failing_mid_stream_is_rebuilt() {
+        let dir = scratch_dir("midstream");
+        let p = small_params();
+        encode(&dir, &test_data(), &p);
+        // Break the last data frame of volume 2, after some of its bytes were written.
+        let vol = dir.join(VolumeSet::dir_name(2));
+        let m = read_manifest(&vol).unwrap();
+        assert!(m.data_end() - (p.sync_frames + p.calibration_frames) > 1);
+        fs::write(frame_path(&vol, m.data_end() - 1, &m), b"not an image").unwrap();
+
+        let (out, report) = decode(&dir, &p).unwrap();
+        assert_eq!(out, test_data());
+        assert_eq!(report.rebuilt, [2]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn volumes_of_another_set_are_rejected() {
+        let a = scratch_dir("set_a");
+        let b = scratch_dir("set_b");
+        let p = small_params();
+        encode(&a, &test_data(), &p);
+        encode(&b, &test_data()[..9000], &p);
+        assert!(matches!(
+            scan_volumes(&[a.join(VolumeSet::dir_name(0)), b.join(VolumeSet::dir_name(1))]),
+            Err(RasterError::Volume(VolumeError::SetMismatch(_)))
+        ));
+        assert!(matches!(scan_volumes(&[a.join("nope")]), Err(RasterError::Volume(VolumeError::NotAVolume(_)))));
+        fs::remove_dir_all(&a).unwrap();
+        fs::remove_dir_all(&b).unwrap();
+    }
+}
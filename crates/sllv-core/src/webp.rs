@@ -0,0 +1,1397 @@
+//! Lossless WebP (VP8L) stills and animations, without libwebp.
+//!
+//! The writer produces an animated WebP (`VP8X`, `ANIM`, then one `ANMF` per frame). Each frame
+//! is a VP8L bitstream: a colour-indexing transform when the frame has at most 256 colours (SLLV
+//! frames pack two to eight pixels per byte that way), then prefix-coded literals and LZ77 copies
+//! from the previous pixel or the row above. The manifest goes in an `SLLV` chunk before the
+//! first frame; libwebp and browsers skip unknown chunks.
+//!
+//! The reader decodes any lossless stream (all four transforms, colour cache, meta prefix codes)
+//! in still or animated files and composites animation frames on the canvas. Lossy (`VP8 `)
+//! frames are rejected.
+//!
+//! Why not `image`'s `webp` feature: without libwebp its encoder writes lossless stills only, no
+//! `ANMF` frames; its decoder fails on an unknown chunk ahead of the frames (our `SLLV` chunk)
+//! and reads every frame of an animation into memory before returning the first.
+
+use image::{Rgb, RgbImage};
+use std::collections::{BinaryHeap, HashMap};
+use std::cmp::Reverse;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use thiserror::Error;
+
+/// Largest VP8L frame side.
+const MAX_SIDE: u32 = 16384;
+const VP8L_SIGNATURE: u8 = 0x2f;
+const NUM_LENGTH_CODES: usize = 24;
+const NUM_DISTANCE_CODES: usize = 40;
+const CODE_LENGTH_CODES: usize = 19;
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const MAX_CODE_LENGTH: u8 = 15;
+/// Longest LZ77 copy the encoder emits (the format allows 4096).
+const MAX_COPY: usize = 4096;
+/// Distance codes 1..=120 name nearby pixels as (dx, dy), packed as `dy << 4 | (8 - dx)`.
+const CODE_TO_PLANE: [u8; 120] = [
+    0x18, 0x07, 0x17, 0x19, 0x28, 0x06, 0x27, 0x29, 0x16, 0x1a, 0x26, 0x2a, 0x38, 0x05, 0x37, 0x39, 0x15, 0x1b, 0x36, 0x3a,
+    0x25, 0x2b, 0x48, 0x04, 0x47, 0x49, 0x14, 0x1c, 0x35, 0x3b, 0x46, 0x4a, 0x24, 0x2c, 0x58, 0x45, 0x4b, 0x34, 0x3c, 0x03,
+    0x57, 0x59, 0x13, 0x1d, 0x56, 0x5a, 0x23, 0x2d, 0x44, 0x4c, 0x55, 0x5b, 0x33, 0x3d, 0x68, 0x02, 0x67, 0x69, 0x12, 0x1e,
+    0x66, 0x6a, 0x22, 0x2e, 0x54, 0x5c, 0x43, 0x4d, 0x65, 0x6b, 0x32, 0x3e, 0x78, 0x01, 0x77, 0x79, 0x53, 0x5d, 0x11, 0x1f,
+    0x64, 0x6c, 0x42, 0x4e, 0x76, 0x7a, 0x21, 0x2f, 0x75, 0x7b, 0x31, 0x3f, 0x63, 0x6d, 0x52, 0x5e, 0x00, 0x74, 0x7c, 0x41,
+    0x4f, 0x10, 0x20, 0x62, 0x6e, 0x30, 0x73, 0x7d, 0x51, 0x5f, 0x40, 0x72, 0x7e, 0x61, 0x6f, 0x50, 0x71, 0x7f, 0x60, 0x70,
+];
+/// Top-level chunk holding the embedded manifest.
+pub const MANIFEST_CHUNK: &[u8; 4] = b"SLLV";
+
+/// A RIFF chunk: FourCC and payload.
+type Chunk = ([u8; 4], Vec<u8>);
+
+#[derive(Debug, Error)]
+pub enum WebpError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a WebP file")]
+    NotWebp,
+    #[error("format: {0}")]
+    Format(String),
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    #[error("frame {index}: {msg}")]
+    Frame { index: u64, msg: String },
+}
+
+fn corrupt(msg: impl Into<String>) -> WebpError {
+    WebpError::Format(msg.into())
+}
+
+/// Writes RGB frames as an endlessly looping animated lossless WebP.
+pub struct WebpWriter<W: Write + Seek> {
+    out: W,
+    start: u64,
+    width: u32,
+    height: u32,
+    delay_ms: u32,
+    frames: u64,
+}
+
+impl<W: Write + Seek> WebpWriter<W> {
+    /// Write the file header; `manifest` goes in an `SLLV` chunk before the first frame.
+    pub fn new(mut out: W, width: u32, height: u32, delay_ms: u32, manifest: Option<&[u8]>) -> Result<Self, WebpError> {
+        if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+            return Err(corrupt(format!("size {width}x{height} outside 1..={MAX_SIDE}")));
+        }
+        let start = out.stream_position()?;
+        out.write_all(b"RIFF\0\0\0\0WEBP")?;
+        let mut vp8x = vec![0x02, 0, 0, 0];
+        vp8x.extend_from_slice(&u24(width - 1));
+        vp8x.extend_from_slice(&u24(height - 1));
+        write_chunk(&mut out, b"VP8X", &vp8x)?;
+        // White, opaque background (BGRA); loop forever.
+        write_chunk(&mut out, b"ANIM", &[0xff, 0xff, 0xff, 0xff, 0, 0])?;
+        if let Some(m) = manifest {
+            write_chunk(&mut out, MANIFEST_CHUNK, m)?;
+        }
+        Ok(Self {
+            out,
+            start,
+            width,
+            height,
+            delay_ms: delay_ms.min(0xff_ffff),
+            frames: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, img: &RgbImage) -> Result<(), WebpError> {
+        if img.dimensions() != (self.width, self.height) {
+            return Err(WebpError::Frame {
+                index: self.frames,
+                msg: format!("{}x{}, expected {}x{}", img.width(), img.height(), self.width, self.height),
+            });
+        }
+        let vp8l = encode_vp8l(img)?;
+        let mut anmf = Vec::with_capacity(vp8l.len() + 32);
+        anmf.extend_from_slice(&[0; 6]);
+        anmf.extend_from_slice(&u24(self.width - 1));
+        anmf.extend_from_slice(&u24(self.height - 1));
+        anmf.extend_from_slice(&u24(self.delay_ms));
+        // Don't blend, don't dispose: every frame covers the whole canvas.
+        anmf.push(0x02);
+        write_chunk(&mut anmf, b"VP8L", &vp8l)?;
+        write_chunk(&mut self.out, b"ANMF", &anmf)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Fill in the RIFF size and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, WebpError> {
+        let end = self.out.stream_position()?;
+        let size = u32::try_from(end - self.start - 8).map_err(|_| corrupt("file larger than 4 GiB"))?;
+        self.out.seek(SeekFrom::Start(self.start + 4))?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// A still lossless WebP: `img` as a lone `VP8L` chunk.
+pub fn encode_still(img: &RgbImage) -> Result<Vec<u8>, WebpError> {
+    let vp8l = encode_vp8l(img)?;
+    let mut out = b"RIFF\0\0\0\0WEBP".to_vec();
+    write_chunk(&mut out, b"VP8L", &vp8l)?;
+    let size = u32::try_from(out.len() - 8).map_err(|_| corrupt("file larger than 4 GiB"))?;
+    out[4..8].copy_from_slice(&size.to_le_bytes());
+    Ok(out)
+}
+
+fn u24(v: u32) -> [u8; 3] {
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8]
+}
+
+fn read_u24(b: &[u8]) -> u32 {
+    b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16
+}
+
+fn write_chunk<W: Write>(out: &mut W, fourcc: &[u8; 4], payload: &[u8]) -> Result<(), WebpError> {
+    let size = u32::try_from(payload.len()).map_err(|_| corrupt("chunk larger than 4 GiB"))?;
+    out.write_all(fourcc)?;
+    out.write_all(&size.to_le_bytes())?;
+    out.write_all(payload)?;
+    if payload.len() % 2 == 1 {
+        out.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Reads a WebP as a sequence of composited RGB frames.
+pub struct WebpReader<R: Read> {
+    input: BufReader<R>,
+    /// Bytes of the RIFF payload not read yet.
+    remaining: u64,
+    canvas: Vec<[u8; 4]>,
+    width: u32,
+    height: u32,
+    background: [u8; 4],
+    manifest: Option<Vec<u8>>,
+    /// First image chunk, read while looking for the manifest.
+    pending: Option<Chunk>,
+    /// Area to clear to the background before the next frame.
+    dispose: Option<(u32, u32, u32, u32)>,
+    delay_ms: u32,
+    read: u64,
+}
+
+impl<R: Read> WebpReader<R> {
+    /// Parse the file header and every chunk up to the first frame.
+    pub fn new(input: R) -> Result<Self, WebpError> {
+        let mut input = BufReader::new(input);
+        let mut header = [0u8; 12];
+        input.read_exact(&mut header).map_err(|_| WebpError::NotWebp)?;
+        if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+            return Err(WebpError::NotWebp);
+        }
+        let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let mut reader = Self {
+            input,
+            remaining: riff_size.saturating_sub(4),
+            canvas: Vec::new(),
+            width: 0,
+            height: 0,
+            background: [0, 0, 0, 0xff],
+            manifest: None,
+            pending: None,
+            dispose: None,
+            delay_ms: 0,
+            read: 0,
+        };
+        while let Some((fourcc, payload)) = reader.next_chunk()? {
+            match &fourcc {
+                b"VP8X" => {
+                    if payload.len() < 10 {
+                        return Err(corrupt("short VP8X chunk"));
+                    }
+                    let (w, h) = (read_u24(&payload[4..]) + 1, read_u24(&payload[7..]) + 1);
+                    if w > MAX_SIDE || h > MAX_SIDE {
+                        return Err(corrupt(format!("canvas {w}x{h} exceeds {MAX_SIDE}")));
+                    }
+                    (reader.width, reader.height) = (w, h);
+                }
+                b"ANIM" => {
+                    if payload.len() < 6 {
+                        return Err(corrupt("short ANIM chunk"));
+                    }
+                    reader.background = [payload[2], payload[1], payload[0], payload[3]];
+                }
+                c if c == MANIFEST_CHUNK => reader.manifest = Some(payload),
+                b"ANMF" | b"VP8L" | b"VP8 " | b"ALPH" => {
+                    if reader.width == 0 {
+                        // Simple format: the canvas is the one image.
+                        let (w, h) = match &fourcc {
+                            b"VP8L" => vp8l_dimensions(&payload)?,
+                            _ => return Err(WebpError::Unsupported("lossy (VP8) WebP".into())),
+                        };
+                        (reader.width, reader.height) = (w, h);
+                    }
+                    reader.pending = Some((fourcc, payload));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if reader.width == 0 {
+            return Err(corrupt("no image"));
+        }
+        let bg = reader.background;
+        reader.canvas = vec![bg; reader.width as usize * reader.height as usize];
+        Ok(reader)
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Payload of the `SLLV` chunk, if the file has one before its first frame.
+    pub fn manifest(&self) -> Option<&[u8]> {
+        self.manifest.as_deref()
+    }
+
+    /// Display time of the last frame returned, in milliseconds (0 for a still).
+    pub fn delay_ms(&self) -> u32 {
+        self.delay_ms
+    }
+
+    /// Frames returned so far.
+    pub fn frames_read(&self) -> u64 {
+        self.read
+    }
+
+    /// The next frame as shown on screen, or `None` at the end of the file.
+    pub fn read_frame(&mut self) -> Result<Option<RgbImage>, WebpError> {
+        let index = self.read;
+        let frame_err = |e: WebpError| match e {
+            WebpError::Format(msg) => WebpError::Frame { index, msg },
+            other => other,
+        };
+        loop {
+            let chunk = match self.pending.take() {
+                Some(c) => Some(c),
+                None => self.next_chunk()?,
+            };
+            let Some((fourcc, payload)) = chunk else {
+                return Ok(None);
+            };
+            match &fourcc {
+                b"ANMF" => {
+                    if payload.len() < 16 {
+                        return Err(corrupt("short ANMF chunk"));
+                    }
+                    let x = read_u24(&payload[0..]) * 2;
+                    let y = read_u24(&payload[3..]) * 2;
+                    let w = read_u24(&payload[6..]) + 1;
+                    let h = read_u24(&payload[9..]) + 1;
+                    self.delay_ms = read_u24(&payload[12..]);
+                    let flags = payload[15];
+                    let image = frame_image(&payload[16..]).map_err(frame_err)?;
+                    if image.width != w || image.height != h {
+                        return Err(WebpError::Frame {
+                            index,
+                            msg: format!("bitstream is {}x{}, frame header says {w}x{h}", image.width, image.height),
+                        });
+                    }
+                    if let Some((dx, dy, dw, dh)) = self.dispose.take() {
+                        self.fill(dx, dy, dw, dh);
+                    }
+                    self.draw(&image, x, y, flags & 0x02 == 0);
+                    if flags & 0x01 != 0 {
+                        self.dispose = Some((x, y, w, h));
+                    }
+                }
+                b"VP8L" => {
+                    let image = decode_vp8l(&payload).map_err(frame_err)?;
+                    self.draw(&image, 0, 0, false);
+                }
+                b"VP8 " => return Err(WebpError::Unsupported("lossy (VP8) frames".into())),
+                _ => continue,
+            }
+            self.read += 1;
+            let mut img = RgbImage::new(self.width, self.height);
+            for (px, c) in img.pixels_mut().zip(&self.canvas) {
+                *px = Rgb([c[0], c[1], c[2]]);
+            }
+            return Ok(Some(img));
+        }
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Chunk>, WebpError> {
+        if self.remaining < 8 {
+            return Ok(None);
+        }
+        let mut head = [0u8; 8];
+        self.input.read_exact(&mut head)?;
+        let size = u32::from_le_bytes([head[4], head[5], head[6], head[7]]) as u64;
+        let padded = size + (size & 1);
+        if 8 + padded > self.remaining {
+            return Err(corrupt("chunk runs past the end of the file"));
+        }
+        self.remaining -= 8 + padded;
+        // Grown as bytes arrive rather than sized from the header, which may lie.
+        let mut payload = Vec::new();
+        if (&mut self.input).take(size).read_to_end(&mut payload)? as u64 != size {
+            return Err(corrupt("chunk runs past the end of the file"));
+        }
+        if size & 1 == 1 {
+            self.input.read_exact(&mut [0u8; 1])?;
+        }
+        Ok(Some(([head[0], head[1], head[2], head[3]], payload)))
+    }
+
+    fn fill(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let width = self.width as usize;
+        for py in y as usize..(y as usize + h as usize).min(self.height as usize) {
+            for px in x as usize..(x as usize + w as usize).min(width) {
+                self.canvas[py * width + px] = self.background;
+            }
+        }
+    }
+
+    fn draw(&mut self, image: &Argb, x: u32, y: u32, blend: bool) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (iw, ih) = (image.width as usize, image.height as usize);
+        for fy in 0..ih {
+            let py = y as usize + fy;
+            if py >= height {
+                break;
+            }
+            for fx in 0..iw {
+                let px = x as usize + fx;
+                if px >= width {
+                    break;
+                }
+                let argb = image.pixels[fy * iw + fx];
+                let src = [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8, (argb >> 24) as u8];
+                let dst = &mut self.canvas[py * width + px];
+                *dst = if blend { blend_over(src, *dst) } else { src };
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for WebpReader<R> {
+    type Item = Result<RgbImage, WebpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Non-premultiplied `src` over `dst`, both RGBA.
+fn blend_over(src: [u8; 4], dst: [u8; 4]) -> [u8; 4] {
+    let sa = src[3] as u32;
+    if sa == 255 {
+        return src;
+    }
+    let da = dst[3] as u32 * (255 - sa) / 255;
+    let out_a = sa + da;
+    if out_a == 0 {
+        return [0, 0, 0, 0];
+    }
+    let mix = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da) / out_a) as u8;
+    [mix(src[0], dst[0]), mix(src[1], dst[1]), mix(src[2], dst[2]), out_a as u8]
+}
+
+/// The VP8L bitstream inside an `ANMF` payload (after its 16-byte header).
+fn frame_image(data: &[u8]) -> Result<Argb, WebpError> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let body = data.get(pos + 8..pos + 8 + size).ok_or_else(|| corrupt("frame chunk runs past its ANMF"))?;
+        match &data[pos..pos + 4] {
+            b"VP8L" => return decode_vp8l(body),
+            b"VP8 " | b"ALPH" => return Err(WebpError::Unsupported("lossy (VP8) frames".into())),
+            _ => pos += 8 + size + (size & 1),
+        }
+    }
+    Err(corrupt("ANMF without image data"))
+}
+
+/// A decoded VP8L image: ARGB pixels, row-major.
+struct Argb {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+// ---- Bit I/O ---------------------------------------------------------------------------------
+
+/// LSB-first bit reader over a VP8L bitstream.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u64,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, acc: 0, bits: 0 }
+    }
+
+    fn read(&mut self, n: u32) -> Result<u32, WebpError> {
+        while self.bits < n {
+            let Some(&b) = self.data.get(self.pos) else {
+                return Err(corrupt("bitstream ends early"));
+            };
+            self.acc |= (b as u64) << self.bits;
+            self.bits += 8;
+            self.pos += 1;
+        }
+        let v = (self.acc & ((1u64 << n) - 1)) as u32;
+        self.acc >>= n;
+        self.bits -= n;
+        Ok(v)
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn put(&mut self, value: u32, n: u32) {
+        self.acc |= (value as u64) << self.bits;
+        self.bits += n;
+        while self.bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+// ---- Prefix codes ----------------------------------------------------------------------------
+
+/// A canonical prefix code, decoded a bit at a time (codes are stored MSB first).
+struct PrefixCode {
+    counts: [u16; MAX_CODE_LENGTH as usize + 1],
+    symbols: Vec<u16>,
+    /// A code with one symbol takes no bits.
+    single: Option<u16>,
+}
+
+impl PrefixCode {
+    fn from_lengths(lengths: &[u8]) -> Result<Self, WebpError> {
+        let mut counts = [0u16; MAX_CODE_LENGTH as usize + 1];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let used: Vec<u16> = (0..lengths.len() as u16).filter(|&s| lengths[s as usize] > 0).collect();
+        match used.len() {
+            0 => return Err(corrupt("empty prefix code")),
+            1 => {
+                return Ok(Self {
+                    counts,
+                    symbols: used.clone(),
+                    single: Some(used[0]),
+                })
+            }
+            _ => {}
+        }
+        // Kraft sum must be exactly one.
+        let mut left = 1i32;
+        for &c in &counts[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 {
+                return Err(corrupt("over-subscribed prefix code"));
+            }
+        }
+        if left != 0 {
+            return Err(corrupt("incomplete prefix code"));
+        }
+        let mut symbols = used;
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        Ok(Self {
+            counts,
+            symbols,
+            single: None,
+        })
+    }
+
+    fn decode(&self, br: &mut BitReader) -> Result<u16, WebpError> {
+        if let Some(s) = self.single {
+            return Ok(s);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_CODE_LENGTH as usize {
+            code |= br.read(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid prefix code"))
+    }
+}
+
+fn read_prefix_code(br: &mut BitReader, alphabet: usize) -> Result<PrefixCode, WebpError> {
+    let mut lengths = vec![0u8; alphabet];
+    if br.read(1)? == 1 {
+        let num_symbols = br.read(1)? + 1;
+        let first_bits = if br.read(1)? == 1 { 8 } else { 1 };
+        let mut symbols = vec![br.read(first_bits)? as usize];
+        if num_symbols == 2 {
+            symbols.push(br.read(8)? as usize);
+        }
+        for s in symbols {
+            *lengths.get_mut(s).ok_or_else(|| corrupt("simple code symbol out of range"))? = 1;
+        }
+        return PrefixCode::from_lengths(&lengths);
+    }
+    let num_codes = br.read(4)? as usize + 4;
+    let mut cl_lengths = [0u8; CODE_LENGTH_CODES];
+    for &i in &CODE_LENGTH_ORDER[..num_codes] {
+        cl_lengths[i] = br.read(3)? as u8;
+    }
+    let cl_code = PrefixCode::from_lengths(&cl_lengths)?;
+    let mut max_tokens = if br.read(1)? == 1 {
+        let nbits = 2 + 2 * br.read(3)?;
+        let max = 2 + br.read(nbits)? as usize;
+        if max > alphabet {
+            return Err(corrupt("code length count exceeds alphabet"));
+        }
+        max
+    } else {
+        alphabet
+    };
+    let mut symbol = 0;
+    let mut prev = 8u8;
+    while symbol < alphabet {
+        if max_tokens == 0 {
+            break;
+        }
+        max_tokens -= 1;
+        let token = cl_code.decode(br)?;
+        if token < 16 {
+            lengths[symbol] = token as u8;
+            symbol += 1;
+            if token != 0 {
+                prev = token as u8;
+            }
+            continue;
+        }
+        let (extra, offset, value) = match token {
+            16 => (2, 3, prev),
+            17 => (3, 3, 0),
+            _ => (7, 11, 0),
+        };
+        let repeat = br.read(extra)? as usize + offset;
+        if symbol + repeat > alphabet {
+            return Err(corrupt("code length repeat past alphabet"));
+        }
+        lengths[symbol..symbol + repeat].fill(value);
+        symbol += repeat;
+    }
+    PrefixCode::from_lengths(&lengths)
+}
+
+/// Green (with length and cache codes), red, blue, alpha and distance codes.
+struct CodeGroup([PrefixCode; 5]);
+
+fn read_code_group(br: &mut BitReader, cache_size: usize) -> Result<CodeGroup, WebpError> {
+    Ok(CodeGroup([
+        read_prefix_code(br, 256 + NUM_LENGTH_CODES + cache_size)?,
+        read_prefix_code(br, 256)?,
+        read_prefix_code(br, 256)?,
+        read_prefix_code(br, 256)?,
+        read_prefix_code(br, NUM_DISTANCE_CODES)?,
+    ]))
+}
+
+/// Length and distance values: a prefix symbol plus extra bits.
+fn read_lz77_value(br: &mut BitReader, symbol: u32) -> Result<usize, WebpError> {
+    if symbol < 4 {
+        return Ok(symbol as usize + 1);
+    }
+    let extra = (symbol - 2) >> 1;
+    let offset = (2 + (symbol & 1)) << extra;
+    Ok((offset + br.read(extra)? + 1) as usize)
+}
+
+/// Inverse of [`read_lz77_value`]: (symbol, extra bit count, extra bits).
+fn lz77_symbol(value: usize) -> (u32, u32, u32) {
+    let v = value as u32 - 1;
+    if v < 4 {
+        return (v, 0, 0);
+    }
+    let high = 31 - v.leading_zeros();
+    let second = (v >> (high - 1)) & 1;
+    let extra = high - 1;
+    (2 * high + second, extra, v & ((1 << extra) - 1))
+}
+
+fn plane_to_distance(xsize: usize, code: usize) -> usize {
+    if code > 120 {
+        return code - 120;
+    }
+    let v = CODE_TO_PLANE[code - 1];
+    let (dy, dx) = ((v >> 4) as isize, 8 - (v & 0xf) as isize);
+    (dy * xsize as isize + dx).max(1) as usize
+}
+
+// ---- Decoder ---------------------------------------------------------------------------------
+
+fn vp8l_dimensions(data: &[u8]) -> Result<(u32, u32), WebpError> {
+    if data.len() < 5 || data[0] != VP8L_SIGNATURE {
+        return Err(corrupt("bad VP8L signature"));
+    }
+    let mut br = BitReader::new(&data[1..]);
+    let w = br.read(14)? + 1;
+    let h = br.read(14)? + 1;
+    Ok((w, h))
+}
+
+enum Transform {
+    Predictor { bits: u32, data: Vec<u32> },
+    Color { bits: u32, data: Vec<u32> },
+    SubtractGreen,
+    ColorIndexing { palette: Vec<u32>, width_bits: u32, xsize: usize },
+}
+
+fn div_round_up(n: usize, bits: u32) -> usize {
+    (n + (1 << bits) - 1) >> bits
+}
+
+fn decode_vp8l(data: &[u8]) -> Result<Argb, WebpError> {
+    let (width, height) = vp8l_dimensions(data)?;
+    let mut br = BitReader::new(&data[1..]);
+    br.read(28)?;
+    let _alpha_used = br.read(1)?;
+    if br.read(3)? != 0 {
+        return Err(WebpError::Unsupported("VP8L version".into()));
+    }
+    let (w, h) = (width as usize, height as usize);
+    let mut xsize = w;
+    let mut transforms = Vec::new();
+    let mut seen = [false; 4];
+    while br.read(1)? == 1 {
+        let kind = br.read(2)? as usize;
+        if std::mem::replace(&mut seen[kind], true) {
+            return Err(corrupt("transform used twice"));
+        }
+        transforms.push(match kind {
+            0 | 1 => {
+                let bits = br.read(3)? + 2;
+                let data = decode_image_stream(&mut br, div_round_up(xsize, bits), div_round_up(h, bits), false)?;
+                if kind == 0 {
+                    Transform::Predictor { bits, data }
+                } else {
+                    Transform::Color { bits, data }
+                }
+            }
+            2 => Transform::SubtractGreen,
+            _ => {
+                let n = br.read(8)? as usize + 1;
+                let mut palette = decode_image_stream(&mut br, n, 1, false)?;
+                for i in 1..n {
+                    palette[i] = add_pixels(palette[i], palette[i - 1]);
+                }
+                let width_bits = match n {
+                    0..=2 => 3,
+                    3..=4 => 2,
+                    5..=16 => 1,
+                    _ => 0,
+                };
+                let t = Transform::ColorIndexing { palette, width_bits, xsize };
+                xsize = div_round_up(xsize, width_bits);
+                t
+            }
+        });
+    }
+    let mut pixels = decode_image_stream(&mut br, xsize, h, true)?;
+    for t in transforms.iter().rev() {
+        match t {
+            Transform::Predictor { bits, data } => inverse_predictor(&mut pixels, xsize, h, *bits, data),
+            Transform::Color { bits, data } => inverse_color(&mut pixels, xsize, *bits, data),
+            Transform::SubtractGreen => {
+                for p in pixels.iter_mut() {
+                    let g = (*p >> 8) & 0xff;
+                    let rb = ((*p & 0x00ff00ff) + (g << 16 | g)) & 0x00ff00ff;
+                    *p = (*p & 0xff00ff00) | rb;
+                }
+            }
+            Transform::ColorIndexing {
+                palette,
+                width_bits,
+                xsize: full,
+            } => {
+                pixels = inverse_color_indexing(&pixels, xsize, *full, h, *width_bits, palette);
+                xsize = *full;
+            }
+        }
+    }
+    Ok(Argb { width, height, pixels })
+}
+
+/// An entropy-coded image: the main image (`main`, which may use meta prefix codes) or one of the
+/// sub-images holding transform data, the palette or the entropy image itself.
+fn decode_image_stream(br: &mut BitReader, xsize: usize, ysize: usize, main: bool) -> Result<Vec<u32>, WebpError> {
+    let cache_bits = if br.read(1)? == 1 {
+        let bits = br.read(4)?;
+        if !(1..=11).contains(&bits) {
+            return Err(corrupt("invalid colour cache size"));
+        }
+        bits
+    } else {
+        0
+    };
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+    let mut meta: Option<(u32, usize, Vec<u32>)> = None;
+    let mut num_groups = 1;
+    if main && br.read(1)? == 1 {
+        let bits = br.read(3)? + 2;
+        let ew = div_round_up(xsize, bits);
+        let entropy = decode_image_stream(br, ew, div_round_up(ysize, bits), false)?;
+        num_groups = entropy.iter().map(|p| ((p >> 8) & 0xffff) as usize).max().unwrap_or(0) + 1;
+        meta = Some((bits, ew, entropy));
+    }
+    let groups = (0..num_groups).map(|_| read_code_group(br, cache_size)).collect::<Result<Vec<_>, _>>()?;
+
+    let total = xsize * ysize;
+    let mut data = vec![0u32; total];
+    let mut cache = vec![0u32; cache_size];
+    let mut cached = 0;
+    let mut pos = 0;
+    while pos < total {
+        let group = match &meta {
+            Some((bits, ew, entropy)) => {
+                let (x, y) = (pos % xsize, pos / xsize);
+                let g = ((entropy[(y >> bits) * ew + (x >> bits)] >> 8) & 0xffff) as usize;
+                &groups[g].0
+            }
+            None => &groups[0].0,
+        };
+        let s = group[0].decode(br)? as usize;
+        if s < 256 {
+            let r = group[1].decode(br)? as u32;
+            let b = group[2].decode(br)? as u32;
+            let a = group[3].decode(br)? as u32;
+            data[pos] = a << 24 | r << 16 | (s as u32) << 8 | b;
+            pos += 1;
+        } else if s < 256 + NUM_LENGTH_CODES {
+            let length = read_lz77_value(br, (s - 256) as u32)?;
+            let dist_symbol = group[4].decode(br)? as u32;
+            let dist = plane_to_distance(xsize, read_lz77_value(br, dist_symbol)?);
+            if dist > pos || pos + length > total {
+                return Err(corrupt("LZ77 copy out of bounds"));
+            }
+            for i in pos..pos + length {
+                data[i] = data[i - dist];
+            }
+            pos += length;
+        } else {
+            let index = s - 256 - NUM_LENGTH_CODES;
+            if index >= cache_size {
+                return Err(corrupt("colour cache index out of range"));
+            }
+            while cached < pos {
+                insert_cache(&mut cache, cache_bits, data[cached]);
+                cached += 1;
+            }
+            data[pos] = cache[index];
+            pos += 1;
+        }
+        if cache_size > 0 {
+            while cached < pos {
+                insert_cache(&mut cache, cache_bits, data[cached]);
+                cached += 1;
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn insert_cache(cache: &mut [u32], bits: u32, argb: u32) {
+    cache[(0x1e35a7bdu32.wrapping_mul(argb) >> (32 - bits)) as usize] = argb;
+}
+
+/// Per-channel sum modulo 256.
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let ag = (a & 0xff00ff00).wrapping_add(b & 0xff00ff00) & 0xff00ff00;
+    let rb = (a & 0x00ff00ff).wrapping_add(b & 0x00ff00ff) & 0x00ff00ff;
+    ag | rb
+}
+
+fn average2(a: u32, b: u32) -> u32 {
+    (((a ^ b) & 0xfefefefe) >> 1) + (a & b)
+}
+
+fn channels(p: u32) -> [i32; 4] {
+    [(p >> 24) as i32, (p >> 16 & 0xff) as i32, (p >> 8 & 0xff) as i32, (p & 0xff) as i32]
+}
+
+fn from_channels(c: [i32; 4]) -> u32 {
+    let c = c.map(|v| v.clamp(0, 255) as u32);
+    c[0] << 24 | c[1] << 16 | c[2] << 8 | c[3]
+}
+
+fn select(l: u32, t: u32, tl: u32) -> u32 {
+    let (l4, t4, tl4) = (channels(l), channels(t), channels(tl));
+    let p_l: i32 = (0..4).map(|i| (t4[i] - tl4[i]).abs()).sum();
+    let p_t: i32 = (0..4).map(|i| (l4[i] - tl4[i]).abs()).sum();
+    if p_l < p_t {
+        l
+    } else {
+        t
+    }
+}
+
+fn clamp_add_subtract_full(a: u32, b: u32, c: u32) -> u32 {
+    let (a, b, c) = (channels(a), channels(b), channels(c));
+    from_channels([0, 1, 2, 3].map(|i| a[i] + b[i] - c[i]))
+}
+
+fn clamp_add_subtract_half(a: u32, b: u32) -> u32 {
+    let (a, b) = (channels(a), channels(b));
+    from_channels([0, 1, 2, 3].map(|i| a[i] + (a[i] - b[i]) / 2))
+}
+
+fn predict(mode: u32, l: u32, t: u32, tl: u32, tr: u32) -> u32 {
+    match mode {
+        1 => l,
+        2 => t,
+        3 => tr,
+        4 => tl,
+        5 => average2(average2(l, tr), t),
+        6 => average2(l, tl),
+        7 => average2(l, t),
+        8 => average2(tl, t),
+        9 => average2(t, tr),
+        10 => average2(average2(l, tl), average2(t, tr)),
+        11 => select(l, t, tl),
+        12 => clamp_add_subtract_full(l, t, tl),
+        13 => clamp_add_subtract_half(average2(l, t), tl),
+        _ => 0xff000000,
+    }
+}
+
+fn inverse_predictor(px: &mut [u32], w: usize, h: usize, bits: u32, modes: &[u32]) {
+    let bw = div_round_up(w, bits);
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let pred = match (x, y) {
+                (0, 0) => 0xff000000,
+                (_, 0) => px[i - 1],
+                (0, _) => px[i - w],
+                _ => {
+                    let mode = (modes[(y >> bits) * bw + (x >> bits)] >> 8) & 0xf;
+                    // For the last column, "top-right" is the first pixel of this row.
+                    predict(mode, px[i - 1], px[i - w], px[i - w - 1], px[i - w + 1])
+                }
+            };
+            px[i] = add_pixels(px[i], pred);
+        }
+    }
+}
+
+fn color_delta(t: u8, c: u8) -> i32 {
+    (t as i8 as i32 * c as i8 as i32) >> 5
+}
+
+fn inverse_color(px: &mut [u32], w: usize, bits: u32, data: &[u32]) {
+    let bw = div_round_up(w, bits);
+    for (i, p) in px.iter_mut().enumerate() {
+        let (x, y) = (i % w, i / w);
+        let m = data[(y >> bits) * bw + (x >> bits)];
+        let (g2r, g2b, r2b) = (m as u8, (m >> 8) as u8, (m >> 16) as u8);
+        let g = (*p >> 8) as u8;
+        let r = ((*p >> 16) as u8 as i32 + color_delta(g2r, g)) as u8;
+        let b = (*p as u8 as i32 + color_delta(g2b, g) + color_delta(r2b, r)) as u8;
+        *p = (*p & 0xff00ff00) | (r as u32) << 16 | b as u32;
+    }
+}
+
+fn inverse_color_indexing(px: &[u32], packed_w: usize, w: usize, h: usize, width_bits: u32, palette: &[u32]) -> Vec<u32> {
+    let bits_per_pixel = 8 >> width_bits;
+    let per = 1usize << width_bits;
+    let mask = (1u32 << bits_per_pixel) - 1;
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let packed = (px[y * packed_w + (x >> width_bits)] >> 8) & 0xff;
+            let index = (packed >> (bits_per_pixel * (x & (per - 1)) as u32)) & mask;
+            out.push(palette.get(index as usize).copied().unwrap_or(0));
+        }
+    }
+    out
+}
+
+// ---- Encoder ---------------------------------------------------------------------------------
+
+enum Token {
+    Literal(u32),
+    Copy { length: usize, dist_code: usize },
+}
+
+/// VP8L bitstream for `img`: palette-indexed if it has at most 256 colours, plain ARGB otherwise.
+fn encode_vp8l(img: &RgbImage) -> Result<Vec<u8>, WebpError> {
+    let (w, h) = img.dimensions();
+    if w > MAX_SIDE || h > MAX_SIDE {
+        return Err(corrupt(format!("size {w}x{h} exceeds {MAX_SIDE}")));
+    }
+    let mut bw = BitWriter::new();
+    bw.put(VP8L_SIGNATURE as u32, 8);
+    bw.put(w - 1, 14);
+    bw.put(h - 1, 14);
+    bw.put(0, 1);
+    bw.put(0, 3);
+
+    let mut index: HashMap<[u8; 3], u32> = HashMap::new();
+    let mut palette: Vec<u32> = Vec::new();
+    for px in img.pixels() {
+        if index.len() > 256 {
+            break;
+        }
+        index.entry(px.0).or_insert_with(|| {
+            palette.push(0xff000000 | (px[0] as u32) << 16 | (px[1] as u32) << 8 | px[2] as u32);
+            palette.len() as u32 - 1
+        });
+    }
+    let argb = |p: &Rgb<u8>| 0xff000000 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32;
+    if palette.len() <= 256 {
+        let width_bits = match palette.len() {
+            0..=2 => 3,
+            3..=4 => 2,
+            5..=16 => 1,
+            _ => 0,
+        };
+        let bits_per_pixel = 8 >> width_bits;
+        let packed_w = div_round_up(w as usize, width_bits);
+        let mut packed = vec![0xff000000u32; packed_w * h as usize];
+        for (x, y, p) in img.enumerate_pixels() {
+            let i = index[&p.0];
+            let slot = &mut packed[y as usize * packed_w + (x as usize >> width_bits)];
+            *slot |= i << (8 + bits_per_pixel * (x & ((1 << width_bits) - 1)));
+        }
+        bw.put(1, 1);
+        bw.put(3, 2);
+        bw.put(palette.len() as u32 - 1, 8);
+        let deltas: Vec<u32> = (0..palette.len())
+            .map(|i| if i == 0 { palette[0] } else { sub_pixels(palette[i], palette[i - 1]) })
+            .collect();
+        write_image_stream(&mut bw, &deltas, palette.len(), false);
+        bw.put(0, 1);
+        write_image_stream(&mut bw, &packed, packed_w, true);
+    } else {
+        bw.put(0, 1);
+        let pixels: Vec<u32> = img.pixels().map(argb).collect();
+        write_image_stream(&mut bw, &pixels, w as usize, true);
+    }
+    Ok(bw.finish())
+}
+
+fn sub_pixels(a: u32, b: u32) -> u32 {
+    let ag = (a | 0x00ff00ff).wrapping_sub(b & 0xff00ff00) & 0xff00ff00;
+    let rb = (a | 0xff00ff00).wrapping_sub(b & 0x00ff00ff) & 0x00ff00ff;
+    ag | rb
+}
+
+/// No colour cache, no meta codes: one group of five prefix codes over greedy LZ77 tokens.
+fn write_image_stream(bw: &mut BitWriter, pixels: &[u32], xsize: usize, main: bool) {
+    bw.put(0, 1);
+    if main {
+        bw.put(0, 1);
+    }
+    let tokens = lz77(pixels, xsize);
+    let mut freq = [
+        vec![0u32; 256 + NUM_LENGTH_CODES],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; NUM_DISTANCE_CODES],
+    ];
+    for t in &tokens {
+        match *t {
+            Token::Literal(p) => {
+                freq[0][(p >> 8 & 0xff) as usize] += 1;
+                freq[1][(p >> 16 & 0xff) as usize] += 1;
+                freq[2][(p & 0xff) as usize] += 1;
+                freq[3][(p >> 24) as usize] += 1;
+            }
+            Token::Copy { length, dist_code } => {
+                freq[0][256 + lz77_symbol(length).0 as usize] += 1;
+                freq[4][lz77_symbol(dist_code).0 as usize] += 1;
+            }
+        }
+    }
+    let codes: Vec<Vec<(u32, u32)>> = freq.iter().map(|f| write_prefix_code(bw, f)).collect();
+    let put = |bw: &mut BitWriter, code: (u32, u32)| bw.put(code.0, code.1);
+    for t in &tokens {
+        match *t {
+            Token::Literal(p) => {
+                put(bw, codes[0][(p >> 8 & 0xff) as usize]);
+                put(bw, codes[1][(p >> 16 & 0xff) as usize]);
+                put(bw, codes[2][(p & 0xff) as usize]);
+                put(bw, codes[3][(p >> 24) as usize]);
+            }
+            Token::Copy { length, dist_code } => {
+                let (sym, n, extra) = lz77_symbol(length);
+                put(bw, codes[0][256 + sym as usize]);
+                bw.put(extra, n);
+                let (sym, n, extra) = lz77_symbol(dist_code);
+                put(bw, codes[4][sym as usize]);
+                bw.put(extra, n);
+            }
+        }
+    }
+}
+
+/// Greedy copies from the previous pixel (distance code 2) or the row above (code 1).
+fn lz77(px: &[u32], xsize: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let match_len = |i: usize, dist: usize| {
+        let max = (px.len() - i).min(MAX_COPY);
+        (0..max).take_while(|&k| px[i + k] == px[i + k - dist]).count()
+    };
+    while i < px.len() {
+        let mut best = (0, 0);
+        if i >= xsize {
+            best = (match_len(i, xsize), 1);
+        }
+        if i >= 1 {
+            let run = match_len(i, 1);
+            if run > best.0 {
+                best = (run, 2);
+            }
+        }
+        if best.0 >= 3 {
+            tokens.push(Token::Copy {
+                length: best.0,
+                dist_code: best.1,
+            });
+            i += best.0;
+        } else {
+            tokens.push(Token::Literal(px[i]));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Write the code for `freq` and return each symbol's (reversed code, length) for writing.
+fn write_prefix_code(bw: &mut BitWriter, freq: &[u32]) -> Vec<(u32, u32)> {
+    let used: Vec<usize> = (0..freq.len()).filter(|&s| freq[s] > 0).collect();
+    let mut codes = vec![(0, 0); freq.len()];
+    if used.len() <= 1 {
+        // A single symbol (or none) takes no bits.
+        let s = used.first().copied().unwrap_or(0);
+        bw.put(1, 1);
+        bw.put(0, 1);
+        if s < 2 {
+            bw.put(0, 1);
+            bw.put(s as u32, 1);
+        } else {
+            bw.put(1, 1);
+            bw.put(s as u32, 8);
+        }
+        return codes;
+    }
+    if used.len() == 2 && used[1] < 256 {
+        bw.put(1, 1);
+        bw.put(1, 1);
+        if used[0] < 2 {
+            bw.put(0, 1);
+            bw.put(used[0] as u32, 1);
+        } else {
+            bw.put(1, 1);
+            bw.put(used[0] as u32, 8);
+        }
+        bw.put(used[1] as u32, 8);
+        codes[used[0]] = (0, 1);
+        codes[used[1]] = (1, 1);
+        return codes;
+    }
+    let lengths = code_lengths(freq, MAX_CODE_LENGTH);
+
+    // Code lengths as tokens: literal lengths, 17/18 for runs of zeros.
+    let mut tokens: Vec<(u32, u32, u32)> = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        if lengths[i] == 0 {
+            let run = lengths[i..].iter().take_while(|&&l| l == 0).count().min(138);
+            if run >= 11 {
+                tokens.push((18, 7, run as u32 - 11));
+                i += run;
+                continue;
+            }
+            if run >= 3 {
+                tokens.push((17, 3, run as u32 - 3));
+                i += run;
+                continue;
+            }
+        }
+        tokens.push((lengths[i] as u32, 0, 0));
+        i += 1;
+    }
+    let mut cl_freq = [0u32; CODE_LENGTH_CODES];
+    for t in &tokens {
+        cl_freq[t.0 as usize] += 1;
+    }
+    let cl_used = cl_freq.iter().filter(|&&f| f > 0).count();
+    let cl_lengths = if cl_used == 1 {
+        // One token kind: a single-symbol code still needs a length to be stored.
+        cl_freq.map(|f| u8::from(f > 0))
+    } else {
+        let v = code_lengths(&cl_freq, 7);
+        std::array::from_fn(|i| v[i])
+    };
+    let num = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&i| cl_lengths[i] > 0)
+        .map_or(4, |p| (p + 1).max(4));
+    bw.put(0, 1);
+    bw.put(num as u32 - 4, 4);
+    for &i in &CODE_LENGTH_ORDER[..num] {
+        bw.put(cl_lengths[i] as u32, 3);
+    }
+    bw.put(0, 1);
+    let cl_codes = if cl_used == 1 { vec![(0, 0); CODE_LENGTH_CODES] } else { canonical_codes(&cl_lengths) };
+    for (sym, n, extra) in tokens {
+        let (code, len) = cl_codes[sym as usize];
+        bw.put(code, len);
+        bw.put(extra, n);
+    }
+    for (s, c) in canonical_codes(&lengths).into_iter().enumerate() {
+        codes[s] = c;
+    }
+    codes
+}
+
+/// Canonical codes for `lengths`, bit-reversed so they can be written LSB first.
+fn canonical_codes(lengths: &[u8]) -> Vec<(u32, u32)> {
+    let mut count = [0u32; 16];
+    for &l in lengths {
+        count[l as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = [0u32; 16];
+    let mut code = 0;
+    for len in 1..16 {
+        code = (code + count[len - 1]) << 1;
+        next[len] = code;
+    }
+    lengths
+        .iter()
+        .map(|&l| {
+            if l == 0 {
+                return (0, 0);
+            }
+            let c = next[l as usize];
+            next[l as usize] += 1;
+            (c.reverse_bits() >> (32 - l as u32), l as u32)
+        })
+        .collect()
+}
+
+/// Huffman code lengths for `freq`, at most `max_len` bits; frequencies are flattened until the
+/// tree is shallow enough.
+fn code_lengths(freq: &[u32], max_len: u8) -> Vec<u8> {
+    let mut f: Vec<u32> = freq.to_vec();
+    loop {
+        let lengths = huffman_lengths(&f);
+        if lengths.iter().all(|&l| l <= max_len) {
+            return lengths;
+        }
+        for x in f.iter_mut().filter(|x| **x > 0) {
+            *x = (*x >> 1).max(1);
+        }
+    }
+}
+
+fn huffman_lengths(freq: &[u32]) -> Vec<u8> {
+    // Nodes 0..n are leaves; internal nodes get parents as they are merged.
+    let n = freq.len();
+    let mut parent = vec![usize::MAX; n];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> =
+        (0..n).filter(|&s| freq[s] > 0).map(|s| Reverse((freq[s] as u64, s))).collect();
+    while heap.len() > 1 {
+        let Reverse((wa, a)) = heap.pop().unwrap_or_default();
+        let Reverse((wb, b)) = heap.pop().unwrap_or_default();
+        let id = parent.len();
+        parent.push(usize::MAX);
+        parent[a] = id;
+        parent[b] = id;
+        heap.push(Reverse((wa + wb, id)));
+    }
+    (0..n)
+        .map(|s| {
+            if freq[s] == 0 {
+                return 0;
+            }
+            let (mut depth, mut node) = (0u8, s);
+            while parent[node] != usize::MAX {
+                node = parent[node];
+                depth = depth.saturating_add(1);
+            }
+            depth
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// `colors` distinct colours laid out left to right, top to bottom.
+    fn test_image(w: u32, h: u32, colors: u32) -> RgbImage {
+        RgbImage::from_fn(w, h, |x, y| {
+            let c = (y * w + x) % colors;
+            Rgb([(c * 37) as u8, (c * 11 + c / 256) as u8, (c / 7) as u8])
+        })
+    }
+
+    fn animation(frames: &[RgbImage], manifest: Option<&[u8]>) -> Vec<u8> {
+        let (w, h) = frames[0].dimensions();
+        let mut writer = WebpWriter::new(Cursor::new(Vec::new()), w, h, 40, manifest).unwrap();
+        for f in frames {
+            writer.write_frame(f).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn read_all(bytes: &[u8]) -> Result<Vec<RgbImage>, WebpError> {
+        WebpReader::new(bytes)?.collect()
+    }
+
+    /// Seeded xorshift, so a failing fuzz case replays.
+    fn rng(mut state: u64) -> impl FnMut() -> u64 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    #[test]
+    fn animation_round_trip() {
+        // 2, 8 and 256 colours exercise the packed palette widths; 1000 the plain ARGB path.
+        let frames = [test_image(37, 23, 2), test_image(37, 23, 8), test_image(37, 23, 256), test_image(37, 23, 1000)];
+        let bytes = animation(&frames, Some(b"{\"manifest\":1}"));
+        let reader = WebpReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.dimensions(), (37, 23));
+        assert_eq!(reader.manifest(), Some(&b"{\"manifest\":1}"[..]));
+        assert_eq!(read_all(&bytes).unwrap(), frames);
+    }
+
+    #[test]
+    fn still_round_trip() {
+        let img = test_image(64, 3, 5);
+        let bytes = encode_still(&img).unwrap();
+        let mut reader = WebpReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.manifest(), None);
+        assert_eq!(reader.read_frame().unwrap(), Some(img));
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_canvas_is_rejected() {
+        let mut bytes = b"RIFF\x16\0\0\0WEBPVP8X\x0a\0\0\0\x02\0\0\0".to_vec();
+        bytes.extend_from_slice(&[0xff; 6]);
+        assert!(matches!(WebpReader::new(&bytes[..]), Err(WebpError::Format(_))));
+    }
+
+    #[test]
+    fn chunk_larger_than_file_is_rejected() {
+        let mut bytes = encode_still(&test_image(8, 8, 2)).unwrap();
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[16..20].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert!(WebpReader::new(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn lossy_and_foreign_files_are_rejected() {
+        assert!(matches!(WebpReader::new(&b"RIFF\x04\0\0\0WAVE"[..]), Err(WebpError::NotWebp)));
+        assert!(matches!(WebpReader::new(&b"RIFF"[..]), Err(WebpError::NotWebp)));
+        let lossy = b"RIFF\x14\0\0\0WEBPVP8 \x08\0\0\0\0\0\0\0\0\0\0\0";
+        assert!(matches!(WebpReader::new(&lossy[..]), Err(WebpError::Unsupported(_))));
+    }
+
+    #[test]
+    fn truncated_or_corrupted_files_fail_cleanly() {
+        let bytes = animation(&[test_image(12, 9, 3), test_image(12, 9, 300)], Some(b"m"));
+        for len in 0..bytes.len() {
+            let _ = read_all(&bytes[..len]);
+        }
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut bad = bytes.clone();
+                bad[i] ^= flip;
+                let _ = read_all(&bad);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzzed_files_fail_cleanly() {
+        let bytes = animation(&[test_image(16, 11, 4), test_image(16, 11, 300), test_image(16, 11, 2)], Some(b"m"));
+        let mut next = rng(0x5eed_0047);
+        for _ in 0..4000 {
+            let mut bad = bytes.clone();
+            for _ in 0..1 + next() % 6 {
+                let i = (next() % bad.len() as u64) as usize;
+                match next() % 4 {
+                    0 => bad[i] = next() as u8,
+                    1 => bad.insert(i, next() as u8),
+                    2 => drop(bad.remove(i)),
+                    _ => {
+                        let run: Vec<u8> = bad[i..].iter().take(1 + (next() % 32) as usize).copied().collect();
+                        let j = (next() % bad.len() as u64) as usize;
+                        bad.splice(j..j, run);
+                    }
+                }
+            }
+            let _ = read_all(&bad);
+        }
+    }
+
+    #[test]
+    fn random_vp8l_bitstreams_fail_cleanly() {
+        // A valid 16x11 header, then noise for the transform, prefix-code and LZ77 readers.
+        let header = [VP8L_SIGNATURE, 15, 0x80, 0x02];
+        let mut next = rng(0x5eed_0b17);
+        for _ in 0..20_000 {
+            let mut data = header.to_vec();
+            data.extend((0..next() % 96).map(|_| next() as u8));
+            if let Ok(img) = decode_vp8l(&data) {
+                assert_eq!(img.pixels.len(), 16 * 11);
+            }
+        }
+    }
+}
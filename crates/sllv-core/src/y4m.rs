@@ -0,0 +1,486 @@
+//! YUV4MPEG2 (`.y4m`) video without ffmpeg: a text header, then uncompressed frames.
+//!
+//! Encode writes 8-bit 4:4:4 (`C444`, BT.601 limited range), which keeps every cell's colour
+//! close enough for nearest-palette decoding, and stores the manifest in the header as an
+//! `XSLLV=<hex JSON>` parameter so the video is a single self-describing file:
+//!
+//! ```text
+//! YUV4MPEG2 W<w> H<h> F<fps>:1 Ip A1:1 C444 XSLLV=<hex> \n
+//! FRAME\n | Y plane | Cb plane | Cr plane   (repeated)
+//! ```
+//!
+//! The reader also takes 4:2:2, 4:2:0 and mono streams (chroma is upsampled by repetition), so a
+//! video converted by another tool can be decoded too. Unknown parameters are ignored.
+
+use crate::frames::{DecodeStatus, FrameDecoder, FrameEncoder};
+use crate::manifest::EncodeManifest;
+use crate::raster::{ProgressMsg, RasterError, RasterParams};
+use image::RgbImage;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"YUV4MPEG2";
+const MANIFEST_TAG: &str = "XSLLV=";
+/// Longest header (or frame header) line accepted; the embedded manifest is a few KiB.
+const MAX_LINE: usize = 1 << 20;
+/// Largest frame side the reader accepts.
+const MAX_SIDE: u32 = 16384;
+
+#[derive(Debug, Error)]
+pub enum Y4mError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a YUV4MPEG2 stream")]
+    NotY4m,
+    #[error("header: {0}")]
+    Header(String),
+    #[error("unsupported colour space {0:?} (8-bit 444, 422, 420 or mono only)")]
+    Colorspace(String),
+    #[error("embedded manifest: {0}")]
+    Manifest(String),
+    #[error("frame {index}: {msg}")]
+    Frame { index: u64, msg: String },
+}
+
+/// Chroma layout of a stream, from its `C` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Chroma {
+    C444,
+    C422,
+    C420,
+    Mono,
+}
+
+impl Chroma {
+    fn parse(tag: &str) -> Result<Self, Y4mError> {
+        match tag {
+            "444" => Ok(Chroma::C444),
+            "422" => Ok(Chroma::C422),
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Ok(Chroma::C420),
+            "mono" => Ok(Chroma::Mono),
+            other => Err(Y4mError::Colorspace(other.into())),
+        }
+    }
+
+    /// Horizontal and vertical chroma subsampling shifts.
+    fn shifts(self) -> (u32, u32) {
+        match self {
+            Chroma::C444 | Chroma::Mono => (0, 0),
+            Chroma::C422 => (1, 0),
+            Chroma::C420 => (1, 1),
+        }
+    }
+}
+
+/// True if `path` starts with the YUV4MPEG2 signature.
+pub fn is_y4m(path: &Path) -> bool {
+    let mut head = [0u8; 9];
+    File::open(path).and_then(|mut f| f.read_exact(&mut head)).is_ok() && head == MAGIC
+}
+
+/// Writes RGB frames as a 4:4:4 YUV4MPEG2 stream.
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+    frames: u64,
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Write the stream header; `manifest` is embedded as `XSLLV=<hex JSON>` when given.
+    pub fn new(mut out: W, width: u32, height: u32, fps: u32, manifest: Option<&EncodeManifest>) -> Result<Self, Y4mError> {
+        if width == 0 || height == 0 {
+            return Err(Y4mError::Header(format!("invalid size {width}x{height}")));
+        }
+        let mut header = format!("YUV4MPEG2 W{width} H{height} F{}:1 Ip A1:1 C444", fps.max(1));
+        if let Some(m) = manifest {
+            let json = serde_json::to_vec(m).map_err(|e| Y4mError::Manifest(e.to_string()))?;
+            header.push(' ');
+            header.push_str(MANIFEST_TAG);
+            header.push_str(&hex::encode(json));
+        }
+        header.push('\n');
+        out.write_all(header.as_bytes())?;
+        let plane = width as usize * height as usize;
+        Ok(Self {
+            out,
+            width,
+            height,
+            frames: 0,
+            planes: vec![0u8; plane * 3],
+        })
+    }
+
+    pub fn write_frame(&mut self, img: &RgbImage) -> Result<(), Y4mError> {
+        if img.dimensions() != (self.width, self.height) {
+            return Err(Y4mError::Frame {
+                index: self.frames,
+                msg: format!("{}x{}, expected {}x{}", img.width(), img.height(), self.width, self.height),
+            });
+        }
+        let plane = self.width as usize * self.height as usize;
+        let (y, rest) = self.planes.split_at_mut(plane);
+        let (cb, cr) = rest.split_at_mut(plane);
+        for (i, px) in img.pixels().enumerate() {
+            (y[i], cb[i], cr[i]) = rgb_to_ycbcr(px[0], px[1], px[2]);
+        }
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, Y4mError> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads a YUV4MPEG2 stream back as RGB frames.
+pub struct Y4mReader<R: Read> {
+    input: BufReader<R>,
+    width: u32,
+    height: u32,
+    fps: (u32, u32),
+    chroma: Chroma,
+    manifest: Option<EncodeManifest>,
+    read: u64,
+    planes: Vec<u8>,
+}
+
+impl Y4mReader<File> {
+    pub fn open(path: &Path) -> Result<Self, Y4mError> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Parse the stream header.
+    pub fn new(input: R) -> Result<Self, Y4mError> {
+        let mut input = BufReader::new(input);
+        let line = read_line(&mut input)?.ok_or(Y4mError::NotY4m)?;
+        let mut fields = line.split(' ').filter(|f| !f.is_empty());
+        if fields.next().map(str::as_bytes) != Some(MAGIC) {
+            return Err(Y4mError::NotY4m);
+        }
+        let (mut width, mut height) = (0u32, 0u32);
+        let mut fps = (25, 1);
+        // No `C` parameter means 4:2:0 in the YUV4MPEG2 spec.
+        let mut chroma = Chroma::C420;
+        let mut manifest = None;
+        for field in fields {
+            let Some(tag) = field.get(..1) else {
+                continue;
+            };
+            let value = &field[1..];
+            match tag {
+                "W" => width = parse_num(field, value)?,
+                "H" => height = parse_num(field, value)?,
+                "F" => {
+                    if let Some((n, d)) = value.split_once(':') {
+                        fps = (parse_num(field, n)?, parse_num(field, d)?);
+                    }
+                }
+                "C" => chroma = Chroma::parse(value)?,
+                "X" => {
+                    if let Some(hex_json) = field.strip_prefix(MANIFEST_TAG) {
+                        let json = hex::decode(hex_json).map_err(|e| Y4mError::Manifest(e.to_string()))?;
+                        let m: EncodeManifest =
+                            serde_json::from_slice(&json).map_err(|e| Y4mError::Manifest(e.to_string()))?;
+                        manifest = Some(m);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+            return Err(Y4mError::Header(format!("missing or invalid size {width}x{height} (1..={MAX_SIDE})")));
+        }
+        let (sx, sy) = chroma.shifts();
+        let luma = (width as usize).checked_mul(height as usize);
+        let chroma_plane = if chroma == Chroma::Mono {
+            Some(0)
+        } else {
+            (width.div_ceil(1 << sx) as usize).checked_mul(height.div_ceil(1 << sy) as usize)
+        };
+        let frame_bytes = luma
+            .zip(chroma_plane)
+            .and_then(|(l, c)| c.checked_mul(2)?.checked_add(l))
+            .ok_or_else(|| Y4mError::Header(format!("frame size {width}x{height} overflows")))?;
+        Ok(Self {
+            input,
+            width,
+            height,
+            fps,
+            chroma,
+            manifest,
+            read: 0,
+            planes: vec![0u8; frame_bytes],
+        })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frame rate as numerator and denominator.
+    pub fn fps(&self) -> (u32, u32) {
+        self.fps
+    }
+
+    /// ffmpeg name of the stream's pixel format (`yuv444p`, `yuv422p`, `yuv420p` or `gray`).
+    pub fn pix_fmt(&self) -> &'static str {
+        match self.chroma {
+            Chroma::C444 => "yuv444p",
+            Chroma::C422 => "yuv422p",
+            Chroma::C420 => "yuv420p",
+            Chroma::Mono => "gray",
+        }
+    }
+
+    /// The manifest embedded by [`Y4mWriter`], if any.
+    pub fn manifest(&self) -> Option<&EncodeManifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Frames returned so far.
+    pub fn frames_read(&self) -> u64 {
+        self.read
+    }
+
+    /// The next frame, or `None` at the end of the stream.
+    pub fn read_frame(&mut self) -> Result<Option<RgbImage>, Y4mError> {
+        let index = self.read;
+        let Some(line) = read_line(&mut self.input)? else {
+            return Ok(None);
+        };
+        if !line.starts_with("FRAME") {
+            return Err(Y4mError::Frame {
+                index,
+                msg: "missing FRAME marker".into(),
+            });
+        }
+        self.input.read_exact(&mut self.planes).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Y4mError::Frame {
+                index,
+                msg: "stream ends inside the frame".into(),
+            },
+            _ => Y4mError::Io(e),
+        })?;
+        self.read += 1;
+        Ok(Some(self.to_rgb()))
+    }
+
+    fn to_rgb(&self) -> RgbImage {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (sx, sy) = self.chroma.shifts();
+        let cw = self.width.div_ceil(1 << sx) as usize;
+        let (y, rest) = self.planes.split_at(w * h);
+        let (cb, cr) = rest.split_at(rest.len() / 2);
+        let mut rgb = Vec::with_capacity(w * h * 3);
+        for row in 0..h {
+            for col in 0..w {
+                let (u, v) = if self.chroma == Chroma::Mono {
+                    (128, 128)
+                } else {
+                    let ci = (row >> sy) * cw + (col >> sx);
+                    (cb[ci], cr[ci])
+                };
+                let (r, g, b) = ycbcr_to_rgb(y[row * w + col], u, v);
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+        RgbImage::from_raw(self.width, self.height, rgb).unwrap_or_default()
+    }
+}
+
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = Result<RgbImage, Y4mError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Encode `input_bytes` to a single `.y4m` file with the manifest in its header; no ffmpeg needed.
+pub fn encode_bytes_to_y4m(
+    input_bytes: &[u8],
+    file_name: &str,
+    out_y4m: &Path,
+    fps: u32,
+    p: &RasterParams,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<EncodeManifest, RasterError> {
+    let encoder = FrameEncoder::new(input_bytes, file_name, p)?;
+    let (w, h) = encoder.frame(0)?.dimensions();
+    let out = BufWriter::new(File::create(out_y4m)?);
+    let mut writer = Y4mWriter::new(out, w, h, fps, Some(encoder.manifest()))?;
+    let total = encoder.total_frames() as u64;
+    encoder.render_in_order(|i, img| -> Result<(), RasterError> {
+        writer.write_frame(&img)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "encode".into(),
+                done: i as u64 + 1,
+                total,
+            });
+        }
+        Ok(())
+    })?;
+    writer.finish()?;
+    Ok(encoder.manifest().clone())
+}
+
+/// Decode a `.y4m` file into `out`.
+///
+/// `m` overrides the manifest embedded in the header; with neither, only FEC archives decode.
+pub fn decode_y4m_to_writer<W: Write>(
+    path: &Path,
+    m: Option<&EncodeManifest>,
+    p: &RasterParams,
+    out: W,
+    progress_tx: Option<mpsc::Sender<ProgressMsg>>,
+) -> Result<W, RasterError> {
+    let mut reader = Y4mReader::open(path)?;
+    let manifest = m.or(reader.manifest()).cloned();
+    let total = manifest.as_ref().map(|m| m.frames as u64).unwrap_or_default();
+    let mut decoder = FrameDecoder::with_writer(p, out);
+    if let Some(m) = manifest {
+        decoder = decoder.with_manifest(m);
+    }
+    while let Some(frame) = reader.read_frame()? {
+        let status = decoder.push_frame(&frame)?;
+        if let Some(ref tx) = progress_tx {
+            let _ = tx.send(ProgressMsg::Stage {
+                name: "decode".into(),
+                done: reader.frames_read(),
+                total,
+            });
+        }
+        if status == DecodeStatus::Complete {
+            break;
+        }
+    }
+    decoder.finish()
+}
+
+/// One header line without its newline; `None` at a clean end of stream.
+fn read_line<R: BufRead>(input: &mut R) -> Result<Option<String>, Y4mError> {
+    let mut line = Vec::new();
+    input.by_ref().take(MAX_LINE as u64).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(Y4mError::Header("unterminated or overlong header line".into()));
+    }
+    String::from_utf8(line).map(Some).map_err(|_| Y4mError::Header("header is not UTF-8".into()))
+}
+
+fn parse_num(field: &str, value: &str) -> Result<u32, Y4mError> {
+    value.parse().map_err(|_| Y4mError::Header(format!("bad parameter {field:?}")))
+}
+
+/// BT.601 limited range, integer approximation.
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let cb = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let cr = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (y as u8, cb as u8, cr as u8)
+}
+
+fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> (u8, u8, u8) {
+    let c = 298 * (y as i32 - 16);
+    let d = cb as i32 - 128;
+    let e = cr as i32 - 128;
+    let clamp = |v: i32| ((v + 128) >> 8).clamp(0, 255) as u8;
+    (clamp(c + 409 * e), clamp(c - 100 * d - 208 * e), clamp(c + 516 * d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::PayloadKind;
+    use crate::profile::Profile;
+
+    fn small_params() -> RasterParams {
+        RasterParams {
+            grid_w: 48,
+            grid_h: 40,
+            sync_frames: 2,
+            payload: PayloadKind::Raw,
+            ..Profile::Archive.defaults()
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sllv_y4m_roundtrip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.y4m");
+        let p = small_params();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 13 % 256) as u8).collect();
+        let m = encode_bytes_to_y4m(&data, "data.bin", &path, 30, &p, None).unwrap();
+        assert!(is_y4m(&path));
+
+        let reader = Y4mReader::open(&path).unwrap();
+        assert_eq!(reader.fps(), (30, 1));
+        assert_eq!(reader.pix_fmt(), "yuv444p");
+        assert_eq!(reader.manifest().unwrap().sha256_hex, m.sha256_hex);
+        assert_eq!(reader.count(), m.frames as usize);
+
+        assert_eq!(decode_y4m_to_writer(&path, None, &p, Vec::new(), None).unwrap(), data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn subsampled_chroma_is_upsampled() {
+        // 4x2 4:2:0: two chroma samples per plane, each covering a 2x2 block.
+        let mut stream = b"YUV4MPEG2 W4 H2 F25:1 C420jpeg\nFRAME\n".to_vec();
+        stream.extend_from_slice(&[235; 8]);
+        stream.extend_from_slice(&[128, 240]);
+        stream.extend_from_slice(&[128, 128]);
+        let mut reader = Y4mReader::new(&stream[..]).unwrap();
+        assert_eq!(reader.pix_fmt(), "yuv420p");
+        let img = reader.read_frame().unwrap().unwrap();
+        assert_eq!(img.get_pixel(1, 1).0, [255, 255, 255]);
+        assert!(img.get_pixel(2, 0)[2] == 255 && img.get_pixel(2, 0)[1] < 240);
+        assert_eq!(img.get_pixel(3, 1), img.get_pixel(2, 0));
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let header = |h: &str| Y4mReader::new(h.as_bytes()).err();
+        assert!(matches!(header("RIFF1234\n"), Some(Y4mError::NotY4m)));
+        assert!(matches!(header(""), Some(Y4mError::NotY4m)));
+        assert!(matches!(header("YUV4MPEG2 W4294967295 H4294967295\n"), Some(Y4mError::Header(_))));
+        assert!(matches!(header("YUV4MPEG2 W16385 H1\n"), Some(Y4mError::Header(_))));
+        assert!(matches!(header("YUV4MPEG2 W0 H8\n"), Some(Y4mError::Header(_))));
+        assert!(matches!(header("YUV4MPEG2 Wx H8\n"), Some(Y4mError::Header(_))));
+        assert!(matches!(header("YUV4MPEG2 W8 H8 C444p10\n"), Some(Y4mError::Colorspace(_))));
+        assert!(matches!(header("YUV4MPEG2 W8 H8 XSLLV=zz\n"), Some(Y4mError::Manifest(_))));
+        assert!(matches!(header("YUV4MPEG2 W8 H8"), Some(Y4mError::Header(_))));
+        assert!(header("YUV4MPEG2 W8 H8 \u{e9}t\u{e9}\n").is_none());
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W2 H2 Cmono\nFRAMX\n\0\0\0\0"[..]).unwrap();
+        assert!(matches!(reader.read_frame(), Err(Y4mError::Frame { index: 0, .. })));
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W2 H2 Cmono\nFRAME\n\0\0\0\0FRAME\n\0"[..]).unwrap();
+        assert!(reader.read_frame().unwrap().is_some());
+        assert!(matches!(reader.read_frame(), Err(Y4mError::Frame { index: 1, .. })));
+    }
+}
@@ -1,16 +1,38 @@
-use std::ffi::{CStr, CString};
+//! Simple C ABI surface for Android/JNI to call into.
+//!
+//! Increment 3a starts with minimal, stable functions.
+//! Later increments will add progress callbacks and video decode entry points.
+//!
+//! Every path and passphrase argument is a C string: unless a function says it may be null, it
+//! must point to a valid NUL-terminated string that stays alive for the duration of the call.
+
+use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
 use std::path::PathBuf;
 
-/// Simple C ABI surface for Android/JNI to call into.
+/// # Safety
 ///
-/// Increment 3a starts with minimal, stable functions.
-/// Later increments will add progress callbacks and video decode entry points.
+/// `input_path` and `out_dir` must be valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn sllv_pack_and_encode_to_frames(
+    input_path: *const c_char,
+    out_dir: *const c_char,
+) -> c_int {
+    sllv_pack_and_encode_to_frames_with_passphrase(input_path, out_dir, std::ptr::null())
+}
 
+/// Like `sllv_pack_and_encode_to_frames`, encrypting the payload when `passphrase` is non-null
+/// and non-empty.
+///
+/// # Safety
+///
+/// `input_path` and `out_dir` must be valid C strings; `passphrase` must be null or a valid C
+/// string.
 #[no_mangle]
-pub extern "C" fn sllv_pack_and_encode_to_frames(
+pub unsafe extern "C" fn sllv_pack_and_encode_to_frames_with_passphrase(
     input_path: *const c_char,
     out_dir: *const c_char,
+    passphrase: *const c_char,
 ) -> c_int {
     let res: anyhow::Result<()> = (|| {
         let input = unsafe { CStr::from_ptr(input_path) }.to_string_lossy().to_string();
@@ -19,7 +41,10 @@ pub extern "C" fn sllv_pack_and_encode_to_frames(
         let out_dir = PathBuf::from(out);
 
         let (tar_bytes, name) = sllv_core::pack_path_to_tar_bytes(&input)?;
-        let p = sllv_core::RasterParams::default();
+        let p = sllv_core::RasterParams {
+            encryption: opt_passphrase(passphrase).map(sllv_core::EncryptionKey::Passphrase),
+            ..Default::default()
+        };
         sllv_core::encode_bytes_to_frames_dir(&tar_bytes, &format!("{}.tar", name), &out_dir, &p)?;
         Ok(())
     })();
@@ -30,25 +55,117 @@ pub extern "C" fn sllv_pack_and_encode_to_frames(
     }
 }
 
+/// # Safety
+///
+/// `in_dir` and `output_tar` must be valid C strings.
 #[no_mangle]
-pub extern "C" fn sllv_decode_frames_to_tar(in_dir: *const c_char, output_tar: *const c_char) -> c_int {
-    let res: anyhow::Result<()> = (|| {
+pub unsafe extern "C" fn sllv_decode_frames_to_tar(in_dir: *const c_char, output_tar: *const c_char) -> c_int {
+    sllv_decode_frames_to_tar_with_passphrase(in_dir, output_tar, std::ptr::null())
+}
+
+/// Decode an archive encrypted with a passphrase (null = not encrypted).
+///
+/// Returns 0 on success, 2 when the archive needs a passphrase or the passphrase is wrong,
+/// 1 for any other failure.
+///
+/// # Safety
+///
+/// `in_dir` and `output_tar` must be valid C strings; `passphrase` must be null or a valid C
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn sllv_decode_frames_to_tar_with_passphrase(
+    in_dir: *const c_char,
+    output_tar: *const c_char,
+    passphrase: *const c_char,
+) -> c_int {
+    let res: Result<(), sllv_core::RasterError> = (|| {
         let in_dir = unsafe { CStr::from_ptr(in_dir) }.to_string_lossy().to_string();
         let output_tar = unsafe { CStr::from_ptr(output_tar) }.to_string_lossy().to_string();
         let in_dir = PathBuf::from(in_dir);
         let output_tar = PathBuf::from(output_tar);
 
-        let bytes = sllv_core::decode_frames_dir_to_bytes(&in_dir)?;
-        std::fs::write(output_tar, bytes)?;
+        let mut out = sllv_core::PartialFile::create(&output_tar)?;
+        let p = sllv_core::RasterParams {
+            decryption: opt_passphrase(passphrase).map(sllv_core::DecryptionKey::Passphrase),
+            ..Default::default()
+        };
+        sllv_core::decode_frames_dir_to_writer(&in_dir, &p, &mut out)?;
+        out.commit()?;
         Ok(())
     })();
 
     match res {
         Ok(_) => 0,
+        Err(sllv_core::RasterError::Crypto(_)) => 2,
         Err(_) => 1,
     }
 }
 
+/// Decode and extract straight into `out_dir` instead of writing a tar.
+///
+/// `on_conflict`: 0 = skip existing files, 1 = overwrite, 2 = rename (`name (1).ext`).
+/// Returns 0 on success, 2 for a missing/wrong passphrase, 3 when the archive contains paths
+/// that would escape `out_dir` (nothing is written), 1 for any other failure.
+///
+/// # Safety
+///
+/// `in_dir` and `out_dir` must be valid C strings; `passphrase` must be null or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn sllv_decode_frames_to_dir(
+    in_dir: *const c_char,
+    out_dir: *const c_char,
+    passphrase: *const c_char,
+    on_conflict: c_int,
+) -> c_int {
+    let res: anyhow::Result<()> = (|| {
+        let in_dir = unsafe { CStr::from_ptr(in_dir) }.to_string_lossy().to_string();
+        let out_dir = unsafe { CStr::from_ptr(out_dir) }.to_string_lossy().to_string();
+
+        let p = sllv_core::RasterParams {
+            decryption: opt_passphrase(passphrase).map(sllv_core::DecryptionKey::Passphrase),
+            ..Default::default()
+        };
+        let in_dir = PathBuf::from(in_dir);
+        let manifest = sllv_core::raster::read_manifest(&in_dir)?;
+        let out_dir = PathBuf::from(out_dir);
+        let mut scratch = sllv_core::PartialFile::scratch_in(&out_dir)?;
+        sllv_core::decode_frames_dir_to_writer(&in_dir, &p, &mut scratch)?;
+
+        let on_collision = match on_conflict {
+            1 => sllv_core::CollisionPolicy::Overwrite,
+            2 => sllv_core::CollisionPolicy::Rename,
+            _ => sllv_core::CollisionPolicy::Skip,
+        };
+        let opts = sllv_core::ExtractOptions { on_collision };
+        sllv_core::extract_payload_to_dir(scratch.reopen()?, &manifest, &out_dir, &opts)?;
+        Ok(())
+    })();
+
+    match res {
+        Ok(_) => 0,
+        Err(e) => match (e.downcast_ref::<sllv_core::RasterError>(), e.downcast_ref::<sllv_core::ExtractError>()) {
+            (Some(sllv_core::RasterError::Crypto(_)), _) => 2,
+            (_, Some(sllv_core::ExtractError::UnsafePath(_) | sllv_core::ExtractError::SymlinkEscape { .. })) => 3,
+            _ => 1,
+        },
+    }
+}
+
+/// # Safety
+///
+/// `passphrase` must be null or a valid C string.
+unsafe fn opt_passphrase(passphrase: *const c_char) -> Option<String> {
+    if passphrase.is_null() {
+        return None;
+    }
+    let s = unsafe { CStr::from_ptr(passphrase) }.to_string_lossy().to_string();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
 // Simple last-error API (placeholder). Real error strings will be added next.
 #[no_mangle]
 pub extern "C" fn sllv_last_error_message() -> *const c_char {
@@ -5,12 +5,27 @@ use std::time::Instant;
 #[derive(Clone, Debug)]
 pub struct EncodeJob {
     pub input: Option<PathBuf>,
+    /// More files/folders packed next to `input`, each as its own top-level entry.
+    pub extra_inputs: Vec<PathBuf>,
     pub out_frames: Option<PathBuf>,
     pub out_mkv: Option<PathBuf>,
-    pub fps: u32,
+    /// Codec, container, FPS and playback timing for `out_mkv`.
+    pub video: sllv_core::VideoExportParams,
     pub profile: sllv_core::Profile,
     pub ffmpeg_path: Option<PathBuf>,
     pub rp: sllv_core::RasterParams,
+    /// Empty = no passphrase.
+    pub passphrase: String,
+    /// X25519 public key (hex); empty = no recipient.
+    pub recipient: String,
+    /// Ed25519 secret key file written by `sllv keygen --signing`.
+    pub sign_key: Option<PathBuf>,
+    pub pack: sllv_core::PackOptions,
+    /// Comma-separated globs for `pack.filter.include` / `pack.filter.exclude`.
+    pub include: String,
+    pub exclude: String,
+    /// 0 = no limit.
+    pub max_file_mib: u64,
 }
 
 impl Default for EncodeJob {
@@ -19,12 +34,20 @@ impl Default for EncodeJob {
         let rp = profile.defaults();
         Self {
             input: None,
+            extra_inputs: Vec::new(),
             out_frames: None,
             out_mkv: None,
-            fps: 24,
+            video: sllv_core::VideoExportParams::default(),
             profile,
             ffmpeg_path: None,
             rp,
+            passphrase: String::new(),
+            recipient: String::new(),
+            sign_key: None,
+            pack: sllv_core::PackOptions::default(),
+            include: String::new(),
+            exclude: String::new(),
+            max_file_mib: 0,
         }
     }
 }
@@ -34,9 +57,17 @@ pub struct DecodeJob {
     pub input_frames: Option<PathBuf>,
     pub input_mkv: Option<PathBuf>,
     pub out_tar: Option<PathBuf>,
+    /// Extract into this folder instead of writing `out_tar`.
+    pub out_dir: Option<PathBuf>,
+    pub on_collision: sllv_core::CollisionPolicy,
     pub profile: sllv_core::Profile,
     pub ffmpeg_path: Option<PathBuf>,
     pub rp: sllv_core::RasterParams,
+    pub passphrase: String,
+    /// Secret key file written by `sllv keygen`.
+    pub identity: Option<PathBuf>,
+    /// Trusted Ed25519 public key (hex) to check the signature against.
+    pub trusted_pubkey: String,
 }
 
 impl Default for DecodeJob {
@@ -47,9 +78,14 @@ impl Default for DecodeJob {
             input_frames: None,
             input_mkv: None,
             out_tar: None,
+            out_dir: None,
+            on_collision: sllv_core::CollisionPolicy::Skip,
             profile,
             ffmpeg_path: None,
             rp,
+            passphrase: String::new(),
+            identity: None,
+            trusted_pubkey: String::new(),
         }
     }
 }
@@ -17,6 +17,13 @@ pub enum HelpTopic {
     Fec,
     Ffmpeg,
     Fps,
+    VideoExport,
+    Compression,
+    Encryption,
+    Signing,
+    Extract,
+    Packing,
+    Filter,
 }
 
 impl HelpTopic {
@@ -30,19 +37,33 @@ impl HelpTopic {
             HelpTopic::Fec => "Error correction (FEC)",
             HelpTopic::Ffmpeg => "FFmpeg path",
             HelpTopic::Fps => "FPS",
+            HelpTopic::VideoExport => "Video export",
+            HelpTopic::Compression => "Compression",
+            HelpTopic::Encryption => "Encryption",
+            HelpTopic::Signing => "Signature",
+            HelpTopic::Extract => "Extract to folder",
+            HelpTopic::Packing => "Packing",
+            HelpTopic::Filter => "Filter",
         }
     }
 
     pub fn body(&self) -> &'static str {
         match self {
-            HelpTopic::Profile => "Choose Archive for clean, exact frames and optional lossless MKV output. Choose Scan for phone/camera capture (bigger cells + redundancy).",
+            HelpTopic::Profile => "Choose Archive for clean, exact frames and optional lossless MKV output. Choose Scan for phone/camera capture (bigger cells + redundancy). Choose Video for uploads to video sites that re-encode to H.264/H.265/AV1: 1080p frames with large, block-aligned cells (pick a lossy codec under Video / FFmpeg).",
             HelpTopic::CellPx => "How many screen pixels each data cell uses. Larger values are easier for cameras but produce bigger frames. Keep this consistent between encode and decode.",
             HelpTopic::BorderCells => "Padding around the grid. Helps decoding by giving the detector room to find the content.",
             HelpTopic::FiducialSize => "Size of the corner markers used for locating the frame. Larger can improve camera robustness but increases overhead.",
             HelpTopic::Deskew => "If enabled, the decoder will try to correct perspective/rotation. Recommended for Scan (phone capture).",
             HelpTopic::Fec => "Forward error correction helps recover data when frames are missing or damaged. Recommended for Scan. Avoid changing FEC settings after encoding.",
             HelpTopic::Ffmpeg => "Only needed when you create or decode MKV. If ffmpeg isn't on PATH, select the ffmpeg.exe location here.",
-            HelpTopic::Fps => "Frames-per-second used only when writing MKV from images. Does not affect decoding from frames.",
+            HelpTopic::Fps => "Frames-per-second used only when writing a video from images. Does not affect decoding from frames.",
+            HelpTopic::VideoExport => "FFV1, x264rgb and VP9 lossless keep every pixel and suit Archive and Scan. libx264, libx265 and AV1 are lossy and only survive with the Video profile; CRF sets their quality (lower is better). Not every container takes every codec. Repeat shows each frame several times and lead-in/lead-out add seconds of solid colour, so a camera filming the playback catches every frame; repeat needs a profile with FEC.",
+            HelpTopic::Compression => "Compresses the packed input before it is turned into frames, so text-heavy folders need far fewer frames. zstd is fast; xz is slower but usually smaller. Decode detects this automatically. Deduplicate stores repeated content (copies of the same file, VM images, backups) only once, before compressing.",
+            HelpTopic::Encryption => "Encrypts the payload (ChaCha20-Poly1305) so the frames reveal nothing without the key. Use a passphrase, or a recipient public key from `sllv keygen` so only the matching secret key file can decode. Leave both empty to skip encryption. A wrong key fails with a clear error instead of producing garbage.",
+            HelpTopic::Signing => "Signs the manifest (which includes the payload SHA-256) with an Ed25519 key from `sllv keygen --signing`, proving who made the archive. On decode, paste the signer's public key to check the archive was signed by someone you trust; the result is shown in the log.",
+            HelpTopic::Packing => "Controls how files are recorded before encoding. Empty folders, symlinks, modification times and permissions are always kept. Follow symlinks stores the files they point to instead. Reproducible sorts entries and fixes owner and timestamps so the same input always gives identical frames (unless encrypted).",
+            HelpTopic::Filter => "Choose which files of a folder are packed. Globs are comma-separated and match the path inside the folder (`src/**`) or just the file name (`*.log`). Include keeps only matching files; exclude drops matching files and folders. Ignore files honors .gitignore and .sllvignore and skips .git. Files above the size limit (MiB, 0 = none) are skipped. Skipped entries are listed in the log and the filter is recorded in the manifest.",
+            HelpTopic::Extract => "Unpacks the recovered files straight into a folder instead of writing a .tar. The archive is fully verified first, and entries that would land outside the folder (absolute paths, `..`, escaping symlinks) are rejected. Choose what happens when a file already exists: skip it, overwrite it, or save the new one as `name (1).ext`. List contents shows what is in the archive by reading only its table-of-contents frames, so it is quick even for long archives (encrypted ones need the key).",
         }
     }
 }
@@ -28,6 +28,10 @@ impl eframe::App for AppState {
                         }
                         ctx.request_repaint();
                     }
+                    sllv_core::raster::ProgressMsg::Info(line) => {
+                        self.log.push_str(&format!("{line}\n"));
+                        ctx.request_repaint();
+                    }
                     sllv_core::raster::ProgressMsg::Done => {
                         self.is_running = false;
                         self.progress = None;
@@ -107,30 +111,35 @@ impl eframe::App for AppState {
     }
 }
 
+fn profile_label(p: sllv_core::Profile) -> &'static str {
+    match p {
+        sllv_core::Profile::Archive => "Archive",
+        sllv_core::Profile::Scan => "Scan",
+        sllv_core::Profile::Video => "Video (lossy)",
+        sllv_core::Profile::Print => "Print (paper)",
+    }
+}
+
 fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
     ui.heading("Encode");
 
     ui.horizontal(|ui| {
         ui.label("Profile");
         help_button(ui, state, HelpTopic::Profile);
-        let mut p = match state.encode.profile {
-            sllv_core::Profile::Archive => 0,
-            sllv_core::Profile::Scan => 1,
-        };
-        egui::ComboBox::from_id_source("encode_profile")
-            .selected_text(if p == 0 { "Archive" } else { "Scan" })
+        let mut new_profile = state.encode.profile;
+        egui::ComboBox::from_id_salt("encode_profile")
+            .selected_text(profile_label(new_profile))
             .show_ui(ui, |ui| {
-                ui.selectable_value(&mut p, 0, "Archive");
-                ui.selectable_value(&mut p, 1, "Scan");
+                for p in sllv_core::Profile::ALL {
+                    ui.selectable_value(&mut new_profile, p, profile_label(p));
+                }
             });
-        let new_profile = if p == 0 {
-            sllv_core::Profile::Archive
-        } else {
-            sllv_core::Profile::Scan
-        };
         if new_profile.name() != state.encode.profile.name() {
+            let (compression, dedup) = (state.encode.rp.compression, state.encode.rp.dedup);
             state.encode.profile = new_profile;
             state.encode.rp = new_profile.defaults();
+            state.encode.rp.compression = compression;
+            state.encode.rp.dedup = dedup;
         }
     });
 
@@ -157,6 +166,26 @@ fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
             }
         }
     });
+    if state.encode.input.is_some() {
+        for extra in &state.encode.extra_inputs {
+            ui.label(format!("  + {}", extra.display()));
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add files").clicked() {
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    state.encode.extra_inputs.extend(paths);
+                }
+            }
+            if ui.button("Add folder").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                    state.encode.extra_inputs.push(path);
+                }
+            }
+            if !state.encode.extra_inputs.is_empty() && ui.button("Clear added").clicked() {
+                state.encode.extra_inputs.clear();
+            }
+        });
+    }
 
     ui.label(format!(
         "Output frames dir: {}",
@@ -179,17 +208,17 @@ fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
         ui.horizontal(|ui| {
             ui.label("Cell size (px)");
             help_button(ui, state, HelpTopic::CellPx);
-            ui.add(egui::DragValue::new(&mut state.encode.rp.cell_px).clamp_range(1..=32));
+            ui.add(egui::DragValue::new(&mut state.encode.rp.cell_px).range(1..=32));
         });
         ui.horizontal(|ui| {
             ui.label("Border cells");
             help_button(ui, state, HelpTopic::BorderCells);
-            ui.add(egui::DragValue::new(&mut state.encode.rp.border_cells).clamp_range(0..=64));
+            ui.add(egui::DragValue::new(&mut state.encode.rp.border_cells).range(0..=64));
         });
         ui.horizontal(|ui| {
             ui.label("Fiducial size (cells)");
             help_button(ui, state, HelpTopic::FiducialSize);
-            ui.add(egui::DragValue::new(&mut state.encode.rp.fiducial_size_cells).clamp_range(4..=64));
+            ui.add(egui::DragValue::new(&mut state.encode.rp.fiducial_size_cells).range(4..=64));
         });
         ui.horizontal(|ui| {
             ui.label("Deskew");
@@ -208,15 +237,15 @@ fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
             if let Some(ref mut fec) = state.encode.rp.fec {
                 ui.horizontal(|ui| {
                     ui.label("Data shards");
-                    ui.add(egui::DragValue::new(&mut fec.data_shards).clamp_range(1..=64));
+                    ui.add(egui::DragValue::new(&mut fec.data_shards).range(1..=64));
                 });
                 ui.horizontal(|ui| {
                     ui.label("Parity shards");
-                    ui.add(egui::DragValue::new(&mut fec.parity_shards).clamp_range(0..=64));
+                    ui.add(egui::DragValue::new(&mut fec.parity_shards).range(0..=64));
                 });
                 ui.horizontal(|ui| {
                     ui.label("Shard bytes");
-                    ui.add(egui::DragValue::new(&mut fec.shard_bytes).clamp_range(64..=4096));
+                    ui.add(egui::DragValue::new(&mut fec.shard_bytes).range(64..=4096));
                 });
             }
         }
@@ -224,9 +253,97 @@ fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
 
     ui.separator();
 
-    ui.collapsing("MKV / FFmpeg (optional)", |ui| {
+    ui.horizontal(|ui| {
+        ui.label("Compression");
+        help_button(ui, state, HelpTopic::Compression);
+        let current = state.encode.rp.compression;
+        egui::ComboBox::from_id_salt("encode_compression")
+            .selected_text(current.name())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.encode.rp.compression, sllv_core::Compression::None, "none");
+                if ui.selectable_label(matches!(current, sllv_core::Compression::Zstd { .. }), "zstd").clicked() {
+                    state.encode.rp.compression = sllv_core::Compression::Zstd {
+                        level: sllv_core::Compression::ZSTD_DEFAULT_LEVEL,
+                    };
+                }
+                if ui.selectable_label(matches!(current, sllv_core::Compression::Xz { .. }), "xz").clicked() {
+                    state.encode.rp.compression = sllv_core::Compression::Xz {
+                        preset: sllv_core::Compression::XZ_DEFAULT_PRESET,
+                    };
+                }
+            });
+        match &mut state.encode.rp.compression {
+            sllv_core::Compression::None => {}
+            sllv_core::Compression::Zstd { level } => {
+                ui.label("Level");
+                ui.add(egui::DragValue::new(level).range(1..=22));
+            }
+            sllv_core::Compression::Xz { preset } => {
+                ui.label("Preset");
+                ui.add(egui::DragValue::new(preset).range(0..=9));
+            }
+        }
+        let mut dedup = state.encode.rp.dedup.is_some();
+        if ui.checkbox(&mut dedup, "Deduplicate").changed() {
+            state.encode.rp.dedup = dedup.then(sllv_core::DedupParams::default);
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Packing");
+        help_button(ui, state, HelpTopic::Packing);
+        ui.checkbox(&mut state.encode.pack.follow_symlinks, "Follow symlinks");
+        ui.checkbox(&mut state.encode.pack.xattrs, "Extended attributes");
+        ui.checkbox(&mut state.encode.pack.reproducible, "Reproducible");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Include");
+        help_button(ui, state, HelpTopic::Filter);
+        ui.add(egui::TextEdit::singleline(&mut state.encode.include).hint_text("all files"));
+        ui.label("Exclude");
+        ui.add(egui::TextEdit::singleline(&mut state.encode.exclude).hint_text("target, *.log"));
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut state.encode.pack.filter.ignore_files, "Ignore files (.gitignore/.sllvignore)");
+        ui.label("Max file size (MiB)");
+        ui.add(egui::DragValue::new(&mut state.encode.max_file_mib));
+    });
+
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.label("Passphrase");
+        help_button(ui, state, HelpTopic::Encryption);
+        ui.add(egui::TextEdit::singleline(&mut state.encode.passphrase).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("or recipient public key");
+        ui.add(egui::TextEdit::singleline(&mut state.encode.recipient).hint_text("64 hex chars"));
+    });
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "Signing key: {}",
+            state
+                .encode
+                .sign_key
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "(unsigned)".into())
+        ));
+        help_button(ui, state, HelpTopic::Signing);
+        if ui.button("Choose key file").clicked() {
+            state.encode.sign_key = rfd::FileDialog::new().pick_file();
+        }
+        if ui.button("Clear").clicked() {
+            state.encode.sign_key = None;
+        }
+    });
+
+    ui.separator();
+
+    ui.collapsing("Video / FFmpeg (optional)", |ui| {
         ui.label(format!(
-            "Output MKV: {}",
+            "Output video: {}",
             state
                 .encode
                 .out_mkv
@@ -235,18 +352,74 @@ fn ui_encode(ui: &mut egui::Ui, state: &mut AppState) {
                 .unwrap_or_else(|| "(disabled)".into())
         ));
         ui.horizontal(|ui| {
-            if ui.button("Choose MKV output").clicked() {
-                state.encode.out_mkv = rfd::FileDialog::new().add_filter("Matroska", &["mkv"]).save_file();
+            if ui.button("Choose video output").clicked() {
+                let v = &mut state.encode.video;
+                state.encode.out_mkv = rfd::FileDialog::new()
+                    .add_filter("Video", &["mkv", "mp4", "webm", "avi"])
+                    .save_file();
+                if let Some(c) = state.encode.out_mkv.as_deref().and_then(sllv_core::VideoContainer::from_path) {
+                    v.container = c;
+                }
             }
-            if ui.button("Disable MKV").clicked() {
+            if ui.button("Disable video").clicked() {
                 state.encode.out_mkv = None;
             }
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Codec");
+            help_button(ui, state, HelpTopic::VideoExport);
+            let v = &mut state.encode.video;
+            let mut codec = v.codec;
+            egui::ComboBox::from_id_salt("encode_codec")
+                .selected_text(codec.name())
+                .show_ui(ui, |ui| {
+                    for c in sllv_core::VideoCodec::ALL {
+                        ui.selectable_value(&mut codec, c, c.name());
+                    }
+                });
+            if codec != v.codec {
+                v.codec = codec;
+                v.crf = None;
+            }
+            ui.label("Container");
+            egui::ComboBox::from_id_salt("encode_container")
+                .selected_text(v.container.extension())
+                .show_ui(ui, |ui| {
+                    for c in sllv_core::VideoContainer::ALL {
+                        ui.selectable_value(&mut v.container, c, c.extension());
+                    }
+                });
+            if let Some(crf) = v.codec.default_crf() {
+                ui.label("CRF");
+                let mut value = v.crf.unwrap_or(crf);
+                if ui.add(egui::DragValue::new(&mut value).range(0..=63)).changed() {
+                    v.crf = Some(value);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Pixel format");
+            let v = &mut state.encode.video;
+            let mut pix_fmt = v.pix_fmt.clone().unwrap_or_default();
+            let edit = egui::TextEdit::singleline(&mut pix_fmt).hint_text(v.codec.default_pix_fmt());
+            if ui.add(edit).changed() {
+                v.pix_fmt = (!pix_fmt.trim().is_empty()).then(|| pix_fmt.trim().to_string());
+            }
+        });
+
         ui.horizontal(|ui| {
             ui.label("FPS");
             help_button(ui, state, HelpTopic::Fps);
-            ui.add(egui::DragValue::new(&mut state.encode.fps).clamp_range(1..=240));
+            let v = &mut state.encode.video;
+            ui.add(egui::DragValue::new(&mut v.fps).range(1..=240));
+            ui.label("Repeat");
+            ui.add(egui::DragValue::new(&mut v.repeat).range(1..=30));
+            ui.label("Lead-in (s)");
+            ui.add(egui::DragValue::new(&mut v.lead_in_secs).range(0.0..=30.0).speed(0.1));
+            ui.label("Lead-out (s)");
+            ui.add(egui::DragValue::new(&mut v.lead_out_secs).range(0.0..=30.0).speed(0.1));
         });
 
         ui.horizontal(|ui| {
@@ -284,21 +457,14 @@ fn ui_decode(ui: &mut egui::Ui, state: &mut AppState) {
     ui.horizontal(|ui| {
         ui.label("Profile");
         help_button(ui, state, HelpTopic::Profile);
-        let mut p = match state.decode.profile {
-            sllv_core::Profile::Archive => 0,
-            sllv_core::Profile::Scan => 1,
-        };
-        egui::ComboBox::from_id_source("decode_profile")
-            .selected_text(if p == 0 { "Archive" } else { "Scan" })
+        let mut new_profile = state.decode.profile;
+        egui::ComboBox::from_id_salt("decode_profile")
+            .selected_text(profile_label(new_profile))
             .show_ui(ui, |ui| {
-                ui.selectable_value(&mut p, 0, "Archive");
-                ui.selectable_value(&mut p, 1, "Scan");
+                for p in sllv_core::Profile::ALL {
+                    ui.selectable_value(&mut new_profile, p, profile_label(p));
+                }
             });
-        let new_profile = if p == 0 {
-            sllv_core::Profile::Archive
-        } else {
-            sllv_core::Profile::Scan
-        };
         if new_profile.name() != state.decode.profile.name() {
             state.decode.profile = new_profile;
             state.decode.rp = new_profile.defaults();
@@ -331,7 +497,9 @@ fn ui_decode(ui: &mut egui::Ui, state: &mut AppState) {
                 .unwrap_or_else(|| "(not set)".into())
         ));
         if ui.button("Choose MKV file").clicked() {
-            state.decode.input_mkv = rfd::FileDialog::new().add_filter("Matroska", &["mkv"]).pick_file();
+            state.decode.input_mkv = rfd::FileDialog::new()
+                .add_filter("Video or animation", &["mkv", "mp4", "webm", "avi", "y4m", "png", "webp", "gif"])
+                .pick_file();
         }
 
         if ui.button("Use frames only").clicked() {
@@ -357,23 +525,88 @@ fn ui_decode(ui: &mut egui::Ui, state: &mut AppState) {
         state.decode.out_tar = rfd::FileDialog::new().add_filter("tar", &["tar"]).save_file();
     }
 
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "Or extract to folder: {}",
+            state
+                .decode
+                .out_dir
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "(off)".into())
+        ));
+        help_button(ui, state, HelpTopic::Extract);
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Choose output folder").clicked() {
+            state.decode.out_dir = rfd::FileDialog::new().pick_folder();
+        }
+        if ui.button("Write .tar instead").clicked() {
+            state.decode.out_dir = None;
+        }
+        if state.decode.out_dir.is_some() {
+            ui.label("If a file exists");
+            egui::ComboBox::from_id_salt("decode_on_collision")
+                .selected_text(state.decode.on_collision.name())
+                .show_ui(ui, |ui| {
+                    for policy in [
+                        sllv_core::CollisionPolicy::Skip,
+                        sllv_core::CollisionPolicy::Overwrite,
+                        sllv_core::CollisionPolicy::Rename,
+                    ] {
+                        ui.selectable_value(&mut state.decode.on_collision, policy, policy.name());
+                    }
+                });
+        }
+    });
+
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.label("Passphrase");
+        help_button(ui, state, HelpTopic::Encryption);
+        ui.add(egui::TextEdit::singleline(&mut state.decode.passphrase).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "or identity key: {}",
+            state
+                .decode
+                .identity
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "(none)".into())
+        ));
+        if ui.button("Choose key file").clicked() {
+            state.decode.identity = rfd::FileDialog::new().pick_file();
+        }
+        if ui.button("Clear").clicked() {
+            state.decode.identity = None;
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Trusted signer public key");
+        help_button(ui, state, HelpTopic::Signing);
+        ui.add(egui::TextEdit::singleline(&mut state.decode.trusted_pubkey).hint_text("64 hex chars (optional)"));
+    });
+
     ui.separator();
 
     ui.collapsing("Safe settings (keep consistent for decode)", |ui| {
         ui.horizontal(|ui| {
             ui.label("Cell size (px)");
             help_button(ui, state, HelpTopic::CellPx);
-            ui.add(egui::DragValue::new(&mut state.decode.rp.cell_px).clamp_range(1..=32));
+            ui.add(egui::DragValue::new(&mut state.decode.rp.cell_px).range(1..=32));
         });
         ui.horizontal(|ui| {
             ui.label("Border cells");
             help_button(ui, state, HelpTopic::BorderCells);
-            ui.add(egui::DragValue::new(&mut state.decode.rp.border_cells).clamp_range(0..=64));
+            ui.add(egui::DragValue::new(&mut state.decode.rp.border_cells).range(0..=64));
         });
         ui.horizontal(|ui| {
             ui.label("Fiducial size (cells)");
             help_button(ui, state, HelpTopic::FiducialSize);
-            ui.add(egui::DragValue::new(&mut state.decode.rp.fiducial_size_cells).clamp_range(4..=64));
+            ui.add(egui::DragValue::new(&mut state.decode.rp.fiducial_size_cells).range(4..=64));
         });
         ui.horizontal(|ui| {
             ui.label("Deskew");
@@ -392,15 +625,15 @@ fn ui_decode(ui: &mut egui::Ui, state: &mut AppState) {
             if let Some(ref mut fec) = state.decode.rp.fec {
                 ui.horizontal(|ui| {
                     ui.label("Data shards");
-                    ui.add(egui::DragValue::new(&mut fec.data_shards).clamp_range(1..=64));
+                    ui.add(egui::DragValue::new(&mut fec.data_shards).range(1..=64));
                 });
                 ui.horizontal(|ui| {
                     ui.label("Parity shards");
-                    ui.add(egui::DragValue::new(&mut fec.parity_shards).clamp_range(0..=64));
+                    ui.add(egui::DragValue::new(&mut fec.parity_shards).range(0..=64));
                 });
                 ui.horizontal(|ui| {
                     ui.label("Shard bytes");
-                    ui.add(egui::DragValue::new(&mut fec.shard_bytes).clamp_range(64..=4096));
+                    ui.add(egui::DragValue::new(&mut fec.shard_bytes).range(64..=4096));
                 });
             }
         }
@@ -432,12 +665,48 @@ fn ui_decode(ui: &mut egui::Ui, state: &mut AppState) {
     ui.separator();
 
     ui.add_enabled_ui(!state.is_running, |ui| {
-        if ui.button("Start decode").clicked() {
-            spawn_decode_thread(state);
-        }
+        ui.horizontal(|ui| {
+            if ui.button("Start decode").clicked() {
+                spawn_decode_thread(state);
+            }
+            if state.decode.input_frames.is_some() && ui.button("List contents").clicked() {
+                list_contents(state);
+            }
+        });
     });
 }
 
+/// Show the archive's table of contents in the log; only its own frames are decoded.
+fn list_contents(state: &mut AppState) {
+    let Some(dir) = state.decode.input_frames.clone() else {
+        return;
+    };
+    let res = decode_params(state).and_then(|rp| sllv_core::read_toc(&dir, &rp).map_err(|e| e.to_string()));
+    match res {
+        Ok(toc) => {
+            for e in &toc.entries {
+                state.log.push_str(&format!("{:<8} {:>12}  {}\n", e.kind.name(), e.size, e.path));
+            }
+            state
+                .log
+                .push_str(&format!("{} entries, {} bytes in files\n", toc.entries.len(), toc.file_bytes()));
+        }
+        Err(e) => state.log.push_str(&format!("Error: list contents: {e}\n")),
+    }
+}
+
+/// Decode parameters with the passphrase or identity key filled in.
+fn decode_params(state: &AppState) -> Result<sllv_core::RasterParams, String> {
+    let mut rp = state.decode.rp.clone();
+    if !state.decode.passphrase.is_empty() {
+        rp.decryption = Some(sllv_core::DecryptionKey::Passphrase(state.decode.passphrase.clone()));
+    } else if let Some(path) = &state.decode.identity {
+        let k = read_key_file(path).map_err(|e| format!("identity key: {e}"))?;
+        rp.decryption = Some(sllv_core::DecryptionKey::Identity(k));
+    }
+    Ok(rp)
+}
+
 fn ui_doctor(ui: &mut egui::Ui, state: &mut AppState) {
     ui.heading("Doctor");
     ui.label("Checks basic things that commonly break installs.");
@@ -458,8 +727,11 @@ fn run_doctor() -> anyhow::Result<String> {
 }
 
 fn spawn_encode_thread(state: &mut AppState) {
-    let input = match state.encode.input.as_ref() {
-        Some(p) => p.clone(),
+    let inputs: Vec<sllv_core::PackInput> = match state.encode.input.as_ref() {
+        Some(p) => std::iter::once(p)
+            .chain(&state.encode.extra_inputs)
+            .map(|p| sllv_core::PackInput::Path(p.clone()))
+            .collect(),
         None => {
             state.log.push_str("Error: Input not set\n");
             return;
@@ -474,9 +746,39 @@ fn spawn_encode_thread(state: &mut AppState) {
     };
 
     let out_mkv = state.encode.out_mkv.clone();
-    let fps = state.encode.fps;
+    let mut pack_opts = state.encode.pack.clone();
+    pack_opts.filter.include = split_globs(&state.encode.include);
+    pack_opts.filter.exclude = split_globs(&state.encode.exclude);
+    pack_opts.filter.max_file_size = (state.encode.max_file_mib > 0).then_some(state.encode.max_file_mib << 20);
+    let video = state.encode.video.clone();
     let ffmpeg_path = state.encode.ffmpeg_path.clone();
-    let rp = state.encode.rp.clone();
+    let mut rp = state.encode.rp.clone();
+    if out_mkv.is_some() {
+        if let Err(e) = video.validate(&rp) {
+            state.log.push_str(&format!("Error: video: {e}\n"));
+            return;
+        }
+    }
+    if !state.encode.passphrase.is_empty() {
+        rp.encryption = Some(sllv_core::EncryptionKey::Passphrase(state.encode.passphrase.clone()));
+    } else if !state.encode.recipient.trim().is_empty() {
+        match sllv_core::crypto::parse_key_hex(&state.encode.recipient) {
+            Ok(k) => rp.encryption = Some(sllv_core::EncryptionKey::Recipient(k)),
+            Err(e) => {
+                state.log.push_str(&format!("Error: recipient key: {e}\n"));
+                return;
+            }
+        }
+    }
+    if let Some(path) = &state.encode.sign_key {
+        match read_key_file(path) {
+            Ok(k) => rp.signing = Some(sllv_core::ManifestSigner(k)),
+            Err(e) => {
+                state.log.push_str(&format!("Error: signing key: {e}\n"));
+                return;
+            }
+        }
+    }
 
     let (tx, rx) = mpsc::channel();
     state.progress_rx = Some(rx);
@@ -490,11 +792,30 @@ fn spawn_encode_thread(state: &mut AppState) {
 
     thread::spawn(move || {
         let res = (|| -> anyhow::Result<()> {
-            let (tar, name) = sllv_core::pack::pack_path_to_tar_bytes(&input)?;
-            sllv_core::raster::encode_bytes_to_frames_dir_with_progress(&tar, &name, &out_frames, &rp, Some(tx.clone()))?;
+            let packed = sllv_core::pack::pack_inputs_to_tar_bytes(&inputs, &pack_opts)?;
+            for s in &packed.skipped {
+                let _ = tx.send(sllv_core::raster::ProgressMsg::Info(s.to_string()));
+            }
+            let mut rp = rp;
+            rp.filter = packed.filter_info(&pack_opts.filter);
+            let manifest = sllv_core::raster::encode_bytes_to_frames_dir_with_progress(
+                &packed.tar,
+                &packed.name,
+                &out_frames,
+                &rp,
+                Some(tx.clone()),
+            )?;
+            if let Some(d) = &manifest.dedup {
+                let _ = tx.send(sllv_core::raster::ProgressMsg::Info(format!(
+                    "Deduplicated: {} of {} chunks unique, ratio {:.2}x",
+                    d.unique_chunks,
+                    d.chunks,
+                    d.ratio()
+                )));
+            }
 
             if let Some(out) = out_mkv {
-                sllv_core::ffmpeg::frames_to_ffv1_mkv(&out_frames, &out, fps, ffmpeg_path.as_deref())?;
+                sllv_core::ffmpeg::frames_to_video(&out_frames, &out, &rp, &video, ffmpeg_path.as_deref())?;
             }
             Ok(())
         })();
@@ -511,10 +832,13 @@ fn spawn_encode_thread(state: &mut AppState) {
 }
 
 fn spawn_decode_thread(state: &mut AppState) {
-    let out_tar = match state.decode.out_tar.as_ref() {
-        Some(p) => p.clone(),
-        None => {
-            state.log.push_str("Error: Output .tar not set\n");
+    let out_dir = state.decode.out_dir.clone();
+    let on_collision = state.decode.on_collision;
+    let out_tar = match (state.decode.out_tar.as_ref(), &out_dir) {
+        (Some(p), _) => p.clone(),
+        (None, Some(_)) => std::path::PathBuf::new(),
+        (None, None) => {
+            state.log.push_str("Error: Output .tar (or folder) not set\n");
             return;
         }
     };
@@ -522,7 +846,23 @@ fn spawn_decode_thread(state: &mut AppState) {
     let input_mkv = state.decode.input_mkv.clone();
     let input_frames = state.decode.input_frames.clone();
     let ffmpeg_path = state.decode.ffmpeg_path.clone();
-    let rp = state.decode.rp.clone();
+    let rp = match decode_params(state) {
+        Ok(rp) => rp,
+        Err(e) => {
+            state.log.push_str(&format!("Error: {e}\n"));
+            return;
+        }
+    };
+    let mut trusted = Vec::new();
+    if !state.decode.trusted_pubkey.trim().is_empty() {
+        match sllv_core::crypto::parse_key_hex(&state.decode.trusted_pubkey) {
+            Ok(k) => trusted.push(k),
+            Err(e) => {
+                state.log.push_str(&format!("Error: trusted public key: {e}\n"));
+                return;
+            }
+        }
+    }
 
     let (tx, rx) = mpsc::channel();
     state.progress_rx = Some(rx);
@@ -536,30 +876,56 @@ fn spawn_decode_thread(state: &mut AppState) {
 
     thread::spawn(move || {
         let res = (|| -> anyhow::Result<()> {
-            let frames_dir: std::path::PathBuf;
-            let _temp_guard;
-
-            if let Some(mkv) = input_mkv {
-                let tmp = std::env::temp_dir().join(format!(
-                    "sllv_gui_decode_frames_{}",
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_millis()
-                ));
-                std::fs::create_dir_all(&tmp)?;
-                sllv_core::ffmpeg::mkv_to_frames(&mkv, &tmp, ffmpeg_path.as_deref())?;
-                frames_dir = tmp;
-                _temp_guard = TempDirCleanup { path: frames_dir.clone() };
-            } else if let Some(frames) = input_frames {
-                frames_dir = frames;
-                _temp_guard = TempDirCleanup { path: std::path::PathBuf::new() };
-            } else {
-                anyhow::bail!("Choose a frames folder or an MKV file");
+            let manifest = match (&input_mkv, &input_frames) {
+                (Some(mkv), _) => sllv_core::read_video_manifest(mkv)?,
+                (None, Some(frames)) => sllv_core::raster::read_manifest(frames)?,
+                (None, None) => anyhow::bail!("Choose a frames folder or an MKV file"),
+            };
+            if let Some(mkv) = &input_mkv {
+                let probe = sllv_core::probe_video(mkv, ffmpeg_path.as_deref())?;
+                let _ = tx.send(sllv_core::raster::ProgressMsg::Info(format!("Video: {}", probe.summary())));
+                for w in probe.warnings(&manifest, &rp) {
+                    let _ = tx.send(sllv_core::raster::ProgressMsg::Info(format!("Warning: {w}")));
+                }
+            }
+            // Frames come straight from ffmpeg for a video, from the frame files otherwise.
+            let decode = |mut out: &mut dyn std::io::Write| -> anyhow::Result<()> {
+                match (&input_mkv, &input_frames) {
+                    (Some(mkv), _) => {
+                        sllv_core::decode_video_to_writer(mkv, &manifest, &rp, out, ffmpeg_path.as_deref(), Some(tx.clone()))?;
+                    }
+                    (None, Some(frames)) => {
+                        sllv_core::raster::decode_frames_dir_to_writer_with_progress(frames, &rp, &mut out, Some(tx.clone()))?;
+                    }
+                    (None, None) => {}
+                }
+                Ok(())
+            };
+            let status = sllv_core::sign::verify_manifest(&manifest, &trusted)?;
+            if matches!(status, sllv_core::SignatureStatus::Invalid { .. }) {
+                anyhow::bail!("signature {status}");
             }
 
-            let bytes = sllv_core::raster::decode_frames_dir_to_bytes_with_progress(&frames_dir, &rp, Some(tx.clone()))?;
-            std::fs::write(&out_tar, bytes)?;
+            if let Some(out_dir) = out_dir {
+                // Verify the whole archive before anything is extracted.
+                let mut scratch = sllv_core::PartialFile::scratch_in(&out_dir)?;
+                decode(&mut scratch)?;
+                let opts = sllv_core::ExtractOptions { on_collision };
+                let report = sllv_core::extract_payload_to_dir(scratch.reopen()?, &manifest, &out_dir, &opts)?;
+                for entry in &report.entries {
+                    let _ = tx.send(sllv_core::raster::ProgressMsg::Info(entry.to_string()));
+                }
+                let _ = tx.send(sllv_core::raster::ProgressMsg::Info(format!(
+                    "Extracted {} files to {}",
+                    report.files_written(),
+                    out_dir.display()
+                )));
+            } else {
+                let mut out = sllv_core::PartialFile::create(&out_tar)?;
+                decode(&mut out)?;
+                out.commit()?;
+            }
+            let _ = tx.send(sllv_core::raster::ProgressMsg::Info(format!("Signature: {status}")));
             Ok(())
         })();
 
@@ -574,15 +940,39 @@ fn spawn_decode_thread(state: &mut AppState) {
     });
 }
 
-struct TempDirCleanup {
-    path: std::path::PathBuf,
+fn read_key_file(path: &std::path::Path) -> Result<[u8; 32], String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    sllv_core::crypto::parse_key_hex(&text).map_err(|e| e.to_string())
 }
 
-impl Drop for TempDirCleanup {
-    fn drop(&mut self) {
-        if self.path.as_os_str().is_empty() {
-            return;
-        }
-        let _ = std::fs::remove_dir_all(&self.path);
+fn split_globs(s: &str) -> Vec<String> {
+    s.split(',').map(|g| g.trim().to_string()).filter(|g| !g.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_are_comma_separated() {
+        assert_eq!(split_globs(" *.rs, target/ ,,docs/**"), ["*.rs", "target/", "docs/**"]);
+        assert!(split_globs(" , ").is_empty());
+    }
+
+    #[test]
+    fn decode_key_is_the_passphrase_else_the_identity_file() {
+        use sllv_core::DecryptionKey;
+        let path = std::env::temp_dir().join(format!("sllv_gui_identity_{}", std::process::id()));
+        std::fs::write(&path, "07".repeat(32)).unwrap();
+        let mut state = AppState::default();
+        assert!(decode_params(&state).unwrap().decryption.is_none());
+        state.decode.identity = Some(path.clone());
+        assert!(matches!(decode_params(&state).unwrap().decryption, Some(DecryptionKey::Identity(k)) if k == [7; 32]));
+        state.decode.passphrase = "secret".into();
+        assert!(matches!(decode_params(&state).unwrap().decryption, Some(DecryptionKey::Passphrase(p)) if p == "secret"));
+        std::fs::write(&path, "not hex").unwrap();
+        state.decode.passphrase.clear();
+        assert!(decode_params(&state).unwrap_err().starts_with("identity key: "));
+        std::fs::remove_file(&path).unwrap();
     }
 }